@@ -42,6 +42,52 @@ mod bitset {
         }
         b.iter(|| range.next().map(|i| bitset.contains(i)))
     }
+
+    fn task_sets() -> Vec<BitSet> {
+        (0..256u32)
+            .map(|i| (i..1_000_000).step_by(251 + i as usize).collect())
+            .collect()
+    }
+
+    #[bench]
+    fn union_of(b: &mut test::Bencher) {
+        use hibitset::BitSetLike;
+
+        let sets = task_sets();
+        let refs: Vec<&dyn BitSetLike> = sets.iter().map(|set| set as _).collect();
+        b.iter(|| BitSet::union_of(&refs))
+    }
+
+    fn dense_pair() -> (BitSet, BitSet) {
+        let a = (0..16_000_000).filter(|i| i % 3 != 0).collect();
+        let b = (0..16_000_000).filter(|i| i % 5 != 0).collect();
+        (a, b)
+    }
+
+    #[bench]
+    fn count_and(b: &mut test::Bencher) {
+        use hibitset::BitSetLike;
+
+        let (x, y) = dense_pair();
+        b.iter(|| (&x & &y).iter().count())
+    }
+
+    #[cfg(feature = "parallel")]
+    #[bench]
+    fn par_count_and(b: &mut test::Bencher) {
+        use hibitset::BitSetLike;
+
+        let (x, y) = dense_pair();
+        b.iter(|| (&x & &y).par_count())
+    }
+
+    #[cfg(feature = "parallel")]
+    #[bench]
+    fn par_union_of(b: &mut test::Bencher) {
+        let sets = task_sets();
+        let refs: Vec<&BitSet> = sets.iter().collect();
+        b.iter(|| BitSet::par_union_of(&refs))
+    }
 }
 
 mod atomic_bitset {
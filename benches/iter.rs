@@ -18,6 +18,8 @@ use self::Mode::*;
 
 enum Mode {
     Seq,
+    Unordered,
+    ForEach,
     #[cfg(feature = "parallel")]
     Par(u8),
 }
@@ -31,6 +33,15 @@ fn bench(n: usize, mode: Mode, b: &mut Bencher) {
     }
     match mode {
         Seq => b.iter(|| black_box((&bitset).iter().map(black_box).count())),
+        Unordered => b.iter(|| black_box((&bitset).iter_unordered().map(black_box).count())),
+        ForEach => b.iter(|| {
+            let mut count = 0;
+            bitset.for_each(|i| {
+                black_box(i);
+                count += 1;
+            });
+            black_box(count)
+        }),
         #[cfg(feature = "parallel")]
         Par(splits) => b.iter(|| {
             black_box(
@@ -69,6 +80,38 @@ fn iter_1000000(b: &mut Bencher) {
     bench(1000000, Seq, b);
 }
 
+#[bench]
+fn for_each_10000(b: &mut Bencher) {
+    bench(10000, ForEach, b);
+}
+
+#[bench]
+fn for_each_1000000(b: &mut Bencher) {
+    bench(1000000, ForEach, b);
+}
+
+#[bench]
+fn iter_unordered_10000(b: &mut Bencher) {
+    bench(10000, Unordered, b);
+}
+
+#[bench]
+fn iter_unordered_1000000(b: &mut Bencher) {
+    bench(1000000, Unordered, b);
+}
+
+/// Sparse indices spread over the whole keyspace, so most words the
+/// iterator reads miss the cache. Compare with `--features prefetch`.
+#[bench]
+fn iter_sparse_16m(b: &mut Bencher) {
+    let mut rng = thread_rng();
+    let mut bitset = BitSet::new();
+    for _ in 0..100_000 {
+        bitset.add(rng.gen_range(0, 1 << 24));
+    }
+    b.iter(|| black_box((&bitset).iter().map(black_box).count()));
+}
+
 #[cfg(feature = "parallel")]
 mod par {
     use super::*;
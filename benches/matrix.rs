@@ -0,0 +1,135 @@
+//! The operations of `BitSet` over a matrix of index patterns, so changes
+//! such as `--features simd` or `--features prefetch` can be compared on
+//! every shape of set instead of one.
+//!
+//! Criterion is only pulled in by the `bench` feature, which this bench
+//! needs:
+//!
+//! ```text
+//! cargo bench --features bench --bench matrix
+//! ```
+//!
+//! Each pattern gets a criterion group, so adding `-- clustered/` runs a
+//! single row and `-- /and_iter` a single column.
+
+#[macro_use]
+extern crate criterion;
+extern crate hibitset;
+extern crate rand;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+
+use criterion::{black_box, BatchSize, Criterion};
+use hibitset::{BitSet, BitSetLike};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+#[cfg(feature = "parallel")]
+use rayon::iter::ParallelIterator;
+
+/// The indices the patterns are drawn from.
+const UNIVERSE: u32 = 1 << 20;
+
+/// The shape of the indices of a benchmarked set.
+#[derive(Clone, Copy)]
+pub enum Pattern {
+    /// Nine in ten indices, so almost every word is full.
+    Dense,
+    /// One in a thousand indices, so most words hold a single bit.
+    Sparse,
+    /// Runs of 256 indices every 16384, so whole subtrees are empty.
+    Clustered,
+    /// Every second or third index, depending on the seed, so every word
+    /// is partially filled.
+    Striped,
+}
+
+impl Pattern {
+    const ALL: [Pattern; 4] = [
+        Pattern::Dense,
+        Pattern::Sparse,
+        Pattern::Clustered,
+        Pattern::Striped,
+    ];
+
+    /// The name of the criterion group of the pattern.
+    pub fn name(self) -> &'static str {
+        match self {
+            Pattern::Dense => "dense",
+            Pattern::Sparse => "sparse",
+            Pattern::Clustered => "clustered",
+            Pattern::Striped => "striped",
+        }
+    }
+
+    /// Generates the indices of the pattern in ascending order, the same
+    /// ones for the same `seed`.
+    pub fn indices(self, seed: u64) -> Vec<u32> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        match self {
+            Pattern::Dense => (0..UNIVERSE).filter(|_| rng.gen_bool(0.9)).collect(),
+            Pattern::Sparse => (0..UNIVERSE).filter(|_| rng.gen_bool(0.001)).collect(),
+            Pattern::Clustered => (0..UNIVERSE / 16_384)
+                .flat_map(|cluster| {
+                    let start = cluster * 16_384 + rng.gen_range(0, 16_384 - 256);
+                    start..start + 256
+                })
+                .collect(),
+            Pattern::Striped => (0..UNIVERSE).step_by(2 + seed as usize % 2).collect(),
+        }
+    }
+
+    /// Generates the set of the pattern for `seed`.
+    pub fn set(self, seed: u64) -> BitSet {
+        self.indices(seed).into_iter().collect()
+    }
+}
+
+fn matrix(c: &mut Criterion) {
+    for &pattern in &Pattern::ALL {
+        let mut group = c.benchmark_group(pattern.name());
+        let indices = pattern.indices(1);
+        let (set, other) = (pattern.set(1), pattern.set(2));
+
+        group.bench_function("add", |b| {
+            b.iter(|| {
+                let mut set = BitSet::with_capacity(UNIVERSE);
+                for &i in &indices {
+                    set.add(i);
+                }
+                black_box(set)
+            })
+        });
+
+        // The set is cloned in the untimed setup of each batch.
+        group.bench_function("remove", |b| {
+            b.iter_batched(
+                || set.clone(),
+                |mut set| {
+                    for &i in &indices {
+                        set.remove(i);
+                    }
+                    set
+                },
+                BatchSize::LargeInput,
+            )
+        });
+
+        group.bench_function("iter", |b| {
+            b.iter(|| black_box((&set).iter().map(black_box).count()))
+        });
+
+        group.bench_function("and_iter", |b| {
+            b.iter(|| black_box((&set & &other).iter().map(black_box).count()))
+        });
+
+        #[cfg(feature = "parallel")]
+        group.bench_function("par_iter", |b| {
+            b.iter(|| black_box((&set).par_iter().map(black_box).count()))
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, matrix);
+criterion_main!(benches);
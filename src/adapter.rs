@@ -0,0 +1,192 @@
+use std::collections::{BTreeSet, HashSet};
+use std::hash::BuildHasher;
+
+use util::*;
+use {BitSet, BitSetLike};
+
+/// A [`BitSetLike`] view of a `BTreeSet<u32>`, so a std set can be joined
+/// with bit sets while code is moved over to them.
+///
+/// The layer words are worked out on demand, with a range query for each
+/// of their set bits, so reading the view costs `O(log n)` per bit.
+/// Indices at or above `MAX_EID` are left out of the view.
+///
+/// # Examples
+///
+/// ```
+/// # use std::collections::BTreeSet;
+/// # use hibitset::{BTreeSetAdapter, BitSet, BitSetLike};
+/// let old: BTreeSet<u32> = [1, 5, 100].iter().cloned().collect();
+/// let new: BitSet = [5, 100, 200].iter().collect();
+/// let both = &new & BTreeSetAdapter(&old);
+/// assert_eq!(both.iter().collect::<Vec<_>>(), [5, 100]);
+/// ```
+///
+/// [`BitSetLike`]: trait.BitSetLike.html
+#[derive(Debug, Clone, Copy)]
+pub struct BTreeSetAdapter<'a>(pub &'a BTreeSet<u32>);
+
+impl BTreeSetAdapter<'_> {
+    /// The word at `idx` of `level`, found by jumping from one non-empty
+    /// block of the level below to the next.
+    fn word(&self, level: usize, idx: usize) -> usize {
+        let shift = BITS * level;
+        let start = (idx << (shift + BITS)) as Index;
+        let end = start + (1 << (shift + BITS));
+        let mut word = 0;
+        let mut from = start;
+        while let Some(&id) = self.0.range(from..end).next() {
+            let bit = (id - start) >> shift;
+            word |= 1 << bit;
+            from = start + ((bit + 1) << shift);
+        }
+        word
+    }
+}
+
+impl BitSetLike for BTreeSetAdapter<'_> {
+    #[inline]
+    fn layer3(&self) -> usize {
+        self.word(3, 0)
+    }
+
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        self.word(2, i)
+    }
+
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        self.word(1, i)
+    }
+
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        self.word(0, i)
+    }
+
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        (i as usize) < MAX_EID && self.0.contains(&i)
+    }
+
+    #[inline]
+    fn exact_summaries(&self) -> bool {
+        true
+    }
+}
+
+/// A [`BitSetLike`] view of a `HashSet<u32>`, so a std set can be joined
+/// with bit sets while code is moved over to them.
+///
+/// A hash set can't be queried by range, so creating the view goes over
+/// the set once to note which layer 0 words are non-empty. The upper
+/// layers are read from that, and each layer 0 word is put together with
+/// a lookup per bit. Indices at or above `MAX_EID` are left out of the
+/// view.
+///
+/// # Examples
+///
+/// ```
+/// # use std::collections::HashSet;
+/// # use hibitset::{BitSet, BitSetLike, HashSetAdapter};
+/// let old: HashSet<u32> = [1, 5, 100].iter().cloned().collect();
+/// let new: BitSet = [5, 100, 200].iter().collect();
+/// let added = (&new).iter_added(HashSetAdapter::new(&old));
+/// assert_eq!(added.collect::<Vec<_>>(), [200]);
+/// ```
+///
+/// [`BitSetLike`]: trait.BitSetLike.html
+#[derive(Debug, Clone)]
+pub struct HashSetAdapter<'a, S> {
+    set: &'a HashSet<u32, S>,
+    // The layer 0 words holding indices, which are the view's layers
+    // shifted down by one.
+    words: BitSet,
+}
+
+impl<'a, S: BuildHasher> HashSetAdapter<'a, S> {
+    /// Creates a view of `set`.
+    pub fn new(set: &'a HashSet<u32, S>) -> Self {
+        let words = set
+            .iter()
+            .filter(|&&id| (id as usize) < MAX_EID)
+            .map(|&id| id >> BITS)
+            .collect();
+        HashSetAdapter { set, words }
+    }
+
+    /// Returns the viewed set.
+    pub fn get(&self) -> &'a HashSet<u32, S> {
+        self.set
+    }
+}
+
+impl<S: BuildHasher> BitSetLike for HashSetAdapter<'_, S> {
+    #[inline]
+    fn layer3(&self) -> usize {
+        self.words.layer2(0)
+    }
+
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        self.words.layer1(i)
+    }
+
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        self.words.layer0(i)
+    }
+
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        if !self.words.contains(i as Index) {
+            return 0;
+        }
+        let base = (i << BITS) as Index;
+        (0..1 << BITS)
+            .filter(|&bit| self.set.contains(&(base + bit)))
+            .fold(0, |word, bit| word | 1 << bit)
+    }
+
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        (i as usize) < MAX_EID && self.set.contains(&i)
+    }
+
+    #[inline]
+    fn exact_summaries(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashSet};
+
+    use {BTreeSetAdapter, BitSet, BitSetLike, HashSetAdapter};
+
+    #[test]
+    fn adapters() {
+        let ids = [0, 1, 63, 64, 4095, 4096, 262_143, 262_144, 9_999_999];
+        let bits: BitSet = ids.iter().collect();
+        let btree: BTreeSet<u32> = ids.iter().cloned().chain(Some(::MAX_EID as u32)).collect();
+        let hash: HashSet<u32> = btree.iter().cloned().collect();
+
+        let btree = BTreeSetAdapter(&btree);
+        let hash = HashSetAdapter::new(&hash);
+        for level in 0..4 {
+            for i in 0..64 {
+                assert_eq!(
+                    btree.get_from_layer(level, i),
+                    bits.get_from_layer(level, i)
+                );
+                assert_eq!(hash.get_from_layer(level, i), bits.get_from_layer(level, i));
+            }
+        }
+        assert_eq!((&btree).iter().collect::<Vec<_>>(), ids);
+        assert_eq!((&hash).iter().collect::<Vec<_>>(), ids);
+        assert!(!btree.contains(::MAX_EID as u32));
+        assert_eq!((&bits ^ &hash).iter().next(), None);
+    }
+}
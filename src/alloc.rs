@@ -0,0 +1,243 @@
+//! Support for allocating the heap layers of the sets with a custom
+//! allocator.
+//!
+//! With the `allocator_api` feature, which needs a nightly compiler, the
+//! layer vectors of a `BitSet` and the layer 0 blocks of an `AtomicBitSet`
+//! come from the allocator the set was created with. Without it, they
+//! always come from the global allocator.
+
+#[cfg(feature = "allocator_api")]
+pub use std::alloc::{Allocator, Global};
+
+use BitSet;
+
+/// An allocator the heap layers of a set can be allocated with.
+///
+/// With the `allocator_api` feature, this is implemented for [`Global`]
+/// and for references to any `Allocator`, like `&Bump` for an arena.
+/// Allocators owned by the set need an empty impl of their own:
+///
+/// ```ignore
+/// impl hibitset::LayerAlloc for MyAllocator {}
+/// ```
+///
+/// [`Global`]: struct.Global.html
+#[cfg(feature = "allocator_api")]
+pub trait LayerAlloc: Allocator + Clone {
+    /// Returns `set` as a set of the global allocator, if `Self` is that
+    /// allocator.
+    ///
+    /// This is how [`BitSetLike::backing_bitset`] finds the sets it can
+    /// return, the default of `None` is right for every other allocator.
+    ///
+    /// [`BitSetLike::backing_bitset`]: trait.BitSetLike.html#method.backing_bitset
+    #[inline]
+    fn global_set(set: &BitSet<Self>) -> Option<&BitSet> {
+        let _ = set;
+        None
+    }
+}
+
+/// An allocator the heap layers of a set can be allocated with.
+///
+/// Without the `allocator_api` feature, this is only implemented for
+/// [`Global`].
+///
+/// [`Global`]: struct.Global.html
+#[cfg(not(feature = "allocator_api"))]
+pub trait LayerAlloc: Clone {
+    /// Returns `set` as a set of the global allocator, if `Self` is that
+    /// allocator.
+    ///
+    /// This is how [`BitSetLike::backing_bitset`] finds the sets it can
+    /// return, the default of `None` is right for every other allocator.
+    ///
+    /// [`BitSetLike::backing_bitset`]: trait.BitSetLike.html#method.backing_bitset
+    #[inline]
+    fn global_set(set: &BitSet<Self>) -> Option<&BitSet> {
+        let _ = set;
+        None
+    }
+}
+
+impl LayerAlloc for Global {
+    #[inline]
+    fn global_set(set: &BitSet) -> Option<&BitSet> {
+        Some(set)
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+impl<A: Allocator + ?Sized> LayerAlloc for &A {}
+
+/// The global memory allocator, the only [`LayerAlloc`] without the
+/// `allocator_api` feature.
+///
+/// [`LayerAlloc`]: trait.LayerAlloc.html
+#[cfg(not(feature = "allocator_api"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Global;
+
+/// A vector allocated with `A`.
+#[cfg(feature = "allocator_api")]
+pub(crate) type Layer<T, A> = Vec<T, A>;
+
+/// A vector allocated with `A`, which is always `Global` here. The
+/// parameter goes through `Ignore` because aliases must use it.
+#[cfg(not(feature = "allocator_api"))]
+pub(crate) type Layer<T, A> = <(T, A) as Ignore>::Vec;
+
+/// A box allocated with `A`.
+#[cfg(feature = "allocator_api")]
+pub(crate) type Boxed<T, A> = Box<T, A>;
+
+/// A box allocated with `A`, which is always `Global` here.
+#[cfg(not(feature = "allocator_api"))]
+pub(crate) type Boxed<T, A> = <(T, A) as Ignore>::Box;
+
+#[cfg(not(feature = "allocator_api"))]
+pub(crate) trait Ignore {
+    type Vec;
+    type Box;
+}
+
+#[cfg(not(feature = "allocator_api"))]
+impl<T, A> Ignore for (T, A) {
+    type Vec = Vec<T>;
+    type Box = Box<T>;
+}
+
+/// Creates an empty vector allocated with `alloc`.
+#[inline]
+pub(crate) fn layer_in<T, A: LayerAlloc>(alloc: &A) -> Layer<T, A> {
+    #[cfg(feature = "allocator_api")]
+    return Vec::new_in(alloc.clone());
+    #[cfg(not(feature = "allocator_api"))]
+    {
+        let _ = alloc;
+        Vec::new()
+    }
+}
+
+/// Creates an empty vector allocated with the allocator of `layer`.
+#[inline]
+pub(crate) fn layer_like<T, U, A: LayerAlloc>(layer: &Layer<U, A>) -> Layer<T, A> {
+    #[cfg(feature = "allocator_api")]
+    return Vec::new_in(layer.allocator().clone());
+    #[cfg(not(feature = "allocator_api"))]
+    {
+        let _ = layer;
+        Vec::new()
+    }
+}
+
+/// Boxes `value` with the allocator of `layer`.
+#[inline]
+pub(crate) fn box_like<T, U, A: LayerAlloc>(value: T, layer: &Layer<U, A>) -> Boxed<T, A> {
+    #[cfg(feature = "allocator_api")]
+    return Box::new_in(value, layer.allocator().clone());
+    #[cfg(not(feature = "allocator_api"))]
+    {
+        let _ = layer;
+        Box::new(value)
+    }
+}
+
+/// Moves `value` to the heap, allocated with `alloc`.
+#[cfg(not(hibitset_safe))]
+#[inline]
+pub(crate) fn box_in<T, A: LayerAlloc>(value: T, alloc: &A) -> *mut T {
+    #[cfg(feature = "allocator_api")]
+    return Box::into_raw_with_allocator(Box::new_in(value, alloc.clone())).0;
+    #[cfg(not(feature = "allocator_api"))]
+    {
+        let _ = alloc;
+        Box::into_raw(Box::new(value))
+    }
+}
+
+/// Drops and frees a value moved to the heap by `box_in`.
+///
+/// # Safety
+///
+/// `ptr` must come from `box_in` with the same `alloc`, and not be used
+/// afterwards.
+#[cfg(not(hibitset_safe))]
+#[inline]
+pub(crate) unsafe fn drop_box_in<T, A: LayerAlloc>(ptr: *mut T, alloc: &A) {
+    #[cfg(feature = "allocator_api")]
+    drop(Box::from_raw_in(ptr, alloc.clone()));
+    #[cfg(not(feature = "allocator_api"))]
+    {
+        let _ = alloc;
+        drop(Box::from_raw(ptr));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {AtomicBitSet, BitSet, BitSetLike, Global};
+
+    #[test]
+    fn global() {
+        let mut set = BitSet::new_in(Global);
+        set.add(100);
+        assert!(set.backing_bitset().is_some());
+        let mut other = BitSet::with_capacity_in(200, Global);
+        assert!(other.capacity() > 200);
+        other.add(100);
+        assert_eq!(set, other);
+        let atomic = AtomicBitSet::new_in(Global);
+        atomic.add_atomic(100);
+        assert_eq!(set, atomic);
+    }
+
+    #[cfg(feature = "allocator_api")]
+    #[test]
+    fn counting() {
+        use std::alloc::{AllocError, Allocator, Global, Layout};
+        use std::cell::Cell;
+        use std::ptr::NonNull;
+
+        #[derive(Default)]
+        struct Counting(Cell<usize>);
+
+        unsafe impl Allocator for Counting {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                self.0.set(self.0.get() + layout.size());
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                self.0.set(self.0.get() - layout.size());
+                Global.deallocate(ptr, layout)
+            }
+        }
+
+        let arena = Counting::default();
+        {
+            let mut set = BitSet::new_in(&arena);
+            set.add(5);
+            set.add(100_000);
+            // The page table and the two pages with words in them.
+            assert!(arena.0.get() > 2 * 64 * 8);
+            let mut copy = set.clone();
+            assert!(copy.remove(5));
+            assert_eq!((&set).iter().collect::<Vec<_>>(), [5, 100_000]);
+            assert!(set.backing_bitset().is_none());
+            assert_eq!(set, (&copy).iter().chain(Some(5)).collect::<BitSet>());
+        }
+        assert_eq!(arena.0.get(), 0);
+
+        {
+            let set = AtomicBitSet::new_in(&arena);
+            let empty = arena.0.get();
+            set.add_atomic(5);
+            set.add_atomic(1_000_000);
+            // The blocks come from the global allocator in the safe mode.
+            assert!(cfg!(hibitset_safe) || arena.0.get() > empty);
+            assert_eq!((&set).iter().collect::<Vec<_>>(), [5, 1_000_000]);
+        }
+        assert_eq!(arena.0.get(), 0);
+    }
+}
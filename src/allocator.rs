@@ -0,0 +1,232 @@
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use util::*;
+use {AtomicBitSet, BitSet, BitSetLike};
+
+/// An allocator of ids that hands out the lowest free id, keeping track
+/// of the ids in use in a [`BitSet`].
+///
+/// Finding a free id uses [`BitSet::next_clear_bit`], starting from the
+/// lowest id that may be free, so the allocated ids stay packed at the
+/// start of the index space.
+///
+/// # Examples
+///
+/// ```
+/// # use hibitset::BitSetAllocator;
+/// let mut ids = BitSetAllocator::new();
+/// assert_eq!(ids.allocate(), 0);
+/// assert_eq!(ids.allocate(), 1);
+/// assert_eq!(ids.allocate_range(3), 2..5);
+/// ids.free(1);
+/// assert_eq!(ids.allocate(), 1);
+/// assert_eq!(ids.allocate(), 5);
+/// ```
+///
+/// [`BitSet`]: struct.BitSet.html
+/// [`BitSet::next_clear_bit`]: struct.BitSet.html#method.next_clear_bit
+#[derive(Clone, Debug, Default)]
+pub struct BitSetAllocator {
+    used: BitSet,
+    // Every id below this one is in use.
+    hint: Index,
+}
+
+impl BitSetAllocator {
+    /// Creates an allocator with all ids free.
+    pub fn new() -> BitSetAllocator {
+        Default::default()
+    }
+
+    /// Allocates the lowest free id.
+    ///
+    /// Panics if every id below the maximum index is in use.
+    pub fn allocate(&mut self) -> Index {
+        let id = self
+            .used
+            .next_clear_bit(self.hint)
+            .expect("Every id of the allocator is in use");
+        self.used.add(id);
+        self.hint = id + 1;
+        id
+    }
+
+    /// Allocates the lowest range of `n` consecutive free ids.
+    ///
+    /// Panics if there is no such range below the maximum index.
+    pub fn allocate_range(&mut self, n: Index) -> Range<Index> {
+        let mut start = self.hint;
+        loop {
+            start = self
+                .used
+                .next_clear_bit(start)
+                .filter(|&start| start as usize + n as usize <= MAX_EID)
+                .expect("No range of free ids is large enough");
+            let end = start + n;
+            match (&self.used).iter_range(start..end).next() {
+                Some(used) => start = used,
+                None => {
+                    self.used.extend(start..end);
+                    if start == self.hint {
+                        self.hint = end;
+                    }
+                    return start..end;
+                }
+            }
+        }
+    }
+
+    /// Frees `id`, returning `true` if it was allocated.
+    pub fn free(&mut self, id: Index) -> bool {
+        if !self.used.remove(id) {
+            return false;
+        }
+        if id < self.hint {
+            self.hint = id;
+        }
+        true
+    }
+
+    /// Returns `true` if `id` is allocated.
+    pub fn is_allocated(&self, id: Index) -> bool {
+        self.used.contains(id)
+    }
+
+    /// Returns the set of allocated ids.
+    pub fn allocated(&self) -> &BitSet {
+        &self.used
+    }
+}
+
+/// An id allocator like [`BitSetAllocator`], which can allocate without
+/// unique ownership, keeping track of the ids in use in an
+/// [`AtomicBitSet`].
+///
+/// Threads racing for the same free id are sorted out by
+/// [`AtomicBitSet::claim`]: the loser moves on to the next free id. The
+/// ids are still handed out lowest first when there is no contention.
+/// Freeing an id needs unique ownership, like removing from an
+/// `AtomicBitSet` does.
+///
+/// [`BitSetAllocator`]: struct.BitSetAllocator.html
+/// [`AtomicBitSet`]: struct.AtomicBitSet.html
+/// [`AtomicBitSet::claim`]: struct.AtomicBitSet.html#method.claim
+#[derive(Debug, Default)]
+pub struct AtomicBitSetAllocator {
+    used: AtomicBitSet,
+    // The layer 0 word which holds the lowest id that may be free.
+    hint: AtomicUsize,
+}
+
+impl AtomicBitSetAllocator {
+    /// Creates an allocator with all ids free.
+    pub fn new() -> AtomicBitSetAllocator {
+        Default::default()
+    }
+
+    /// Allocates the lowest free id.
+    ///
+    /// Panics if every id below the maximum index is in use.
+    pub fn allocate(&self) -> Index {
+        let mut p0 = self.hint.load(Ordering::Relaxed);
+        while p0 < MAX_EID >> BITS {
+            let free = !self.used.layer0(p0);
+            if free != 0 {
+                let id = ((p0 << BITS) | free.trailing_zeros() as usize) as Index;
+                if self.used.claim(id) {
+                    self.hint.fetch_max(p0, Ordering::Relaxed);
+                    return id;
+                }
+                // Lost the id to another thread, look at the word again.
+                continue;
+            }
+            p0 += 1;
+        }
+        panic!("Every id of the allocator is in use");
+    }
+
+    /// Frees `id`, returning `true` if it was allocated.
+    pub fn free(&mut self, id: Index) -> bool {
+        if !self.used.remove(id) {
+            return false;
+        }
+        let hint = self.hint.get_mut();
+        *hint = ::std::cmp::min(*hint, id.offset(SHIFT1));
+        true
+    }
+
+    /// Returns `true` if `id` is allocated.
+    pub fn is_allocated(&self, id: Index) -> bool {
+        self.used.contains(id)
+    }
+
+    /// Returns the set of allocated ids.
+    pub fn allocated(&self) -> &AtomicBitSet {
+        &self.used
+    }
+}
+
+#[cfg(test)]
+mod allocator_test {
+    use std::sync::Arc;
+    use std::thread;
+
+    use {AtomicBitSetAllocator, BitSetAllocator, BitSetLike};
+
+    #[test]
+    fn allocate_free() {
+        let mut ids = BitSetAllocator::new();
+        for i in 0..1_000 {
+            assert_eq!(ids.allocate(), i);
+        }
+        assert!(ids.free(10));
+        assert!(ids.free(500));
+        assert!(!ids.free(500));
+        assert_eq!(ids.allocate(), 10);
+        assert_eq!(ids.allocate(), 500);
+        assert_eq!(ids.allocate(), 1_000);
+        assert!(ids.is_allocated(999));
+    }
+
+    #[test]
+    fn allocate_range() {
+        let mut ids = BitSetAllocator::new();
+        assert_eq!(ids.allocate_range(100), 0..100);
+        for i in (0..100).filter(|i| i % 10 == 0) {
+            ids.free(i);
+        }
+        assert_eq!(ids.allocate_range(5), 100..105);
+        assert_eq!(ids.allocate(), 0);
+        assert!(!ids.free(50));
+        assert!(ids.free(51));
+        assert_eq!(ids.allocate_range(2), 50..52);
+        assert_eq!(ids.allocate_range(0), 10..10);
+        assert_eq!(ids.allocated().iter().count(), 97);
+    }
+
+    #[test]
+    fn atomic_allocate() {
+        let ids = Arc::new(AtomicBitSetAllocator::new());
+        let threads = (0..4)
+            .map(|_| {
+                let ids = ids.clone();
+                thread::spawn(move || (0..1_000).map(|_| ids.allocate()).collect::<Vec<_>>())
+            })
+            .collect::<Vec<_>>();
+        let mut all = threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap())
+            .collect::<Vec<_>>();
+        all.sort();
+        assert_eq!(all, (0..4_000).collect::<Vec<_>>());
+
+        let mut ids = Arc::try_unwrap(ids).unwrap();
+        assert!(ids.free(7));
+        assert!(!ids.free(7));
+        assert_eq!(ids.allocate(), 7);
+        assert_eq!(ids.allocate(), 4_000);
+        assert!(ids.is_allocated(3_999));
+        assert_eq!(ids.allocated().len_hint(), 4_001);
+    }
+}
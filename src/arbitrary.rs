@@ -0,0 +1,123 @@
+//! Generators of `BitSet`s for property testing.
+//!
+//! The generated sets are drawn from a few density profiles: empty sets,
+//! sparse spikes spread over the whole index range, dense runs of
+//! consecutive indices, and runs mixed with spikes. Shrinking removes
+//! indices and moves the remaining ones towards zero.
+
+use std::cmp;
+
+use util::*;
+use BitSet;
+
+/// The longest dense run a generated set has.
+const MAX_RUN: Index = 1 << (2 * BITS);
+
+#[cfg(feature = "quickcheck")]
+mod quickcheck_impl {
+    use quickcheck::{Arbitrary, Gen};
+
+    use super::*;
+    use BitSetLike;
+
+    fn index(g: &mut Gen) -> Index {
+        Index::arbitrary(g) % MAX_EID as Index
+    }
+
+    fn spikes(g: &mut Gen) -> Vec<Index> {
+        let len = usize::arbitrary(g) % (g.size() + 1);
+        (0..len).map(|_| index(g)).collect()
+    }
+
+    fn run(g: &mut Gen) -> Vec<Index> {
+        let start = index(g);
+        let len = Index::arbitrary(g) % MAX_RUN;
+        (start..cmp::min(start.saturating_add(len), MAX_EID as Index)).collect()
+    }
+
+    impl Arbitrary for BitSet {
+        fn arbitrary(g: &mut Gen) -> Self {
+            match g.choose(&[0, 1, 2, 3]) {
+                Some(1) => spikes(g).into_iter().collect(),
+                Some(2) => run(g).into_iter().collect(),
+                Some(3) => run(g).into_iter().chain(spikes(g)).collect(),
+                _ => BitSet::new(),
+            }
+        }
+
+        fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+            let ids = self.iter().collect::<Vec<_>>();
+            Box::new(ids.shrink().map(|ids| ids.into_iter().collect()))
+        }
+    }
+}
+
+#[cfg(feature = "proptest")]
+mod proptest_impl {
+    use std::iter;
+
+    use proptest::arbitrary::Arbitrary;
+    use proptest::collection::vec;
+    use proptest::strategy::{BoxedStrategy, Just, Strategy};
+
+    use super::*;
+
+    impl Arbitrary for BitSet {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<BitSet>;
+
+        fn arbitrary_with(_: ()) -> Self::Strategy {
+            let spikes = vec(0..MAX_EID as Index, 0..64);
+            let run = (0..MAX_EID as Index, 0..MAX_RUN).prop_map(|(start, len)| {
+                start..cmp::min(start.saturating_add(len), MAX_EID as Index)
+            });
+            ::proptest::prop_oneof![
+                Just(BitSet::new()),
+                spikes.clone().prop_map(|ids| ids.into_iter().collect()),
+                run.clone().prop_map(|run| iter::once(run).collect()),
+                (run, spikes).prop_map(|(run, ids)| {
+                    let mut set: BitSet = iter::once(run).collect();
+                    set.extend(ids);
+                    set
+                }),
+            ]
+            .boxed()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use BitSetLike;
+
+    #[cfg(feature = "quickcheck")]
+    #[test]
+    fn quickcheck_profiles() {
+        use quickcheck::{Arbitrary, Gen};
+        use BitSet;
+
+        let mut g = Gen::new(100);
+        let sets = (0..200)
+            .map(|_| BitSet::arbitrary(&mut g))
+            .collect::<Vec<_>>();
+        assert!(sets.iter().any(|set| set.is_empty()));
+        assert!(sets.iter().any(|set| set.iter().count() > 100));
+
+        let set = sets.iter().find(|set| !set.is_empty()).unwrap();
+        let count = set.iter().count();
+        assert!(set
+            .shrink()
+            .take(100)
+            .all(|smaller| smaller.iter().count() <= count));
+        assert!(set.shrink().next().unwrap().is_empty());
+    }
+
+    #[cfg(feature = "proptest")]
+    ::proptest::proptest! {
+        #[test]
+        fn proptest_in_range(set: ::BitSet) {
+            ::proptest::prop_assert!((&set).iter().all(|i| (i as usize) < ::MAX_EID));
+            ::proptest::prop_assert_eq!(&set, &(&set).iter().collect::<::BitSet>());
+        }
+    }
+}
@@ -1,12 +1,25 @@
+use std::array;
+use std::cmp;
 use std::default::Default;
 use std::fmt::{Debug, Error as FormatError, Formatter};
-use std::iter::repeat;
+#[cfg(not(hibitset_safe))]
 use std::marker::PhantomData;
+use std::mem;
+use std::ops::Range;
+#[cfg(not(hibitset_safe))]
 use std::ptr;
-use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+#[cfg(hibitset_safe)]
+use std::sync::OnceLock;
 
+use alloc::{self, Global, Layer, LayerAlloc};
+use debug;
+#[cfg(not(hibitset_safe))]
+use sync::AtomicPtr;
+use sync::{AtomicIsize, AtomicUsize, Ordering, UniqueAccess};
 use util::*;
-use {BitSetLike, DrainableBitSet};
+use {
+    BitIter, BitSet, BitSetLike, ConcurrentModification, DrainableBitSet, MemoryStats, OutOfRange,
+};
 
 /// This is similar to a [`BitSet`] but allows setting of value
 /// without unique ownership of the structure
@@ -17,20 +30,49 @@ use {BitSetLike, DrainableBitSet};
 /// of the hierarchy it holds. Worst case multiple writers set the
 /// same bit twice (but only is told they set it).
 ///
-/// It is possible to atomically remove from the set, but not at the
-/// same time as atomically adding. This is because there is no way
-/// to know if layer 1-3 would be left in a consistent state if they are
-/// being cleared and set at the same time.
+/// The whole set can be cleared through a shared reference with
+/// [`clear_atomic`], but layers 1-3 are only guaranteed to be consistent
+/// with layer 0 when no add runs at the same time. An add racing the
+/// clear may leave summary bits over empty words, or an index in layer 0
+/// that iteration doesn't reach, until the set is next cleared; see
+/// [`clear_atomic`] for the exact outcomes. Single bits can only be
+/// removed with unique access.
 ///
-/// `AtromicBitSet` resolves this race by disallowing atomic
-/// clearing of bits.
+/// # Memory ordering
 ///
+/// [`add_atomic`], [`contains`] and the [`BitSetLike`] accessors used for
+/// iteration are `Relaxed`: they agree on which bits are set, but seeing a
+/// bit says nothing about other memory. To use the set to publish data,
+/// like "bit `id` set ⇒ slot `id` of a side table is written", add with
+/// [`add_atomic_ordered`] and `Release` after writing the data, and check
+/// with [`contains_ordered`] and `Acquire` before reading it. When the
+/// bits are found by iterating instead, issue an `Acquire` [`fence`]
+/// after the iterator returned them.
+///
+/// The upper layers are always updated with `Relaxed` and only guide the
+/// search, they don't carry any of these guarantees.
+///
+/// [`clear_atomic`]: #method.clear_atomic
+/// [`add_atomic`]: #method.add_atomic
+/// [`contains`]: #method.contains
+/// [`add_atomic_ordered`]: #method.add_atomic_ordered
+/// [`contains_ordered`]: #method.contains_ordered
+/// [`BitSetLike`]: ../trait.BitSetLike.html
+/// [`fence`]: https://doc.rust-lang.org/std/sync/atomic/fn.fence.html
 /// [`BitSet`]: ../struct.BitSet.html
-#[derive(Debug)]
-pub struct AtomicBitSet {
+pub struct AtomicBitSet<A: LayerAlloc = Global> {
     layer3: AtomicUsize,
-    layer2: Vec<AtomicUsize>,
-    layer1: Vec<AtomicBlock>,
+    layer2: Layer<AtomicUsize, A>,
+    layer1: Layer<AtomicBlock, A>,
+    // Signed, as a racing `clear_atomic` may subtract an index before
+    // the add that set it has counted it.
+    len: AtomicIsize,
+    // Bumped by every change made through a shared reference, so
+    // `iter_consistent` can tell the set changed under it.
+    #[cfg(debug_assertions)]
+    epoch: AtomicUsize,
+    // Allocates the layer 0 blocks as they're first written to.
+    alloc: A,
 }
 
 impl AtomicBitSet {
@@ -39,6 +81,57 @@ impl AtomicBitSet {
         Default::default()
     }
 
+    /// Builds an `AtomicBitSet` from the words of a [`BitSet`].
+    ///
+    /// [`BitSet`]: ../struct.BitSet.html
+    pub(crate) fn from_bitset(set: &BitSet) -> AtomicBitSet {
+        let mut atomic = AtomicBitSet::new();
+        atomic.layer3.with_unique(|layer3| *layer3 = set.layer3);
+        for (dst, &src) in atomic.layer2.iter_mut().zip(&set.layer2) {
+            dst.with_unique(|dst| *dst = src);
+        }
+        for (dst, &src) in atomic.layer1.iter_mut().zip(&set.layer1) {
+            dst.mask.with_unique(|dst| *dst = src);
+        }
+        for (p1, page) in set.layer0.iter() {
+            for (row, &word) in page.iter().enumerate() {
+                if word != 0 {
+                    atomic
+                        .len
+                        .with_unique(|len| *len += word.count_ones() as isize);
+                    let block = &atomic.layer1[p1];
+                    block.atom.get_or_init(&atomic.alloc)[row].store(word, Ordering::Relaxed);
+                }
+            }
+        }
+        atomic
+    }
+}
+
+impl<A: LayerAlloc> AtomicBitSet<A> {
+    /// Creates an empty `AtomicBitSet` whose layers and layer 0 blocks
+    /// are allocated with `alloc`.
+    ///
+    /// Without the `allocator_api` feature, `alloc` can only be the
+    /// [`Global`] allocator.
+    ///
+    /// [`Global`]: struct.Global.html
+    pub fn new_in(alloc: A) -> Self {
+        let mut layer2 = alloc::layer_in(&alloc);
+        layer2.resize_with(1 << BITS, || AtomicUsize::new(0));
+        let mut layer1 = alloc::layer_in(&alloc);
+        layer1.resize_with(1 << (2 * BITS), AtomicBlock::new);
+        AtomicBitSet {
+            layer3: AtomicUsize::new(0),
+            layer2,
+            layer1,
+            len: AtomicIsize::new(0),
+            #[cfg(debug_assertions)]
+            epoch: AtomicUsize::new(0),
+            alloc,
+        }
+    }
+
     /// Adds `id` to the `AtomicBitSet`. Returns `true` if the value was
     /// already in the set.
     ///
@@ -46,6 +139,37 @@ impl AtomicBitSet {
     /// this will panic if the Index is out of range.
     #[inline]
     pub fn add_atomic(&self, id: Index) -> bool {
+        self.add_atomic_ordered(id, Ordering::Relaxed)
+    }
+
+    /// Like [`add_atomic`], but sets the bit in layer 0 with `order`.
+    ///
+    /// With `Release` (or stronger), everything this thread did before
+    /// the call happens-before whatever a thread does after seeing the
+    /// bit through [`contains_ordered`] with `Acquire` (or stronger).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::AtomicBitSet;
+    /// # use std::sync::atomic::{AtomicUsize, Ordering};
+    /// # use std::thread;
+    /// let ready = AtomicBitSet::new();
+    /// let slots = (0..64).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+    /// thread::scope(|s| {
+    ///     s.spawn(|| {
+    ///         slots[7].store(42, Ordering::Relaxed);
+    ///         ready.add_atomic_ordered(7, Ordering::Release);
+    ///     });
+    ///     while !ready.contains_ordered(7, Ordering::Acquire) {}
+    ///     assert_eq!(slots[7].load(Ordering::Relaxed), 42);
+    /// });
+    /// ```
+    ///
+    /// [`add_atomic`]: #method.add_atomic
+    /// [`contains_ordered`]: #method.contains_ordered
+    #[inline]
+    pub fn add_atomic_ordered(&self, id: Index, order: Ordering) -> bool {
         let (_, p1, p2) = offsets(id);
 
         // While it is tempting to check of the bit was set and exit here if it
@@ -53,22 +177,133 @@ impl AtomicBitSet {
         // thread both set the same bit it is possible for the second thread
         // to exit before l3 was set. Resulting in the iterator to be in an
         // incorrect state. The window is small, but it exists.
-        let set = self.layer1[p1].add(id);
+        let set = self.layer1[p1].add_ordered(id, order, &self.alloc);
         self.layer2[p2].fetch_or(id.mask(SHIFT2), Ordering::Relaxed);
         self.layer3.fetch_or(id.mask(SHIFT3), Ordering::Relaxed);
+        if !set {
+            self.len.fetch_add(1, Ordering::Relaxed);
+            self.bump_epoch();
+        }
         set
     }
 
+    /// Atomically adds every index of `ids` to the set. Returns how many of
+    /// them weren't in the set before.
+    ///
+    /// The indices are grouped by the words they fall in, so each touched
+    /// word of every layer is updated with a single `fetch_or` instead of
+    /// one per index, which cuts down on contention when other threads add
+    /// nearby indices. Input that isn't sorted is sorted into a copy
+    /// first.
+    ///
+    /// Panics if any index is out of range, before adding any of them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::AtomicBitSet;
+    /// let set = AtomicBitSet::new();
+    /// set.add_atomic(3);
+    /// assert_eq!(set.add_atomic_many(&[1, 2, 3, 4_000, 70]), 4);
+    /// assert!(set.contains(70) && set.contains(4_000));
+    /// ```
+    pub fn add_atomic_many(&self, ids: &[Index]) -> usize {
+        if let Some(&id) = ids.iter().find(|&&id| id as usize >= MAX_EID) {
+            panic!("Expected index to be less then {}, found {}", MAX_EID, id);
+        }
+        let sorted;
+        let mut ids = if ids.windows(2).all(|pair| pair[0] <= pair[1]) {
+            ids
+        } else {
+            let mut copy = ids.to_vec();
+            copy.sort_unstable();
+            sorted = copy;
+            &sorted[..]
+        };
+
+        // The summary bits are gathered until the indices leave the word
+        // they belong to, and are always set after the words below them.
+        let (mut layer1, mut layer2, mut layer3) = (0, 0, 0);
+        let mut added = 0;
+        while let Some(&first) = ids.first() {
+            let p0 = first.offset(SHIFT1);
+            let run = ids.iter().take_while(|id| id.offset(SHIFT1) == p0).count();
+            let word = ids[..run].iter().fold(0, |word, id| word | id.mask(SHIFT0));
+            ids = &ids[run..];
+
+            let block = &self.layer1[first.offset(SHIFT2)];
+            let old = block.atom.get_or_init(&self.alloc)[first.row(SHIFT1)]
+                .fetch_or(word, Ordering::Relaxed);
+            added += (word & !old).count_ones() as usize;
+
+            // Whether the next id still falls into the same layer 1 and
+            // layer 2 words, whose masks are flushed once it doesn't.
+            let (same1, same2) = match ids.first() {
+                Some(next) => (
+                    next.offset(SHIFT2) == first.offset(SHIFT2),
+                    next.offset(SHIFT3) == first.offset(SHIFT3),
+                ),
+                None => (false, false),
+            };
+            layer1 |= first.mask(SHIFT1);
+            if !same1 {
+                block.mask.fetch_or(layer1, Ordering::Relaxed);
+                layer1 = 0;
+                layer2 |= first.mask(SHIFT2);
+            }
+            if !same2 {
+                self.layer2[first.offset(SHIFT3)].fetch_or(layer2, Ordering::Relaxed);
+                layer2 = 0;
+                layer3 |= first.mask(SHIFT3);
+            }
+        }
+        if layer3 != 0 {
+            self.layer3.fetch_or(layer3, Ordering::Relaxed);
+        }
+        if added != 0 {
+            self.len.fetch_add(added as isize, Ordering::Relaxed);
+            self.bump_epoch();
+        }
+        added
+    }
+
+    /// Like [`add_atomic`], but returns an error instead of panicking if
+    /// `id` is out of range.
+    ///
+    /// [`add_atomic`]: #method.add_atomic
+    #[inline]
+    pub fn try_add_atomic(&self, id: Index) -> Result<bool, OutOfRange> {
+        Self::check_range(id)?;
+        Ok(self.add_atomic(id))
+    }
+
+    /// Atomically adds `id` to the set. Returns `true` if this call is the
+    /// one that added it, and `false` if it was already in the set.
+    ///
+    /// Of any number of concurrent `claim`s of the same `id`, exactly one
+    /// returns `true`. Unlike [`add_atomic`], the bit is set with `AcqRel`
+    /// ordering, so everything the winning thread did before its `claim`
+    /// happens-before the return of every later `claim` of that `id`.
+    ///
+    /// Like `add_atomic`, this will panic if the Index is out of range.
+    ///
+    /// [`add_atomic`]: #method.add_atomic
+    #[inline]
+    pub fn claim(&self, id: Index) -> bool {
+        !self.add_atomic_ordered(id, Ordering::AcqRel)
+    }
+
     /// Adds `id` to the `BitSet`. Returns `true` if the value was
     /// already in the set.
     #[inline]
     pub fn add(&mut self, id: Index) -> bool {
-        use std::sync::atomic::Ordering::Relaxed;
+        use sync::Ordering::Relaxed;
 
         let (_, p1, p2) = offsets(id);
-        if self.layer1[p1].add(id) {
+        if self.layer1[p1].add(id, &self.alloc) {
             return true;
         }
+        self.len.with_unique(|len| *len += 1);
 
         self.layer2[p2].store(self.layer2[p2].load(Relaxed) | id.mask(SHIFT2), Relaxed);
         self.layer3
@@ -81,7 +316,7 @@ impl AtomicBitSet {
     /// to begin with.
     #[inline]
     pub fn remove(&mut self, id: Index) -> bool {
-        use std::sync::atomic::Ordering::Relaxed;
+        use sync::Ordering::Relaxed;
         let (_, p1, p2) = offsets(id);
 
         // if the bitmask was set we need to clear
@@ -94,6 +329,7 @@ impl AtomicBitSet {
         if !self.layer1[p1].remove(id) {
             return false;
         }
+        self.len.with_unique(|len| *len -= 1);
         if self.layer1[p1].mask.load(Ordering::Relaxed) != 0 {
             return true;
         }
@@ -112,8 +348,99 @@ impl AtomicBitSet {
     /// Returns `true` if `id` is in the set.
     #[inline]
     pub fn contains(&self, id: Index) -> bool {
+        self.contains_ordered(id, Ordering::Relaxed)
+    }
+
+    /// Like [`contains`], but loads the layer 0 word with `order`.
+    ///
+    /// See [`add_atomic_ordered`] for the guarantees of `Acquire`.
+    ///
+    /// Panics if `order` is `Release` or `AcqRel`, like an atomic load.
+    ///
+    /// [`contains`]: #method.contains
+    /// [`add_atomic_ordered`]: #method.add_atomic_ordered
+    #[inline]
+    pub fn contains_ordered(&self, id: Index, order: Ordering) -> bool {
         let i = id.offset(SHIFT2);
-        self.layer1[i].contains(id)
+        self.layer1[i].contains(id, order)
+    }
+
+    /// Like [`contains`], but returns an error instead of panicking if
+    /// `id` is out of range.
+    ///
+    /// [`contains`]: #method.contains
+    #[inline]
+    pub fn try_contains(&self, id: Index) -> Result<bool, OutOfRange> {
+        Self::check_range(id)?;
+        Ok(self.contains(id))
+    }
+
+    #[inline]
+    fn check_range(id: Index) -> Result<(), OutOfRange> {
+        if id as usize >= MAX_EID {
+            return Err(OutOfRange::new(id, MAX_EID));
+        }
+        Ok(())
+    }
+
+    /// Clears all bits in the set without unique ownership.
+    ///
+    /// The layers are walked from the top, swapping every occupied word
+    /// to zero with `AcqRel` ordering, so a `clear_atomic` synchronizes
+    /// with the previous one and with anything else that read the words
+    /// with `Acquire`.
+    ///
+    /// This is meant for sets where writers are kept off the set while it
+    /// is cleared, for example by an epoch the readers and writers agree
+    /// on. If `add_atomic` or `claim` do run concurrently, each index they
+    /// add ends up in one of these states:
+    ///
+    /// * cleared, possibly leaving summary bits set above empty words,
+    ///   which iteration skips but which keep `is_empty` from returning
+    ///   `true`,
+    /// * kept, and reachable as usual,
+    /// * kept in layer 0 with a summary bit above it cleared, so that
+    ///   `contains` reports it but iteration does not see it until the
+    ///   index is added again or the set is cleared with [`clear`].
+    ///
+    /// Readers iterating at the same time see some mix of the indices
+    /// before and after the clear. [`len_hint`] is kept up to date by
+    /// subtracting the cleared words. An add whose bit is cleared before
+    /// it counts it takes the count below zero until it does, which
+    /// [`len_hint`] reports as zero, so the count is exact again once the
+    /// racing adds have returned.
+    ///
+    /// [`clear`]: #method.clear
+    /// [`len_hint`]: #method.len_hint
+    pub fn clear_atomic(&self) {
+        let mut m3 = self.layer3.swap(0, Ordering::AcqRel);
+        while m3 != 0 {
+            let p2 = m3.trailing_zeros() as usize;
+            m3 &= !(1 << p2);
+
+            let mut m2 = self.layer2[p2].swap(0, Ordering::AcqRel);
+            while m2 != 0 {
+                let bit2 = m2.trailing_zeros() as usize;
+                m2 &= !(1 << bit2);
+
+                let block = &self.layer1[(p2 << BITS) | bit2];
+                let mut m1 = block.mask.swap(0, Ordering::AcqRel);
+                let layer0 = match block.atom.get() {
+                    Some(layer0) => layer0,
+                    None => continue,
+                };
+                let mut cleared = 0;
+                while m1 != 0 {
+                    let bit1 = m1.trailing_zeros() as usize;
+                    m1 &= !(1 << bit1);
+                    cleared += layer0[bit1].swap(0, Ordering::AcqRel).count_ones() as isize;
+                }
+                // This goes below zero if an add racing the clear hasn't
+                // counted its index yet, and is set right by that add.
+                self.len.fetch_sub(cleared, Ordering::Relaxed);
+                self.bump_epoch();
+            }
+        }
     }
 
     /// Clear all bits in the set
@@ -123,6 +450,7 @@ impl AtomicBitSet {
         // that are already clear. In the best case when the set is already cleared,
         // this will only touch the highest layer.
 
+        self.len.with_unique(|len| *len = 0);
         let (mut m3, mut m2) = (self.layer3.swap(0, Ordering::Relaxed), 0usize);
         let mut offset = 0;
 
@@ -148,9 +476,228 @@ impl AtomicBitSet {
             break;
         }
     }
+
+    /// Returns the number of indices in the set, as counted by the
+    /// additions and removals made so far.
+    ///
+    /// Each index is counted by the call that actually added it, so this
+    /// is exact once all in-flight `add_atomic`s have returned. While
+    /// other threads are still adding, it is only a hint, and it may be
+    /// briefly behind the bits already visible to [`count`].
+    ///
+    /// [`count`]: #method.count
+    pub fn len_hint(&self) -> usize {
+        // A racing `clear_atomic` may have taken the count below zero.
+        cmp::max(self.len.load(Ordering::Relaxed), 0) as usize
+    }
+
+    /// Iterates over the indices of the set like [`iter`], but reports
+    /// when the set is changed through a shared reference while iterating.
+    ///
+    /// Iterating an `AtomicBitSet` while other threads add to or clear it
+    /// is allowed, but it yields an unspecified mix of the indices before
+    /// and after the changes. This iterator yields `Ok` indices until it
+    /// sees that the set changed since it was created, then yields one
+    /// `Err` and stops, including when the change comes after the last
+    /// index.
+    ///
+    /// Changes are tracked with a counter that only exists in builds with
+    /// debug assertions. Without them, every index is yielded as `Ok`.
+    /// Detection is best effort: a change racing the end of the iteration
+    /// may go unreported.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::AtomicBitSet;
+    /// let set = AtomicBitSet::new();
+    /// set.add_atomic(1);
+    /// set.add_atomic(2);
+    /// let mut iter = set.iter_consistent();
+    /// assert_eq!(iter.next(), Some(Ok(1)));
+    /// set.add_atomic(3);
+    /// # if cfg!(debug_assertions) {
+    /// assert!(iter.next().unwrap().is_err());
+    /// assert_eq!(iter.next(), None);
+    /// # }
+    /// ```
+    ///
+    /// [`iter`]: ../trait.BitSetLike.html#method.iter
+    pub fn iter_consistent(&self) -> ConsistentIter<'_, A> {
+        ConsistentIter {
+            iter: self.iter(),
+            set: self,
+            epoch: self.epoch(),
+            yielded: 0,
+            done: false,
+        }
+    }
+
+    #[inline]
+    fn bump_epoch(&self) {
+        #[cfg(debug_assertions)]
+        self.epoch.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn epoch(&self) -> usize {
+        #[cfg(debug_assertions)]
+        return self.epoch.load(Ordering::Relaxed);
+        #[cfg(not(debug_assertions))]
+        0
+    }
+
+    /// Allocates the layer 0 blocks covering `range` up front, so adding
+    /// indices in it doesn't allocate.
+    ///
+    /// Blocks are otherwise allocated by the first add of an index in
+    /// them. Like adding, this doesn't need unique ownership of the set.
+    ///
+    /// Panics if `range` reaches past the maximum index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::AtomicBitSet;
+    /// let set = AtomicBitSet::new();
+    /// set.reserve(0..100_000);
+    /// let reserved = set.heap_bytes();
+    /// for i in 0..100_000 {
+    ///     set.add_atomic(i);
+    /// }
+    /// assert_eq!(set.heap_bytes(), reserved);
+    /// ```
+    pub fn reserve(&self, range: Range<Index>) {
+        assert!(
+            range.end as usize <= MAX_EID,
+            "Expected range to end at most at {}, found {}",
+            MAX_EID,
+            range.end
+        );
+        if range.start >= range.end {
+            return;
+        }
+        let (_, first, _) = offsets(range.start);
+        let (_, last, _) = offsets(range.end - 1);
+        for block in &self.layer1[first..=last] {
+            block.atom.get_or_init(&self.alloc);
+        }
+    }
+
+    /// Returns the number of bytes the set has allocated on the heap.
+    pub fn heap_bytes(&self) -> usize {
+        self.memory_stats().total()
+    }
+
+    /// Returns the bytes the set has allocated on the heap for each layer.
+    ///
+    /// Layers 2 and 1 are allocated in full up front, while the blocks of
+    /// layer 0 are only allocated once a bit inside them is first set.
+    /// Blocks allocated by other threads while this runs may or may not be
+    /// counted.
+    pub fn memory_stats(&self) -> MemoryStats {
+        let blocks = self
+            .layer1
+            .iter()
+            .filter(|block| block.atom.get().is_some())
+            .count();
+        MemoryStats {
+            layer2: self.layer2.capacity() * mem::size_of::<AtomicUsize>(),
+            layer1: self.layer1.capacity() * mem::size_of::<AtomicBlock>(),
+            layer0: blocks * mem::size_of::<[AtomicUsize; 1 << BITS]>(),
+        }
+    }
+
+    /// Frees the layer 0 blocks that no longer hold any index, and returns
+    /// the number of bytes reclaimed.
+    ///
+    /// Blocks are allocated the first time an index inside them is added
+    /// and kept when their indices are removed again, so a set whose
+    /// indices move around over time keeps growing until it is shrunk.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::AtomicBitSet;
+    /// let mut set = AtomicBitSet::new();
+    /// set.add_atomic(5);
+    /// set.add_atomic(100_000);
+    /// set.remove(100_000);
+    /// assert!(set.shrink() > 0);
+    /// assert_eq!(set.shrink(), 0);
+    /// assert!(set.contains(5));
+    /// ```
+    pub fn shrink(&mut self) -> usize {
+        let mut freed = 0;
+        for block in self.layer1.iter_mut() {
+            let empty = match block.atom.get_mut() {
+                Some(layer0) => layer0
+                    .iter_mut()
+                    .all(|word| word.with_unique(|word| *word == 0)),
+                None => false,
+            };
+            if empty {
+                block.mask.with_unique(|mask| *mask = 0);
+                // SAFETY: The block was allocated with `self.alloc`, and
+                // nothing can borrow it while the set is borrowed mutably.
+                unsafe { block.atom.free(&self.alloc) };
+                freed += mem::size_of::<[AtomicUsize; 1 << BITS]>();
+            }
+        }
+        freed
+    }
+
+    /// Counts the indices in the set by popcounting the occupied layer 0
+    /// words.
+    pub fn count(&self) -> usize {
+        self.masks()
+            .map(|(_, word)| word.count_ones() as usize)
+            .sum()
+    }
+
+    /// Takes a snapshot of the set as a plain [`BitSet`].
+    ///
+    /// The hierarchy is walked from the top so only occupied words are
+    /// visited, and the layers of the snapshot are built from the layer 0
+    /// words actually copied, so they are consistent even if the set is
+    /// cleared meanwhile. Bits added by other threads while the snapshot
+    /// is being taken may or may not be included.
+    ///
+    /// [`BitSet`]: ../struct.BitSet.html
+    pub fn to_bitset(&self) -> BitSet {
+        let mut set = BitSet::new();
+        let mut m3 = self.layer3.load(Ordering::Relaxed);
+        while m3 != 0 {
+            let p2 = m3.trailing_zeros() as usize;
+            m3 &= !(1 << p2);
+
+            let mut m2 = self.layer2[p2].load(Ordering::Relaxed);
+            while m2 != 0 {
+                let bit2 = m2.trailing_zeros() as usize;
+                m2 &= !(1 << bit2);
+                let p1 = (p2 << BITS) | bit2;
+
+                let block = &self.layer1[p1];
+                let mut m1 = block.mask.load(Ordering::Relaxed);
+                let layer0 = match block.atom.get() {
+                    Some(layer0) => layer0,
+                    None => continue,
+                };
+                while m1 != 0 {
+                    let bit1 = m1.trailing_zeros() as usize;
+                    m1 &= !(1 << bit1);
+                    let word = layer0[bit1].load(Ordering::Relaxed);
+                    if word != 0 {
+                        set.add_word((p1 << BITS) | bit1, word);
+                    }
+                }
+            }
+        }
+        set
+    }
 }
 
-impl BitSetLike for AtomicBitSet {
+impl<A: LayerAlloc> BitSetLike for AtomicBitSet<A> {
     #[inline]
     fn layer3(&self) -> usize {
         self.layer3.load(Ordering::Relaxed)
@@ -178,7 +725,7 @@ impl BitSetLike for AtomicBitSet {
     }
 }
 
-impl DrainableBitSet for AtomicBitSet {
+impl<A: LayerAlloc> DrainableBitSet for AtomicBitSet<A> {
     #[inline]
     fn remove(&mut self, i: Index) -> bool {
         self.remove(i)
@@ -187,36 +734,139 @@ impl DrainableBitSet for AtomicBitSet {
 
 impl Default for AtomicBitSet {
     fn default() -> Self {
-        AtomicBitSet {
-            layer3: Default::default(),
-            layer2: repeat(0)
-                .map(|_| AtomicUsize::new(0))
-                .take(1 << BITS)
-                .collect(),
-            layer1: repeat(0)
-                .map(|_| AtomicBlock::new())
-                .take(1 << (2 * BITS))
-                .collect(),
+        AtomicBitSet::new_in(Global)
+    }
+}
+
+/// Copies the set word by word, only visiting the allocated layer 0
+/// blocks. The upper layers of the copy are rebuilt from the copied words,
+/// so it is consistent even if other threads add to the set while it is
+/// cloned, though those additions may or may not be included.
+impl<A: LayerAlloc> Clone for AtomicBitSet<A> {
+    fn clone(&self) -> Self {
+        let mut set = AtomicBitSet::new_in(self.alloc.clone());
+        let mut len = 0;
+        for (p1, block) in self.layer1.iter().enumerate() {
+            let layer0 = match block.atom.get() {
+                Some(layer0) => layer0,
+                None => continue,
+            };
+            let mut mask = 0;
+            for (i, word) in layer0.iter().enumerate() {
+                let word = word.load(Ordering::Relaxed);
+                if word != 0 {
+                    set.layer1[p1].atom.get_or_init(&set.alloc)[i].store(word, Ordering::Relaxed);
+                    mask |= 1 << i;
+                    len += word.count_ones() as isize;
+                }
+            }
+            if mask != 0 {
+                let (p2, bit2) = (p1 >> BITS, p1 & ((1 << BITS) - 1));
+                set.layer1[p1].mask.with_unique(|m| *m = mask);
+                set.layer2[p2].with_unique(|m| *m |= 1 << bit2);
+                set.layer3.with_unique(|m| *m |= 1 << p2);
+            }
         }
+        set.len.with_unique(|l| *l = len);
+        set
     }
 }
 
-struct OnceAtom {
-    inner: AtomicPtr<[AtomicUsize; 1 << BITS]>,
-    marker: PhantomData<Option<Box<[AtomicUsize; 1 << BITS]>>>,
+/// Takes a snapshot of the set, see [`AtomicBitSet::to_bitset`].
+///
+/// [`AtomicBitSet::to_bitset`]: struct.AtomicBitSet.html#method.to_bitset
+impl<'a> From<&'a AtomicBitSet> for BitSet {
+    fn from(set: &'a AtomicBitSet) -> Self {
+        set.to_bitset()
+    }
 }
 
-impl Drop for OnceAtom {
+impl<A: LayerAlloc> Drop for AtomicBitSet<A> {
     fn drop(&mut self) {
-        let ptr = *self.inner.get_mut();
-        if !ptr.is_null() {
-            // SAFETY: If the pointer is not null, we created it from
-            // `Box::into_raw` in `Self::atom_get_or_init`.
-            unsafe { Box::from_raw(ptr) };
+        for block in self.layer1.iter_mut() {
+            // SAFETY: The blocks were allocated with `self.alloc`, and the
+            // set is going away.
+            unsafe { block.atom.free(&self.alloc) };
+        }
+    }
+}
+
+/// An `Iterator` over the indices of an [`AtomicBitSet`] that reports
+/// changes made to the set while iterating, see
+/// [`AtomicBitSet::iter_consistent`].
+///
+/// [`AtomicBitSet`]: struct.AtomicBitSet.html
+/// [`AtomicBitSet::iter_consistent`]: struct.AtomicBitSet.html#method.iter_consistent
+#[derive(Debug)]
+pub struct ConsistentIter<'a, A: LayerAlloc = Global> {
+    iter: BitIter<&'a AtomicBitSet<A>>,
+    set: &'a AtomicBitSet<A>,
+    epoch: usize,
+    yielded: usize,
+    done: bool,
+}
+
+impl<A: LayerAlloc> Iterator for ConsistentIter<'_, A> {
+    type Item = Result<Index, ConcurrentModification>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
         }
+        let next = self.iter.next();
+        if self.set.epoch() != self.epoch {
+            self.done = true;
+            return Some(Err(ConcurrentModification::new(self.yielded)));
+        }
+        match next {
+            Some(id) => {
+                self.yielded += 1;
+                Some(Ok(id))
+            }
+            None => {
+                self.done = true;
+                None
+            }
+        }
+    }
+}
+
+/// Shows the number of indices, the lowest and highest of them and the
+/// first runs of consecutive indices, or every run with `{:#?}`.
+///
+/// # Examples
+///
+/// ```
+/// # use hibitset::AtomicBitSet;
+/// let set = AtomicBitSet::new();
+/// for id in &[1, 2, 3, 10] {
+///     set.add_atomic(*id);
+/// }
+/// assert_eq!(
+///     format!("{:?}", set),
+///     "AtomicBitSet { len: 4, min: 1, max: 10, density: 0.400, runs: [1..4, 10] }"
+/// );
+/// ```
+impl<A: LayerAlloc> Debug for AtomicBitSet<A> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
+        debug::summary("AtomicBitSet", self, f)
     }
 }
 
+/// The lazily allocated layer 0 words of an `AtomicBlock`.
+///
+/// The pointer only ever holds null or a pointer returned by `box_in`, and
+/// is turned back into a reference or a box without any integer casts, so
+/// the accesses keep the provenance of the allocation and pass Miri with
+/// `-Zmiri-strict-provenance`. Building with `--cfg hibitset_safe` swaps
+/// in an implementation without any `unsafe`.
+#[cfg(not(hibitset_safe))]
+struct OnceAtom {
+    inner: AtomicPtr<[AtomicUsize; 1 << BITS]>,
+    marker: PhantomData<Option<Box<[AtomicUsize; 1 << BITS]>>>,
+}
+
+#[cfg(not(hibitset_safe))]
 impl OnceAtom {
     fn new() -> Self {
         Self {
@@ -225,11 +875,10 @@ impl OnceAtom {
         }
     }
 
-    fn get_or_init(&self) -> &[AtomicUsize; 1 << BITS] {
+    fn get_or_init<A: LayerAlloc>(&self, alloc: &A) -> &[AtomicUsize; 1 << BITS] {
         let current_ptr = self.inner.load(Ordering::Acquire);
         let ptr = if current_ptr.is_null() {
-            const ZERO: AtomicUsize = AtomicUsize::new(0);
-            let new_ptr = Box::into_raw(Box::new([ZERO; 1 << BITS]));
+            let new_ptr = alloc::box_in(array::from_fn(|_| AtomicUsize::new(0)), alloc);
             if let Err(existing_ptr) = self.inner.compare_exchange(
                 ptr::null_mut(),
                 new_ptr,
@@ -238,9 +887,9 @@ impl OnceAtom {
                 Ordering::Release,
                 Ordering::Acquire,
             ) {
-                // SAFETY: We obtained this pointer from `Box::into_raw` above
-                // and failed to publish it to the `AtomicPtr`.
-                unsafe { Box::from_raw(new_ptr) };
+                // SAFETY: We obtained this pointer from `box_in` above and
+                // failed to publish it to the `AtomicPtr`.
+                unsafe { alloc::drop_box_in(new_ptr, alloc) };
                 existing_ptr
             } else {
                 new_ptr
@@ -250,8 +899,8 @@ impl OnceAtom {
         };
 
         // SAFETY: We checked that this pointer is not null (either by
-        // `.is_null()` check, `compare_exhange`, or from `Box::into_raw`). We
-        // created from `Box::into_raw` (at some point) and we only use it to
+        // `.is_null()` check, `compare_exchange`, or from `box_in`). We
+        // created it from `box_in` (at some point) and we only use it to
         // create immutable references (unless we have exclusive access to self)
         unsafe { &*ptr }
     }
@@ -259,19 +908,79 @@ impl OnceAtom {
     fn get(&self) -> Option<&[AtomicUsize; 1 << BITS]> {
         let ptr = self.inner.load(Ordering::Acquire);
         // SAFETY: If it is not null, we created this pointer from
-        // `Box::into_raw` and only use it to create immutable references
+        // `box_in` and only use it to create immutable references
         // (unless we have exclusive access to self)
         unsafe { ptr.as_ref() }
     }
 
+    /// Frees the block, if it was allocated.
+    ///
+    /// # Safety
+    ///
+    /// `alloc` must be the allocator the block was allocated with.
+    unsafe fn free<A: LayerAlloc>(&mut self, alloc: &A) {
+        let ptr = self
+            .inner
+            .with_unique(|ptr| mem::replace(ptr, ptr::null_mut()));
+        if !ptr.is_null() {
+            // SAFETY: If the pointer is not null, we created it from
+            // `box_in` in `Self::get_or_init`.
+            alloc::drop_box_in(ptr, alloc);
+        }
+    }
+
     fn get_mut(&mut self) -> Option<&mut [AtomicUsize; 1 << BITS]> {
-        let ptr = self.inner.get_mut();
+        let ptr = self.inner.with_unique(|ptr| *ptr);
         // SAFETY: If this is not null, we created this pointer from
-        // `Box::into_raw` and we have an exclusive borrow of self.
+        // `box_in` and we have an exclusive borrow of self.
         unsafe { ptr.as_mut() }
     }
 }
 
+/// The lazily allocated layer 0 words of an `AtomicBlock`, kept in a
+/// `OnceLock`.
+///
+/// This is slower than the pointer based version, as every access goes
+/// through the lock's state, and it always allocates the words with the
+/// global allocator. It isn't modelled by loom.
+#[cfg(hibitset_safe)]
+struct OnceAtom {
+    inner: OnceLock<Box<[AtomicUsize; 1 << BITS]>>,
+}
+
+#[cfg(hibitset_safe)]
+impl OnceAtom {
+    fn new() -> Self {
+        Self {
+            inner: OnceLock::new(),
+        }
+    }
+
+    fn get_or_init<A: LayerAlloc>(&self, alloc: &A) -> &[AtomicUsize; 1 << BITS] {
+        let _ = alloc;
+        self.inner
+            .get_or_init(|| Box::new(array::from_fn(|_| AtomicUsize::new(0))))
+    }
+
+    fn get(&self) -> Option<&[AtomicUsize; 1 << BITS]> {
+        self.inner.get().map(|layer0| &**layer0)
+    }
+
+    /// Frees the block, if it was allocated.
+    ///
+    /// # Safety
+    ///
+    /// Always safe, the signature matches the pointer based version.
+    unsafe fn free<A: LayerAlloc>(&mut self, alloc: &A) {
+        let _ = alloc;
+        self.inner.take();
+    }
+
+    fn get_mut(&mut self) -> Option<&mut [AtomicUsize; 1 << BITS]> {
+        self.inner.get_mut().map(|layer0| &mut **layer0)
+    }
+}
+
 struct AtomicBlock {
     mask: AtomicUsize,
     atom: OnceAtom,
@@ -285,30 +994,36 @@ impl AtomicBlock {
         }
     }
 
-    fn add(&self, id: Index) -> bool {
+    fn add<A: LayerAlloc>(&self, id: Index, alloc: &A) -> bool {
+        self.add_ordered(id, Ordering::Relaxed, alloc)
+    }
+
+    fn add_ordered<A: LayerAlloc>(&self, id: Index, order: Ordering, alloc: &A) -> bool {
         let (i, m) = (id.row(SHIFT1), id.mask(SHIFT0));
-        let old = self.atom.get_or_init()[i].fetch_or(m, Ordering::Relaxed);
+        let old = self.atom.get_or_init(alloc)[i].fetch_or(m, order);
         self.mask.fetch_or(id.mask(SHIFT1), Ordering::Relaxed);
         old & m != 0
     }
 
-    fn contains(&self, id: Index) -> bool {
+    fn contains(&self, id: Index, order: Ordering) -> bool {
         self.atom
             .get()
-            .map(|layer0| layer0[id.row(SHIFT1)].load(Ordering::Relaxed) & id.mask(SHIFT0) != 0)
+            .map(|layer0| layer0[id.row(SHIFT1)].load(order) & id.mask(SHIFT0) != 0)
             .unwrap_or(false)
     }
 
     fn remove(&mut self, id: Index) -> bool {
         if let Some(layer0) = self.atom.get_mut() {
             let (i, m) = (id.row(SHIFT1), !id.mask(SHIFT0));
-            let v = layer0[i].get_mut();
-            let was_set = *v & id.mask(SHIFT0) == id.mask(SHIFT0);
-            *v = *v & m;
-            if *v == 0 {
+            let (was_set, empty) = layer0[i].with_unique(|v| {
+                let was_set = *v & id.mask(SHIFT0) == id.mask(SHIFT0);
+                *v = *v & m;
+                (was_set, *v == 0)
+            });
+            if empty {
                 // no other bits are set
                 // so unset bit in the next level up
-                *self.mask.get_mut() &= !id.mask(SHIFT1);
+                self.mask.with_unique(|mask| *mask &= !id.mask(SHIFT1));
             }
             was_set
         } else {
@@ -317,10 +1032,10 @@ impl AtomicBlock {
     }
 
     fn clear(&mut self) {
-        *self.mask.get_mut() = 0;
+        self.mask.with_unique(|mask| *mask = 0);
         self.atom.get_mut().map(|layer0| {
             for l in layer0 {
-                *l.get_mut() = 0;
+                l.with_unique(|l| *l = 0);
             }
         });
     }
@@ -330,14 +1045,170 @@ impl Debug for AtomicBlock {
     fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
         f.debug_struct("AtomicBlock")
             .field("mask", &self.mask)
-            .field("atom", &self.atom.get().unwrap().iter())
+            .field("allocated", &self.atom.get().is_some())
             .finish()
     }
 }
 
 #[cfg(test)]
 mod atomic_set_test {
-    use {AtomicBitSet, BitSetAnd, BitSetLike};
+    use sync::Ordering;
+    use {AtomicBitSet, BitSet, BitSetAnd, BitSetLike};
+
+    #[test]
+    fn debug() {
+        let set = AtomicBitSet::new();
+        assert_eq!(format!("{:?}", set), "AtomicBitSet { len: 0 }");
+
+        for id in (0..20).map(|i| i * 2).chain(100..110).chain(Some(100_000)) {
+            set.add_atomic(id);
+        }
+        assert_eq!(
+            format!("{:?}", set),
+            "AtomicBitSet { len: 31, min: 0, max: 100000, density: 0.000, \
+             runs: [0, 2, 4, 6, 8, 10, 12, 14, ...] }"
+        );
+        let pretty = format!("{:#?}", set);
+        assert!(pretty.contains("    runs: [\n        0,\n"));
+        assert!(pretty.contains("        100..110,\n        100000,\n    ],"));
+    }
+
+    #[test]
+    fn iter_consistent() {
+        let set = AtomicBitSet::new();
+        for id in 0..100 {
+            set.add_atomic(id);
+        }
+        assert_eq!(
+            set.iter_consistent().collect::<Result<Vec<_>, _>>(),
+            Ok((0..100).collect())
+        );
+
+        let mut iter = set.iter_consistent();
+        assert_eq!(iter.by_ref().take(10).count(), 10);
+        set.add_atomic(50);
+        assert_eq!(iter.next(), Some(Ok(10)));
+        set.add_atomic(1_000);
+        if cfg!(debug_assertions) {
+            let err = iter.next().unwrap().unwrap_err();
+            assert_eq!(err.yielded(), 11);
+            assert_eq!(iter.next(), None);
+
+            // A change after the last index is still reported.
+            let mut iter = set.iter_consistent();
+            assert_eq!(iter.by_ref().take(101).count(), 101);
+            set.clear_atomic();
+            assert!(iter.next().unwrap().is_err());
+        } else {
+            assert!(iter.all(|id| id.is_ok()));
+        }
+    }
+
+    #[test]
+    fn memory_stats() {
+        let c = AtomicBitSet::new();
+        let empty = c.memory_stats();
+        assert_eq!(empty.layer0, 0);
+        assert!(empty.layer1 > 0 && empty.layer2 > 0);
+
+        c.add_atomic(1);
+        c.add_atomic(2);
+        let block = c.memory_stats().layer0;
+        assert!(block > 0);
+        c.add_atomic(1_000_000);
+        assert_eq!(c.memory_stats().layer0, 2 * block);
+        assert_eq!(c.heap_bytes(), empty.total() + 2 * block);
+    }
+
+    #[test]
+    fn reserve() {
+        let c = AtomicBitSet::new();
+        c.reserve(10..10);
+        assert_eq!(c.memory_stats().layer0, 0);
+
+        c.reserve(100..200);
+        let block = c.memory_stats().layer0;
+        assert!(block > 0);
+        c.reserve(0..4_096);
+        assert_eq!(c.memory_stats().layer0, block);
+        c.reserve(4_096..4_097);
+        assert_eq!(c.memory_stats().layer0, 2 * block);
+
+        // Reserving doesn't add anything.
+        assert!(c.is_empty());
+        c.add_atomic(4_096);
+        assert_eq!(c.memory_stats().layer0, 2 * block);
+        assert_eq!((&c).iter().collect::<Vec<_>>(), [4_096]);
+
+        c.reserve(::MAX_EID as u32 - 1..::MAX_EID as u32);
+        assert_eq!(c.memory_stats().layer0, 3 * block);
+    }
+
+    #[test]
+    fn shrink() {
+        let mut c = AtomicBitSet::new();
+        c.reserve(0..10_000);
+        let block = c.memory_stats().layer0 / 3;
+        c.add_atomic(5_000);
+        c.add_atomic(1_000_000);
+        assert_eq!(c.shrink(), 2 * block);
+        assert_eq!(c.memory_stats().layer0, 2 * block);
+
+        c.remove(1_000_000);
+        c.clear_atomic();
+        assert_eq!(c.shrink(), 2 * block);
+        assert_eq!(c.memory_stats().layer0, 0);
+        assert_eq!(c.shrink(), 0);
+
+        c.add_atomic(1_000_000);
+        assert_eq!((&c).iter().collect::<Vec<_>>(), [1_000_000]);
+        assert_eq!(c.memory_stats().layer0, block);
+    }
+
+    #[test]
+    fn publish_ordered() {
+        use std::sync::atomic::{fence, AtomicUsize, Ordering};
+        use std::thread;
+
+        let ready = AtomicBitSet::new();
+        let slots = (0..1_000).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for (i, slot) in slots.iter().enumerate() {
+                    slot.store(i + 1, Ordering::Relaxed);
+                    assert!(!ready.add_atomic_ordered(i as u32, Ordering::Release));
+                }
+            });
+            for i in (0..1_000).rev() {
+                while !ready.contains_ordered(i, Ordering::Acquire) {
+                    thread::yield_now();
+                }
+                assert_eq!(slots[i as usize].load(Ordering::Relaxed), i as usize + 1);
+            }
+        });
+
+        for i in &ready {
+            fence(Ordering::Acquire);
+            assert_eq!(slots[i as usize].load(Ordering::Relaxed), i as usize + 1);
+        }
+        assert_eq!(ready.len_hint(), 1_000);
+    }
+
+    #[test]
+    fn try_add_atomic() {
+        let c = AtomicBitSet::new();
+        assert_eq!(c.try_add_atomic(5), Ok(false));
+        assert_eq!(c.try_add_atomic(5), Ok(true));
+        assert_eq!(c.try_contains(5), Ok(true));
+        assert_eq!(c.try_contains(6), Ok(false));
+
+        let max = ::MAX_EID as u32;
+        assert_eq!(c.try_add_atomic(max - 1), Ok(false));
+        let err = c.try_add_atomic(max).unwrap_err();
+        assert_eq!((err.index(), err.max()), (max, ::MAX_EID));
+        assert!(c.try_contains(!0).is_err());
+        assert_eq!(c.len_hint(), 2);
+    }
 
     #[test]
     fn insert() {
@@ -365,6 +1236,45 @@ mod atomic_set_test {
         }
     }
 
+    #[test]
+    fn add_atomic_many() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let set = Arc::new(AtomicBitSet::new());
+        set.add_atomic(5);
+        let threads: Vec<_> = (0..4u32)
+            .map(|t| {
+                let set = set.clone();
+                thread::spawn(move || {
+                    let mut ids: Vec<_> = (0..200_000).filter(|i| i % (t + 2) == 0).collect();
+                    if t == 3 {
+                        ids.reverse();
+                        ids.push(ids[0]);
+                    }
+                    set.add_atomic_many(&ids)
+                })
+            })
+            .collect();
+        let added: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+
+        let expected: BitSet = (0..200_000)
+            .filter(|i| (2..6).any(|d| i % d == 0) || *i == 5)
+            .collect();
+        assert_eq!(expected, *set);
+        assert_eq!(set.len_hint(), expected.len());
+        assert_eq!(added, expected.len() - 1);
+        assert_eq!(set.add_atomic_many(&[]), 0);
+        assert_eq!(set.add_atomic_many(&[1, 1, 16_000_000]), 2);
+        assert_eq!(set.clone().to_bitset().validate(), Ok(()));
+    }
+
+    #[test]
+    #[should_panic]
+    fn add_atomic_many_out_of_range() {
+        AtomicBitSet::new().add_atomic_many(&[1, ::MAX_EID as u32]);
+    }
+
     #[test]
     fn add_atomic() {
         let c = AtomicBitSet::new();
@@ -391,6 +1301,132 @@ mod atomic_set_test {
         }
     }
 
+    #[test]
+    fn clear_atomic() {
+        use std::sync::{Arc, Barrier};
+        use std::thread;
+
+        let c = AtomicBitSet::new();
+        for i in 0..100_000 {
+            c.add_atomic(i * 97 % 1_000_000);
+        }
+        c.clear_atomic();
+        assert!(c.is_empty());
+        assert_eq!(c.len_hint(), 0);
+        assert_eq!((&c).iter().next(), None);
+        assert!(!c.contains(97));
+
+        // An add whose bit was cleared before it counted it.
+        c.len.fetch_sub(1, Ordering::Relaxed);
+        assert_eq!(c.len_hint(), 0);
+        c.len.fetch_add(1, Ordering::Relaxed);
+        assert_eq!(c.len_hint(), 0);
+        c.len.fetch_add(1, Ordering::Relaxed);
+        assert_eq!(c.len_hint(), 1);
+        c.len.fetch_sub(1, Ordering::Relaxed);
+
+        // Swap buffer: a writer refills the set between clears, handing it
+        // over at a barrier.
+        let c = Arc::new(c);
+        let barrier = Arc::new(Barrier::new(2));
+        let writer = {
+            let (c, barrier) = (c.clone(), barrier.clone());
+            thread::spawn(move || {
+                for round in 0..10 {
+                    for i in 0..1_000 {
+                        c.add_atomic(round * 1_000 + i);
+                    }
+                    barrier.wait();
+                    barrier.wait();
+                }
+            })
+        };
+        for round in 0..10 {
+            barrier.wait();
+            assert_eq!(
+                (&*c).iter().collect::<Vec<_>>(),
+                (round * 1_000..(round + 1) * 1_000).collect::<Vec<_>>()
+            );
+            c.clear_atomic();
+            barrier.wait();
+        }
+        writer.join().unwrap();
+        assert!(c.is_empty());
+    }
+
+    #[test]
+    fn claim() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use std::thread;
+
+        let c = Arc::new(AtomicBitSet::new());
+        let wins = Arc::new(AtomicUsize::new(0));
+        let threads = (0..4)
+            .map(|_| {
+                let c = c.clone();
+                let wins = wins.clone();
+                thread::spawn(move || {
+                    for i in 0..10_000 {
+                        if c.claim(i * 7) {
+                            wins.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(wins.load(Ordering::Relaxed), 10_000);
+        assert_eq!((&*c).iter().count(), 10_000);
+        assert!(!c.claim(7));
+        assert!(c.claim(8));
+    }
+
+    #[test]
+    fn len() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let c = Arc::new(AtomicBitSet::new());
+        let threads = (0..4)
+            .map(|t| {
+                let c = c.clone();
+                thread::spawn(move || {
+                    for i in 0..10_000 {
+                        c.add_atomic(i * (t + 1));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let expected = (0..40_000)
+            .filter(|i| (1..5).any(|t| i % t == 0 && i / t < 10_000))
+            .count();
+        assert_eq!(c.len_hint(), expected);
+        assert_eq!(c.count(), expected);
+
+        let mut c = Arc::try_unwrap(c).unwrap();
+        assert!(c.remove(0));
+        assert!(!c.remove(0));
+        assert_eq!(c.len_hint(), expected - 1);
+        assert!(!c.add(0));
+        assert!(c.add(0));
+        assert_eq!(c.len_hint(), expected);
+        assert_eq!(c.count(), expected);
+
+        c.clear();
+        assert_eq!(c.len_hint(), 0);
+        assert_eq!(c.count(), 0);
+        let set: BitSet = (0..100).collect();
+        assert_eq!(set.into_atomic().len_hint(), 100);
+    }
+
     #[test]
     fn remove() {
         let mut c = AtomicBitSet::new();
@@ -438,6 +1474,71 @@ mod atomic_set_test {
         assert_eq!(BitSetAnd(&odd, &even).iter().count(), 0);
     }
 
+    #[test]
+    fn to_bitset() {
+        let c = AtomicBitSet::new();
+        assert_eq!(c.to_bitset(), BitSet::new());
+
+        for i in 0..1_000 {
+            c.add_atomic(i * 997);
+        }
+        let set = c.to_bitset();
+        assert_eq!(
+            (&set).iter().collect::<Vec<_>>(),
+            (&c).iter().collect::<Vec<_>>()
+        );
+        assert!(!set.contains(1));
+        assert_eq!(set.validate(), Ok(()));
+
+        // Summary bits left over an emptied word, as a racing clear may
+        // leave them, and over a block without layer 0 words.
+        let c = AtomicBitSet::new();
+        c.add_atomic(5);
+        c.add_atomic(300_000);
+        c.layer1[0].atom.get().unwrap()[0].store(0, Ordering::Relaxed);
+        c.layer2[1].fetch_or(1, Ordering::Relaxed);
+        c.layer3.fetch_or(0b10, Ordering::Relaxed);
+        let set = c.to_bitset();
+        assert_eq!((&set).iter().collect::<Vec<_>>(), [300_000]);
+        assert_eq!(set.len(), 1);
+        assert_eq!(set.validate(), Ok(()));
+    }
+
+    #[test]
+    fn clone() {
+        let mut c = AtomicBitSet::new();
+        for i in 0..1_000 {
+            c.add_atomic(i * 997);
+        }
+        c.remove(997);
+        c.remove(500 * 997);
+        let copy = c.clone();
+        assert_eq!(copy.to_bitset(), c.to_bitset());
+        assert_eq!(copy.count(), 998);
+        assert_eq!(BitSet::from(&copy), c.to_bitset());
+        assert_eq!(copy.layer3(), c.layer3());
+
+        c.add_atomic(1);
+        copy.add_atomic(2);
+        assert!(c.contains(1) && !c.contains(2));
+        assert!(copy.contains(2) && !copy.contains(1));
+        assert_eq!(AtomicBitSet::new().clone().to_bitset(), BitSet::new());
+    }
+
+    #[test]
+    fn into_atomic() {
+        let mut set = BitSet::new();
+        for i in 0..1_000 {
+            set.add(i * 997);
+        }
+        let c = set.clone().into_atomic();
+        for i in 0..1_000 {
+            assert!(c.contains(i * 997));
+        }
+        assert!(!c.contains(1));
+        assert_eq!(c.to_bitset(), set);
+    }
+
     #[test]
     fn clear() {
         let mut set = AtomicBitSet::new();
@@ -484,3 +1585,86 @@ mod atomic_set_test {
         assert_eq!((&set).iter().count(), 0);
     }
 }
+
+/// Model checks of the lock-free paths, run with
+///
+/// ```text
+/// RUSTFLAGS="--cfg loom" cargo test --lib --release loom
+/// ```
+#[cfg(all(loom, test))]
+mod loom_test {
+    use loom::cell::UnsafeCell;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    use super::AtomicBitSet;
+    use sync::Ordering;
+    use BitSetLike;
+
+    #[test]
+    fn loom_add_same_bit() {
+        loom::model(|| {
+            let set = Arc::new(AtomicBitSet::new());
+            let other = set.clone();
+            let thread = thread::spawn(move || other.add_atomic(70));
+            let ours = set.add_atomic(70);
+            let theirs = thread.join().unwrap();
+            // Exactly one of the adds saw the bit unset.
+            assert!(ours != theirs);
+            assert_eq!(set.len_hint(), 1);
+            assert_eq!((&*set).iter().collect::<Vec<_>>(), [70]);
+        });
+    }
+
+    #[test]
+    fn loom_get_or_init() {
+        loom::model(|| {
+            let set = Arc::new(AtomicBitSet::new());
+            let other = set.clone();
+            // Both ids are in the same block, so both threads race to
+            // allocate it.
+            let thread = thread::spawn(move || other.add_atomic(1));
+            set.add_atomic(2);
+            thread.join().unwrap();
+            assert!(set.contains(1));
+            assert!(set.contains(2));
+        });
+    }
+
+    #[test]
+    fn loom_iter_while_adding() {
+        loom::model(|| {
+            let set = Arc::new(AtomicBitSet::new());
+            let other = set.clone();
+            let thread = thread::spawn(move || {
+                other.add_atomic(5);
+                other.add_atomic(100_000);
+            });
+            // The adds are relaxed, so any subset of them may be seen, in
+            // particular 100_000 without 5. The iterator must still not
+            // panic, yield anything that wasn't added, or repeat an index.
+            let seen = (&*set).iter().collect::<Vec<_>>();
+            assert!(seen.is_empty() || seen == [5] || seen == [5, 100_000] || seen == [100_000]);
+            // Once the adding thread is joined, both are visible.
+            thread.join().unwrap();
+            assert_eq!((&*set).iter().collect::<Vec<_>>(), [5, 100_000]);
+        });
+    }
+
+    #[test]
+    fn loom_publish_ordered() {
+        loom::model(|| {
+            let set = Arc::new(AtomicBitSet::new());
+            let slot = Arc::new(UnsafeCell::new(0));
+            let (other, other_slot) = (set.clone(), slot.clone());
+            let thread = thread::spawn(move || {
+                other_slot.with_mut(|slot| unsafe { *slot = 42 });
+                other.add_atomic_ordered(3, Ordering::Release);
+            });
+            if set.contains_ordered(3, Ordering::Acquire) {
+                assert_eq!(slot.with(|slot| unsafe { *slot }), 42);
+            }
+            thread.join().unwrap();
+        });
+    }
+}
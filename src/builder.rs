@@ -0,0 +1,137 @@
+use util::*;
+use BitSet;
+
+/// Builds a [`BitSet`] by setting bits in layer 0 only, and rebuilding the
+/// upper layers in one pass when [`maintain`] is called.
+///
+/// Adding an index to a `BitSet` updates every layer above it on the
+/// first index of each word, which adds up in write-heavy phases. The
+/// builder defers that work, so it can't be iterated until the layers are
+/// maintained, which is why it only hands out the set from `maintain` and
+/// [`build`].
+///
+/// # Examples
+///
+/// ```
+/// # use hibitset::{BitSetBuilder, BitSetLike};
+/// let mut builder = BitSetBuilder::new();
+/// for i in 0..100_000 {
+///     builder.add_fast(i * 3);
+/// }
+/// assert!(builder.contains(299_997));
+/// let set = builder.build();
+/// assert_eq!(set.iter().count(), 100_000);
+/// ```
+///
+/// [`BitSet`]: struct.BitSet.html
+/// [`maintain`]: #method.maintain
+/// [`build`]: #method.build
+#[derive(Debug, Default)]
+pub struct BitSetBuilder {
+    set: BitSet,
+}
+
+impl BitSetBuilder {
+    /// Creates a builder for an empty set.
+    pub fn new() -> BitSetBuilder {
+        Default::default()
+    }
+
+    /// Creates a builder for an empty set, preallocated for up to `max`
+    /// indices.
+    pub fn with_capacity(max: Index) -> BitSetBuilder {
+        BitSetBuilder {
+            set: BitSet::with_capacity(max),
+        }
+    }
+
+    /// Sets `id` in layer 0 only. Returns `true` if the value was already
+    /// in the set.
+    #[inline]
+    pub fn add_fast(&mut self, id: Index) -> bool {
+        let (p0, mask) = (id.offset(SHIFT1), id.mask(SHIFT0));
+        if p0 >= self.set.layer0.len() {
+            self.set.grow(id);
+        }
+        let old = self.set.layer0[p0];
+        self.set.layer0[p0] |= mask;
+        old & mask != 0
+    }
+
+    /// Returns `true` if `id` was added.
+    #[inline]
+    pub fn contains(&self, id: Index) -> bool {
+        let p0 = id.offset(SHIFT1);
+        p0 < self.set.layer0.len() && self.set.layer0[p0] & id.mask(SHIFT0) != 0
+    }
+
+    /// Rebuilds the upper layers from layer 0 and returns the set, which
+    /// can then be read until more indices are added.
+    pub fn maintain(&mut self) -> &BitSet {
+        self.set.rebuild_summaries();
+        &self.set
+    }
+
+    /// Rebuilds the upper layers and returns the finished set.
+    pub fn build(mut self) -> BitSet {
+        self.set.rebuild_summaries();
+        self.set
+    }
+}
+
+/// Continues building on an existing set.
+impl From<BitSet> for BitSetBuilder {
+    fn from(set: BitSet) -> Self {
+        BitSetBuilder { set }
+    }
+}
+
+impl Extend<Index> for BitSetBuilder {
+    fn extend<T: IntoIterator<Item = Index>>(&mut self, iter: T) {
+        for id in iter {
+            self.add_fast(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {BitSet, BitSetBuilder, BitSetLike};
+
+    #[test]
+    fn build() {
+        let mut builder = BitSetBuilder::new();
+        assert!(!builder.add_fast(5));
+        assert!(builder.add_fast(5));
+        builder.extend((1_000..2_000).filter(|i| i % 7 == 0));
+        builder.add_fast(1_000_000);
+        assert!(builder.contains(1_001));
+        assert!(!builder.contains(1_002));
+        assert!(!builder.contains(5_000_000));
+
+        let mut expected: BitSet = (1_000..2_000).filter(|i| i % 7 == 0).collect();
+        expected.add(5);
+        expected.add(1_000_000);
+        let set = builder.build();
+        assert_eq!(
+            (&set).iter().collect::<Vec<_>>(),
+            (&expected).iter().collect::<Vec<_>>()
+        );
+        assert_eq!(set.layer3(), expected.layer3());
+    }
+
+    #[test]
+    fn maintain() {
+        let mut set = BitSet::new();
+        set.add(10);
+        let mut builder = BitSetBuilder::from(set);
+        builder.add_fast(100_000);
+        assert_eq!(builder.maintain().iter().collect::<Vec<_>>(), [10, 100_000]);
+        builder.add_fast(20);
+        assert_eq!(
+            builder.maintain().iter().collect::<Vec<_>>(),
+            [10, 20, 100_000]
+        );
+        assert!(BitSetBuilder::with_capacity(1_000).build().is_empty());
+    }
+}
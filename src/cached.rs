@@ -0,0 +1,157 @@
+use std::cell::OnceCell;
+
+use util::*;
+use {BitSet, BitSetLike};
+
+/// A [`BitSetLike`] whose contents are worked out once and then kept in a
+/// [`BitSet`], for combinators that are iterated many times.
+///
+/// Iterating a tree of [`BitSetAnd`]s and friends recomputes every word on
+/// each pass. A `CachedBitSet` does that on the first access and answers
+/// from the stored set after that, until [`dirty`] is called because the
+/// sets the combinator reads from have changed. It is created with
+/// [`materialize_when_hot`].
+///
+/// # Examples
+///
+/// ```
+/// # use hibitset::{BitSet, BitSetLike};
+/// let a: BitSet = (0..10).collect();
+/// let b: BitSet = (5..20).collect();
+///
+/// let both = (&a & &b).materialize_when_hot();
+/// assert!(!both.is_cached());
+/// assert_eq!((&both).iter().count(), 5);
+/// assert!(both.is_cached());
+/// assert_eq!((&both).iter().count(), 5);
+/// ```
+///
+/// [`BitSetLike`]: trait.BitSetLike.html
+/// [`BitSet`]: struct.BitSet.html
+/// [`BitSetAnd`]: struct.BitSetAnd.html
+/// [`dirty`]: #method.dirty
+/// [`materialize_when_hot`]: trait.BitSetLike.html#method.materialize_when_hot
+#[derive(Debug, Clone)]
+pub struct CachedBitSet<T> {
+    set: T,
+    cache: OnceCell<BitSet>,
+}
+
+impl<T: BitSetLike> CachedBitSet<T> {
+    /// Creates a `CachedBitSet` for `set`, which is only read on the first
+    /// access.
+    pub fn new(set: T) -> Self {
+        CachedBitSet {
+            set,
+            cache: OnceCell::new(),
+        }
+    }
+
+    /// Returns the stored contents of the set, working them out first if
+    /// they aren't cached.
+    pub fn get(&self) -> &BitSet {
+        self.cache.get_or_init(|| {
+            let mut cache = BitSet::new();
+            cache.copy_from(&self.set);
+            cache
+        })
+    }
+
+    /// Returns `true` if the contents are cached, i.e. the next access
+    /// won't read the wrapped set.
+    pub fn is_cached(&self) -> bool {
+        self.cache.get().is_some()
+    }
+
+    /// Drops the cached contents, so the next access reads the wrapped set
+    /// again. Call this whenever the sets it depends on change.
+    pub fn dirty(&mut self) {
+        self.cache.take();
+    }
+
+    /// Returns the wrapped set.
+    pub fn inner(&self) -> &T {
+        &self.set
+    }
+
+    /// Returns the wrapped set mutably, dropping the cached contents.
+    pub fn inner_mut(&mut self) -> &mut T {
+        self.dirty();
+        &mut self.set
+    }
+
+    /// Returns the wrapped set.
+    pub fn into_inner(self) -> T {
+        self.set
+    }
+}
+
+impl<T: BitSetLike> BitSetLike for CachedBitSet<T> {
+    #[inline]
+    fn layer3(&self) -> usize {
+        self.get().layer3()
+    }
+
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        self.get().layer2(i)
+    }
+
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        self.get().layer1(i)
+    }
+
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        self.get().layer0(i)
+    }
+
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        self.get().contains(i)
+    }
+
+    #[inline]
+    fn exact_summaries(&self) -> bool {
+        true
+    }
+
+    #[inline]
+    fn len_upper_bound(&self) -> usize {
+        self.get().len()
+    }
+
+    #[inline]
+    fn backing_bitset(&self) -> Option<&BitSet> {
+        Some(self.get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {BitSet, BitSetLike, BitSetNot};
+
+    #[test]
+    fn cached() {
+        let mut a: BitSet = (0..1_000).step_by(2).collect();
+        let b: BitSet = (0..1_000).step_by(3).collect();
+
+        let mut cached = (&a & BitSetNot(&b)).materialize_when_hot();
+        let expected: Vec<_> = (0..1_000)
+            .filter(|&i| a.contains(i) && !b.contains(i))
+            .collect();
+        assert_eq!((&cached).iter().collect::<Vec<_>>(), expected);
+        assert_eq!(cached.get().len(), expected.len());
+        assert!(cached.contains(4) && !cached.contains(6));
+        assert_eq!(cached.backing_bitset(), Some(cached.get()));
+
+        cached.dirty();
+        assert!(!cached.is_cached());
+        drop(cached);
+        a.add(9_999);
+        let cached = (&a & BitSetNot(&b)).materialize_when_hot();
+        assert!(cached.contains(9_999));
+        assert_eq!((&cached).iter().count(), expected.len() + 1);
+    }
+}
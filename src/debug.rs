@@ -0,0 +1,70 @@
+use std::fmt::{Debug, Formatter, Result};
+use std::ops::Range;
+
+use util::*;
+use BitSetLike;
+
+/// How many runs `{:?}` shows before eliding the rest.
+const SHOWN_RUNS: usize = 8;
+
+/// Writes a summary of `set` as a struct called `name`, with the number of
+/// indices, the lowest and highest index, how dense the set is between
+/// them and its first runs of consecutive indices.
+///
+/// The alternate form, `{:#?}`, lists every run instead.
+pub(crate) fn summary<T: BitSetLike>(name: &str, set: T, f: &mut Formatter) -> Result {
+    // Only the runs that are shown are kept, the rest are just counted.
+    let keep = if f.alternate() {
+        usize::MAX
+    } else {
+        SHOWN_RUNS
+    };
+    let (mut runs, mut more) = (Vec::new(), false);
+    let (mut len, mut max) = (0, 0);
+    for run in set.iter_ranges() {
+        len += (run.end - run.start) as usize;
+        max = run.end - 1;
+        if runs.len() < keep {
+            runs.push(run);
+        } else {
+            more = true;
+        }
+    }
+    let mut out = f.debug_struct(name);
+    out.field("len", &len);
+    if let Some(first) = runs.first() {
+        let min = first.start;
+        out.field("min", &min)
+            .field("max", &max)
+            .field(
+                "density",
+                &format_args!("{:.3}", len as f64 / (max - min + 1) as f64),
+            )
+            .field("runs", &Runs { runs: &runs, more });
+    }
+    out.finish()
+}
+
+/// Lists runs, as single indices where they only hold one, followed by an
+/// ellipsis if there are `more` that aren't listed.
+struct Runs<'a> {
+    runs: &'a [Range<Index>],
+    more: bool,
+}
+
+impl Debug for Runs<'_> {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        let mut list = f.debug_list();
+        for run in self.runs {
+            if run.end - run.start == 1 {
+                list.entry(&run.start);
+            } else {
+                list.entry(run);
+            }
+        }
+        if self.more {
+            list.entry(&format_args!("..."));
+        }
+        list.finish()
+    }
+}
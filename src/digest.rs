@@ -0,0 +1,138 @@
+use BitSetLike;
+
+const PRIME1: u64 = 0x9E37_79B1_85EB_CA87;
+const PRIME2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const PRIME3: u64 = 0x1656_67B1_9E37_79F9;
+const PRIME4: u64 = 0x85EB_CA77_C2B2_AE63;
+const PRIME5: u64 = 0x27D4_EB2F_1656_67C5;
+
+/// Hashes the non-zero 64 bit words of `set` with XXH64, see
+/// `BitSetLike::content_hash`.
+pub(crate) fn content_hash<T: BitSetLike>(set: T) -> u64 {
+    let mut hasher = Xxh64::new();
+    // On targets with 32 bit words, the two halves of a 64 bit word come
+    // one after the other and are joined before they're hashed.
+    let mut pending: Option<(u64, u64)> = None;
+    for (base, word) in set.masks() {
+        let (idx, word) = (base as u64 >> 6, (word as u64) << (base & 63));
+        pending = match pending {
+            Some((last, bits)) if last == idx => Some((idx, bits | word)),
+            Some((last, bits)) => {
+                hasher.write_pair(last, bits);
+                Some((idx, word))
+            }
+            None => Some((idx, word)),
+        };
+    }
+    if let Some((last, bits)) = pending {
+        hasher.write_pair(last, bits);
+    }
+    hasher.finish()
+}
+
+/// XXH64 with a seed of zero, streaming input made of whole `u64`s.
+struct Xxh64 {
+    acc: [u64; 4],
+    stripe: [u64; 4],
+    buffered: usize,
+    len: u64,
+}
+
+impl Xxh64 {
+    fn new() -> Self {
+        Xxh64 {
+            acc: [
+                PRIME1.wrapping_add(PRIME2),
+                PRIME2,
+                0,
+                0u64.wrapping_sub(PRIME1),
+            ],
+            stripe: [0; 4],
+            buffered: 0,
+            len: 0,
+        }
+    }
+
+    fn round(acc: u64, lane: u64) -> u64 {
+        acc.wrapping_add(lane.wrapping_mul(PRIME2))
+            .rotate_left(31)
+            .wrapping_mul(PRIME1)
+    }
+
+    fn write_pair(&mut self, idx: u64, word: u64) {
+        self.write(idx);
+        self.write(word);
+    }
+
+    fn write(&mut self, lane: u64) {
+        self.stripe[self.buffered] = lane;
+        self.buffered += 1;
+        self.len += 8;
+        if self.buffered == 4 {
+            for (acc, &lane) in self.acc.iter_mut().zip(&self.stripe) {
+                *acc = Xxh64::round(*acc, lane);
+            }
+            self.buffered = 0;
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        let mut hash = if self.len >= 32 {
+            let [v1, v2, v3, v4] = self.acc;
+            let hash = v1
+                .rotate_left(1)
+                .wrapping_add(v2.rotate_left(7))
+                .wrapping_add(v3.rotate_left(12))
+                .wrapping_add(v4.rotate_left(18));
+            self.acc.iter().fold(hash, |hash, &acc| {
+                (hash ^ Xxh64::round(0, acc))
+                    .wrapping_mul(PRIME1)
+                    .wrapping_add(PRIME4)
+            })
+        } else {
+            PRIME5
+        };
+        hash = hash.wrapping_add(self.len);
+        for &lane in &self.stripe[..self.buffered] {
+            hash = (hash ^ Xxh64::round(0, lane))
+                .rotate_left(27)
+                .wrapping_mul(PRIME1)
+                .wrapping_add(PRIME4);
+        }
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(PRIME2);
+        hash ^= hash >> 29;
+        hash = hash.wrapping_mul(PRIME3);
+        hash ^ (hash >> 32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {AtomicBitSet, BitSet, BitSetLike, BitSetNot};
+
+    #[test]
+    fn content_hash() {
+        // Reference values from another XXH64 implementation, over the
+        // same little-endian index and word pairs.
+        assert_eq!(BitSet::new().content_hash(), 0xEF46_DB37_51D8_E999);
+        let set: BitSet = [0, 1, 63, 64, 1_000_000].iter().collect();
+        assert_eq!(set.content_hash(), 0x9D23_DEFF_9F46_D812);
+        let set: BitSet = (0..100_000).step_by(3).collect();
+        assert_eq!(set.content_hash(), 0x2F2B_320A_7075_F39C);
+
+        let mut one = BitSet::with_capacity(1_000_000);
+        one.add(5);
+        assert_eq!(one.content_hash(), 0x7252_5DE3_76C4_4574);
+        one.add(6);
+        one.remove(6);
+        assert_eq!(one.content_hash(), 0x7252_5DE3_76C4_4574);
+        let atomic = AtomicBitSet::new();
+        atomic.add_atomic(5);
+        assert_eq!(atomic.content_hash(), one.content_hash());
+        assert_eq!(
+            BitSetNot(BitSetNot(&one)).content_hash(),
+            one.content_hash()
+        );
+    }
+}
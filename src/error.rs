@@ -0,0 +1,220 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use stats::ExtendStats;
+use util::*;
+
+/// The error returned when an index is beyond the maximum index a set
+/// can hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OutOfRange {
+    index: Index,
+    max: usize,
+}
+
+impl OutOfRange {
+    pub(crate) fn new(index: Index, max: usize) -> Self {
+        OutOfRange { index, max }
+    }
+
+    /// The index that was out of range.
+    pub fn index(&self) -> Index {
+        self.index
+    }
+
+    /// The bound the index should have been less than.
+    pub fn max(&self) -> usize {
+        self.max
+    }
+}
+
+impl Display for OutOfRange {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(
+            f,
+            "Expected index to be less then {}, found {}",
+            self.max, self.index
+        )
+    }
+}
+
+impl Error for OutOfRange {}
+
+/// The error yielded by [`AtomicBitSet::iter_consistent`] when the set was
+/// changed while iterating.
+///
+/// [`AtomicBitSet::iter_consistent`]: struct.AtomicBitSet.html#method.iter_consistent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConcurrentModification {
+    yielded: usize,
+}
+
+impl ConcurrentModification {
+    pub(crate) fn new(yielded: usize) -> Self {
+        ConcurrentModification { yielded }
+    }
+
+    /// The number of indices yielded before the change was seen.
+    pub fn yielded(&self) -> usize {
+        self.yielded
+    }
+}
+
+impl Display for ConcurrentModification {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(
+            f,
+            "The set was modified while iterating, after {} indices",
+            self.yielded
+        )
+    }
+}
+
+impl Error for ConcurrentModification {}
+
+/// The error returned by [`BitSet::try_extend`] when the input holds an
+/// index that is too large.
+///
+/// The indices before it were added, and are counted in [`stats`].
+///
+/// [`BitSet::try_extend`]: struct.BitSet.html#method.try_extend
+/// [`stats`]: #method.stats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExtendError {
+    stats: ExtendStats,
+    error: OutOfRange,
+}
+
+impl ExtendError {
+    pub(crate) fn new(stats: ExtendStats, error: OutOfRange) -> Self {
+        ExtendError { stats, error }
+    }
+
+    /// What the load did up to the index that was too large.
+    pub fn stats(&self) -> &ExtendStats {
+        &self.stats
+    }
+
+    /// The index that was too large, and the bound it should have been
+    /// less than.
+    pub fn out_of_range(&self) -> &OutOfRange {
+        &self.error
+    }
+}
+
+impl Display for ExtendError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(
+            f,
+            "{}, after adding {} indices",
+            self.error, self.stats.added
+        )
+    }
+}
+
+impl Error for ExtendError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// A word of a summary layer that doesn't match the words below it, found
+/// by [`BitSet::validate`].
+///
+/// [`BitSet::validate`]: struct.BitSet.html#method.validate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LayerMismatch {
+    layer: usize,
+    index: usize,
+    expected: usize,
+    found: usize,
+}
+
+impl LayerMismatch {
+    pub(crate) fn new(layer: usize, index: usize, expected: usize, found: usize) -> Self {
+        LayerMismatch {
+            layer,
+            index,
+            expected,
+            found,
+        }
+    }
+
+    /// The layer of the word, from 1 to 3.
+    pub fn layer(&self) -> usize {
+        self.layer
+    }
+
+    /// The position of the word in its layer.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The word summarizing the layer below, with a bit set for each
+    /// non-empty child.
+    pub fn expected(&self) -> usize {
+        self.expected
+    }
+
+    /// The word that is stored, `0` when the layer is too short to hold it.
+    pub fn found(&self) -> usize {
+        self.found
+    }
+}
+
+impl Display for LayerMismatch {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(
+            f,
+            "layer {} word {} is {:#x}, expected {:#x}",
+            self.layer, self.index, self.found, self.expected
+        )
+    }
+}
+
+/// The error returned by [`BitSet::validate`] when the layers of a set are
+/// inconsistent.
+///
+/// [`BitSet::validate`]: struct.BitSet.html#method.validate
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InvalidHierarchy {
+    mismatches: Vec<LayerMismatch>,
+    len: Option<(usize, usize)>,
+}
+
+impl InvalidHierarchy {
+    pub(crate) fn new(mismatches: Vec<LayerMismatch>, len: Option<(usize, usize)>) -> Self {
+        InvalidHierarchy { mismatches, len }
+    }
+
+    /// The summary words that don't match their children, from layer 3
+    /// down and in ascending order within a layer.
+    pub fn mismatches(&self) -> &[LayerMismatch] {
+        &self.mismatches
+    }
+
+    /// The stored count of indices and the number of bits set in layer 0,
+    /// if they differ.
+    pub fn len_mismatch(&self) -> Option<(usize, usize)> {
+        self.len
+    }
+}
+
+impl Display for InvalidHierarchy {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(
+            f,
+            "{} summary words are inconsistent",
+            self.mismatches.len()
+        )?;
+        if let Some(first) = self.mismatches.first() {
+            write!(f, ", the first: {}", first)?;
+        }
+        if let Some((stored, counted)) = self.len {
+            write!(f, "; the length is {} but {} bits are set", stored, counted)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for InvalidHierarchy {}
@@ -0,0 +1,290 @@
+use std::cmp::{self, Reverse};
+use std::fmt;
+use std::ops::{BitAnd, BitOr, Not};
+use std::sync::Arc;
+
+use util::*;
+use BitSetLike;
+
+/// A set algebra expression that is built at runtime, for when the shape
+/// of a query isn't known at compile time.
+///
+/// The leaves are shared sets of any kind, and the expression itself is a
+/// [`BitSetLike`] that evaluates the tree word by word, like the
+/// combinator types do. [`optimize`] rewrites the tree into a form that is
+/// cheaper to evaluate.
+///
+/// # Examples
+///
+/// ```
+/// # use hibitset::{BitSet, BitSetLike, Expr};
+/// let a: BitSet = (0..100).collect();
+/// let b: BitSet = (50..60).collect();
+/// let c: BitSet = (55..57).collect();
+///
+/// let expr = (Expr::leaf(a) & (Expr::leaf(b) & !Expr::leaf(c))).optimize();
+/// assert_eq!(expr.iter().collect::<Vec<_>>(), [50, 51, 52, 53, 54, 57, 58, 59]);
+/// ```
+///
+/// [`BitSetLike`]: trait.BitSetLike.html
+/// [`optimize`]: #method.optimize
+#[derive(Clone)]
+pub enum Expr {
+    /// A set.
+    Leaf(Arc<dyn BitSetLike + Send + Sync>),
+    /// The intersection of the expressions, every index when empty.
+    And(Vec<Expr>),
+    /// The union of the expressions, no index when empty.
+    Or(Vec<Expr>),
+    /// The complement of the expression.
+    Not(Box<Expr>),
+}
+
+impl Expr {
+    /// Creates a leaf holding `set`.
+    pub fn leaf<T>(set: T) -> Expr
+    where
+        T: BitSetLike + Send + Sync + 'static,
+    {
+        Expr::Leaf(Arc::new(set))
+    }
+
+    /// Creates the intersection of `exprs`.
+    pub fn and<I: IntoIterator<Item = Expr>>(exprs: I) -> Expr {
+        Expr::And(exprs.into_iter().collect())
+    }
+
+    /// Creates the union of `exprs`.
+    pub fn or<I: IntoIterator<Item = Expr>>(exprs: I) -> Expr {
+        Expr::Or(exprs.into_iter().collect())
+    }
+
+    /// Rewrites the expression into an equivalent one that is cheaper to
+    /// evaluate.
+    ///
+    /// Nested intersections and unions are flattened, double negations
+    /// and single-operand nodes are removed, and the operands are ordered
+    /// by their [`len_upper_bound`]: smallest first in an intersection and
+    /// largest first in a union, so the evaluation of a word can stop as
+    /// early as possible.
+    ///
+    /// [`len_upper_bound`]: trait.BitSetLike.html#method.len_upper_bound
+    pub fn optimize(self) -> Expr {
+        match self {
+            Expr::Leaf(set) => Expr::Leaf(set),
+            Expr::Not(expr) => match expr.optimize() {
+                Expr::Not(expr) => *expr,
+                expr => Expr::Not(Box::new(expr)),
+            },
+            Expr::And(exprs) => {
+                let mut exprs = flatten(exprs, |expr| match expr {
+                    Expr::And(exprs) => Ok(exprs),
+                    expr => Err(expr),
+                });
+                exprs.sort_by_cached_key(|expr| expr.len_upper_bound());
+                single_or(exprs, Expr::And)
+            }
+            Expr::Or(exprs) => {
+                let mut exprs = flatten(exprs, |expr| match expr {
+                    Expr::Or(exprs) => Ok(exprs),
+                    expr => Err(expr),
+                });
+                exprs.sort_by_cached_key(|expr| Reverse(expr.len_upper_bound()));
+                single_or(exprs, Expr::Or)
+            }
+        }
+    }
+
+    /// Evaluates the word at `idx` of `level`.
+    fn word(&self, level: usize, idx: usize) -> usize {
+        match self {
+            Expr::Leaf(set) => set.get_from_layer(level, idx),
+            Expr::And(exprs) => {
+                let mut word = !0;
+                for expr in exprs {
+                    word &= expr.word(level, idx);
+                    if word == 0 {
+                        break;
+                    }
+                }
+                word
+            }
+            Expr::Or(exprs) => {
+                let mut word = 0;
+                for expr in exprs {
+                    word |= expr.word(level, idx);
+                    if word == !0 {
+                        break;
+                    }
+                }
+                word
+            }
+            Expr::Not(expr) if level == 0 => !expr.word(0, idx),
+            Expr::Not(_) => !0,
+        }
+    }
+}
+
+/// Optimizes `exprs`, splicing in the operands of the ones `split` returns
+/// them for.
+fn flatten<F>(exprs: Vec<Expr>, split: F) -> Vec<Expr>
+where
+    F: Fn(Expr) -> Result<Vec<Expr>, Expr>,
+{
+    let mut flat = Vec::with_capacity(exprs.len());
+    for expr in exprs {
+        match split(expr.optimize()) {
+            Ok(exprs) => flat.extend(exprs),
+            Err(expr) => flat.push(expr),
+        }
+    }
+    flat
+}
+
+/// Returns the only expression of `exprs`, or `node` of all of them.
+fn single_or(mut exprs: Vec<Expr>, node: fn(Vec<Expr>) -> Expr) -> Expr {
+    if exprs.len() == 1 {
+        exprs.pop().unwrap()
+    } else {
+        node(exprs)
+    }
+}
+
+impl BitSetLike for Expr {
+    #[inline]
+    fn layer3(&self) -> usize {
+        self.word(3, 0)
+    }
+
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        self.word(2, i)
+    }
+
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        self.word(1, i)
+    }
+
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        self.word(0, i)
+    }
+
+    fn contains(&self, i: Index) -> bool {
+        match self {
+            Expr::Leaf(set) => set.contains(i),
+            Expr::And(exprs) => exprs.iter().all(|expr| expr.contains(i)),
+            Expr::Or(exprs) => exprs.iter().any(|expr| expr.contains(i)),
+            Expr::Not(expr) => !expr.contains(i),
+        }
+    }
+
+    fn len_upper_bound(&self) -> usize {
+        match self {
+            Expr::Leaf(set) => set.len_upper_bound(),
+            Expr::And(exprs) => exprs
+                .iter()
+                .map(|expr| expr.len_upper_bound())
+                .fold(MAX_EID, cmp::min),
+            Expr::Or(exprs) => {
+                let sum = exprs.iter().map(|expr| expr.len_upper_bound()).sum();
+                cmp::min(sum, MAX_EID)
+            }
+            Expr::Not(_) => MAX_EID,
+        }
+    }
+}
+
+impl BitAnd for Expr {
+    type Output = Expr;
+
+    fn bitand(self, rhs: Expr) -> Expr {
+        Expr::And(vec![self, rhs])
+    }
+}
+
+impl BitOr for Expr {
+    type Output = Expr;
+
+    fn bitor(self, rhs: Expr) -> Expr {
+        Expr::Or(vec![self, rhs])
+    }
+}
+
+impl Not for Expr {
+    type Output = Expr;
+
+    fn not(self) -> Expr {
+        Expr::Not(Box::new(self))
+    }
+}
+
+// Written out as the leaves aren't `Debug`.
+impl fmt::Debug for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Leaf(set) => f
+                .debug_tuple("Leaf")
+                .field(&format_args!("{} at most", set.len_upper_bound()))
+                .finish(),
+            Expr::And(exprs) => f.debug_tuple("And").field(exprs).finish(),
+            Expr::Or(exprs) => f.debug_tuple("Or").field(exprs).finish(),
+            Expr::Not(expr) => f.debug_tuple("Not").field(expr).finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Expr;
+    use {BitSet, BitSetAnd, BitSetLike, BitSetNot, BitSetOr};
+
+    #[test]
+    fn evaluate_and_optimize() {
+        let a: BitSet = (0..100_000).filter(|i| i % 2 == 0).collect();
+        let b: BitSet = (0..100_000).filter(|i| i % 3 == 0).collect();
+        let c: BitSet = (0..300_000).filter(|i| i % 1_000 < 10).collect();
+        let d: BitSet = (5..50).collect();
+
+        let expected = BitSetOr(
+            BitSetAnd(BitSetAnd(&a, &b), BitSetNot(&c)),
+            BitSetNot(BitSetNot(&d)),
+        )
+        .iter()
+        .collect::<Vec<_>>();
+
+        let expr = (Expr::leaf(a.clone()) & (Expr::leaf(b.clone()) & !Expr::leaf(c.clone())))
+            | !!Expr::leaf(d.clone());
+        assert_eq!((&expr).iter().collect::<Vec<_>>(), expected);
+        assert!(expr.contains(6) && expr.contains(7) && !expr.contains(1_000));
+
+        let optimized = expr.clone().optimize();
+        assert_eq!((&optimized).iter().collect::<Vec<_>>(), expected);
+        match &optimized {
+            Expr::Or(exprs) => {
+                assert_eq!(exprs.len(), 2);
+                match &exprs[0] {
+                    Expr::And(exprs) => {
+                        assert_eq!(exprs.len(), 3);
+                        assert_eq!(exprs[0].len_upper_bound(), b.len());
+                        assert!(matches!(exprs[2], Expr::Not(_)));
+                    }
+                    other => panic!("{:?}", other),
+                }
+                assert!(matches!(exprs[1], Expr::Leaf(_)));
+            }
+            other => panic!("{:?}", other),
+        }
+
+        assert_eq!(Expr::or(vec![]).iter().next(), None);
+        assert_eq!(
+            (Expr::and(vec![]) & Expr::leaf(d.clone()))
+                .optimize()
+                .iter()
+                .collect::<BitSet>(),
+            d
+        );
+        assert!(format!("{:?}", optimized).starts_with("Or([And([Leaf("));
+    }
+}
@@ -0,0 +1,227 @@
+use util::*;
+use {BitSetLike, DrainableBitSet};
+
+/// A `BitSet` with its layers stored inline, so it never allocates.
+///
+/// `N` is the number of layer 0 words, which makes the set hold indices
+/// below `N * usize_bits` (see [`CAPACITY`]). As all the storage has a
+/// size known at compile time, a `StaticBitSet` can live on the stack or
+/// in a `static`, which suits realtime code that must not allocate.
+///
+/// Layer 1 is always stored in full for `usize_bits` layer 0 words, so
+/// `N` may be at most `usize_bits.pow(2)`, giving a maximum capacity of
+/// `262,144` indices on 64-bit targets. Larger `N` fail to compile:
+///
+/// ```compile_fail
+/// # use hibitset::StaticBitSet;
+/// let set = StaticBitSet::<{ 1 << 20 }>::new();
+/// ```
+///
+/// # Examples
+///
+/// ```
+/// # use hibitset::{BitSetLike, StaticBitSet};
+/// let mut set = StaticBitSet::<4>::new();
+/// assert_eq!(StaticBitSet::<4>::CAPACITY, 4 * std::mem::size_of::<usize>() * 8);
+/// set.add(3);
+/// set.add(100);
+/// assert_eq!((&set).iter().collect::<Vec<_>>(), [3, 100]);
+/// ```
+///
+/// [`CAPACITY`]: #associatedconstant.CAPACITY
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StaticBitSet<const N: usize> {
+    layer2: usize,
+    layer1: [usize; 1 << BITS],
+    layer0: [usize; N],
+}
+
+impl<const N: usize> StaticBitSet<N> {
+    /// The number of indices the set can hold, all indices have to be
+    /// less than this.
+    pub const CAPACITY: usize = N << BITS;
+
+    const VALID: () = assert!(
+        N <= 1 << (2 * BITS),
+        "StaticBitSet can have at most usize_bits.pow(2) layer 0 words"
+    );
+
+    /// Creates an empty `StaticBitSet`.
+    pub const fn new() -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::VALID;
+        StaticBitSet {
+            layer2: 0,
+            layer1: [0; 1 << BITS],
+            layer0: [0; N],
+        }
+    }
+
+    /// Adds `id` to the `StaticBitSet`. Returns `true` if the value was
+    /// already in the set.
+    ///
+    /// Panics if `id` is not less than `CAPACITY`.
+    #[inline]
+    pub fn add(&mut self, id: Index) -> bool {
+        if id as usize >= Self::CAPACITY {
+            panic!(
+                "Expected index to be less then {}, found {}",
+                Self::CAPACITY,
+                id
+            );
+        }
+        let (p0, p1, _) = offsets(id);
+        let old = self.layer0[p0];
+        if old & id.mask(SHIFT0) != 0 {
+            return true;
+        }
+
+        self.layer0[p0] |= id.mask(SHIFT0);
+        if old == 0 {
+            self.layer1[p1] |= id.mask(SHIFT1);
+            self.layer2 |= id.mask(SHIFT2);
+        }
+        false
+    }
+
+    /// Removes `id` from the set, returns `true` if the value
+    /// was removed, and `false` if the value was not set
+    /// to begin with.
+    #[inline]
+    pub fn remove(&mut self, id: Index) -> bool {
+        let (p0, p1, _) = offsets(id);
+        if !self.contains(id) {
+            return false;
+        }
+
+        self.layer0[p0] &= !id.mask(SHIFT0);
+        if self.layer0[p0] != 0 {
+            return true;
+        }
+
+        self.layer1[p1] &= !id.mask(SHIFT1);
+        if self.layer1[p1] != 0 {
+            return true;
+        }
+
+        self.layer2 &= !id.mask(SHIFT2);
+        true
+    }
+
+    /// Returns `true` if `id` is in the set.
+    #[inline]
+    pub fn contains(&self, id: Index) -> bool {
+        self.layer0(id.offset(SHIFT1)) & id.mask(SHIFT0) != 0
+    }
+
+    /// Completely wipes out the bit set.
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl<const N: usize> Default for StaticBitSet<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> BitSetLike for StaticBitSet<N> {
+    #[inline]
+    fn layer3(&self) -> usize {
+        (self.layer2 != 0) as usize
+    }
+
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        if i == 0 {
+            self.layer2
+        } else {
+            0
+        }
+    }
+
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        self.layer1.get(i).cloned().unwrap_or(0)
+    }
+
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        self.layer0.get(i).cloned().unwrap_or(0)
+    }
+
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        self.contains(i)
+    }
+}
+
+impl<const N: usize> DrainableBitSet for StaticBitSet<N> {
+    #[inline]
+    fn remove(&mut self, i: Index) -> bool {
+        self.remove(i)
+    }
+}
+
+#[cfg(test)]
+mod static_set_test {
+    use {BitSet, BitSetLike, DrainableBitSet, StaticBitSet};
+
+    static EMPTY: StaticBitSet<2> = StaticBitSet::new();
+    const EMPTY_32: StaticBitSet<32> = StaticBitSet::new();
+
+    #[test]
+    fn insert_remove() {
+        let mut c = StaticBitSet::<32>::new();
+        for i in 0..1_000 {
+            assert!(!c.add(i));
+            assert!(c.add(i));
+        }
+        for i in 0..1_000 {
+            assert!(c.contains(i));
+        }
+        assert!(!c.contains(1_000));
+        assert!(!c.contains(1_000_000));
+
+        for i in 0..1_000 {
+            assert!(c.remove(i));
+            assert!(!c.remove(i));
+        }
+        assert!(c.is_empty());
+        assert_eq!(c, EMPTY_32);
+    }
+
+    #[test]
+    fn matches_bitset() {
+        let mut c = StaticBitSet::<{ 1 << (2 * ::BITS) }>::new();
+        let mut set = BitSet::new();
+        for i in 0..5_000 {
+            let id = (i * 7_919) % StaticBitSet::<{ 1 << (2 * ::BITS) }>::CAPACITY as u32;
+            c.add(id);
+            set.add(id);
+        }
+        assert_eq!(
+            c.iter().collect::<Vec<_>>(),
+            (&set).iter().collect::<Vec<_>>()
+        );
+        assert!(c.is_subset(&set) && c.is_superset(&set));
+    }
+
+    #[test]
+    fn drain() {
+        let mut c = StaticBitSet::<4>::default();
+        c.add(1);
+        c.add(255);
+        assert_eq!(c.drain().collect::<Vec<_>>(), [1, 255]);
+        assert!(c.is_empty());
+        assert!(EMPTY.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn out_of_range() {
+        let mut c = StaticBitSet::<1>::new();
+        c.add(1 << ::BITS);
+    }
+}
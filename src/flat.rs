@@ -0,0 +1,147 @@
+use util::*;
+use BitSetLike;
+
+/// `FlatLayer0` wraps a borrowed slice of words in the layout of a
+/// `BitSet`'s layer 0, so it can be combined with other [`BitSetLike`]s
+/// without copying it.
+///
+/// Bit `n % usize_bits` of word `n / usize_bits` stands for index `n`.
+/// The summary layers above it are computed once on construction, and
+/// take up roughly 1/64th of the space of the words themselves.
+///
+/// # Example
+///
+/// ```
+/// use hibitset::{BitSet, BitSetAnd, BitSetLike, FlatLayer0};
+///
+/// let words = [0b1010, 0, 0b1];
+/// let flat = FlatLayer0::new(&words);
+///
+/// let mut set = BitSet::new();
+/// set.add(3);
+/// set.add(4);
+///
+/// assert_eq!(BitSetAnd(&flat, &set).iter().collect::<Vec<_>>(), [3]);
+/// ```
+///
+/// [`BitSetLike`]: ../trait.BitSetLike.html
+#[derive(Debug, Clone)]
+pub struct FlatLayer0<'a> {
+    layer3: usize,
+    layer2: Vec<usize>,
+    layer1: Vec<usize>,
+    layer0: &'a [usize],
+}
+
+impl<'a> FlatLayer0<'a> {
+    /// Wraps `words`, computing the summary layers above them.
+    ///
+    /// Panics if `words` holds more bits than a `BitSet` can index.
+    pub fn new(words: &'a [usize]) -> FlatLayer0<'a> {
+        assert!(
+            words.len() <= MAX_EID >> BITS,
+            "Expected at most {} words, found {}",
+            MAX_EID >> BITS,
+            words.len()
+        );
+        let summarize = |lower: &[usize]| {
+            lower
+                .chunks(1 << BITS)
+                .map(|chunk| {
+                    chunk
+                        .iter()
+                        .enumerate()
+                        .filter(|&(_, &word)| word != 0)
+                        .fold(0, |mask, (i, _)| mask | 1 << i)
+                })
+                .collect::<Vec<usize>>()
+        };
+        let layer1 = summarize(words);
+        let layer2 = summarize(&layer1);
+        let layer3 = summarize(&layer2).first().cloned().unwrap_or(0);
+        FlatLayer0 {
+            layer3,
+            layer2,
+            layer1,
+            layer0: words,
+        }
+    }
+
+    /// Returns the wrapped words.
+    pub fn words(&self) -> &'a [usize] {
+        self.layer0
+    }
+}
+
+impl<'a> From<&'a [usize]> for FlatLayer0<'a> {
+    fn from(words: &'a [usize]) -> Self {
+        FlatLayer0::new(words)
+    }
+}
+
+impl<'a> BitSetLike for FlatLayer0<'a> {
+    #[inline]
+    fn layer3(&self) -> usize {
+        self.layer3
+    }
+
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        self.layer2.get(i).cloned().unwrap_or(0)
+    }
+
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        self.layer1.get(i).cloned().unwrap_or(0)
+    }
+
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        self.layer0.get(i).cloned().unwrap_or(0)
+    }
+
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        self.layer0(i.offset(SHIFT1)) & i.mask(SHIFT0) != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {BitSet, BitSetAnd, BitSetLike, FlatLayer0};
+
+    #[test]
+    fn matches_bitset() {
+        let set: BitSet = (0..1_000_000).filter(|i| i % 997 == 3).collect();
+        let words = set.layer0_as_slice();
+        let flat = FlatLayer0::new(&words);
+        assert_eq!(
+            (&flat).iter().collect::<Vec<_>>(),
+            (&set).iter().collect::<Vec<_>>()
+        );
+        assert!(flat.contains(3));
+        assert!(!flat.contains(4));
+        assert!(!flat.contains(!0));
+        assert_eq!(flat.layer3(), set.layer3());
+    }
+
+    #[test]
+    fn and() {
+        let words = vec![!0; 100];
+        let flat = FlatLayer0::from(&words[..]);
+        let set: BitSet = (0..100_000).filter(|i| i % 2 == 0).collect();
+        assert_eq!(
+            BitSetAnd(&flat, &set).iter().collect::<Vec<_>>(),
+            (0..100 << ::BITS)
+                .filter(|i| i % 2 == 0)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn empty() {
+        let flat = FlatLayer0::new(&[0, 0, 0]);
+        assert!(flat.is_empty());
+        assert_eq!(flat.iter().next(), None);
+    }
+}
@@ -0,0 +1,213 @@
+use util::*;
+use {BitIter, BitSet, BitSetLike, DrainableBitSet};
+
+/// The generation of an index in a [`GenerationalBitSet`].
+///
+/// [`GenerationalBitSet`]: struct.GenerationalBitSet.html
+pub type Generation = u32;
+
+/// A [`BitSet`] which also counts the generation of each index.
+///
+/// The generation of an index is bumped every time it is added to the set
+/// while not already being in it, and is kept when it is removed. Pairing
+/// an index with the generation it was added with makes a handle which
+/// stops being [`contains`]ed once the index is removed, even if it is
+/// added again later. This is the liveness tracking an ECS does for its
+/// entities.
+///
+/// Indices that were never added are at generation 0, which is never
+/// alive.
+///
+/// # Examples
+///
+/// ```
+/// # use hibitset::GenerationalBitSet;
+/// let mut set = GenerationalBitSet::new();
+/// let first = set.add(3);
+/// assert!(set.contains(3, first));
+///
+/// set.remove(3);
+/// let second = set.add(3);
+/// assert!(!set.contains(3, first));
+/// assert!(set.contains(3, second));
+/// assert_eq!(set.iter_generations().collect::<Vec<_>>(), [(3, second)]);
+/// ```
+///
+/// [`BitSet`]: struct.BitSet.html
+/// [`contains`]: #method.contains
+#[derive(Clone, Debug, Default)]
+pub struct GenerationalBitSet {
+    set: BitSet,
+    generations: Vec<Generation>,
+}
+
+impl GenerationalBitSet {
+    /// Creates an empty `GenerationalBitSet`.
+    pub fn new() -> GenerationalBitSet {
+        Default::default()
+    }
+
+    /// Adds `id` to the set and returns its generation.
+    ///
+    /// If `id` was not in the set, its generation is bumped first. If it
+    /// already was, the set is left as is and the current generation is
+    /// returned.
+    pub fn add(&mut self, id: Index) -> Generation {
+        if self.set.add(id) {
+            return self.generations[id as usize];
+        }
+        if self.generations.len() <= id as usize {
+            self.generations.resize(id as usize + 1, 0);
+        }
+        let generation = &mut self.generations[id as usize];
+        *generation = generation.wrapping_add(1);
+        *generation
+    }
+
+    /// Removes `id` from the set, returns `true` if the value
+    /// was removed, and `false` if the value was not set
+    /// to begin with.
+    ///
+    /// The generation of `id` is kept, so it is bumped past every handle
+    /// to it once it is added again.
+    pub fn remove(&mut self, id: Index) -> bool {
+        self.set.remove(id)
+    }
+
+    /// Returns `true` if `id` is in the set at `generation`.
+    pub fn contains(&self, id: Index, generation: Generation) -> bool {
+        self.set.contains(id) && self.generations[id as usize] == generation
+    }
+
+    /// Returns the generation of `id` if it is in the set.
+    pub fn generation(&self, id: Index) -> Option<Generation> {
+        if self.set.contains(id) {
+            Some(self.generations[id as usize])
+        } else {
+            None
+        }
+    }
+
+    /// Removes all indices from the set, keeping their generations.
+    pub fn clear(&mut self) {
+        self.set.clear();
+    }
+
+    /// Returns the underlying `BitSet` of the indices in the set.
+    pub fn as_bitset(&self) -> &BitSet {
+        &self.set
+    }
+
+    /// Create an iterator over the indices in the set along with their
+    /// generations.
+    ///
+    /// The plain indices can be iterated with [`iter`] like for any
+    /// other set.
+    ///
+    /// [`iter`]: trait.BitSetLike.html#method.iter
+    pub fn iter_generations(&self) -> GenerationIter<'_> {
+        GenerationIter {
+            iter: (&self.set).iter(),
+            generations: &self.generations,
+        }
+    }
+}
+
+impl BitSetLike for GenerationalBitSet {
+    #[inline]
+    fn layer3(&self) -> usize {
+        self.set.layer3()
+    }
+
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        self.set.layer2(i)
+    }
+
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        self.set.layer1(i)
+    }
+
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        self.set.layer0(i)
+    }
+
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        self.set.contains(i)
+    }
+}
+
+impl DrainableBitSet for GenerationalBitSet {
+    #[inline]
+    fn remove(&mut self, i: Index) -> bool {
+        self.remove(i)
+    }
+}
+
+/// An `Iterator` over a [`GenerationalBitSet`], yielding each index along
+/// with its generation.
+///
+/// [`GenerationalBitSet`]: struct.GenerationalBitSet.html
+#[derive(Debug, Clone)]
+pub struct GenerationIter<'a> {
+    iter: BitIter<&'a BitSet>,
+    generations: &'a [Generation],
+}
+
+impl<'a> Iterator for GenerationIter<'a> {
+    type Item = (Index, Generation);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter
+            .next()
+            .map(|id| (id, self.generations[id as usize]))
+    }
+}
+
+#[cfg(test)]
+mod generational_set_test {
+    use {BitSet, BitSetAnd, BitSetLike, DrainableBitSet, GenerationalBitSet};
+
+    #[test]
+    fn generations() {
+        let mut c = GenerationalBitSet::new();
+        assert_eq!(c.generation(10), None);
+        assert!(!c.contains(10, 0));
+
+        assert_eq!(c.add(10), 1);
+        assert_eq!(c.add(10), 1);
+        assert!(c.remove(10));
+        assert!(!c.remove(10));
+        assert!(!c.contains(10, 1));
+        assert_eq!(c.add(10), 2);
+        assert_eq!(c.generation(10), Some(2));
+
+        c.clear();
+        assert_eq!(c.generation(10), None);
+        assert_eq!(c.add(10), 3);
+    }
+
+    #[test]
+    fn iter_and_join() {
+        let mut c = GenerationalBitSet::new();
+        for i in 0..1_000 {
+            c.add(i);
+        }
+        for i in (0..1_000).filter(|i| i % 2 == 0) {
+            c.remove(i);
+            c.add(i);
+        }
+        assert!(c
+            .iter_generations()
+            .all(|(id, gen)| gen == 1 + (id % 2 == 0) as u32));
+
+        let mask: BitSet = (500..600).collect();
+        assert_eq!(BitSetAnd(&c, &mask).iter().count(), 100);
+        assert_eq!(c.drain().count(), 1_000);
+        assert!(c.as_bitset().is_empty());
+        assert_eq!(c.generations.len(), 1_000);
+    }
+}
@@ -0,0 +1,235 @@
+use std::sync::Arc;
+
+use util::*;
+use BitSetLike;
+
+/// The layer 0 words below one layer 1 word, and that layer 1 word.
+#[derive(Debug, Clone)]
+struct Page {
+    layer1: usize,
+    layer0: [usize; 1 << BITS],
+}
+
+/// A persistent bit set, whose clones share their memory until they are
+/// modified.
+///
+/// The layer 0 words are kept in pages of `usize_bits` words, one per
+/// layer 1 word, which are reference counted. Cloning an `ImBitSet` only
+/// copies layers 2 and 3 and bumps a reference count, and modifying a
+/// clone copies the pages it changes, along with the list of pages the
+/// first time. This makes it cheap to keep many snapshots of a set that
+/// changes a little at a time, like the history of an undo stack.
+///
+/// # Examples
+///
+/// ```
+/// # use hibitset::{BitSetLike, ImBitSet};
+/// let mut set: ImBitSet = (0..100_000).collect();
+/// let snapshot = set.clone();
+/// set.remove(5);
+/// set.add(200_000);
+///
+/// assert!(snapshot.contains(5) && !snapshot.contains(200_000));
+/// assert!(!set.contains(5) && set.contains(200_000));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ImBitSet {
+    layer3: usize,
+    layer2: Vec<usize>,
+    pages: Arc<Vec<Option<Arc<Page>>>>,
+    len: usize,
+}
+
+impl ImBitSet {
+    /// Creates an empty `ImBitSet`.
+    pub fn new() -> ImBitSet {
+        Default::default()
+    }
+
+    /// Returns the number of indices in the set.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the set holds no index.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds `id` to the set, copying its page if it is shared. Returns
+    /// `true` if the value was already in the set, in which case nothing
+    /// is copied.
+    ///
+    /// Panics if `id` is beyond the maximum index.
+    pub fn add(&mut self, id: Index) -> bool {
+        assert!(
+            (id as usize) < MAX_EID,
+            "Expected index to be less then {}, found {}",
+            MAX_EID,
+            id
+        );
+        if self.contains(id) {
+            return true;
+        }
+        let (p0, p1, p2) = offsets(id);
+        let pages = Arc::make_mut(&mut self.pages);
+        if pages.len() <= p1 {
+            pages.resize(p1 + 1, None);
+        }
+        let page = pages[p1].get_or_insert_with(|| {
+            Arc::new(Page {
+                layer1: 0,
+                layer0: [0; 1 << BITS],
+            })
+        });
+        let page = Arc::make_mut(page);
+        page.layer0[p0 & ((1 << BITS) - 1)] |= id.mask(SHIFT0);
+        page.layer1 |= id.mask(SHIFT1);
+        if self.layer2.len() <= p2 {
+            self.layer2.resize(p2 + 1, 0);
+        }
+        self.layer2[p2] |= id.mask(SHIFT2);
+        self.layer3 |= id.mask(SHIFT3);
+        self.len += 1;
+        false
+    }
+
+    /// Removes `id` from the set, copying its page if it is shared.
+    /// Returns `true` if the value was in the set.
+    pub fn remove(&mut self, id: Index) -> bool {
+        if !self.contains(id) {
+            return false;
+        }
+        let (p0, p1, p2) = offsets(id);
+        let pages = Arc::make_mut(&mut self.pages);
+        let empty = {
+            let page = Arc::make_mut(pages[p1].as_mut().unwrap());
+            let word = &mut page.layer0[p0 & ((1 << BITS) - 1)];
+            *word &= !id.mask(SHIFT0);
+            if *word == 0 {
+                page.layer1 &= !id.mask(SHIFT1);
+            }
+            page.layer1 == 0
+        };
+        if empty {
+            pages[p1] = None;
+            self.layer2[p2] &= !id.mask(SHIFT2);
+            if self.layer2[p2] == 0 {
+                self.layer3 &= !id.mask(SHIFT3);
+            }
+        }
+        self.len -= 1;
+        true
+    }
+
+    /// Returns `true` if `id` is in the set.
+    #[inline]
+    pub fn contains(&self, id: Index) -> bool {
+        self.layer0(id.offset(SHIFT1)) & id.mask(SHIFT0) != 0
+    }
+
+    /// Removes every index from the set, without touching the pages other
+    /// clones share.
+    pub fn clear(&mut self) {
+        *self = ImBitSet::new();
+    }
+
+    /// Returns the number of pages this set and `other` share, i.e. the
+    /// pages neither has modified since one was cloned from the other.
+    pub fn shared_pages(&self, other: &ImBitSet) -> usize {
+        self.pages
+            .iter()
+            .zip(other.pages.iter())
+            .filter(|&(ours, theirs)| match (ours, theirs) {
+                (Some(ours), Some(theirs)) => Arc::ptr_eq(ours, theirs),
+                _ => false,
+            })
+            .count()
+    }
+
+    #[inline]
+    fn page(&self, p1: usize) -> Option<&Page> {
+        self.pages.get(p1).and_then(|page| page.as_deref())
+    }
+}
+
+impl BitSetLike for ImBitSet {
+    #[inline]
+    fn layer3(&self) -> usize {
+        self.layer3
+    }
+
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        self.layer2.get(i).cloned().unwrap_or(0)
+    }
+
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        self.page(i).map_or(0, |page| page.layer1)
+    }
+
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        self.page(i >> BITS)
+            .map_or(0, |page| page.layer0[i & ((1 << BITS) - 1)])
+    }
+
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        ImBitSet::contains(self, i)
+    }
+
+    #[inline]
+    fn exact_summaries(&self) -> bool {
+        true
+    }
+
+    #[inline]
+    fn len_upper_bound(&self) -> usize {
+        self.len
+    }
+}
+
+impl Extend<Index> for ImBitSet {
+    fn extend<T: IntoIterator<Item = Index>>(&mut self, iter: T) {
+        for id in iter {
+            self.add(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {BitSet, BitSetLike, ImBitSet};
+
+    #[test]
+    fn copy_on_write() {
+        let ids = (0..200_000).filter(|i| i % 3 == 1 || i % 5_000 < 10);
+        let mut set: ImBitSet = ids.clone().collect();
+        let bitset: BitSet = ids.collect();
+        assert_eq!(set.len(), bitset.len());
+        assert_eq!((&set).iter().collect::<BitSet>(), bitset);
+
+        let pages = set.pages.iter().filter(|page| page.is_some()).count();
+        let snapshot = set.clone();
+        assert_eq!(set.shared_pages(&snapshot), pages);
+        assert!(set.add(1));
+        assert!(set.remove(1));
+        assert!(!set.remove(1));
+        assert!(!set.add(190_011));
+        assert_eq!(set.shared_pages(&snapshot), pages - 2);
+        assert!(snapshot.contains(1) && !snapshot.contains(190_011));
+        assert_eq!((&snapshot).iter().collect::<BitSet>(), bitset);
+
+        // Emptying a page drops it and clears the summaries above it.
+        let mut sparse: ImBitSet = [5, 70_000].iter().collect();
+        let before = sparse.clone();
+        assert!(sparse.remove(70_000));
+        assert_eq!((&sparse).iter().collect::<Vec<_>>(), [5]);
+        assert_eq!(sparse.layer3(), 1);
+        assert!(sparse.remove(5));
+        assert!(sparse.is_empty() && BitSetLike::is_empty(&sparse));
+        assert_eq!((&before).iter().collect::<Vec<_>>(), [5, 70_000]);
+    }
+}
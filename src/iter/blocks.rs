@@ -0,0 +1,138 @@
+use std::iter::from_fn;
+
+use iter::BitMaskIter;
+use util::*;
+use BitSetLike;
+
+/// The indices of a set within one aligned block of `usize::BITS`
+/// indices, yielded by [`iter_blocks`].
+///
+/// Bit `i` of `mask` stands for index `base + i`, so a block maps onto
+/// the lanes of a SIMD kernel without any shuffling.
+///
+/// [`iter_blocks`]: ../trait.BitSetLike.html#method.iter_blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockRef {
+    /// The index that the lowest bit of `mask` stands for, a multiple of
+    /// `usize::BITS`.
+    pub base: Index,
+    /// The indices of the block, one bit each. Never zero for the blocks
+    /// of `iter_blocks`.
+    pub mask: usize,
+}
+
+impl BlockRef {
+    /// Iterates over the indices in the block, in ascending order.
+    pub fn indices(self) -> impl Iterator<Item = Index> {
+        let mut mask = self.mask;
+        from_fn(move || {
+            if mask == 0 {
+                return None;
+            }
+            let bit = mask.trailing_zeros();
+            mask &= mask - 1;
+            Some(self.base + bit)
+        })
+    }
+
+    /// Returns the indices in both blocks, as a mask over this block. The
+    /// mask is zero when the blocks cover different indices.
+    pub fn and_mask(self, other: BlockRef) -> usize {
+        if self.base == other.base {
+            self.mask & other.mask
+        } else {
+            0
+        }
+    }
+
+    /// Returns `true` if `id` is in the block.
+    pub fn contains(self, id: Index) -> bool {
+        id.wrapping_sub(self.base) < 1 << BITS && self.mask & id.mask(SHIFT0) != 0
+    }
+
+    /// Returns the number of indices in the block.
+    pub fn len(self) -> usize {
+        self.mask.count_ones() as usize
+    }
+
+    /// Returns `true` if the block has no indices.
+    pub fn is_empty(self) -> bool {
+        self.mask == 0
+    }
+
+    /// Returns `true` if every index of the block is in it, so a kernel
+    /// can run on all of its lanes unmasked.
+    pub fn is_full(self) -> bool {
+        self.mask == !0
+    }
+}
+
+/// An `Iterator` over the non-empty blocks of a [`BitSetLike`], created
+/// by [`iter_blocks`].
+///
+/// [`BitSetLike`]: ../trait.BitSetLike.html
+/// [`iter_blocks`]: ../trait.BitSetLike.html#method.iter_blocks
+#[derive(Debug, Clone)]
+pub struct BitBlockIter<T> {
+    masks: BitMaskIter<T>,
+}
+
+impl<T: BitSetLike> BitBlockIter<T> {
+    /// Creates a new `BitBlockIter`. You usually don't call this function
+    /// but just [`.iter_blocks()`] on a bit set.
+    ///
+    /// [`.iter_blocks()`]: ../trait.BitSetLike.html#method.iter_blocks
+    pub fn new(set: T) -> Self {
+        BitBlockIter {
+            masks: BitMaskIter::new(set),
+        }
+    }
+}
+
+impl<T: BitSetLike> Iterator for BitBlockIter<T> {
+    type Item = BlockRef;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.masks
+            .next()
+            .map(|(base, mask)| BlockRef { base, mask })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BlockRef;
+    use {BitSet, BitSetLike};
+
+    #[test]
+    fn blocks() {
+        let a: BitSet = (0..10_000).filter(|i| i % 3 == 0 || i / 640 == 2).collect();
+        let b: BitSet = (0..10_000).filter(|i| i % 5 == 0).collect();
+
+        let blocks: Vec<_> = (&a).iter_blocks().collect();
+        assert!(blocks
+            .iter()
+            .all(|block| block.base & 63 == 0 && !block.is_empty()));
+        let indices: Vec<_> = blocks.iter().flat_map(|block| block.indices()).collect();
+        assert_eq!(indices, (&a).iter().collect::<Vec<_>>());
+        assert_eq!(
+            blocks.iter().map(|block| block.len()).sum::<usize>(),
+            a.len()
+        );
+        assert_eq!(blocks.iter().filter(|block| block.is_full()).count(), 10);
+        assert!(blocks[0].contains(63) && !blocks[0].contains(64) && !blocks[1].contains(63));
+
+        let mut both = Vec::new();
+        for (x, y) in (&a).iter_blocks().zip((&b).iter_blocks()) {
+            assert_eq!(x.base, y.base);
+            let block = BlockRef {
+                base: x.base,
+                mask: x.and_mask(y),
+            };
+            both.extend(block.indices());
+        }
+        assert_eq!(both, (&a & &b).iter().collect::<Vec<_>>());
+        assert_eq!(blocks[0].and_mask(blocks[1]), 0);
+    }
+}
@@ -0,0 +1,127 @@
+use iter::BitIter;
+use util::*;
+use BitSetLike;
+
+/// A resumable `Iterator` over a [`BitSetLike`] structure.
+///
+/// The cursor keeps track of the index it will continue from, which can
+/// be stored away as a plain [`Index`] with [`position`] and restored
+/// later with [`advance_to`], even on a different cursor over a set that
+/// has been modified in the meantime.
+///
+/// # Example
+///
+/// ```
+/// use hibitset::{BitSet, BitSetLike};
+///
+/// let set: BitSet = (0..10).collect();
+/// let mut cursor = (&set).cursor();
+/// assert_eq!(cursor.by_ref().take(3).collect::<Vec<_>>(), [0, 1, 2]);
+/// let position = cursor.position();
+///
+/// let mut resumed = (&set).cursor();
+/// resumed.advance_to(position);
+/// assert_eq!(resumed.next(), Some(3));
+/// assert_eq!(resumed.next_after(7), Some(8));
+/// ```
+///
+/// [`BitSetLike`]: ../trait.BitSetLike.html
+/// [`Index`]: ../type.Index.html
+/// [`position`]: #method.position
+/// [`advance_to`]: #method.advance_to
+#[derive(Debug, Clone)]
+pub struct BitCursor<T> {
+    iter: BitIter<T>,
+    position: Index,
+}
+
+impl<T: BitSetLike> BitCursor<T> {
+    /// Creates a new `BitCursor` at the start of the set. You usually
+    /// don't call this function but just [`.cursor()`] on a bit set.
+    ///
+    /// [`.cursor()`]: ../trait.BitSetLike.html#method.cursor
+    pub fn new(set: T) -> Self {
+        BitCursor {
+            iter: BitIter::starting_at(set, 0),
+            position: 0,
+        }
+    }
+
+    /// Returns the index the cursor continues from. Every index below
+    /// it has already been returned or skipped.
+    pub fn position(&self) -> Index {
+        self.position
+    }
+
+    /// Moves the cursor so that the next index it returns is the first
+    /// one at or after `idx`.
+    ///
+    /// This seeks through the layers of the set instead of iterating,
+    /// so it is cheap regardless of the distance moved.
+    pub fn advance_to(&mut self, idx: Index) {
+        self.iter.seek(idx);
+        self.position = idx;
+    }
+
+    /// Returns the first index after `idx`, leaving the cursor behind it.
+    pub fn next_after(&mut self, idx: Index) -> Option<Index> {
+        self.advance_to(idx.saturating_add(1));
+        self.next()
+    }
+}
+
+impl<T> Iterator for BitCursor<T>
+where
+    T: BitSetLike,
+{
+    type Item = Index;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.iter.next() {
+            Some(next) => {
+                self.position = next + 1;
+                Some(next)
+            }
+            None => {
+                self.position = MAX_EID as Index;
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {BitSet, BitSetLike};
+
+    #[test]
+    fn resume() {
+        let set: BitSet = (0..100_000).filter(|i| i % 7 == 0).collect();
+        let mut cursor = (&set).cursor();
+        let mut collected = Vec::new();
+        loop {
+            // a fresh cursor per slice, only the position is carried over
+            let position = cursor.position();
+            cursor = (&set).cursor();
+            cursor.advance_to(position);
+            let chunk = cursor.by_ref().take(1_000).collect::<Vec<_>>();
+            if chunk.is_empty() {
+                break;
+            }
+            collected.extend(chunk);
+        }
+        assert_eq!(collected, (&set).iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn next_after() {
+        let set: BitSet = (0..100_000).filter(|i| i % 7 == 0).collect();
+        let mut cursor = (&set).cursor();
+        assert_eq!(cursor.next_after(0), Some(7));
+        assert_eq!(cursor.next_after(50_000), Some(50_001));
+        assert_eq!(cursor.next(), Some(50_008));
+        assert_eq!(cursor.next_after(10), Some(14));
+        assert_eq!(cursor.next_after(99_995), None);
+        assert_eq!(cursor.next_after(!0), None);
+    }
+}
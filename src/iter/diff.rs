@@ -0,0 +1,119 @@
+use iter::BitMaskIter;
+use ops::BitSetXor;
+use util::*;
+use BitSetLike;
+
+/// An index that is in only one of two sets, as yielded by
+/// [`BitSetLike::diff`].
+///
+/// [`BitSetLike::diff`]: ../trait.BitSetLike.html#method.diff
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Change {
+    /// The index is in the new set but not in the old one.
+    Added(Index),
+    /// The index is in the old set but not in the new one.
+    Removed(Index),
+}
+
+impl Change {
+    /// Returns the index that changed.
+    #[inline]
+    pub fn index(self) -> Index {
+        match self {
+            Change::Added(id) | Change::Removed(id) => id,
+        }
+    }
+}
+
+/// An `Iterator` over the indices that differ between two
+/// [`BitSetLike`] structures, in ascending order.
+///
+/// [`BitSetLike`]: ../trait.BitSetLike.html
+#[derive(Debug, Clone)]
+pub struct BitDiffIter<A: BitSetLike, B: BitSetLike> {
+    words: BitMaskIter<BitSetXor<A, B>>,
+    base: Index,
+    added: usize,
+    removed: usize,
+}
+
+impl<A: BitSetLike, B: BitSetLike> BitDiffIter<A, B> {
+    /// Creates a new `BitDiffIter` from the `new` and `old` sets. You
+    /// usually don't call this function but just [`.diff()`] on a bit set.
+    ///
+    /// [`.diff()`]: ../trait.BitSetLike.html#method.diff
+    pub fn new(new: A, old: B) -> Self {
+        BitDiffIter {
+            words: BitMaskIter::new(BitSetXor(new, old)),
+            base: 0,
+            added: 0,
+            removed: 0,
+        }
+    }
+}
+
+impl<A, B> Iterator for BitDiffIter<A, B>
+where
+    A: BitSetLike,
+    B: BitSetLike,
+{
+    type Item = Change;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.added | self.removed == 0 {
+            // Split each differing word on whether the new set has the bit.
+            let (base, word) = self.words.next()?;
+            let new = self.words.set().0.layer0(base as usize >> BITS);
+            self.base = base;
+            self.added = word & new;
+            self.removed = word & !new;
+        }
+
+        let added = self.added.trailing_zeros();
+        let removed = self.removed.trailing_zeros();
+        Some(if added < removed {
+            self.added &= self.added - 1;
+            Change::Added(self.base + added)
+        } else {
+            self.removed &= self.removed - 1;
+            Change::Removed(self.base + removed)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Change::{Added, Removed};
+    use {BitSet, BitSetLike};
+
+    #[test]
+    fn diff() {
+        let old: BitSet = [1, 2, 64, 4095, 4096, 1_000_000].iter().collect();
+        let new: BitSet = [2, 3, 63, 64, 4096, 999_999].iter().collect();
+
+        assert_eq!(
+            (&new).diff(&old).collect::<Vec<_>>(),
+            [
+                Removed(1),
+                Added(3),
+                Added(63),
+                Removed(4095),
+                Added(999_999),
+                Removed(1_000_000),
+            ]
+        );
+        assert_eq!(
+            (&new).iter_added(&old).collect::<Vec<_>>(),
+            [3, 63, 999_999]
+        );
+        assert_eq!(
+            (&new).iter_removed(&old).collect::<Vec<_>>(),
+            [1, 4095, 1_000_000]
+        );
+        assert_eq!((&new).diff(&new).next(), None);
+
+        let empty = BitSet::new();
+        assert!((&empty).diff(&old).all(|c| c == Removed(c.index())));
+        assert_eq!((&empty).diff(&old).count(), 6);
+    }
+}
@@ -1,4 +1,6 @@
-use iter::BitIter;
+use std::ops::Range;
+
+use iter::{BitIter, BitMaskIter};
 use util::*;
 use DrainableBitSet;
 
@@ -36,6 +38,132 @@ where
     }
 }
 
+/// An `Iterator` over a [`DrainableBitSet`] structure which removes and
+/// yields the indices matching a predicate.
+///
+/// Indices are only removed as they are yielded, so if the iterator is
+/// dropped early, the rest of the set is left untouched.
+///
+/// [`DrainableBitSet`]: ../trait.DrainableBitSet.html
+pub struct DrainFilterIter<'a, T: 'a, F> {
+    iter: BitIter<&'a mut T>,
+    pred: F,
+}
+
+impl<'a, T: DrainableBitSet, F> DrainFilterIter<'a, T, F>
+where
+    F: FnMut(Index) -> bool,
+{
+    /// Creates a new `DrainFilterIter`. You usually don't call this
+    /// function but just [`.drain_filter()`] on a bit set.
+    ///
+    /// [`.drain_filter()`]: ../trait.DrainableBitSet.html#method.drain_filter
+    pub fn new(set: &'a mut T, pred: F) -> Self {
+        let layer3 = set.layer3();
+        DrainFilterIter {
+            iter: BitIter::new(set, [0, 0, 0, layer3], [0; LAYERS - 1]),
+            pred,
+        }
+    }
+}
+
+impl<'a, T, F> Iterator for DrainFilterIter<'a, T, F>
+where
+    T: DrainableBitSet,
+    F: FnMut(Index) -> bool,
+{
+    type Item = Index;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(next) = self.iter.next() {
+            if (self.pred)(next) {
+                self.iter.set.remove(next);
+                return Some(next);
+            }
+        }
+        None
+    }
+}
+
+/// A draining `Iterator` over the runs of consecutive indices in a
+/// [`DrainableBitSet`] structure, yielding each run as a `Range`.
+///
+/// Each run is removed from the set a word at a time as it is yielded, so
+/// if the iterator is dropped early, the runs it didn't yield are left in
+/// the set.
+///
+/// [`DrainableBitSet`]: ../trait.DrainableBitSet.html
+pub struct DrainRangeIter<'a, T: 'a> {
+    words: BitMaskIter<&'a mut T>,
+    base: Index,
+    word: usize,
+}
+
+impl<'a, T: DrainableBitSet> DrainRangeIter<'a, T> {
+    /// Creates a new `DrainRangeIter`. You usually don't call this
+    /// function but just [`.drain_ranges()`] on a bit set.
+    ///
+    /// [`.drain_ranges()`]: ../trait.DrainableBitSet.html#method.drain_ranges
+    pub fn new(set: &'a mut T) -> Self {
+        DrainRangeIter {
+            words: BitMaskIter::new(set),
+            base: 0,
+            word: 0,
+        }
+    }
+
+    fn remove(&mut self, base: Index, mask: usize) {
+        self.words
+            .set_mut()
+            .remove_word(base as usize >> BITS, mask);
+    }
+}
+
+impl<'a, T> Iterator for DrainRangeIter<'a, T>
+where
+    T: DrainableBitSet,
+{
+    type Item = Range<Index>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.word == 0 {
+            let (base, word) = self.words.next()?;
+            self.base = base;
+            self.word = word;
+        }
+
+        let (base, word) = (self.base, self.word);
+        let zeros = word.trailing_zeros();
+        let end = zeros + (word >> zeros).trailing_ones();
+        let start = base + zeros;
+        if end < (1 << BITS) {
+            self.word &= !0 << end;
+            self.remove(base, word & !self.word);
+            return Some(start..base + end);
+        }
+        self.remove(base, word);
+
+        // The run reaches the end of the word, so it goes on for as long
+        // as the following words are adjacent and start with set bits.
+        let mut end = base + end;
+        self.word = 0;
+        while let Some((base, word)) = self.words.next() {
+            let ones = if base == end { word.trailing_ones() } else { 0 };
+            end += ones;
+            if ones < (1 << BITS) {
+                self.base = base;
+                self.word = if ones == 0 { word } else { word & (!0 << ones) };
+                if ones > 0 {
+                    self.remove(base, word & !self.word);
+                }
+                break;
+            }
+            self.remove(base, word);
+        }
+        Some(start..end)
+    }
+}
+
 #[test]
 fn drain_all() {
     use {BitSet, BitSetLike};
@@ -43,3 +171,52 @@ fn drain_all() {
     bit_set.drain().for_each(|_| {});
     assert_eq!(0, bit_set.iter().count());
 }
+
+#[test]
+fn drain_filter() {
+    use {BitSet, BitSetLike};
+    let mut bit_set: BitSet = (0..10000).collect();
+    let drained = bit_set.drain_filter(|i| i % 3 == 0).collect::<Vec<_>>();
+    assert_eq!(
+        drained,
+        (0..10000).filter(|i| i % 3 == 0).collect::<Vec<_>>()
+    );
+    assert!((&bit_set).iter().eq((0..10000).filter(|i| i % 3 != 0)));
+
+    // Stopping early leaves the rest in place.
+    assert_eq!(
+        bit_set
+            .drain_filter(|i| i >= 5000)
+            .take(2)
+            .collect::<Vec<_>>(),
+        [5000, 5002]
+    );
+    assert!(bit_set.contains(5003));
+    assert_eq!(bit_set.drain_filter(|_| true).count(), 6664);
+    assert!(bit_set.is_empty());
+    assert_eq!(bit_set.layer3(), 0);
+}
+
+#[test]
+fn drain_ranges() {
+    use {BitSet, BitSetLike};
+    let runs = [0..3, 10..11, 60..200, 256..320, 320..321, 4_000..70_000];
+    let mut bit_set: BitSet = runs.iter().cloned().collect();
+    let expected = [0..3, 10..11, 60..200, 256..321, 4_000..70_000];
+    assert_eq!(bit_set.drain_ranges().collect::<Vec<_>>(), expected);
+    assert!(bit_set.is_empty());
+    assert_eq!(bit_set.layer3(), 0);
+
+    // Stopping early leaves the runs that weren't yielded.
+    let mut bit_set: BitSet = runs.iter().cloned().collect();
+    assert_eq!(bit_set.drain_ranges().nth(2), Some(60..200));
+    assert_eq!(
+        (&bit_set).iter_ranges().collect::<Vec<_>>(),
+        [256..321, 4_000..70_000]
+    );
+    assert_eq!(bit_set.len(), 65 + 66_000);
+
+    let mut boxed: Box<BitSet> = Box::new(expected.iter().cloned().collect());
+    assert_eq!(boxed.drain_ranges().count(), 5);
+    assert!(boxed.is_empty());
+}
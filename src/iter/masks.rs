@@ -0,0 +1,64 @@
+use iter::{BitIter, State};
+use util::*;
+use BitSetLike;
+
+/// An `Iterator` over the non-zero layer 0 words of a [`BitSetLike`]
+/// structure, yielding each word along with the index of its lowest bit.
+///
+/// [`BitSetLike`]: ../trait.BitSetLike.html
+#[derive(Debug, Clone)]
+pub struct BitMaskIter<T> {
+    iter: BitIter<T>,
+}
+
+impl<T: BitSetLike> BitMaskIter<T> {
+    /// Creates a new `BitMaskIter`. You usually don't call this function
+    /// but just [`.masks()`] on a bit set.
+    ///
+    /// [`.masks()`]: ../trait.BitSetLike.html#method.masks
+    pub fn new(set: T) -> Self {
+        BitMaskIter { iter: set.iter() }
+    }
+}
+
+impl<T> BitMaskIter<T> {
+    /// Walks the words left to visit by `iter`.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn from_iter(iter: BitIter<T>) -> Self {
+        BitMaskIter { iter }
+    }
+
+    /// Returns the set the words are read from.
+    pub(crate) fn set(&self) -> &T {
+        &self.iter.set
+    }
+
+    /// Returns the set the words are read from, mutably.
+    pub(crate) fn set_mut(&mut self) -> &mut T {
+        &mut self.iter.set
+    }
+}
+
+impl<T> Iterator for BitMaskIter<T>
+where
+    T: BitSetLike,
+{
+    type Item = (Index, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Only the upper layers are walked, stopping whenever a layer 0
+        // word has been loaded into the mask of the underlying iterator.
+        while let Some(level) =
+            (1..LAYERS).find(|&level| self.iter.handle_level(level) == State::Continue)
+        {
+            if level == 1 {
+                let word = self.iter.masks[0];
+                self.iter.masks[0] = 0;
+                if word != 0 {
+                    return Some((self.iter.prefix[0], word));
+                }
+            }
+        }
+        None
+    }
+}
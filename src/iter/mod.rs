@@ -1,14 +1,32 @@
+use std::cmp;
+
 use util::*;
 use {BitSet, BitSetLike};
 
-pub use self::drain::DrainBitIter;
+pub use self::blocks::{BitBlockIter, BlockRef};
+pub use self::cursor::BitCursor;
+pub use self::diff::{BitDiffIter, Change};
+pub use self::drain::{DrainBitIter, DrainFilterIter, DrainRangeIter};
+pub use self::masks::BitMaskIter;
+pub use self::range::BitRangeIter;
+pub use self::runs::BitRunIter;
+pub use self::unordered::BitUnorderedIter;
+pub use self::zip::BitZipMut;
 
 #[cfg(feature = "parallel")]
-pub use self::parallel::{BitParIter, BitProducer};
+pub use self::parallel::{BitParIter, BitParWordIter, BitProducer};
 
+mod blocks;
+mod cursor;
+mod diff;
 mod drain;
+mod masks;
 #[cfg(feature = "parallel")]
 mod parallel;
+mod range;
+mod runs;
+mod unordered;
+mod zip;
 
 /// An `Iterator` over a [`BitSetLike`] structure.
 ///
@@ -18,6 +36,10 @@ pub struct BitIter<T> {
     pub(crate) set: T,
     pub(crate) masks: [usize; LAYERS],
     pub(crate) prefix: [u32; LAYERS - 1],
+    /// The number of indices left, when it is known from the length the
+    /// set keeps. Kept up to date by `next` and `nth`, anything else that
+    /// moves the masks around resets it to `None`.
+    pub(crate) left: Option<usize>,
 }
 
 impl<T> BitIter<T> {
@@ -30,29 +52,63 @@ impl<T> BitIter<T> {
             set: set,
             masks: masks,
             prefix: prefix,
+            left: None,
         }
     }
 }
 
 impl<T: BitSetLike> BitIter<T> {
-    /// Allows checking if set bit is contained in underlying bit set.
-    pub fn contains(&self, i: Index) -> bool {
-        self.set.contains(i)
+    /// Creates a `BitIter` which skips every index below `start`.
+    pub(crate) fn starting_at(set: T, start: Index) -> Self {
+        let mut iter = BitIter::new(set, [0; LAYERS], [0; LAYERS - 1]);
+        iter.seek(start);
+        iter
     }
-}
 
-impl<'a> BitIter<&'a mut BitSet> {
-    /// Clears the rest of the bitset starting from the next inner layer.
-    pub(crate) fn clear(&mut self) {
-        use self::State::Continue;
-        while let Some(level) = (1..LAYERS).find(|&level| self.handle_level(level) == Continue) {
-            let lower = level - 1;
-            let idx = (self.prefix[lower] >> BITS) as usize;
-            *self.set.layer_mut(lower, idx) = 0;
-            if level == LAYERS - 1 {
-                self.set.layer3 &= !((2 << idx) - 1);
+    /// Moves the iterator so that the next index it returns is the first
+    /// one at or after `start`.
+    ///
+    /// Rather than iterating up to `start`, the masks of each layer
+    /// are seeded with the bits at or after it, so this is `O(LAYERS)`.
+    /// For sets backed by a `BitSet`, the indices left are then counted
+    /// once so that the size hint stays exact.
+    pub(crate) fn seek(&mut self, start: Index) {
+        self.masks = [0; LAYERS];
+        self.left = None;
+        if (start as usize) >= MAX_EID {
+            return;
+        }
+
+        // Bits strictly after the one `start` is in are left for later,
+        // the bit `start` is in is descended into right away.
+        let after = |shift| !0usize << start.row(shift) << 1;
+        let (p0, p1, p2) = offsets(start);
+        let layer3 = self.set.layer3();
+        self.masks[3] = layer3 & after(SHIFT3);
+        if layer3 & start.mask(SHIFT3) != 0 {
+            let layer2 = self.set.layer2(p2);
+            self.masks[2] = layer2 & after(SHIFT2);
+            if layer2 & start.mask(SHIFT2) != 0 {
+                let layer1 = self.set.layer1(p1);
+                self.masks[1] = layer1 & after(SHIFT1);
+                if layer1 & start.mask(SHIFT1) != 0 {
+                    self.masks[0] = self.set.layer0(p0) & (!0usize << start.row(SHIFT0));
+                }
             }
         }
+        self.prefix = [
+            (p0 << BITS) as u32,
+            (p1 << BITS) as u32,
+            (p2 << BITS) as u32,
+        ];
+        if self.set.backing_bitset().is_some() {
+            self.left = Some(self.masks[0].count_ones() as usize + self.remaining_below());
+        }
+    }
+
+    /// Allows checking if set bit is contained in underlying bit set.
+    pub fn contains(&self, i: Index) -> bool {
+        self.set.contains(i)
     }
 }
 
@@ -74,7 +130,12 @@ where
         'find: loop {
             for level in 0..LAYERS {
                 match self.handle_level(level) {
-                    Value(v) => return Some(v),
+                    Value(v) => {
+                        if let Some(left) = self.left.as_mut() {
+                            *left -= 1;
+                        }
+                        return Some(v);
+                    }
                     Continue => continue 'find,
                     Empty => {}
                 }
@@ -83,9 +144,199 @@ where
             return None;
         }
     }
+
+    /// Skips whole layer 0 words by their popcount, so this takes time in
+    /// the number of words skipped rather than indices.
+    ///
+    /// Skipping stops at words: the upper layers only tell which words
+    /// are occupied, not how many indices they hold, so every occupied
+    /// word of the layer 1 and 2 subtrees passed over is still visited.
+    /// Empty subtrees are passed over from their summary bits as usual.
+    fn nth(&mut self, mut n: usize) -> Option<Self::Item> {
+        loop {
+            let ones = self.masks[0].count_ones() as usize;
+            if n < ones {
+                for _ in 0..n {
+                    self.masks[0] &= self.masks[0] - 1;
+                }
+                if let Some(left) = self.left.as_mut() {
+                    *left -= n;
+                }
+                return self.next();
+            }
+            n -= ones;
+            self.masks[0] = 0;
+            if let Some(left) = self.left.as_mut() {
+                *left -= ones;
+            }
+            if !self.next_word() {
+                return None;
+            }
+        }
+    }
+
+    /// Descends from the highest bits left in the masks, rather than
+    /// iterating up to the end.
+    fn last(self) -> Option<Self::Item> {
+        (0..LAYERS).rev().find_map(|level| {
+            let prefix = self.prefix.get(level).cloned().unwrap_or(0);
+            last_in_word(&self.set, level, prefix, self.masks[level])
+        })
+    }
+
+    /// Exact for iterators created by [`iter`] on a set backed by a
+    /// `BitSet`, whose length is counted down as indices are returned.
+    ///
+    /// Otherwise each bit of the upper layers left to visit is bounded by
+    /// the size of its subtree, and the total by [`len_upper_bound`] of the
+    /// set. When the set has
+    /// [exact summaries](../trait.BitSetLike.html#method.exact_summaries),
+    /// each of those bits also covers at least one index. Nothing below the
+    /// masks is read, so this is `O(1)`.
+    ///
+    /// [`iter`]: ../trait.BitSetLike.html#method.iter
+    /// [`len_upper_bound`]: ../trait.BitSetLike.html#method.len_upper_bound
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if let Some(left) = self.left {
+            return (left, Some(left));
+        }
+        let current = self.masks[0].count_ones() as usize;
+        let lower = if self.set.exact_summaries() {
+            (1..LAYERS).fold(current, |lower, level| {
+                lower + self.masks[level].count_ones() as usize
+            })
+        } else {
+            current
+        };
+        let upper = (1..LAYERS).fold(current, |upper, level| {
+            upper.saturating_add((self.masks[level].count_ones() as usize) << (BITS * level))
+        });
+        let upper = cmp::min(upper, self.set.len_upper_bound());
+        (lower, Some(cmp::max(lower, upper)))
+    }
+
+    /// Walks the rest of the set with a loop per layer rather than going
+    /// through `next` for each index, see [`BitSetLike::for_each`].
+    ///
+    /// `try_fold` can't be overridden on stable Rust, so adapters that
+    /// stop early still go through `next`.
+    ///
+    /// [`BitSetLike::for_each`]: ../trait.BitSetLike.html#method.for_each
+    fn fold<B, F>(self, init: B, mut f: F) -> B
+    where
+        F: FnMut(B, Self::Item) -> B,
+    {
+        // The bits left in the masks of the lower layers come before the
+        // ones left in the layers above them.
+        let mut acc = init;
+        for level in 0..LAYERS {
+            let prefix = self.prefix.get(level).cloned().unwrap_or(0);
+            acc = fold_word(&self.set, level, prefix, self.masks[level], acc, &mut f);
+        }
+        acc
+    }
+}
+
+/// Finds the highest index below the set bits of `mask`, a word of `level`
+/// whose first bit is at `prefix`.
+fn last_in_word<T: BitSetLike>(
+    set: &T,
+    level: usize,
+    prefix: u32,
+    mut mask: usize,
+) -> Option<Index> {
+    while mask != 0 {
+        let bit = usize::BITS - 1 - mask.leading_zeros();
+        mask &= !(1 << bit);
+        let idx = prefix | bit;
+        if level == 0 {
+            return Some(idx);
+        }
+        // Summaries of combinators may have bits set over empty words.
+        let lower = set.get_from_layer(level - 1, idx as usize);
+        if let Some(last) = last_in_word(set, level - 1, idx << BITS, lower) {
+            return Some(last);
+        }
+    }
+    None
+}
+
+/// Folds the indices below the set bits of `mask`, a word of `level` whose
+/// first bit is at `prefix`, in ascending order.
+#[inline]
+fn fold_word<T, B, F>(
+    set: &T,
+    level: usize,
+    prefix: u32,
+    mut mask: usize,
+    mut acc: B,
+    f: &mut F,
+) -> B
+where
+    T: BitSetLike,
+    F: FnMut(B, Index) -> B,
+{
+    while mask != 0 {
+        let bit = mask.trailing_zeros();
+        mask &= mask - 1;
+        let idx = prefix | bit;
+        acc = if level == 0 {
+            f(acc, idx)
+        } else {
+            let lower = set.get_from_layer(level - 1, idx as usize);
+            fold_word(set, level - 1, idx << BITS, lower, acc, f)
+        };
+    }
+    acc
+}
+
+impl<T: BitSetLike> BitIter<T> {
+    /// Counts the indices in the subtrees left to visit in the masks of
+    /// layers 1 to 3.
+    fn remaining_below(&self) -> usize {
+        let mut count = 0;
+        for level in 1..LAYERS {
+            let prefix = self.prefix.get(level).cloned().unwrap_or(0) as usize;
+            let mut mask = self.masks[level];
+            while mask != 0 {
+                let bit = mask.trailing_zeros() as usize;
+                mask &= !(1 << bit);
+                count += ::count_at(
+                    &self.set,
+                    &self.set,
+                    level - 1,
+                    prefix | bit,
+                    |ours, _| ours,
+                    |ours, _| ours,
+                );
+            }
+        }
+        count
+    }
 }
 
+/// The size hint is exact for sets that store their layers.
+impl ExactSizeIterator for BitIter<BitSet> {}
+impl ExactSizeIterator for BitIter<&BitSet> {}
+
 impl<T: BitSetLike> BitIter<T> {
+    /// Moves on to the next non-empty mask of layer 1 and loads the layer
+    /// 0 word below it, with the current layer 0 mask empty. Returns
+    /// `false` if there is none left.
+    fn next_word(&mut self) -> bool {
+        use self::State::*;
+        'find: loop {
+            for level in 1..LAYERS {
+                match self.handle_level(level) {
+                    Continue if level == 1 => return true,
+                    Continue => continue 'find,
+                    _ => {}
+                }
+            }
+            return false;
+        }
+    }
+
     pub(crate) fn handle_level(&mut self, level: usize) -> State {
         use self::State::*;
         if self.masks[level] == 0 {
@@ -104,10 +355,31 @@ impl<T: BitSetLike> BitIter<T> {
                 // Take the corresponding `usize` from the layer below
                 self.masks[level - 1] = self.set.get_from_layer(level - 1, idx as usize);
                 self.prefix[level - 1] = idx << BITS;
+                #[cfg(feature = "prefetch")]
+                self.prefetch(level, idx);
                 Continue
             }
         }
     }
+
+    /// Prefetches the words the iterator reads next after descending
+    /// from `level` into the word at `idx`: the word of the next sibling
+    /// and the first word below the one descended into.
+    #[cfg(feature = "prefetch")]
+    #[inline]
+    fn prefetch(&self, level: usize, idx: u32) {
+        let siblings = self.masks[level];
+        if siblings != 0 {
+            let prefix = self.prefix.get(level).cloned().unwrap_or(0);
+            let next = prefix | siblings.trailing_zeros();
+            self.set.prefetch(level - 1, next as usize);
+        }
+        let children = self.masks[level - 1];
+        if level >= 2 && children != 0 {
+            let child = (idx << BITS) | children.trailing_zeros();
+            self.set.prefetch(level - 2, child as usize);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -115,26 +387,224 @@ mod tests {
     use {BitSet, BitSetLike};
 
     #[test]
-    fn iterator_clear_empties() {
-        use rand::prelude::*;
+    fn fold() {
+        let set: BitSet = (0..300_000)
+            .filter(|i| i % 7 == 0 || i % 4_096 < 70)
+            .collect();
+        let expected = (&set).iter().collect::<Vec<_>>();
+        let mut seen = Vec::new();
+        set.for_each(|i| seen.push(i));
+        assert_eq!(seen, expected);
+
+        // Resumes wherever `next` left off, in every layer.
+        for &skip in &[0, 1, 69, 70, 600, expected.len() - 1, expected.len()] {
+            let mut iter = (&set).iter();
+            for _ in 0..skip {
+                iter.next();
+            }
+            let rest = iter.fold(Vec::new(), |mut rest, i| {
+                rest.push(i);
+                rest
+            });
+            assert_eq!(rest, &expected[skip..]);
+        }
 
+        let other: BitSet = (0..300_000).step_by(3).collect();
+        let mut seen = Vec::new();
+        (&set & !&other).for_each(|i| seen.push(i));
+        assert_eq!(seen, (&set & !&other).iter().collect::<Vec<_>>());
+        BitSet::new().for_each(|_| panic!());
+    }
+
+    #[test]
+    fn nth_last() {
+        use BitSetNot;
+
+        let set: BitSet = (0..300_000)
+            .filter(|i| i % 7 == 0 || i % 4_096 < 70)
+            .collect();
+        let expected = (&set).iter().collect::<Vec<_>>();
+        for &n in &[0, 1, 69, 70, 1_000, 20_000, expected.len() - 1] {
+            assert_eq!((&set).iter().nth(n), Some(expected[n]), "nth {}", n);
+            let mut iter = (&set).iter();
+            assert_eq!(iter.nth(n), Some(expected[n]));
+            assert_eq!(iter.nth(1), expected.get(n + 2).cloned());
+            assert_eq!(iter.nth(5), expected.get(n + 8).cloned());
+            assert_eq!(
+                iter.last(),
+                expected.get(n + 9).map(|_| expected[expected.len() - 1])
+            );
+        }
+        assert_eq!((&set).iter().nth(expected.len()), None);
+        assert_eq!(BitSet::new().iter().nth(3), None);
+        assert_eq!(BitSet::new().iter().last(), None);
+
+        // Upper layers of a combinator with bits over empty words.
+        let other: BitSet = (0..300_000).filter(|i| i % 4_096 < 70).collect();
+        let diff = (&set & BitSetNot(&other)).iter().collect::<Vec<_>>();
+        assert_eq!(
+            (&set & BitSetNot(&other)).iter().last(),
+            diff.last().cloned()
+        );
+        assert_eq!(
+            (&set & BitSetNot(&other)).iter().nth(1_000),
+            Some(diff[1_000])
+        );
+        assert_eq!((&other & BitSetNot(&other)).iter().last(), None);
+    }
+
+    #[test]
+    fn iter_from() {
         let mut set = BitSet::new();
-        let mut rng = thread_rng();
-        let limit = 1_048_576;
-        for _ in 0..(limit / 10) {
-            set.add(rng.gen_range(0, limit));
+        for i in 0..1_000 {
+            set.add(i * 997);
         }
-        (&mut set).iter().clear();
-        assert_eq!(0, set.layer3);
-        for &i in &set.layer2 {
-            assert_eq!(0, i);
+        for &start in &[
+            0, 1, 996, 997, 998, 4_096, 262_143, 262_144, 996_003, 996_004,
+        ] {
+            let expected = (&set).iter().filter(|&i| i >= start).collect::<Vec<_>>();
+            assert_eq!((&set).iter_from(start).collect::<Vec<_>>(), expected);
         }
-        for &i in &set.layer1 {
-            assert_eq!(0, i);
+        assert_eq!((&set).iter_from(!0).next(), None);
+    }
+
+    #[test]
+    fn iter_range() {
+        let mut set = BitSet::new();
+        for i in 0..1_000 {
+            set.add(i * 997);
         }
-        for &i in &set.layer0 {
-            assert_eq!(0, i);
+        assert_eq!(
+            (&set).iter_range(997..2_991).collect::<Vec<_>>(),
+            [997, 1_994]
+        );
+        assert_eq!((&set).iter_range(998..1_994).next(), None);
+        assert_eq!((&set).iter_range(5..5).next(), None);
+        assert_eq!((&set).iter_range(0..!0).count(), 1_000);
+    }
+
+    #[test]
+    fn iter_ranges() {
+        let ranges = [0..1, 3..5, 63..65, 100..300, 4_000..4_096, 4_096..4_097];
+        let mut set = BitSet::new();
+        for range in &ranges {
+            set.extend(range.clone());
+        }
+        assert_eq!(
+            (&set).iter_ranges().collect::<Vec<_>>(),
+            [0..1, 3..5, 63..65, 100..300, 4_000..4_097]
+        );
+
+        let full: BitSet = (0..100_000).filter(|i| i % 1_000 != 999).collect();
+        let runs = (&full).iter_ranges().collect::<Vec<_>>();
+        assert_eq!(runs.len(), 100);
+        assert_eq!(runs[0], 0..999);
+        assert_eq!(runs[99], 99_000..99_999);
+        assert_eq!(BitSet::new().iter_ranges().next(), None);
+
+        let mut last = BitSet::new();
+        let max = ::MAX_EID as u32;
+        last.extend(max - 200..max);
+        let runs = last.iter_ranges().collect::<Vec<_>>();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0], max - 200..max);
+    }
+
+    #[test]
+    fn masks() {
+        use BitSetAnd;
+
+        let a: BitSet = (0..10_000).filter(|i| i % 2 == 0).collect();
+        let b: BitSet = (0..10_000).filter(|i| i % 3 == 0).collect();
+        let and = BitSetAnd(&a, &b);
+
+        let mut count = 0;
+        for (base, word) in (&and).masks() {
+            assert_eq!(base as usize & ((1 << ::BITS) - 1), 0);
+            assert_ne!(word, 0);
+            for bit in 0..(1 << ::BITS) {
+                let i = base + bit as u32;
+                assert_eq!(word & (1 << bit) != 0, and.contains(i));
+            }
+            count += word.count_ones();
+        }
+        assert_eq!(count as usize, and.iter().count());
+
+        let mut odd = BitSet::new();
+        odd.add(1);
+        let disjoint = BitSetAnd(&a, &odd);
+        assert_eq!(disjoint.masks().next(), None);
+    }
+
+    #[test]
+    fn exact_size() {
+        use AtomicBitSet;
+
+        let set: BitSet = (0..100_000).filter(|i| i % 7 == 0).collect();
+        let mut iter = (&set).iter();
+        let mut len = (&set).iter().count();
+        assert_eq!(iter.len(), len);
+        while iter.next().is_some() {
+            len -= 1;
+            if len % 997 == 1 {
+                assert_eq!(iter.len(), len);
+            }
+        }
+        assert_eq!(iter.len(), 0);
+        assert_eq!(BitSet::new().iter().len(), 0);
+
+        let total = set.len();
+        let mut iter = (&set).iter();
+        assert_eq!(iter.nth(1_000), Some(7_000));
+        assert_eq!(iter.len(), total - 1_001);
+        assert_eq!(iter.nth(5), Some(7_042));
+        assert_eq!(iter.len(), total - 1_007);
+        assert_eq!(iter.nth(total), None);
+        assert_eq!(iter.len(), 0);
+        assert_eq!((&set).iter_from(7_001).len(), total - 1_001);
+
+        // Other threads may add to an `AtomicBitSet` while iterating, so
+        // its size hint is only bounded by the masks left.
+        let atomic = AtomicBitSet::new();
+        for i in (0..100_000).filter(|i| i % 3 == 0) {
+            atomic.add_atomic(i);
         }
+        let mut iter = (&atomic).iter();
+        iter.nth(9_999);
+        let (lower, upper) = iter.size_hint();
+        assert!(lower <= 33_334 - 10_000);
+        assert!(upper.unwrap() >= 33_334 - 10_000);
+        iter.next();
+        let (lower, upper) = iter.size_hint();
+        assert!(lower <= 33_334 - 10_001);
+        assert!(upper.unwrap() >= 33_334 - 10_001);
+    }
+
+    #[test]
+    fn size_hint_bounds() {
+        use {BitSetAnd, BitSetNot};
+
+        let a: BitSet = (0..1_000).filter(|i| i % 2 == 0).collect();
+        let b: BitSet = (0..1_000).filter(|i| i % 2 == 1).collect();
+        let mut and = BitSetAnd(&a, &b).iter();
+        let (lower, upper) = and.size_hint();
+        assert_eq!(lower, 0);
+        assert_eq!(upper, Some(500));
+        assert_eq!(and.next(), None);
+        assert_eq!(and.size_hint(), (0, Some(0)));
+
+        // Bounded by the smallest set of an intersection and the sum of the
+        // sets of a union.
+        let small: BitSet = (0..10).collect();
+        let big: BitSet = (0..300_000).collect();
+        assert_eq!((&small & &big & !&a).iter().size_hint().1, Some(10));
+        assert_eq!((&small, &big, 5..8).iter().size_hint().1, Some(3));
+        assert_eq!((&small | &a).iter().size_hint().1, Some(510));
+        assert_eq!((&small | &big).len_upper_bound(), 300_010);
+
+        let (lower, upper) = BitSetNot(&a).iter().size_hint();
+        assert!(lower <= ::MAX_EID - 500);
+        assert_eq!(upper, Some(::MAX_EID));
     }
 
     #[test]
@@ -1,14 +1,17 @@
 use rayon::iter::plumbing::{bridge_unindexed, Folder, UnindexedConsumer, UnindexedProducer};
-use rayon::iter::ParallelIterator;
+use rayon::iter::{FromParallelIterator, IntoParallelIterator, ParallelIterator};
 
-use iter::{BitIter, BitSetLike, Index, BITS, LAYERS};
+use std::cmp;
+
+use iter::{BitIter, BitMaskIter, BitSetLike, Index, BITS, LAYERS};
 use util::average_ones;
+use {AtomicBitSet, BitSet};
 
 /// A `ParallelIterator` over a [`BitSetLike`] structure.
 ///
 /// [`BitSetLike`]: ../../trait.BitSetLike.html
 #[derive(Debug)]
-pub struct BitParIter<T>(T, u8);
+pub struct BitParIter<T>(T, u8, Option<usize>);
 
 impl<T> BitParIter<T> {
     /// Creates a new `BitParIter`. You usually don't call this function
@@ -18,7 +21,7 @@ impl<T> BitParIter<T> {
     ///
     /// [`.par_iter()`]: ../../trait.BitSetLike.html#method.par_iter
     pub fn new(set: T) -> Self {
-        BitParIter(set, 3)
+        BitParIter(set, 3, None)
     }
 
     /// Sets how many layers are split when forking.
@@ -52,6 +55,41 @@ impl<T> BitParIter<T> {
         self.1 = layers;
         self
     }
+
+    /// Splits on the population of the set rather than on the layout of
+    /// its layers.
+    ///
+    /// Every split looks at the popcounts of the words one layer down
+    /// and cuts the set bits of the highest non-trivial layer where the
+    /// populations on both sides are closest to equal. This keeps the
+    /// jobs balanced when the set bits are clustered in one region of
+    /// the keyspace. `layers_split` is ignored by this splitter.
+    pub fn adaptive(self) -> Self {
+        self.with_min_len(1)
+    }
+
+    /// Uses the [adaptive](#method.adaptive) splitter and stops splitting
+    /// jobs that are estimated to hold fewer than `bits` set bits.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rayon;
+    /// # extern crate hibitset;
+    /// # use hibitset::{BitSet, BitSetLike};
+    /// # use rayon::iter::ParallelIterator;
+    /// # fn main() {
+    /// let bitset: BitSet = (0..100_000).collect();
+    /// let count = bitset.par_iter()
+    ///     .with_min_len(4_096)
+    ///     .count();
+    /// assert_eq!(count, 100_000);
+    /// # }
+    /// ```
+    pub fn with_min_len(mut self, bits: usize) -> Self {
+        self.2 = Some(cmp::max(bits, 1));
+        self
+    }
 }
 
 impl<T> ParallelIterator for BitParIter<T>
@@ -64,7 +102,16 @@ where
     where
         C: UnindexedConsumer<Self::Item>,
     {
-        bridge_unindexed(BitProducer((&self.0).iter(), self.1), consumer)
+        match self.2 {
+            Some(min_len) => bridge_unindexed(
+                AdaptiveProducer {
+                    iter: (&self.0).iter(),
+                    min_len,
+                },
+                consumer,
+            ),
+            None => bridge_unindexed(BitProducer((&self.0).iter(), self.1), consumer),
+        }
     }
 }
 
@@ -103,21 +150,27 @@ where
     /// `BitIter` which internals are modified by this splitting
     ///  algorithm.
     ///
+    /// The summaries of combinators such as `BitSetAnd` can have bits set
+    /// over empty subtrees, so before a layer is split, the bits leading
+    /// only to empty layer 0 words of the composed set are cleared.
+    ///
     /// This splitting strategy should split work evenly if the set bits
     /// are distributed close to uniformly random.
     /// As the strategy only looks one layer at the time, if there are subtrees
     /// that have lots of work and sibling subtrees that have little of work,
     /// then it will produce non-optimal splittings.
     fn split(mut self) -> (Self, Option<Self>) {
+        self.0.left = None;
         let splits = self.1;
         let other = {
             let mut handle_level = |level: usize| {
+                // Top levels prefix is zero because there is nothing before it
+                let level_prefix = self.0.prefix.get(level).cloned().unwrap_or(0);
+                self.0.masks[level] = prune(self.0.set, level, level_prefix, self.0.masks[level]);
                 if self.0.masks[level] == 0 {
                     // Skip the empty layers
                     None
                 } else {
-                    // Top levels prefix is zero because there is nothing before it
-                    let level_prefix = self.0.prefix.get(level).cloned().unwrap_or(0);
                     let first_bit = self.0.masks[level].trailing_zeros();
                     average_ones(self.0.masks[level])
                         .and_then(|average_bit| {
@@ -167,6 +220,255 @@ where
     }
 }
 
+/// Clears the bits of `mask`, a word of `level` below `prefix`, that only
+/// lead to empty layer 0 words of `set`.
+///
+/// A set with exact summaries has no such bits. For the others, each bit
+/// is followed down until a non-empty word is found, so the cost is about
+/// that of iterating the empty subtrees, which are then never visited
+/// again.
+fn prune<T: BitSetLike>(set: &T, level: usize, prefix: u32, mask: usize) -> usize {
+    if set.exact_summaries() {
+        return mask;
+    }
+    let mut pruned = mask;
+    let mut m = mask;
+    while m != 0 {
+        let bit = m.trailing_zeros();
+        m &= !(1 << bit);
+        if !occupied(set, level - 1, (prefix | bit) as usize) {
+            pruned &= !(1 << bit);
+        }
+    }
+    pruned
+}
+
+/// Returns `true` if the word at `idx` of `level` leads to any index of
+/// `set`.
+fn occupied<T: BitSetLike>(set: &T, level: usize, idx: usize) -> bool {
+    let mut word = set.get_from_layer(level, idx);
+    if level == 0 || word == 0 {
+        return word != 0;
+    }
+    while word != 0 {
+        let bit = word.trailing_zeros() as usize;
+        word &= !(1 << bit);
+        if occupied(set, level - 1, (idx << BITS) | bit) {
+            return true;
+        }
+    }
+    false
+}
+
+/// A `ParallelIterator` over the non-zero layer 0 words of a
+/// [`BitSetLike`] structure, yielding `(word_index, word)` pairs.
+///
+/// [`BitSetLike`]: ../../trait.BitSetLike.html
+#[derive(Debug)]
+pub struct BitParWordIter<T>(T);
+
+impl<T> BitParWordIter<T> {
+    /// Creates a new `BitParWordIter`. You usually don't call this function
+    /// but just [`.par_words()`] on a bit set.
+    ///
+    /// [`.par_words()`]: ../../trait.BitSetLike.html#method.par_words
+    pub fn new(set: T) -> Self {
+        BitParWordIter(set)
+    }
+}
+
+impl<T> ParallelIterator for BitParWordIter<T>
+where
+    T: BitSetLike + Send + Sync,
+{
+    type Item = (usize, usize);
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        bridge_unindexed(WordProducer(BitProducer((&self.0).iter(), 2)), consumer)
+    }
+}
+
+/// Splits like a `BitProducer` over the top two layers, but folds
+/// whole layer 0 words instead of their bits.
+struct WordProducer<'a, T: 'a + Send + Sync>(BitProducer<'a, T>);
+
+impl<'a, T: 'a + Send + Sync> UnindexedProducer for WordProducer<'a, T>
+where
+    T: BitSetLike,
+{
+    type Item = (usize, usize);
+
+    fn split(self) -> (Self, Option<Self>) {
+        let (us, them) = self.0.split();
+        (WordProducer(us), them.map(WordProducer))
+    }
+
+    fn fold_with<F>(self, folder: F) -> F
+    where
+        F: Folder<Self::Item>,
+    {
+        let words = BitMaskIter::from_iter((self.0).0);
+        folder.consume_iter(words.map(|(base, word)| ((base >> BITS) as usize, word)))
+    }
+}
+
+/// Splits a `BitIter` into halves of about equal population.
+///
+/// Used by `BitParIter` once `adaptive` or `with_min_len` was called.
+struct AdaptiveProducer<'a, T: 'a> {
+    iter: BitIter<&'a T>,
+    min_len: usize,
+}
+
+impl<'a, T: 'a + BitSetLike> AdaptiveProducer<'a, T> {
+    /// Estimated number of set bits below `bit` of `level`.
+    ///
+    /// This is exact for layer 1 and an upper bound for the layers above,
+    /// where each set bit of the word one layer down is counted as full.
+    fn weight(&self, level: usize, bit: u32) -> usize {
+        let idx = self.iter.prefix.get(level).cloned().unwrap_or(0) | bit;
+        let ones = self
+            .iter
+            .set
+            .get_from_layer(level - 1, idx as usize)
+            .count_ones() as usize;
+        ones << (BITS * (level - 1))
+    }
+}
+
+impl<'a, T: 'a + Send + Sync> UnindexedProducer for AdaptiveProducer<'a, T>
+where
+    T: BitSetLike,
+{
+    type Item = Index;
+
+    /// Descends through layers that have a single bit set, like
+    /// `BitProducer` does, until a layer with more than one bit is found.
+    /// The bits of that layer are then cut where the summed weights of the
+    /// lower half first reach half of the total.
+    fn split(mut self) -> (Self, Option<Self>) {
+        self.iter.left = None;
+        for level in (1..LAYERS).rev() {
+            let level_prefix = self.iter.prefix.get(level).cloned().unwrap_or(0);
+            let mask = prune(self.iter.set, level, level_prefix, self.iter.masks[level]);
+            self.iter.masks[level] = mask;
+            if mask == 0 {
+                continue;
+            }
+            if mask.count_ones() == 1 {
+                let idx = level_prefix | mask.trailing_zeros();
+                self.iter.masks[level] = 0;
+                self.iter.masks[level - 1] = self.iter.set.get_from_layer(level - 1, idx as usize);
+                self.iter.prefix[level - 1] = idx << BITS;
+                continue;
+            }
+
+            let mut weights = [0; 1 << BITS];
+            let mut total = 0;
+            let mut m = mask;
+            while m != 0 {
+                let bit = m.trailing_zeros();
+                m &= !(1 << bit);
+                weights[bit as usize] = self.weight(level, bit);
+                total += weights[bit as usize];
+            }
+            if total < self.min_len.saturating_mul(2) {
+                break;
+            }
+
+            // The highest bit always goes to the other half, so neither
+            // side ends up empty.
+            let mut lower = 0;
+            let mut sum = 0;
+            let highest = (1 << BITS) - 1 - mask.leading_zeros() as usize;
+            let mut m = mask & !(1 << highest);
+            while m != 0 && sum * 2 < total {
+                let bit = m.trailing_zeros();
+                m &= !(1 << bit);
+                lower |= 1 << bit;
+                sum += weights[bit as usize];
+            }
+
+            let mut other = AdaptiveProducer {
+                iter: BitIter::new(self.iter.set, [0; LAYERS], self.iter.prefix),
+                min_len: self.min_len,
+            };
+            other.iter.masks[level] = mask & !lower;
+            self.iter.masks[level] = lower;
+            return (self, Some(other));
+        }
+        (self, None)
+    }
+
+    fn fold_with<F>(self, folder: F) -> F
+    where
+        F: Folder<Self::Item>,
+    {
+        folder.consume_iter(self.iter)
+    }
+}
+
+/// Collects into a `BitSet` per rayon job, which are then merged
+/// with word-level unions.
+impl FromParallelIterator<Index> for BitSet {
+    fn from_par_iter<I>(par_iter: I) -> Self
+    where
+        I: IntoParallelIterator<Item = Index>,
+    {
+        par_iter
+            .into_par_iter()
+            .fold(BitSet::new, |mut set, id| {
+                set.add(id);
+                set
+            })
+            .reduce(BitSet::new, |mut set, other| {
+                set |= &other;
+                set
+            })
+    }
+}
+
+/// Collects straight into a single shared set with `add_atomic`.
+impl FromParallelIterator<Index> for AtomicBitSet {
+    fn from_par_iter<I>(par_iter: I) -> Self
+    where
+        I: IntoParallelIterator<Item = Index>,
+    {
+        let set = AtomicBitSet::new();
+        par_iter.into_par_iter().for_each(|id| {
+            set.add_atomic(id);
+        });
+        set
+    }
+}
+
+#[cfg(test)]
+mod test_word_producer {
+    use rayon::iter::ParallelIterator;
+
+    use iter::BitSetLike;
+    use BitSet;
+
+    #[test]
+    fn par_words() {
+        let mut set = BitSet::new();
+        for i in 0..100_000 {
+            set.add(i * 7);
+        }
+        let mut words = (&set).par_words().collect::<Vec<_>>();
+        words.sort();
+        let expected = (&set)
+            .masks()
+            .map(|(base, word)| ((base >> ::BITS) as usize, word))
+            .collect::<Vec<_>>();
+        assert_eq!(words, expected);
+        assert_eq!(BitSet::new().par_words().count(), 0);
+    }
+}
+
 #[cfg(test)]
 mod test_bit_producer {
     use rayon::iter::plumbing::UnindexedProducer;
@@ -239,4 +541,124 @@ mod test_bit_producer {
     fn max_1_splitting_of_two_top_bits() {
         test_splitting(1);
     }
+
+    #[test]
+    fn splits_composed_view() {
+        use rayon::iter::ParallelIterator;
+        use {BitSet, BitSetAnd, BitSetOr};
+
+        // The operands share their summaries in every top layer subtree,
+        // but only intersect in the last four.
+        let block = |i: u32| i << 18..(i << 18) + 1_000;
+        let a: BitSet = (0..8).flat_map(block).filter(|i| i % 2 == 0).collect();
+        let b: BitSet = (0..8)
+            .flat_map(block)
+            .filter(|&i| (i % 2 == 0) == (i >= 4 << 18))
+            .collect();
+        let and = BitSetAnd(&a, &b);
+
+        let (us, them) = BitProducer((&and).iter(), 3).split();
+        let (us, them) = (
+            us.0.collect::<Vec<_>>(),
+            them.unwrap().0.collect::<Vec<_>>(),
+        );
+        assert_eq!(us.len(), 1_000);
+        assert_eq!(them.len(), 1_000);
+        assert_eq!(us[0], 4 << 18);
+
+        let c: BitSet = (2_000_000..2_100_000).collect();
+        let nested = BitSetAnd(BitSetOr(&and, &c), BitSetOr(&b, &c));
+        let mut par = (&nested).par_iter().collect::<Vec<_>>();
+        par.sort();
+        assert_eq!(par, (&nested).iter().collect::<Vec<_>>());
+        assert_eq!((&nested).par_iter().adaptive().count(), 102_000);
+    }
+}
+
+#[cfg(test)]
+mod test_adaptive_producer {
+    use rayon::iter::plumbing::UnindexedProducer;
+    use rayon::iter::ParallelIterator;
+
+    use super::AdaptiveProducer;
+    use {BitSet, BitSetLike};
+
+    fn producer(set: &BitSet, min_len: usize) -> AdaptiveProducer<'_, BitSet> {
+        AdaptiveProducer {
+            iter: set.iter(),
+            min_len,
+        }
+    }
+
+    #[test]
+    fn splits_on_population() {
+        // One full top layer subtree and eight sparse ones after it.
+        let mut set: BitSet = (0..1 << 18).collect();
+        for i in 1..9 {
+            set.extend(i << 18..(i << 18) + 4_096);
+        }
+        let (us, them) = producer(&set, 1).split();
+        let them = them.expect("splitting a populated set");
+        assert_eq!(them.iter.clone().count(), 8 * 4_096);
+        assert_eq!(them.iter.min(), Some(1 << 18));
+
+        let (us, them) = us.split();
+        let them = them.expect("splitting the full subtree");
+        assert_eq!(
+            us.iter.collect::<Vec<_>>(),
+            (0..1 << 17).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            them.iter.collect::<Vec<_>>(),
+            (1 << 17..1 << 18).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn min_len_stops_splitting() {
+        let set: BitSet = (0..1_000).collect();
+        assert!(producer(&set, 1_000).split().1.is_none());
+        assert!(producer(&set, 100).split().1.is_some());
+        assert!(producer(&BitSet::new(), 1).split().1.is_none());
+
+        let mut single = BitSet::new();
+        single.add(12_345);
+        assert!(producer(&single, 1).split().1.is_none());
+    }
+
+    #[test]
+    fn splits_down_to_single_bits() {
+        let set: BitSet = (0..300).map(|i| i * 7_919).collect();
+        fn visit(us: AdaptiveProducer<BitSet>, out: &mut Vec<u32>) {
+            match us.split() {
+                (us, Some(them)) => {
+                    visit(us, out);
+                    visit(them, out);
+                }
+                (us, None) => {
+                    let bits = us.iter.collect::<Vec<_>>();
+                    assert_eq!(bits.len(), 1);
+                    out.extend(bits);
+                }
+            }
+        }
+        let mut out = Vec::new();
+        visit(producer(&set, 1), &mut out);
+        assert_eq!(out, (&set).iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn par_iter_adaptive() {
+        let mut set: BitSet = (0..50_000).collect();
+        set.add(9_000_000);
+        assert_eq!((&set).par_iter().adaptive().count(), 50_001);
+        assert_eq!(
+            (&set)
+                .par_iter()
+                .with_min_len(10_000)
+                .map(|i| i as u64)
+                .sum::<u64>(),
+            (&set).iter().map(|i| i as u64).sum::<u64>()
+        );
+    }
 }
@@ -0,0 +1,45 @@
+use iter::BitIter;
+use util::*;
+use BitSetLike;
+
+/// An `Iterator` over the indices of a [`BitSetLike`] structure which
+/// fall inside a range.
+///
+/// [`BitSetLike`]: ../trait.BitSetLike.html
+#[derive(Debug, Clone)]
+pub struct BitRangeIter<T> {
+    iter: BitIter<T>,
+    end: Index,
+}
+
+impl<T: BitSetLike> BitRangeIter<T> {
+    /// Creates a new `BitRangeIter` over the indices in `start..end`.
+    /// You usually don't call this function but just [`.iter_range()`]
+    /// on a bit set.
+    ///
+    /// [`.iter_range()`]: ../trait.BitSetLike.html#method.iter_range
+    pub fn new(set: T, start: Index, end: Index) -> Self {
+        BitRangeIter {
+            iter: BitIter::starting_at(set, start),
+            end,
+        }
+    }
+}
+
+impl<T> Iterator for BitRangeIter<T>
+where
+    T: BitSetLike,
+{
+    type Item = Index;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.iter.next() {
+            Some(next) if next < self.end => Some(next),
+            _ => {
+                // Everything left is past the end of the range.
+                self.iter.masks = [0; LAYERS];
+                None
+            }
+        }
+    }
+}
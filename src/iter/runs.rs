@@ -0,0 +1,68 @@
+use std::ops::Range;
+
+use iter::BitMaskIter;
+use util::*;
+use BitSetLike;
+
+/// An `Iterator` over the runs of consecutive indices in a [`BitSetLike`]
+/// structure, yielding each run as a `Range`.
+///
+/// [`BitSetLike`]: ../trait.BitSetLike.html
+#[derive(Debug, Clone)]
+pub struct BitRunIter<T> {
+    words: BitMaskIter<T>,
+    base: Index,
+    word: usize,
+}
+
+impl<T: BitSetLike> BitRunIter<T> {
+    /// Creates a new `BitRunIter`. You usually don't call this function
+    /// but just [`.iter_ranges()`] on a bit set.
+    ///
+    /// [`.iter_ranges()`]: ../trait.BitSetLike.html#method.iter_ranges
+    pub fn new(set: T) -> Self {
+        BitRunIter {
+            words: BitMaskIter::new(set),
+            base: 0,
+            word: 0,
+        }
+    }
+}
+
+impl<T> Iterator for BitRunIter<T>
+where
+    T: BitSetLike,
+{
+    type Item = Range<Index>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.word == 0 {
+            let (base, word) = self.words.next()?;
+            self.base = base;
+            self.word = word;
+        }
+
+        let zeros = self.word.trailing_zeros();
+        let end = zeros + (self.word >> zeros).trailing_ones();
+        let start = self.base + zeros;
+        if end < (1 << BITS) {
+            self.word &= !0 << end;
+            return Some(start..self.base + end);
+        }
+
+        // The run reaches the end of the word, so it goes on for as long
+        // as the following words are adjacent and start with set bits.
+        let mut end = self.base + end;
+        self.word = 0;
+        for (base, word) in self.words.by_ref() {
+            let ones = if base == end { word.trailing_ones() } else { 0 };
+            end += ones;
+            if ones < (1 << BITS) {
+                self.base = base;
+                self.word = if ones == 0 { word } else { word & (!0 << ones) };
+                break;
+            }
+        }
+        Some(start..end)
+    }
+}
@@ -0,0 +1,89 @@
+use iter::BitMaskIter;
+use util::*;
+use BitSetLike;
+
+/// An `Iterator` over a [`BitSetLike`] structure that makes no promise
+/// about the order of the indices it yields.
+///
+/// [`BitSetLike`]: ../trait.BitSetLike.html
+#[derive(Debug, Clone)]
+pub struct BitUnorderedIter<T> {
+    words: BitMaskIter<T>,
+    base: Index,
+    word: usize,
+}
+
+impl<T: BitSetLike> BitUnorderedIter<T> {
+    /// Creates a new `BitUnorderedIter`. You usually don't call this
+    /// function but just [`.iter_unordered()`] on a bit set.
+    ///
+    /// [`.iter_unordered()`]: ../trait.BitSetLike.html#method.iter_unordered
+    pub fn new(set: T) -> Self {
+        BitUnorderedIter {
+            words: BitMaskIter::new(set),
+            base: 0,
+            word: 0,
+        }
+    }
+}
+
+impl<T> Iterator for BitUnorderedIter<T>
+where
+    T: BitSetLike,
+{
+    type Item = Index;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.word == 0 {
+            let (base, word) = self.words.next()?;
+            self.base = base;
+            self.word = word;
+        }
+        let bit = self.word.trailing_zeros();
+        self.word &= self.word - 1;
+        Some(self.base + bit)
+    }
+
+    fn fold<B, F>(self, init: B, mut f: F) -> B
+    where
+        F: FnMut(B, Self::Item) -> B,
+    {
+        // Drain whole words at a time, without going back through the
+        // state of the iterator for every index.
+        let mut drain = |mut acc, base: Index, mut word: usize| {
+            while word != 0 {
+                acc = f(acc, base + word.trailing_zeros());
+                word &= word - 1;
+            }
+            acc
+        };
+        let acc = drain(init, self.base, self.word);
+        self.words
+            .fold(acc, |acc, (base, word)| drain(acc, base, word))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {BitSet, BitSetLike};
+
+    #[test]
+    fn iter_unordered() {
+        let set: BitSet = (0..300_000)
+            .filter(|i| i % 7 == 0 || i % 64 == 63)
+            .collect();
+        let mut seen = (&set).iter_unordered().collect::<Vec<_>>();
+        seen.sort();
+        assert_eq!(seen, (&set).iter().collect::<Vec<_>>());
+
+        let mut iter = (&set).iter_unordered();
+        let first = iter.by_ref().take(10).collect::<BitSet>();
+        let rest = iter.fold(first, |mut acc, i| {
+            assert!(!acc.add(i));
+            acc
+        });
+        assert_eq!(rest, set);
+        assert_eq!(BitSet::new().iter_unordered().next(), None);
+    }
+}
@@ -0,0 +1,104 @@
+use std::marker::PhantomData;
+
+use iter::BitMaskIter;
+use util::*;
+use BitSetLike;
+
+/// An `Iterator` over the indices of a [`BitSetLike`] structure, each
+/// paired with a mutable reference to the element at that index of a
+/// slice.
+///
+/// The slice is checked to cover each layer 0 word once, when the word is
+/// loaded, so the elements are handed out without a bounds check each.
+///
+/// [`BitSetLike`]: ../trait.BitSetLike.html
+#[derive(Debug)]
+pub struct BitZipMut<'a, T, V: 'a> {
+    words: BitMaskIter<T>,
+    base: Index,
+    word: usize,
+    ptr: *mut V,
+    len: usize,
+    marker: PhantomData<&'a mut [V]>,
+}
+
+// SAFETY: The iterator only hands out the `&mut V`s of the slice it
+// borrows, so it can move between threads whenever the borrow could.
+unsafe impl<T: Send, V: Send> Send for BitZipMut<'_, T, V> {}
+// SAFETY: There's no shared access to the elements through `&BitZipMut`.
+unsafe impl<T: Sync, V: Sync> Sync for BitZipMut<'_, T, V> {}
+
+impl<'a, T: BitSetLike, V> BitZipMut<'a, T, V> {
+    /// Creates a new `BitZipMut`. You usually don't call this function
+    /// but just [`.zip_mut()`] on a bit set.
+    ///
+    /// [`.zip_mut()`]: ../trait.BitSetLike.html#method.zip_mut
+    pub fn new(set: T, slice: &'a mut [V]) -> Self {
+        BitZipMut {
+            words: BitMaskIter::new(set),
+            base: 0,
+            word: 0,
+            ptr: slice.as_mut_ptr(),
+            len: slice.len(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T, V> Iterator for BitZipMut<'a, T, V>
+where
+    T: BitSetLike,
+{
+    type Item = (Index, &'a mut V);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.word == 0 {
+            let (base, word) = self.words.next()?;
+            let last = base as usize + (usize::BITS - 1 - word.leading_zeros()) as usize;
+            assert!(
+                last < self.len,
+                "Expected the slice to cover index {}, but its length is {}",
+                last,
+                self.len
+            );
+            self.base = base;
+            self.word = word;
+        }
+        let id = self.base + self.word.trailing_zeros();
+        self.word &= self.word - 1;
+        // SAFETY: `id` is below the length of the slice, checked for the
+        // highest index of its word above, and the words come in strictly
+        // ascending order, so every element is handed out at most once.
+        Some((id, unsafe { &mut *self.ptr.add(id as usize) }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {BitSet, BitSetLike};
+
+    #[test]
+    fn zip_mut() {
+        let set: BitSet = [0, 3, 63, 64, 4_000, 9_999].iter().collect();
+        let mut values = vec![0; 10_000];
+        for (id, value) in (&set).zip_mut(&mut values) {
+            *value += id + 1;
+        }
+        for (id, &value) in values.iter().enumerate() {
+            let expected = if set.contains(id as u32) { id + 1 } else { 0 };
+            assert_eq!(value, expected as u32);
+        }
+        assert_eq!(BitSet::new().zip_mut(&mut [0u8; 0]).next(), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn short_slice() {
+        let set: BitSet = [1, 200].iter().collect();
+        let mut values = [0; 200];
+        for (_, value) in (&set).zip_mut(&mut values) {
+            *value = 1;
+        }
+    }
+}
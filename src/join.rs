@@ -0,0 +1,294 @@
+use std::marker::PhantomData;
+
+use iter::BitMaskIter;
+use util::*;
+use BitSetLike;
+
+/// Storage that holds one element per index, such as the components of an
+/// ECS, so it can take part in a [`join`].
+///
+/// It is implemented for slices and `Vec`s, shared and mutable.
+///
+/// [`join`]: fn.join.html
+pub trait Keyed {
+    /// The element handed out for an index, usually a reference into the
+    /// storage.
+    type Item;
+
+    /// The state the elements are fetched through during a join.
+    type Raw;
+
+    /// Prepares the storage for a join, returning its state and the number
+    /// of leading indices it has an element for.
+    fn open(self) -> (Self::Raw, usize);
+
+    /// Fetches the element at `id`.
+    ///
+    /// # Safety
+    ///
+    /// `id` has to be below the length returned by [`open`], and each index
+    /// may be fetched at most once per `raw`.
+    ///
+    /// [`open`]: #tymethod.open
+    unsafe fn fetch(raw: &mut Self::Raw, id: Index) -> Self::Item;
+}
+
+impl<'a, T> Keyed for &'a [T] {
+    type Item = &'a T;
+    type Raw = &'a [T];
+
+    #[inline]
+    fn open(self) -> (Self::Raw, usize) {
+        (self, self.len())
+    }
+
+    #[inline]
+    unsafe fn fetch(raw: &mut Self::Raw, id: Index) -> Self::Item {
+        raw.get_unchecked(id as usize)
+    }
+}
+
+impl<'a, T> Keyed for &'a mut [T] {
+    type Item = &'a mut T;
+    type Raw = RawSliceMut<'a, T>;
+
+    #[inline]
+    fn open(self) -> (Self::Raw, usize) {
+        let len = self.len();
+        let raw = RawSliceMut {
+            ptr: self.as_mut_ptr(),
+            marker: PhantomData,
+        };
+        (raw, len)
+    }
+
+    #[inline]
+    unsafe fn fetch(raw: &mut Self::Raw, id: Index) -> Self::Item {
+        &mut *raw.ptr.add(id as usize)
+    }
+}
+
+impl<'a, T> Keyed for &'a Vec<T> {
+    type Item = &'a T;
+    type Raw = &'a [T];
+
+    #[inline]
+    fn open(self) -> (Self::Raw, usize) {
+        self.as_slice().open()
+    }
+
+    #[inline]
+    unsafe fn fetch(raw: &mut Self::Raw, id: Index) -> Self::Item {
+        <&[T]>::fetch(raw, id)
+    }
+}
+
+impl<'a, T> Keyed for &'a mut Vec<T> {
+    type Item = &'a mut T;
+    type Raw = RawSliceMut<'a, T>;
+
+    #[inline]
+    fn open(self) -> (Self::Raw, usize) {
+        self.as_mut_slice().open()
+    }
+
+    #[inline]
+    unsafe fn fetch(raw: &mut Self::Raw, id: Index) -> Self::Item {
+        <&mut [T]>::fetch(raw, id)
+    }
+}
+
+/// The state of a mutably borrowed slice during a join.
+#[derive(Debug)]
+pub struct RawSliceMut<'a, T> {
+    ptr: *mut T,
+    marker: PhantomData<&'a mut [T]>,
+}
+
+// SAFETY: The pointer is the mutable borrow of a slice, which can move
+// between threads whenever its elements can.
+unsafe impl<T: Send> Send for RawSliceMut<'_, T> {}
+// SAFETY: There's no access to the elements through `&RawSliceMut`.
+unsafe impl<T: Sync> Sync for RawSliceMut<'_, T> {}
+
+/// A tuple of `(mask, storage)` pairs that can be [`join`]ed.
+///
+/// It is implemented for tuples of up to 8 pairs, where each mask is a
+/// [`BitSetLike`] and each storage is [`Keyed`].
+///
+/// [`join`]: fn.join.html
+/// [`BitSetLike`]: trait.BitSetLike.html
+/// [`Keyed`]: trait.Keyed.html
+pub trait Join {
+    /// The intersection of the masks.
+    type Mask: BitSetLike;
+    /// The state of the storages.
+    type Raw;
+    /// The tuple of elements yielded for each index.
+    type Item;
+
+    /// Splits the pairs into the intersection of their masks, the state of
+    /// their storages and the number of leading indices they all cover.
+    fn open(self) -> (Self::Mask, Self::Raw, usize);
+
+    /// Fetches the elements at `id` from every storage.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`Keyed::fetch`].
+    ///
+    /// [`Keyed::fetch`]: trait.Keyed.html#tymethod.fetch
+    unsafe fn fetch(raw: &mut Self::Raw, id: Index) -> Self::Item;
+}
+
+macro_rules! join_tuple {
+    ( $( $mask:ident $store:ident $idx:tt ),* ) => {
+        impl<$( $mask: BitSetLike, $store: Keyed ),*> Join for ($( ($mask, $store), )*) {
+            type Mask = ($( $mask, )*);
+            type Raw = ($( $store::Raw, )*);
+            type Item = ($( $store::Item, )*);
+
+            #[inline]
+            fn open(self) -> (Self::Mask, Self::Raw, usize) {
+                let mut end = usize::MAX;
+                let raw = ($( {
+                    let (raw, len) = self.$idx.1.open();
+                    end = end.min(len);
+                    raw
+                }, )*);
+                (($( self.$idx.0, )*), raw, end)
+            }
+
+            #[inline]
+            unsafe fn fetch(raw: &mut Self::Raw, id: Index) -> Self::Item {
+                ($( $store::fetch(&mut raw.$idx, id), )*)
+            }
+        }
+    };
+}
+
+join_tuple!(M0 S0 0);
+join_tuple!(M0 S0 0, M1 S1 1);
+join_tuple!(M0 S0 0, M1 S1 1, M2 S2 2);
+join_tuple!(M0 S0 0, M1 S1 1, M2 S2 2, M3 S3 3);
+join_tuple!(M0 S0 0, M1 S1 1, M2 S2 2, M3 S3 3, M4 S4 4);
+join_tuple!(M0 S0 0, M1 S1 1, M2 S2 2, M3 S3 3, M4 S4 4, M5 S5 5);
+join_tuple!(M0 S0 0, M1 S1 1, M2 S2 2, M3 S3 3, M4 S4 4, M5 S5 5, M6 S6 6);
+join_tuple!(M0 S0 0, M1 S1 1, M2 S2 2, M3 S3 3, M4 S4 4, M5 S5 5, M6 S6 6, M7 S7 7);
+
+/// Visits the indices that are in every mask, yielding a tuple with the
+/// element of each storage at that index.
+///
+/// The masks are intersected a layer 0 word at a time, and each storage is
+/// checked to cover a word once, when it is loaded, so the elements are
+/// fetched without a bounds check each.
+///
+/// # Panics
+///
+/// The iterator panics when it reaches an index that one of the storages
+/// doesn't have an element for.
+///
+/// # Examples
+///
+/// ```
+/// # use hibitset::{join, BitSet};
+/// let positions: BitSet = [1, 2, 5].iter().collect();
+/// let velocities: BitSet = [2, 5, 7].iter().collect();
+/// let mut pos = vec![0; 8];
+/// let vel = vec![1, 1, 2, 2, 3, 3, 4, 4];
+///
+/// for (p, v) in join(((&positions, &mut pos), (&velocities, &vel))) {
+///     *p += *v;
+/// }
+/// assert_eq!(pos, [0, 0, 2, 0, 0, 3, 0, 0]);
+/// ```
+pub fn join<J: Join>(parts: J) -> JoinIter<J> {
+    let (mask, raw, end) = parts.open();
+    JoinIter {
+        words: BitMaskIter::new(mask),
+        base: 0,
+        word: 0,
+        raw,
+        end,
+    }
+}
+
+/// An `Iterator` over the elements of a [`join`].
+///
+/// [`join`]: fn.join.html
+#[derive(Debug)]
+pub struct JoinIter<J: Join> {
+    words: BitMaskIter<J::Mask>,
+    base: Index,
+    word: usize,
+    raw: J::Raw,
+    end: usize,
+}
+
+impl<J: Join> Iterator for JoinIter<J> {
+    type Item = J::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.word == 0 {
+            let (base, word) = self.words.next()?;
+            let last = base as usize + (usize::BITS - 1 - word.leading_zeros()) as usize;
+            assert!(
+                last < self.end,
+                "Expected the storages to cover index {}, but they end at {}",
+                last,
+                self.end
+            );
+            self.base = base;
+            self.word = word;
+        }
+        let id = self.base + self.word.trailing_zeros();
+        self.word &= self.word - 1;
+        // SAFETY: `id` is below the length of every storage, checked for the
+        // highest index of its word above, and the words come in strictly
+        // ascending order, so every index is fetched at most once.
+        Some(unsafe { J::fetch(&mut self.raw, id) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::join;
+    use {BitSet, BitSetLike, BitSetNot};
+
+    #[test]
+    fn join_storages() {
+        let a: BitSet = (0..1_000).step_by(2).collect();
+        let b: BitSet = (0..1_000).step_by(3).collect();
+        let c: BitSet = (0..1_000).step_by(5).collect();
+        let ids: Vec<u32> = (0..1_000).collect();
+        let mut out = vec![0; 1_000];
+        let mut flags = vec![false; 1_000];
+
+        let mut count = 0;
+        for (id, o, f) in join(((&a, &ids), (&b, &mut out), (&c, &mut flags[..]))) {
+            *o = *id;
+            *f = true;
+            count += 1;
+        }
+        assert_eq!(count, (&a & &b & &c).iter().count());
+        for id in 0..1_000 {
+            let joined = a.contains(id) && b.contains(id) && c.contains(id);
+            assert_eq!(flags[id as usize], joined);
+            assert_eq!(out[id as usize], if joined { id } else { 0 });
+        }
+
+        let none: Vec<_> = join(((&a, &ids), (BitSetNot(&a), &ids))).collect();
+        assert!(none.is_empty());
+        let single: Vec<_> = join(((&c, &ids[..]),)).map(|(&id,)| id).collect();
+        assert_eq!(single, c.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[should_panic]
+    fn short_storage() {
+        let set: BitSet = [1, 200].iter().collect();
+        let values = vec![0; 200];
+        for _ in join(((&set, &values),)) {}
+    }
+}
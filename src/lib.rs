@@ -44,23 +44,110 @@
 //!
 
 #![deny(missing_docs)]
-
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
+extern crate arc_swap;
+#[cfg(loom)]
+extern crate loom;
+#[cfg(feature = "proptest")]
+extern crate proptest;
+#[cfg(feature = "quickcheck")]
+extern crate quickcheck;
 #[cfg(test)]
 extern crate rand;
 #[cfg(feature = "parallel")]
 extern crate rayon;
-
+#[cfg(feature = "roaring")]
+extern crate roaring;
+
+mod adapter;
+mod alloc;
+mod allocator;
+#[cfg(any(feature = "proptest", feature = "quickcheck"))]
+mod arbitrary;
 mod atomic;
+mod builder;
+mod cached;
+mod debug;
+mod digest;
+mod error;
+mod expr;
+mod fixed;
+mod flat;
+mod generational;
+mod immutable;
 mod iter;
+#[cfg(feature = "join")]
+mod join;
+mod matrix;
+mod multiset;
 mod ops;
+mod pages;
+mod persist;
+mod rcu;
+mod remap;
+#[cfg(feature = "roaring")]
+mod roaring_bitmap;
+mod saturated;
+mod sharded;
+mod simd;
+mod sparse;
+mod stats;
+mod sync;
+mod tracked;
 mod util;
-
-pub use atomic::AtomicBitSet;
-pub use iter::{BitIter, DrainBitIter};
+mod view;
+mod wide;
+mod word;
+
+pub use adapter::{BTreeSetAdapter, HashSetAdapter};
+pub use alloc::{Global, LayerAlloc};
+pub use allocator::{AtomicBitSetAllocator, BitSetAllocator};
+pub use atomic::{AtomicBitSet, ConsistentIter};
+pub use builder::BitSetBuilder;
+pub use cached::CachedBitSet;
+pub use error::{ConcurrentModification, ExtendError, InvalidHierarchy, LayerMismatch, OutOfRange};
+pub use expr::Expr;
+pub use fixed::StaticBitSet;
+pub use flat::FlatLayer0;
+pub use generational::{Generation, GenerationIter, GenerationalBitSet};
+pub use immutable::ImBitSet;
+pub use iter::{
+    BitBlockIter, BitCursor, BitDiffIter, BitIter, BitMaskIter, BitRangeIter, BitRunIter,
+    BitUnorderedIter, BitZipMut, BlockRef, Change, DrainBitIter, DrainFilterIter, DrainRangeIter,
+};
 #[cfg(feature = "parallel")]
-pub use iter::{BitParIter, BitProducer};
-pub use ops::{BitSetAll, BitSetAnd, BitSetNot, BitSetOr, BitSetXor};
-
+pub use iter::{BitParIter, BitParWordIter, BitProducer};
+#[cfg(feature = "join")]
+pub use join::{join, Join, JoinIter, Keyed, RawSliceMut};
+pub use matrix::{BitColumnIter, BitMatrix};
+pub use multiset::BitMultiSet;
+pub use ops::{
+    BitSetAll, BitSetAnd, BitSetAndN, BitSetFromFn, BitSetNot, BitSetNotIn, BitSetOr, BitSetOrN,
+    BitSetThreshold, BitSetXor,
+};
+pub use rcu::RcuBitSet;
+pub use remap::Remap;
+pub use sharded::ShardedAtomicBitSet;
+pub use sparse::SparseBitSet;
+pub use stats::{ExtendStats, MemoryStats};
+pub use tracked::TrackedBitSet;
+pub use view::BitSetView;
+pub use wide::{WideBitIter, WideBitSet, WidePages};
+pub use word::WordMut;
+
+use std::borrow::Cow;
+use std::cmp::{self, Ordering};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::mem;
+use std::ops::Range;
+use std::slice;
+
+use alloc::Layer;
+use pages::Pages;
+use saturated::Saturated;
 use util::*;
 
 /// A `BitSet` is a simple set designed to track which indices are placed
@@ -68,12 +155,67 @@ use util::*;
 ///
 /// Note, a `BitSet` is limited by design to only `usize**4` indices.
 /// Adding beyond this limit will cause the `BitSet` to panic.
-#[derive(Clone, Debug, Default)]
-pub struct BitSet {
+///
+/// A `BitSet` can be iterated by value, in which case the iterator owns
+/// the set, so it can be returned from the function that built the set:
+///
+/// ```
+/// # use hibitset::BitSet;
+/// fn evens(n: u32) -> impl Iterator<Item = u32> {
+///     let set: BitSet = (0..n).filter(|i| i % 2 == 0).collect();
+///     set.into_iter()
+/// }
+/// assert_eq!(evens(7).collect::<Vec<_>>(), [0, 2, 4, 6]);
+/// ```
+///
+/// The layers are allocated with `A`, which is the global allocator
+/// unless the set was created with [`new_in`]. Most of the API is only
+/// available for sets of the global allocator.
+///
+/// # Memory
+///
+/// Layers 1 and up are contiguous vectors of words. Layer 0 is split
+/// into pages of 64 words, one per layer 1 word, and a page is only
+/// allocated once an index in it is added. Adding index 10,000,000 to an
+/// empty set thus allocates a single 512 byte page plus a pointer per
+/// page below it, about 40 KB with layer 1, rather than 1.2 MB of zero
+/// words. The vectors grow like a `Vec` does, by at least doubling, so
+/// adding indices in ascending order only reallocates a logarithmic
+/// number of times. Call [`with_capacity`] or [`reserve`] up front when
+/// the highest index is known to allocate the pages ahead of time, and
+/// [`shrink_to_fit`] to free the pages that were emptied again.
+///
+/// [`new_in`]: #method.new_in
+/// [`with_capacity`]: #method.with_capacity
+/// [`shrink_to_fit`]: #method.shrink_to_fit
+/// [`reserve`]: #method.reserve
+pub struct BitSet<A: LayerAlloc = Global> {
     layer3: usize,
-    layer2: Vec<usize>,
-    layer1: Vec<usize>,
-    layer0: Vec<usize>,
+    layer2: Layer<usize, A>,
+    layer1: Layer<usize, A>,
+    layer0: Pages<A>,
+    saturated: Saturated<A>,
+    len: usize,
+}
+
+// Written out rather than derived so the allocator doesn't have to be
+// `Debug`.
+impl<A: LayerAlloc> fmt::Debug for BitSet<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BitSet")
+            .field("layer3", &self.layer3)
+            .field("layer2", &self.layer2)
+            .field("layer1", &self.layer1)
+            .field("layer0", &self.layer0)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl Default for BitSet {
+    fn default() -> Self {
+        BitSet::new_in(Global)
+    }
 }
 
 impl BitSet {
@@ -82,37 +224,221 @@ impl BitSet {
         Default::default()
     }
 
-    #[inline]
-    fn valid_range(max: Index) {
-        if (MAX_EID as u32) < max {
-            panic!("Expected index to be less then {}, found {}", MAX_EID, max);
+    /// Creates an empty `BitSet`, preallocated for up to `max` indices.
+    ///
+    /// Every layer is allocated up front to cover the indices up to and
+    /// including `max`, so adding any of them never reallocates. There is
+    /// no need to add and remove a sentinel index to pre-size a set.
+    ///
+    /// Panics if `max` is beyond the maximum index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::BitSet;
+    /// let mut set = BitSet::with_capacity(999_999);
+    /// let capacity = set.capacity();
+    /// assert!(capacity >= 1_000_000);
+    /// for id in 0..1_000_000 {
+    ///     set.add(id);
+    /// }
+    /// assert_eq!(set.capacity(), capacity);
+    /// ```
+    pub fn with_capacity(max: Index) -> BitSet {
+        BitSet::with_capacity_in(max, Global)
+    }
+}
+
+impl<A: LayerAlloc> BitSet<A> {
+    /// Creates an empty `BitSet` whose layers are allocated with `alloc`.
+    ///
+    /// Without the `allocator_api` feature, `alloc` can only be the
+    /// [`Global`] allocator.
+    ///
+    /// [`Global`]: struct.Global.html
+    pub fn new_in(alloc: A) -> Self {
+        BitSet {
+            layer3: 0,
+            layer2: alloc::layer_in(&alloc),
+            layer1: alloc::layer_in(&alloc),
+            layer0: Pages::new_in(&alloc),
+            saturated: Saturated::new_in(&alloc),
+            len: 0,
         }
     }
 
-    /// Creates an empty `BitSet`, preallocated for up to `max` indices.
-    pub fn with_capacity(max: Index) -> BitSet {
+    /// Creates an empty `BitSet` whose layers are allocated with `alloc`,
+    /// preallocated for up to `max` indices.
+    pub fn with_capacity_in(max: Index, alloc: A) -> Self {
         Self::valid_range(max);
-        let mut value = BitSet::new();
-        value.extend(max);
+        let mut value = BitSet::new_in(alloc);
+        value.grow(max);
+        value.layer0.fill(max.offset(SHIFT1));
         value
     }
 
+    /// Reserves memory for every layer, so that indices up to and
+    /// including `max` can be added without allocating.
+    ///
+    /// Unlike `with_capacity`, the layers aren't grown yet, so this only
+    /// allocates and doesn't change [`capacity`]. Together with
+    /// [`add_within_capacity`] it lets hot paths check that they never
+    /// reallocate.
+    ///
+    /// Panics if `max` is beyond the maximum index.
+    ///
+    /// [`capacity`]: #method.capacity
+    /// [`add_within_capacity`]: #method.add_within_capacity
+    pub fn reserve(&mut self, max: Index) {
+        Self::valid_range(max);
+        let (p0, p1, p2) = offsets(max);
+        let reserve = |vec: &mut Layer<usize, A>, upper_index: usize| {
+            vec.reserve((upper_index + 1).saturating_sub(vec.len()));
+        };
+        reserve(&mut self.layer2, p2);
+        reserve(&mut self.layer1, p1);
+        self.layer0.reserve(p0);
+    }
+
+    /// Returns how many indices the set can hold without allocating, i.e.
+    /// every index below this value can be added without allocating.
+    ///
+    /// This is at least [`capacity`], and also counts the memory set
+    /// aside by [`reserve`].
+    ///
+    /// [`capacity`]: #method.capacity
+    /// [`reserve`]: #method.reserve
+    pub fn reserved_capacity(&self) -> usize {
+        cmp::min(
+            cmp::min(
+                self.layer0.reserved() << BITS,
+                self.layer1.capacity() << (BITS * 2),
+            ),
+            cmp::min(self.layer2.capacity() << (BITS * 3), MAX_EID),
+        )
+    }
+
+    /// Adds `id` to the set without ever allocating. Returns whether the
+    /// value was already in the set, like [`add`], or an error if `id` is
+    /// not below [`reserved_capacity`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::BitSet;
+    /// let mut set = BitSet::new();
+    /// set.reserve(1000);
+    /// assert_eq!(set.add_within_capacity(1000), Ok(false));
+    /// assert!(set.add_within_capacity(1_000_000).is_err());
+    /// ```
+    ///
+    /// [`add`]: #method.add
+    /// [`reserved_capacity`]: #method.reserved_capacity
+    #[inline]
+    pub fn add_within_capacity(&mut self, id: Index) -> Result<bool, OutOfRange> {
+        let max = self.reserved_capacity();
+        if id as usize >= max {
+            return Err(OutOfRange::new(id, max));
+        }
+        Ok(self.add(id))
+    }
+
+    /// Adds the indices of `iter` to the set, stopping at the first one
+    /// that isn't below `cap`, and reports what was added.
+    ///
+    /// Indices are added one at a time as they come, so on an error the
+    /// ones before the offending index stay in the set. The cap can't go
+    /// past the maximum index. Use [`extend_within`] to skip the indices
+    /// that are too large instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::BitSet;
+    /// let mut set = BitSet::new();
+    /// let stats = set.try_extend(vec![3, 1, 3], 100).unwrap();
+    /// assert_eq!((stats.added, stats.duplicates, stats.max), (2, 1, Some(3)));
+    ///
+    /// let err = set.try_extend(vec![5, 500, 6], 100).unwrap_err();
+    /// assert_eq!(err.out_of_range().index(), 500);
+    /// assert_eq!(err.stats().added, 1);
+    /// assert!(!set.contains(6));
+    /// ```
+    ///
+    /// [`extend_within`]: #method.extend_within
+    pub fn try_extend<I>(&mut self, iter: I, cap: Index) -> Result<ExtendStats, ExtendError>
+    where
+        I: IntoIterator<Item = Index>,
+    {
+        self.load(iter, cap, true)
+    }
+
+    /// Adds the indices of `iter` that are below `cap` to the set,
+    /// counting the others as out of range, and reports what was added.
+    ///
+    /// The cap can't go past the maximum index.
+    pub fn extend_within<I>(&mut self, iter: I, cap: Index) -> ExtendStats
+    where
+        I: IntoIterator<Item = Index>,
+    {
+        match self.load(iter, cap, false) {
+            Ok(stats) => stats,
+            Err(err) => *err.stats(),
+        }
+    }
+
+    fn load<I>(&mut self, iter: I, cap: Index, fail: bool) -> Result<ExtendStats, ExtendError>
+    where
+        I: IntoIterator<Item = Index>,
+    {
+        let cap = cmp::min(cap as usize, MAX_EID);
+        let mut stats = ExtendStats::default();
+        for id in iter {
+            stats.max = cmp::max(stats.max, Some(id));
+            if id as usize >= cap {
+                if fail {
+                    return Err(ExtendError::new(stats, OutOfRange::new(id, cap)));
+                }
+                stats.out_of_range += 1;
+            } else if self.add(id) {
+                stats.duplicates += 1;
+            } else {
+                stats.added += 1;
+            }
+        }
+        Ok(stats)
+    }
+
+    #[inline]
+    fn valid_range(max: Index) {
+        if (MAX_EID as u32) < max {
+            panic!("Expected index to be less then {}, found {}", MAX_EID, max);
+        }
+    }
+
     #[inline(never)]
-    fn extend(&mut self, id: Index) {
+    fn grow(&mut self, id: Index) {
         Self::valid_range(id);
         let (p0, p1, p2) = offsets(id);
 
         Self::fill_up(&mut self.layer2, p2);
         Self::fill_up(&mut self.layer1, p1);
-        Self::fill_up(&mut self.layer0, p0);
+        self.layer0.grow(p0);
     }
 
-    fn fill_up(vec: &mut Vec<usize>, upper_index: usize) {
+    fn fill_up(vec: &mut Layer<usize, A>, upper_index: usize) {
         if vec.len() <= upper_index {
             vec.resize(upper_index + 1, 0);
         }
     }
 
+    fn copy_layer(dst: &mut Layer<usize, A>, src: &[usize]) {
+        dst.truncate(src.len());
+        let len = dst.len();
+        dst.copy_from_slice(&src[..len]);
+        dst.extend_from_slice(&src[len..]);
+    }
+
     /// This is used to set the levels in the hierarchy
     /// when the lowest layer was set from 0.
     #[inline(never)]
@@ -125,12 +451,15 @@ impl BitSet {
 
     /// Adds `id` to the `BitSet`. Returns `true` if the value was
     /// already in the set.
+    ///
+    /// If `id` is beyond the capacity of the set, every layer is first
+    /// grown to cover it, see the [memory notes](#memory).
     #[inline]
     pub fn add(&mut self, id: Index) -> bool {
         let (p0, mask) = (id.offset(SHIFT1), id.mask(SHIFT0));
 
         if p0 >= self.layer0.len() {
-            self.extend(id);
+            self.grow(id);
         }
 
         if self.layer0[p0] & mask != 0 {
@@ -141,16 +470,73 @@ impl BitSet {
         // that the value can be found here.
         let old = self.layer0[p0];
         self.layer0[p0] |= mask;
+        self.saturated.update(p0, old, old | mask);
+        self.len += 1;
         if old == 0 {
             self.add_slow(id);
         }
         false
     }
 
+    /// Adds all of `ids` to the `BitSet`.
+    ///
+    /// The set is grown once for the last id, and ids falling into the
+    /// same layer 0 word are ORed into it together, so the upper layers
+    /// are updated at most once per word. `ids` should be sorted to get
+    /// the most out of this, unsorted ids are still added correctly.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{BitSet, BitSetLike};
+    /// let mut set = BitSet::new();
+    /// set.add_sorted(&[1, 2, 3, 100, 4_000]);
+    /// assert_eq!(set.iter().collect::<Vec<_>>(), [1, 2, 3, 100, 4_000]);
+    /// ```
+    pub fn add_sorted(&mut self, ids: &[Index]) {
+        let last = match ids.last() {
+            Some(&last) => last,
+            None => return,
+        };
+        if last.offset(SHIFT1) >= self.layer0.len() {
+            self.grow(last);
+        }
+
+        let mut rest = ids;
+        while let Some(&first) = rest.first() {
+            let p0 = first.offset(SHIFT1);
+            let len = rest
+                .iter()
+                .position(|id| id.offset(SHIFT1) != p0)
+                .unwrap_or(rest.len());
+            let mask = rest[..len]
+                .iter()
+                .fold(0, |mask, id| mask | id.mask(SHIFT0));
+            self.add_word(p0, mask);
+            rest = &rest[len..];
+        }
+    }
+
+    /// Ors `mask` into the layer 0 word at `p0`, growing the set and
+    /// setting the summary bits if needed.
+    fn add_word(&mut self, p0: usize, mask: usize) {
+        let id = (p0 << BITS) as Index;
+        if p0 >= self.layer0.len() {
+            self.grow(id);
+        }
+        let old = self.layer0[p0];
+        self.layer0[p0] |= mask;
+        self.saturated.update(p0, old, old | mask);
+        self.len += (mask & !old).count_ones() as usize;
+        if old == 0 && mask != 0 {
+            self.add_slow(id);
+        }
+    }
+
     fn layer_mut(&mut self, level: usize, idx: usize) -> &mut usize {
         match level {
             0 => {
-                Self::fill_up(&mut self.layer0, idx);
+                self.layer0.grow(idx);
                 &mut self.layer0[idx]
             }
             1 => {
@@ -166,12 +552,24 @@ impl BitSet {
         }
     }
 
+    /// Replaces the layer 0 word at `p0` with `f` of it, growing the
+    /// layer if needed and keeping the count and the saturated summaries
+    /// up to date. The layers above are left to the caller.
+    fn update_word<F: FnOnce(usize) -> usize>(&mut self, p0: usize, f: F) {
+        let word = self.layer_mut(0, p0);
+        let old = *word;
+        *word = f(old);
+        let new = *word;
+        self.saturated.update(p0, old, new);
+        self.len = self.len + new.count_ones() as usize - old.count_ones() as usize;
+    }
+
     /// Removes `id` from the set, returns `true` if the value
     /// was removed, and `false` if the value was not set
     /// to begin with.
     #[inline]
     pub fn remove(&mut self, id: Index) -> bool {
-        let (p0, p1, p2) = offsets(id);
+        let p0 = id.offset(SHIFT1);
 
         if p0 >= self.layer0.len() {
             return false;
@@ -185,41 +583,131 @@ impl BitSet {
         // its bit from layer0 to 3. the layers abover only
         // should be cleared if the bit cleared was the last bit
         // in its set
-        self.layer0[p0] &= !id.mask(SHIFT0);
-        if self.layer0[p0] != 0 {
-            return true;
+        let old = self.layer0[p0];
+        self.layer0[p0] = old & !id.mask(SHIFT0);
+        self.saturated.update(p0, old, old & !id.mask(SHIFT0));
+        self.len -= 1;
+        if self.layer0[p0] == 0 {
+            self.remove_slow(id);
         }
+        true
+    }
+
+    /// This is used to clear the levels in the hierarchy
+    /// when the lowest layer was cleared to 0.
+    #[inline(never)]
+    fn remove_slow(&mut self, id: Index) {
+        let (_, p1, p2) = offsets(id);
 
         self.layer1[p1] &= !id.mask(SHIFT1);
         if self.layer1[p1] != 0 {
-            return true;
+            return;
         }
 
         self.layer2[p2] &= !id.mask(SHIFT2);
         if self.layer2[p2] != 0 {
-            return true;
+            return;
         }
 
         self.layer3 &= !id.mask(SHIFT3);
-        return true;
     }
 
-    /// Returns `true` if `id` is in the set.
+    /// Flips `id` in the set, adding it if it was missing and removing
+    /// it otherwise. Returns `true` if the value was in the set before.
+    ///
+    /// The layer 0 word is looked up only once, the layers above are
+    /// touched only when it becomes zero or non-zero.
     #[inline]
-    pub fn contains(&self, id: Index) -> bool {
-        let p0 = id.offset(SHIFT1);
-        p0 < self.layer0.len() && (self.layer0[p0] & id.mask(SHIFT0)) != 0
+    pub fn toggle(&mut self, id: Index) -> bool {
+        let (p0, mask) = (id.offset(SHIFT1), id.mask(SHIFT0));
+
+        if p0 >= self.layer0.len() {
+            self.grow(id);
+        }
+
+        let old = self.layer0[p0];
+        self.layer0[p0] ^= mask;
+        self.saturated.update(p0, old, old ^ mask);
+        if old & mask == 0 {
+            self.len += 1;
+        } else {
+            self.len -= 1;
+        }
+        if old == 0 {
+            self.add_slow(id);
+        } else if old == mask {
+            self.remove_slow(id);
+        }
+        old & mask != 0
     }
 
-    /// Returns `true` if all ids in `other` are contained in this set
+    /// Adds `id` to the set if `value` is `true` and removes it
+    /// otherwise. Returns `true` if the value was in the set before.
     #[inline]
-    pub fn contains_set(&self, other: &BitSet) -> bool {
-        for id in other.iter() {
-            if !self.contains(id) {
+    pub fn set(&mut self, id: Index, value: bool) -> bool {
+        let (p0, mask) = (id.offset(SHIFT1), id.mask(SHIFT0));
+
+        if p0 >= self.layer0.len() {
+            if !value {
                 return false;
             }
+            self.grow(id);
         }
-        true
+
+        let old = self.layer0[p0];
+        if (old & mask != 0) == value {
+            return value;
+        }
+        self.layer0[p0] ^= mask;
+        self.saturated.update(p0, old, old ^ mask);
+        if value {
+            self.len += 1;
+        } else {
+            self.len -= 1;
+        }
+        if old == 0 {
+            self.add_slow(id);
+        } else if old == mask {
+            self.remove_slow(id);
+        }
+        !value
+    }
+
+    /// Returns `true` if `id` is in the set.
+    #[inline]
+    pub fn contains(&self, id: Index) -> bool {
+        self.layer0.get(id.offset(SHIFT1)) & id.mask(SHIFT0) != 0
+    }
+
+    /// Returns the number of indices in the set.
+    ///
+    /// The number is kept up to date as the set is modified, so unlike
+    /// [`count`](#method.count), this doesn't look at the words at all.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::BitSet;
+    /// let mut set: BitSet = (0..100).collect();
+    /// set.remove(5);
+    /// set &= &(50..1_000).collect::<BitSet>();
+    /// assert_eq!(set.len(), 50);
+    /// ```
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the set holds no indices.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Counts the indices in the set by popcounting all of layer 0, with
+    /// SIMD when the `simd` feature is enabled and the CPU supports it.
+    pub fn count(&self) -> usize {
+        self.layer0.count_ones()
     }
 
     /// Completely wipes out the bit set.
@@ -228,102 +716,813 @@ impl BitSet {
         self.layer1.clear();
         self.layer2.clear();
         self.layer3 = 0;
+        self.saturated.clear();
+        self.len = 0;
     }
+}
 
-    /// How many bits are in a `usize`.
+impl BitSet {
+    /// Returns `true` if all ids in `other` are contained in this set
+    #[inline]
+    pub fn contains_set(&self, other: &BitSet) -> bool {
+        self.is_superset(other)
+    }
+
+    /// Retains only the indices for which `f` returns `true`.
     ///
-    /// This value can be trivially determined. It is provided here as a constant for clarity.
+    /// Only the occupied layer 0 words are visited, and the upper layers
+    /// are fixed up as each word is processed, so no temporary storage
+    /// is needed.
     ///
     /// # Example
     ///
     /// ```
-    /// use hibitset::BitSet;
-    /// assert_eq!(BitSet::BITS_PER_USIZE, std::mem::size_of::<usize>()*8);
+    /// use hibitset::{BitSet, BitSetLike};
+    ///
+    /// let mut set: BitSet = (0..10).collect();
+    /// set.retain(|i| i % 3 == 0);
+    /// assert_eq!(set.iter().collect::<Vec<_>>(), [0, 3, 6, 9]);
     /// ```
-    #[cfg(target_pointer_width = "32")]
-    pub const BITS_PER_USIZE: usize = 32;
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(Index) -> bool,
+    {
+        let mut m3 = self.layer3;
+        while m3 != 0 {
+            let p2 = m3.trailing_zeros() as usize;
+            m3 &= !(1 << p2);
+
+            let mut m2 = self.layer2[p2];
+            while m2 != 0 {
+                let bit2 = m2.trailing_zeros() as usize;
+                m2 &= !(1 << bit2);
+                let p1 = (p2 << BITS) | bit2;
+
+                let mut m1 = self.layer1[p1];
+                while m1 != 0 {
+                    let bit1 = m1.trailing_zeros() as usize;
+                    m1 &= !(1 << bit1);
+                    let p0 = (p1 << BITS) | bit1;
+
+                    let old = self.layer0[p0];
+                    let mut m0 = old;
+                    let mut word = m0;
+                    while m0 != 0 {
+                        let bit0 = m0.trailing_zeros() as usize;
+                        m0 &= !(1 << bit0);
+                        if !f(((p0 << BITS) | bit0) as Index) {
+                            word &= !(1 << bit0);
+                        }
+                    }
+                    self.layer0[p0] = word;
+                    self.saturated.update(p0, old, word);
+                    self.len -= (old ^ word).count_ones() as usize;
+                    if word == 0 {
+                        self.layer1[p1] &= !(1 << bit1);
+                    }
+                }
+                if self.layer1[p1] == 0 {
+                    self.layer2[p2] &= !(1 << bit2);
+                }
+            }
+            if self.layer2[p2] == 0 {
+                self.layer3 &= !(1 << p2);
+            }
+        }
+    }
 
-    /// How many bits are in a `usize`.
+    /// Converts this set into an [`AtomicBitSet`] by copying its layer
+    /// words, without re-adding every index.
     ///
-    /// This value can be trivially determined. It is provided here as a constant for clarity.
-    ///
-    /// # Example
+    /// [`AtomicBitSet`]: struct.AtomicBitSet.html
+    pub fn into_atomic(self) -> AtomicBitSet {
+        AtomicBitSet::from_bitset(&self)
+    }
+
+    /// Overwrites this set with the contents of `other`, reusing the
+    /// existing allocations.
     ///
-    /// ```
-    /// use hibitset::BitSet;
-    /// assert_eq!(BitSet::BITS_PER_USIZE, std::mem::size_of::<usize>()*8);
-    /// ```
-    #[cfg(target_pointer_width = "64")]
-    pub const BITS_PER_USIZE: usize = 64;
+    /// To copy from another `BitSet`, `clone_from` is faster.
+    pub fn copy_from<B>(&mut self, other: &B)
+    where
+        B: BitSetLike + ?Sized,
+    {
+        self.clear();
+        self.add_words(other);
+    }
 
-    /// Returns the bottom layer of the bitset as a slice. Each bit in this slice refers to a single
-    /// `Index`.
+    /// Creates a `BitSet` holding every index found in any of `sets`.
     ///
-    /// The slice's length will be at least the length needed to reflect all the `1`s in the bitset,
-    /// but is not otherwise guaranteed. Consider it to be an implementation detail.
+    /// All of the sets are merged word by word in a single pass over
+    /// their combined hierarchy.
     ///
     /// # Example
     ///
     /// ```
-    /// use hibitset::BitSet;
-    ///
-    /// let index: u32 = 12345;
-    ///
-    /// let mut bitset = BitSet::new();
-    /// bitset.add(index);
+    /// use hibitset::{BitSet, BitSetAll, BitSetLike};
     ///
-    /// // layer 0 is 1:1 with Indexes, so we expect that bit in the slice to be set
-    /// let slice = bitset.layer0_as_slice();
-    /// let bit_index = index as usize;
+    /// let a: BitSet = (0..3).collect();
+    /// let b: BitSet = (10..12).collect();
+    /// let c: BitSet = (20..21).collect();
     ///
-    /// // map that bit index to a usize in the slice and a bit within that usize
-    /// let slice_index = bit_index / BitSet::BITS_PER_USIZE;
-    /// let bit_at_index = bit_index % BitSet::BITS_PER_USIZE;
+    /// let union = BitSet::union_of(&[&a, &b, &c]);
+    /// assert_eq!(union.iter().collect::<Vec<_>>(), [0, 1, 2, 10, 11, 20]);
     ///
-    /// assert_eq!(slice[slice_index], 1 << bit_at_index);
+    /// let intersection = BitSet::intersection_of(&[&a, &BitSetAll]);
+    /// assert_eq!(intersection, a);
     /// ```
-    pub fn layer0_as_slice(&self) -> &[usize] {
-        self.layer0.as_slice()
+    pub fn union_of(sets: &[&dyn BitSetLike]) -> BitSet {
+        let mut set = BitSet::new();
+        set.add_words(&BitSetOrN(sets.to_vec()));
+        set
     }
 
-    /// How many `Index`es are described by as single layer 1 bit, intended for use with
-    /// `BitSet::layer1_as_slice()`.
+    /// Creates the union of many `BitSet`s, ORing their layer 0 words in
+    /// parallel.
     ///
-    /// `BitSet`s are defined in terms of `usize`s summarizing `usize`s, so this value can be
-    /// trivially determined. It is provided here as a constant for clarity.
+    /// Layer 0 is split into blocks of `usize_bits²` words, each of which
+    /// is merged from every set by a rayon job, and the layers above are
+    /// rebuilt once at the end. This pays off over [`union_of`] when
+    /// there are many or large sets to merge.
     ///
     /// # Example
     ///
     /// ```
     /// use hibitset::BitSet;
-    /// assert_eq!(BitSet::LAYER1_GRANULARITY, BitSet::BITS_PER_USIZE);
-    /// ```
-    pub const LAYER1_GRANULARITY: usize = Self::BITS_PER_USIZE;
-
-    /// Returns the second layer of the bitset as a slice. Each bit in this slice summarizes a
-    /// corresponding `usize` from `layer0`. (If `usize` is 64 bits, bit 0 will be set if any
-    /// `Index`es 0-63 are set, bit 1 will be set if any `Index`es 64-127 are set, etc.)
-    /// `BitSet::LAYER1_GRANULARITY` reflects how many indexes are summarized per layer 1 bit.
-    ///
-    /// The slice's length is not guaranteed, except that it will be at least the length needed to
-    /// reflect all the `1`s in the bitset.
-    ///
-    /// # Example
     ///
+    /// let sets: Vec<BitSet> = (0..100).map(|i| (i * 10..i * 10 + 3).collect()).collect();
+    /// let refs: Vec<&BitSet> = sets.iter().collect();
+    /// let union = BitSet::par_union_of(&refs);
+    /// assert_eq!(union.len(), 300);
+    /// assert_eq!(union, BitSet::union_of(&sets.iter().map(|s| s as _).collect::<Vec<_>>()));
     /// ```
-    /// use hibitset::BitSet;
-    ///
-    /// let index: u32 = 12345;
-    ///
-    /// let mut bitset = BitSet::new();
-    /// bitset.add(index);
     ///
-    /// // layer 1 summarizes multiple indexes per bit, so divide appropriately
-    /// let slice = bitset.layer1_as_slice();
-    /// let bit_index = index as usize / BitSet::LAYER1_GRANULARITY;
+    /// [`union_of`]: #method.union_of
+    #[cfg(feature = "parallel")]
+    pub fn par_union_of(sets: &[&BitSet]) -> BitSet {
+        use pages::Page;
+        use rayon::prelude::*;
+
+        let words = sets.iter().map(|set| set.layer0.len()).max().unwrap_or(0);
+        let pages: Vec<Option<Page>> = (0..words >> BITS)
+            .into_par_iter()
+            .map(|p1| {
+                let mut union = None;
+                for other in sets {
+                    if let Some(page) = other.layer0.page(p1) {
+                        simd::or_into(union.get_or_insert([0; 1 << BITS]), page);
+                    }
+                }
+                union
+            })
+            .collect();
+        let mut layer0 = Pages::new_in(&Global);
+        if words > 0 {
+            layer0.grow(words - 1);
+        }
+        for (p1, page) in pages.into_iter().enumerate() {
+            if let Some(page) = page {
+                *layer0.page_mut(p1) = page;
+            }
+        }
+        BitSet::from_layer0(layer0)
+    }
+
+    /// Creates a `BitSet` holding the indices found in all of `sets`.
     ///
-    /// // map that bit index to a usize in the slice and a bit within that usize
-    /// let slice_index = bit_index / BitSet::BITS_PER_USIZE;
+    /// See [`union_of`](#method.union_of).
+    pub fn intersection_of(sets: &[&dyn BitSetLike]) -> BitSet {
+        let mut set = BitSet::new();
+        set.add_words(&BitSetAndN(sets.to_vec()));
+        set
+    }
+
+    /// Ors the non-empty layer 0 words of `other` into this set, only
+    /// setting summary bits above words that are actually non-empty.
+    fn add_words<B>(&mut self, other: &B)
+    where
+        B: BitSetLike + ?Sized,
+    {
+        let mut m3 = other.layer3();
+        while m3 != 0 {
+            let p2 = m3.trailing_zeros() as usize;
+            m3 &= !(1 << p2);
+
+            let mut m2 = other.layer2(p2);
+            while m2 != 0 {
+                let bit2 = m2.trailing_zeros() as usize;
+                m2 &= !(1 << bit2);
+                let p1 = (p2 << BITS) | bit2;
+
+                let mut m1 = other.layer1(p1);
+                while m1 != 0 {
+                    let bit1 = m1.trailing_zeros() as usize;
+                    m1 &= !(1 << bit1);
+                    let p0 = (p1 << BITS) | bit1;
+
+                    let word = other.layer0(p0);
+                    if word != 0 {
+                        self.add_word(p0, word);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Creates a `BitSet` from the non-empty layer 0 words of `other`.
+    pub(crate) fn from_words<B>(other: &B) -> BitSet
+    where
+        B: BitSetLike + ?Sized,
+    {
+        let mut set = BitSet::new();
+        set.add_words(other);
+        set
+    }
+
+    /// Creates a `BitSet` from its layer 0 words, summarizing them into
+    /// the layers above.
+    pub(crate) fn from_layer0(layer0: Pages<Global>) -> BitSet {
+        let upper = |lower: usize| (lower + (1 << BITS) - 1) >> BITS;
+        let mut set = BitSet::new();
+        set.layer1 = vec![0; upper(layer0.len())];
+        set.layer2 = vec![0; upper(set.layer1.len())];
+        set.layer0 = layer0;
+        set.rebuild_summaries();
+        set
+    }
+
+    /// Returns how many indices the set can hold without growing, i.e.
+    /// every index below this value can be added without allocating.
+    ///
+    /// Layer 0 pages are allocated on first write, so this only counts
+    /// up to the first page that isn't allocated yet.
+    pub fn capacity(&self) -> usize {
+        self.layer0.capacity() << BITS
+    }
+
+    /// Releases the memory held for indices above the highest one in the
+    /// set, by dropping the trailing zero words of each layer and freeing
+    /// the layer 0 pages that are all zero.
+    pub fn shrink_to_fit(&mut self) {
+        let round_up = |len: usize| (len + (1 << BITS) - 1) >> BITS;
+        self.layer0.shrink_to_fit();
+        let len1 = cmp::max(trim_words(&self.layer1).len(), round_up(self.layer0.len()));
+        let len2 = cmp::max(trim_words(&self.layer2).len(), round_up(len1));
+
+        self.layer1.truncate(len1);
+        self.layer1.shrink_to_fit();
+        self.layer2.truncate(len2);
+        self.layer2.shrink_to_fit();
+        self.saturated.shrink_to_fit();
+    }
+
+    /// Returns the lowest index that is not in the set, or `None` if every
+    /// index up to the maximum is in it.
+    ///
+    /// This is a shorthand for [`next_clear_bit(0)`].
+    ///
+    /// [`next_clear_bit(0)`]: #method.next_clear_bit
+    pub fn first_zero(&self) -> Option<Index> {
+        self.next_clear_bit(0)
+    }
+
+    /// Returns the lowest index at or after `from` that is not in the
+    /// set, or `None` if there is none below the maximum index.
+    ///
+    /// Next to the summaries of the non-empty words, the set keeps
+    /// summaries of the words that are all ones. The search climbs them
+    /// until a block with room in it turns up and descends into it, so
+    /// runs of indices that are all in the set are skipped a layer 1, 2
+    /// or 3 block at a time rather than read word by word.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::BitSet;
+    /// let set: BitSet = (0..100).chain(101..200).collect();
+    /// assert_eq!(set.first_zero(), Some(100));
+    /// assert_eq!(set.next_clear_bit(101), Some(200));
+    /// ```
+    pub fn next_clear_bit(&self, from: Index) -> Option<Index> {
+        if from as usize >= MAX_EID {
+            return None;
+        }
+        let p0 = from.offset(SHIFT1);
+        let first = !self.layer0.get(p0) & (!0 << from.row(SHIFT0));
+        if first != 0 {
+            return Some(((p0 << BITS) | first.trailing_zeros() as usize) as Index);
+        }
+        let p0 = self.saturated.next_unsaturated(p0 + 1);
+        if p0 >= MAX_EID >> BITS {
+            return None;
+        }
+        let id = (p0 << BITS) | (!self.layer0.get(p0)).trailing_zeros() as usize;
+        Some(id as Index)
+    }
+
+    /// Create an iterator over the indices below `end` that are not in
+    /// the set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::BitSet;
+    /// let set: BitSet = [0, 2, 3].iter().collect();
+    /// assert_eq!(set.iter_clear(6).collect::<Vec<_>>(), [1, 4, 5]);
+    /// ```
+    pub fn iter_clear(&self, end: Index) -> BitIter<BitSetNotIn<&BitSet>> {
+        BitSetNotIn(self, end).iter()
+    }
+
+    /// Adds every index of `other` to the set, working on whole layers.
+    ///
+    /// Unlike `|=`, which only visits the words `other` occupies, this
+    /// ORs every word of every layer, which is faster for dense sets. The
+    /// words are processed with SIMD when the `simd` feature is enabled
+    /// and the CPU supports it.
+    pub fn union_with(&mut self, other: &BitSet) {
+        if other.layer0.len() > 0 {
+            self.layer0.grow(other.layer0.len() - 1);
+        }
+        for (p1, page) in other.layer0.iter() {
+            simd::or_into(self.layer0.page_mut(p1), page);
+        }
+        Self::or_layer(&mut self.layer1, &other.layer1);
+        Self::or_layer(&mut self.layer2, &other.layer2);
+        self.layer3 |= other.layer3;
+        self.saturated.rebuild(&self.layer0);
+        self.len = self.layer0.count_ones();
+    }
+
+    /// Removes every index not in `other` from the set, working on whole
+    /// layers.
+    ///
+    /// Like [`union_with`], this is meant for dense sets, the layer 0
+    /// words are ANDed as a whole and the upper layers are rebuilt from
+    /// them.
+    ///
+    /// [`union_with`]: #method.union_with
+    pub fn intersect_with(&mut self, other: &BitSet) {
+        for (p1, page) in self.layer0.iter_mut() {
+            match other.layer0.page(p1) {
+                Some(theirs) => simd::and_into(page, theirs),
+                None => *page = [0; 1 << BITS],
+            }
+        }
+        self.rebuild_summaries();
+    }
+
+    /// Replaces the set with its complement in `0..=max`, i.e. the indices
+    /// up to and including `max` that weren't in it. Indices above `max`
+    /// are removed.
+    ///
+    /// The layer 0 words are inverted in place and the upper layers are
+    /// rebuilt from them, so unlike collecting a [`BitSetNot`] this
+    /// doesn't allocate beyond growing the set to `max`.
+    ///
+    /// Panics if `max` is beyond the maximum index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{BitSet, BitSetLike};
+    /// let mut set: BitSet = [1, 3, 100].iter().collect();
+    /// set.invert_up_to(5);
+    /// assert_eq!((&set).iter().collect::<Vec<_>>(), [0, 2, 4, 5]);
+    /// ```
+    ///
+    /// [`BitSetNot`]: struct.BitSetNot.html
+    pub fn invert_up_to(&mut self, max: Index) {
+        self.grow(max);
+        let (p0, p1, p2) = offsets(max);
+        self.layer0.truncate(p0 + 1);
+        self.layer1.truncate(p1 + 1);
+        self.layer2.truncate(p2 + 1);
+        // Every word is set after inverting, so every page is needed.
+        self.layer0.fill(p0);
+        for (_, page) in self.layer0.iter_mut() {
+            for word in page.iter_mut() {
+                *word = !*word;
+            }
+        }
+        // Keep the bits of the last word up to and including `max`, and
+        // none of the words the last page has past it.
+        self.layer0[p0] &= !0 >> ((1 << BITS) - 1 - max.row(SHIFT0));
+        self.layer0.truncate(p0 + 1);
+        self.rebuild_summaries();
+    }
+
+    /// Adds `n` to every index in the set. Indices that would go past the
+    /// maximum index are removed.
+    ///
+    /// The layer 0 words are shifted in place, carrying bits across word
+    /// boundaries, and the upper layers are rebuilt from them, so this
+    /// takes time in the number of words rather than indices.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{BitSet, BitSetLike};
+    /// let mut set: BitSet = [0, 3, 63].iter().collect();
+    /// set.shift_left(100);
+    /// assert_eq!((&set).iter().collect::<Vec<_>>(), [100, 103, 163]);
+    /// ```
+    pub fn shift_left(&mut self, n: usize) {
+        let (words, bits) = (n >> BITS, n & ((1 << BITS) - 1));
+        let max_words = MAX_EID >> BITS;
+        let len = self.layer0.trimmed_len();
+        if len == 0 || words >= max_words {
+            self.clear();
+            return;
+        }
+        let new_len = cmp::min(len + words + (bits != 0) as usize, max_words);
+        let mut layer0 = Pages::new_in(&Global);
+        layer0.grow(new_len - 1);
+        for (p1, page) in self.layer0.iter() {
+            for (row, &word) in page.iter().enumerate().filter(|&(_, &word)| word != 0) {
+                let p0 = ((p1 << BITS) | row) + words;
+                if p0 < new_len {
+                    layer0[p0] |= word << bits;
+                }
+                if bits != 0 && p0 + 1 < new_len {
+                    layer0[p0 + 1] |= word >> ((1 << BITS) - bits);
+                }
+            }
+        }
+        self.layer0 = layer0;
+        self.fit_summaries();
+        self.rebuild_summaries();
+    }
+
+    /// Subtracts `n` from every index in the set. Indices below `n` are
+    /// removed.
+    ///
+    /// Like [`shift_left`], this works on whole layer 0 words.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{BitSet, BitSetLike};
+    /// let mut set: BitSet = [1, 100, 1_000].iter().collect();
+    /// set.shift_right(100);
+    /// assert_eq!((&set).iter().collect::<Vec<_>>(), [0, 900]);
+    /// ```
+    ///
+    /// [`shift_left`]: #method.shift_left
+    pub fn shift_right(&mut self, n: usize) {
+        let (words, bits) = (n >> BITS, n & ((1 << BITS) - 1));
+        let len = self.layer0.len();
+        if words >= len {
+            self.clear();
+            return;
+        }
+        let new_len = len - words;
+        let mut layer0 = Pages::new_in(&Global);
+        layer0.grow(new_len - 1);
+        for (p1, page) in self.layer0.iter() {
+            for (row, &word) in page.iter().enumerate().filter(|&(_, &word)| word != 0) {
+                let p0 = (p1 << BITS) | row;
+                if p0 >= words {
+                    layer0[p0 - words] |= word >> bits;
+                }
+                if bits != 0 && p0 > words {
+                    layer0[p0 - words - 1] |= word << ((1 << BITS) - bits);
+                }
+            }
+        }
+        self.layer0 = layer0;
+        self.fit_summaries();
+        self.rebuild_summaries();
+    }
+
+    /// Sizes layers 1 and 2 to exactly cover layer 0.
+    fn fit_summaries(&mut self) {
+        let upper = |lower: usize| (lower + (1 << BITS) - 1) >> BITS;
+        let len1 = upper(self.layer0.len());
+        self.layer1.resize(len1, 0);
+        self.layer2.resize(upper(len1), 0);
+    }
+
+    /// Splits the set in two at `at`, returning a new set with every index
+    /// greater than or equal to `at`, and keeping the ones below it.
+    ///
+    /// The layer 0 words are moved over as a whole and the upper layers
+    /// of both halves are rebuilt from them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{BitSet, BitSetLike};
+    /// let mut low: BitSet = (0..10).map(|i| i * 100).collect();
+    /// let high = low.split_off(450);
+    /// assert_eq!((&low).iter().collect::<Vec<_>>(), [0, 100, 200, 300, 400]);
+    /// assert_eq!((&high).iter().collect::<Vec<_>>(), [500, 600, 700, 800, 900]);
+    /// ```
+    pub fn split_off(&mut self, at: Index) -> BitSet {
+        let (p0, mask) = (at.offset(SHIFT1), at.mask(SHIFT0));
+        let mut other = BitSet::new();
+        if p0 >= self.layer0.len() {
+            return other;
+        }
+
+        other.layer0.grow(self.layer0.len() - 1);
+        for (p1, page) in self.layer0.iter().filter(|&(p1, _)| p1 >= p0 >> BITS) {
+            *other.layer0.page_mut(p1) = *page;
+        }
+        // The words below `p0` and the indices below `at` stay here.
+        other.layer0.clear_below(p0);
+        if other.layer0.get(p0) != 0 {
+            other.layer0[p0] &= !(mask - 1);
+        }
+        other.layer1 = vec![0; self.layer1.len()];
+        other.layer2 = vec![0; self.layer2.len()];
+        other.rebuild_summaries();
+
+        // Keep the word `at` falls in only if it has indices below `at`.
+        if self.layer0.get(p0) != 0 {
+            self.layer0[p0] &= mask - 1;
+        }
+        let words = p0 + (self.layer0.get(p0) != 0) as usize;
+        self.layer0.truncate(words);
+        let upper = |lower: usize| (lower + (1 << BITS) - 1) >> BITS;
+        self.layer1.truncate(upper(self.layer0.len()));
+        self.layer2.truncate(upper(self.layer1.len()));
+        self.rebuild_summaries();
+        other
+    }
+
+    /// Renumbers the indices of the set to `0..len`, keeping their order,
+    /// and returns the [`Remap`] from the old indices to the new ones.
+    ///
+    /// This packs an id space that removals have left fragmented, so the
+    /// arrays indexed by it can be shrunk along with it, by
+    /// [`Remap::apply`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{BitSet, BitSetLike};
+    /// let mut set: BitSet = [3, 40, 41, 1_000].iter().collect();
+    /// let remap = set.compact();
+    /// assert_eq!((&set).iter().collect::<Vec<_>>(), [0, 1, 2, 3]);
+    /// assert_eq!(remap.get(1_000), Some(3));
+    /// assert_eq!(remap.get(4), None);
+    /// assert_eq!(remap.old(1), Some(40));
+    /// ```
+    ///
+    /// [`Remap`]: struct.Remap.html
+    /// [`Remap::apply`]: struct.Remap.html#method.apply
+    pub fn compact(&mut self) -> Remap {
+        let len = self.len;
+        let mut layer0 = vec![!0; len >> BITS];
+        if len & ((1 << BITS) - 1) != 0 {
+            layer0.push((1 << (len & ((1 << BITS) - 1))) - 1);
+        }
+        let old = mem::replace(self, BitSet::from_layer0(Pages::from_words(layer0)));
+        Remap::new(old)
+    }
+
+    /// Rebuilds layers 1 to 3 from layer 0, and the count of indices and
+    /// the saturated summaries.
+    fn rebuild_summaries(&mut self) {
+        self.len = self.layer0.count_ones();
+        self.saturated.rebuild(&self.layer0);
+        for (p1, word) in self.layer1.iter_mut().enumerate() {
+            *word = match self.layer0.page(p1) {
+                Some(page) => summary(page),
+                None => 0,
+            };
+        }
+        Self::summarize(&mut self.layer2, &self.layer1);
+        self.layer3 = self
+            .layer2
+            .iter()
+            .enumerate()
+            .fold(0, |layer3, (i, &word)| {
+                layer3 | (((word != 0) as usize) << i)
+            });
+    }
+
+    fn or_layer(dst: &mut Vec<usize>, src: &[usize]) {
+        if dst.len() < src.len() {
+            dst.resize(src.len(), 0);
+        }
+        simd::or_into(&mut dst[..src.len()], src);
+    }
+
+    /// Sets each bit of `upper` to whether the matching word of `lower`
+    /// is non-zero.
+    fn summarize(upper: &mut [usize], lower: &[usize]) {
+        for (word, chunk) in upper.iter_mut().zip(lower.chunks(1 << BITS)) {
+            *word = summary(chunk);
+        }
+    }
+
+    /// Returns the number of bytes the set has allocated on the heap.
+    pub fn heap_bytes(&self) -> usize {
+        self.memory_stats().total()
+    }
+
+    /// Returns the bytes the set has allocated on the heap for each layer.
+    ///
+    /// Layers 1 and 2 include the words marking the saturated blocks
+    /// below them, which [`next_clear_bit`] skips.
+    ///
+    /// [`next_clear_bit`]: #method.next_clear_bit
+    pub fn memory_stats(&self) -> MemoryStats {
+        let words = |layer: &Vec<usize>| layer.capacity() * mem::size_of::<usize>();
+        let (saturated1, saturated2) = self.saturated.heap_size();
+        MemoryStats {
+            layer2: words(&self.layer2) + saturated2,
+            layer1: words(&self.layer1) + saturated1,
+            layer0: self.layer0.heap_size(),
+        }
+    }
+
+    /// Creates a `BitSet` from a plain bitmap, where bit `i` of byte `k`,
+    /// counting from the least significant bit, is index `8 * k + i`.
+    ///
+    /// The bytes are packed into words directly and the upper layers are
+    /// built from them afterwards, rather than adding each index.
+    ///
+    /// Panics if the bitmap holds more than the maximum number of indices.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{BitSet, BitSetLike};
+    /// let set = BitSet::from_bytes(&[0b0000_0101, 0, 0b1000_0000]);
+    /// assert_eq!((&set).iter().collect::<Vec<_>>(), [0, 2, 23]);
+    /// assert_eq!(set.to_bytes(), [0b0000_0101, 0, 0b1000_0000]);
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> BitSet {
+        let mut set = BitSet::new();
+        if bytes.is_empty() {
+            return set;
+        }
+        assert!(
+            bytes.len() * 8 <= MAX_EID,
+            "Expected at most {} bits, found {}",
+            MAX_EID,
+            bytes.len() * 8
+        );
+        set.grow((bytes.len() * 8 - 1) as Index);
+        set.layer0 = Pages::from_words(bytes.chunks(mem::size_of::<usize>()).map(|chunk| {
+            let mut le = [0; mem::size_of::<usize>()];
+            le[..chunk.len()].copy_from_slice(chunk);
+            usize::from_le_bytes(le)
+        }));
+        set.rebuild_summaries();
+        set
+    }
+
+    /// Converts the set to a plain bitmap, in the bit order read by
+    /// [`from_bytes`](#method.from_bytes).
+    ///
+    /// The bitmap ends with the byte holding the highest index, so it's
+    /// empty for an empty set.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let words = self.layer0.trimmed_len();
+        let mut bytes = self
+            .layer0
+            .words()
+            .take(words)
+            .flat_map(|word| word.to_le_bytes())
+            .collect::<Vec<_>>();
+        let len = bytes
+            .iter()
+            .rposition(|&byte| byte != 0)
+            .map_or(0, |last| last + 1);
+        bytes.truncate(len);
+        bytes
+    }
+
+    /// Writes the set to `writer` in a compact binary format.
+    ///
+    /// The format doesn't depend on the width of `usize` or on the
+    /// endianness of the platform. Runs of empty words are skipped rather
+    /// than written, so sparse sets stay small.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::BitSet;
+    /// let set: BitSet = (0..1_000).filter(|i| i % 7 == 0).collect();
+    /// let mut bytes = Vec::new();
+    /// set.write_to(&mut bytes).unwrap();
+    /// assert_eq!(BitSet::read_from(&bytes[..]).unwrap(), set);
+    /// ```
+    pub fn write_to<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        persist::write_to(self, writer)
+    }
+
+    /// Reads a set written by [`write_to`](#method.write_to) from
+    /// `reader`.
+    ///
+    /// Fails with `ErrorKind::InvalidData` if the data isn't a bit set of a
+    /// supported version of the format or holds an index out of range.
+    pub fn read_from<R: io::Read>(reader: R) -> io::Result<BitSet> {
+        persist::read_from(reader)
+    }
+
+    /// How many bits are in a `usize`.
+    ///
+    /// This value can be trivially determined. It is provided here as a constant for clarity.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::BitSet;
+    /// assert_eq!(BitSet::BITS_PER_USIZE, std::mem::size_of::<usize>()*8);
+    /// ```
+    #[cfg(target_pointer_width = "32")]
+    pub const BITS_PER_USIZE: usize = 32;
+
+    /// How many bits are in a `usize`.
+    ///
+    /// This value can be trivially determined. It is provided here as a constant for clarity.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::BitSet;
+    /// assert_eq!(BitSet::BITS_PER_USIZE, std::mem::size_of::<usize>()*8);
+    /// ```
+    #[cfg(target_pointer_width = "64")]
+    pub const BITS_PER_USIZE: usize = 64;
+
+    /// Returns the bottom layer of the bitset as a slice. Each bit in this slice refers to a single
+    /// `Index`.
+    ///
+    /// The slice's length will be at least the length needed to reflect all the `1`s in the bitset,
+    /// but is not otherwise guaranteed. Consider it to be an implementation detail.
+    ///
+    /// The layer is stored in pages, see the [memory notes](#memory). The slice is borrowed
+    /// while the set only covers the first page, and is otherwise a copy with the pages that
+    /// were never written to filled in with zeros.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::BitSet;
+    ///
+    /// let index: u32 = 12345;
+    ///
+    /// let mut bitset = BitSet::new();
+    /// bitset.add(index);
+    ///
+    /// // layer 0 is 1:1 with Indexes, so we expect that bit in the slice to be set
+    /// let slice = bitset.layer0_as_slice();
+    /// let bit_index = index as usize;
+    ///
+    /// // map that bit index to a usize in the slice and a bit within that usize
+    /// let slice_index = bit_index / BitSet::BITS_PER_USIZE;
+    /// let bit_at_index = bit_index % BitSet::BITS_PER_USIZE;
+    ///
+    /// assert_eq!(slice[slice_index], 1 << bit_at_index);
+    /// ```
+    pub fn layer0_as_slice(&self) -> Cow<'_, [usize]> {
+        self.layer0.as_slice()
+    }
+
+    /// How many `Index`es are described by as single layer 1 bit, intended for use with
+    /// `BitSet::layer1_as_slice()`.
+    ///
+    /// `BitSet`s are defined in terms of `usize`s summarizing `usize`s, so this value can be
+    /// trivially determined. It is provided here as a constant for clarity.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::BitSet;
+    /// assert_eq!(BitSet::LAYER1_GRANULARITY, BitSet::BITS_PER_USIZE);
+    /// ```
+    pub const LAYER1_GRANULARITY: usize = Self::BITS_PER_USIZE;
+
+    /// Returns the second layer of the bitset as a slice. Each bit in this slice summarizes a
+    /// corresponding `usize` from `layer0`. (If `usize` is 64 bits, bit 0 will be set if any
+    /// `Index`es 0-63 are set, bit 1 will be set if any `Index`es 64-127 are set, etc.)
+    /// `BitSet::LAYER1_GRANULARITY` reflects how many indexes are summarized per layer 1 bit.
+    ///
+    /// The slice's length is not guaranteed, except that it will be at least the length needed to
+    /// reflect all the `1`s in the bitset.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::BitSet;
+    ///
+    /// let index: u32 = 12345;
+    ///
+    /// let mut bitset = BitSet::new();
+    /// bitset.add(index);
+    ///
+    /// // layer 1 summarizes multiple indexes per bit, so divide appropriately
+    /// let slice = bitset.layer1_as_slice();
+    /// let bit_index = index as usize / BitSet::LAYER1_GRANULARITY;
+    ///
+    /// // map that bit index to a usize in the slice and a bit within that usize
+    /// let slice_index = bit_index / BitSet::BITS_PER_USIZE;
     /// let bit_at_index = bit_index % BitSet::BITS_PER_USIZE;
     ///
     /// assert_eq!(slice[slice_index], 1 << bit_at_index);
@@ -376,20 +1575,115 @@ impl BitSet {
     pub fn layer2_as_slice(&self) -> &[usize] {
         self.layer2.as_slice()
     }
-}
 
-/// A generic interface for [`BitSetLike`]-like types.
-///
-/// Every `BitSetLike` is hierarchical, meaning that there
-/// are multiple levels that branch out in a tree like structure.
-///
-/// Layer0 each bit represents one Index of the set
-/// Layer1 each bit represents one `usize` of Layer0, and will be
+    /// Returns the words of layer `n`, with layer 3 as a single word.
+    ///
+    /// Like the `layer*_as_slice` methods, the slices may be longer than
+    /// needed for the indices in the set, and layer 0 is copied out of its
+    /// pages.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is above 3.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::BitSet;
+    ///
+    /// let set: BitSet = [1, 70].iter().collect();
+    /// assert_eq!(set.layer_words(0)[..2], [0b10, 0b1000000]);
+    /// assert_eq!(*set.layer_words(1), [0b11]);
+    /// assert_eq!(*set.layer_words(3), [0b1]);
+    /// ```
+    pub fn layer_words(&self, n: usize) -> Cow<'_, [usize]> {
+        match n {
+            0 => self.layer0_as_slice(),
+            1 => Cow::Borrowed(&self.layer1),
+            2 => Cow::Borrowed(&self.layer2),
+            3 => Cow::Borrowed(slice::from_ref(&self.layer3)),
+            _ => panic!("Expected a layer from 0 to 3, found {}", n),
+        }
+    }
+
+    /// Checks that every word of layers 1 to 3 has a bit set exactly for
+    /// the non-empty words below it, and that the stored length matches
+    /// the bits of layer 0.
+    ///
+    /// The set's own methods keep this true. It's meant for debugging
+    /// code that edits words directly, and reports every word that is off.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::BitSet;
+    ///
+    /// let set: BitSet = (0..10_000).step_by(7).collect();
+    /// assert!(set.validate().is_ok());
+    /// ```
+    pub fn validate(&self) -> Result<(), InvalidHierarchy> {
+        let mut mismatches = Vec::new();
+        for layer in (1..LAYERS).rev() {
+            let (upper, lower) = (self.layer_words(layer), self.layer_words(layer - 1));
+            let words = cmp::max(upper.len(), (lower.len() + (1 << BITS) - 1) >> BITS);
+            for i in 0..words {
+                let expected = lower
+                    .iter()
+                    .skip(i << BITS)
+                    .take(1 << BITS)
+                    .enumerate()
+                    .fold(0, |word, (bit, &child)| {
+                        word | (((child != 0) as usize) << bit)
+                    });
+                let found = upper.get(i).cloned().unwrap_or(0);
+                if expected != found {
+                    mismatches.push(LayerMismatch::new(layer, i, expected, found));
+                }
+            }
+        }
+        let counted = self.layer0.count_ones();
+        let len = if counted == self.len {
+            None
+        } else {
+            Some((self.len, counted))
+        };
+        if mismatches.is_empty() && len.is_none() {
+            Ok(())
+        } else {
+            Err(InvalidHierarchy::new(mismatches, len))
+        }
+    }
+}
+
+/// A generic interface for [`BitSetLike`]-like types.
+///
+/// Every `BitSetLike` is hierarchical, meaning that there
+/// are multiple levels that branch out in a tree like structure.
+///
+/// Layer0 each bit represents one Index of the set
+/// Layer1 each bit represents one `usize` of Layer0, and will be
 /// set only if the word below it is not zero.
 /// Layer2 has the same arrangement but with Layer1, and Layer3 with Layer2.
 ///
 /// This arrangement allows for rapid jumps across the key-space.
 ///
+/// The trait is object safe: every method that is generic or takes `self`
+/// by value requires `Self: Sized`, so different kinds of sets can be
+/// stored together as `&dyn BitSetLike` or `Box<dyn BitSetLike>`. Both of
+/// these are `BitSetLike` themselves, so they can be iterated and
+/// combined like any other set.
+///
+/// ```
+/// # use hibitset::{BitSet, BitSetAnd, BitSetLike, BitSetNot, BitSetOrN};
+/// let a: BitSet = (0..10).collect();
+/// let b: BitSet = (5..15).collect();
+/// let sets: Vec<Box<dyn BitSetLike>> = vec![
+///     Box::new(BitSetAnd(a.clone(), b.clone())),
+///     Box::new(BitSetNot(BitSetNot(b))),
+/// ];
+/// assert_eq!(BitSetOrN(sets).iter().collect::<Vec<_>>(), (5..15).collect::<Vec<_>>());
+/// ```
+///
 /// [`BitSetLike`]: ../trait.BitSetLike.html
 pub trait BitSetLike {
     /// Gets the `usize` corresponding to layer and index.
@@ -429,14 +1723,517 @@ pub trait BitSetLike {
     /// Allows checking if set bit is contained in the bit set.
     fn contains(&self, i: Index) -> bool;
 
+    /// Hints that the word at `idx` of `level` will be read soon.
+    ///
+    /// `BitIter` calls this for the words it's about to descend into when
+    /// the `prefetch` feature is on. Sets backed by memory can issue a
+    /// software prefetch for the word, the default does nothing.
+    #[inline]
+    fn prefetch(&self, _level: usize, _idx: usize) {}
+
+    /// Returns `true` if every set bit of layers 1 to 3 has a non-empty
+    /// word below it, which holds for sets that store their layers.
+    ///
+    /// `BitIter` then counts the indices it has left to report an exact
+    /// size hint. Combinators whose upper layers can only over-approximate
+    /// return `false`, the default, and get cheaper bounds instead.
+    #[inline]
+    fn exact_summaries(&self) -> bool {
+        false
+    }
+
+    /// Returns an upper bound on the number of indices in the set, cheap
+    /// enough to compute for every size hint.
+    ///
+    /// The default counts the subtrees below layer 3. Sets that keep their
+    /// length return it, and combinators combine the bounds of the sets
+    /// they are built from, e.g. an intersection is no larger than the
+    /// smaller of its sets. `BitIter` caps its size hint with it.
+    #[inline]
+    fn len_upper_bound(&self) -> usize {
+        cmp::min((self.layer3().count_ones() as usize) << (BITS * 3), MAX_EID)
+    }
+
+    /// Returns the `BitSet` this set reads its layers from, if any.
+    ///
+    /// Combinators use it to spot when both of their operands are the same
+    /// set, as in `BitSetAnd(&set, &set)`, and read it only once.
+    #[inline]
+    fn backing_bitset(&self) -> Option<&BitSet> {
+        None
+    }
+
+    /// Collects the set into a new `BitSet`.
+    ///
+    /// Only the occupied layer 0 words are read, and each is stored whole,
+    /// instead of adding one index at a time as collecting [`iter`] does.
+    /// `BitSetAnd`, `BitSetOr`, `BitSetXor` and `BitSetNotIn` over sets
+    /// backed by `BitSet`s combine the layer 0 slices of their operands
+    /// in bulk and summarize the result once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{BitSet, BitSetLike};
+    /// let a: BitSet = (0..100).collect();
+    /// let b: BitSet = (50..200).collect();
+    /// let both = (&a & &b).to_bitset();
+    /// assert_eq!(both, (50..100).collect::<BitSet>());
+    /// ```
+    ///
+    /// [`iter`]: #method.iter
+    fn to_bitset(&self) -> BitSet {
+        BitSet::from_words(self)
+    }
+
+    /// Counts the indices in both `self` and `other`.
+    ///
+    /// Words are ANDed and popcounted layer by layer, only descending into
+    /// subtrees both sets occupy, so no intermediate set is built and no
+    /// indices are iterated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{BitSet, BitSetLike};
+    /// let a: BitSet = (0..100).collect();
+    /// let b: BitSet = (50..200).collect();
+    /// let common = a.intersection_count(&b);
+    /// let union = common + a.symmetric_difference_count(&b);
+    /// assert_eq!((common, union), (50, 200));
+    /// ```
+    fn intersection_count<B>(&self, other: &B) -> usize
+    where
+        Self: Sized,
+        B: BitSetLike + ?Sized,
+    {
+        count_at(self, other, LAYERS - 1, 0, |a, b| a & b, |a, b| a & b)
+    }
+
+    /// Returns bounds on the number of indices in both `self` and
+    /// `other`, for picking the order of joins before running them, in
+    /// the form of `Iterator::size_hint`.
+    ///
+    /// Only layers 3 to 1 are read: every layer 0 word both sets occupy
+    /// counts as full for the upper bound, so this costs a fraction of
+    /// [`intersection_count`]. The lower bound is always 0, as two
+    /// occupied words may still share no bits, which only reading layer 0
+    /// tells. The upper bound is always `Some`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{BitSet, BitSetLike};
+    /// let a: BitSet = (0..100).collect();
+    /// let b: BitSet = (90..200).collect();
+    /// let c: BitSet = (1_000..1_010).collect();
+    /// assert_eq!(a.estimate_intersection(&b), (0, Some(64)));
+    /// assert_eq!(a.estimate_intersection(&c), (0, Some(0)));
+    /// ```
+    ///
+    /// [`intersection_count`]: #method.intersection_count
+    fn estimate_intersection<B>(&self, other: &B) -> (usize, Option<usize>)
+    where
+        Self: Sized,
+        B: BitSetLike + ?Sized,
+    {
+        (0, Some(common_words_at(self, other, LAYERS - 1, 0) << BITS))
+    }
+
+    /// Counts the indices in exactly one of `self` and `other`.
+    ///
+    /// Like [`intersection_count`], this XORs and popcounts the layer 0
+    /// words without building a set, descending into the subtrees either
+    /// set occupies.
+    ///
+    /// [`intersection_count`]: #method.intersection_count
+    fn symmetric_difference_count<B>(&self, other: &B) -> usize
+    where
+        Self: Sized,
+        B: BitSetLike + ?Sized,
+    {
+        count_at(self, other, LAYERS - 1, 0, |a, b| a | b, |a, b| a ^ b)
+    }
+
+    /// Returns `true` if `self` and `other` hold exactly the same indices.
+    ///
+    /// The layers are compared from the top, descending only into
+    /// subtrees whose summary words differ or are occupied in both sets,
+    /// so sets that differ in their upper layers are told apart without
+    /// visiting any layer 0 words.
+    fn is_equal<B>(&self, other: &B) -> bool
+    where
+        Self: Sized,
+        B: BitSetLike + ?Sized,
+    {
+        equal_at(self, other, LAYERS - 1, 0)
+    }
+
+    /// Returns `true` if `self` has no indices in common with `other`.
+    ///
+    /// Only subtrees that are occupied in both sets are visited, so sets
+    /// that occupy different regions are rejected from the upper layers.
+    fn is_disjoint<B>(&self, other: &B) -> bool
+    where
+        Self: Sized,
+        B: BitSetLike + ?Sized,
+    {
+        disjoint_at(self, other, LAYERS - 1, 0)
+    }
+
+    /// Returns `true` if every index in `self` is also in `other`.
+    fn is_subset<B>(&self, other: &B) -> bool
+    where
+        Self: Sized,
+        B: BitSetLike + ?Sized,
+    {
+        subset_at(self, other, LAYERS - 1, 0)
+    }
+
+    /// Returns `true` if every index in `other` is also in `self`.
+    fn is_superset<B>(&self, other: &B) -> bool
+    where
+        Self: Sized,
+        B: BitSetLike + ?Sized,
+    {
+        subset_at(other, self, LAYERS - 1, 0)
+    }
+
     /// Create an iterator that will scan over the keyspace
+    ///
+    /// The indices are guaranteed to come in ascending order, which the
+    /// other ordered iterators of this trait, like [`iter_from`] and
+    /// [`iter_ranges`], rely on as well. Use [`iter_unordered`] where the
+    /// order doesn't matter.
+    ///
+    /// [`iter_from`]: #method.iter_from
+    /// [`iter_ranges`]: #method.iter_ranges
+    /// [`iter_unordered`]: #method.iter_unordered
     fn iter(self) -> BitIter<Self>
     where
         Self: Sized,
     {
         let layer3 = self.layer3();
+        let left = match layer3 {
+            0 => Some(0),
+            _ => self.backing_bitset().map(BitSet::len),
+        };
+
+        let mut iter = BitIter::new(self, [0, 0, 0, layer3], [0; LAYERS - 1]);
+        iter.left = left;
+        iter
+    }
+
+    /// Calls `f` with every index in the set, in ascending order.
+    ///
+    /// Unlike driving a [`BitIter`] with `next`, this walks the layers
+    /// with a nested loop per layer and keeps no iterator state between
+    /// indices, which is usually faster. `fold` on a `BitIter`, and the
+    /// adapters built on it like `count` and `sum`, take the same path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{BitSet, BitSetLike};
+    /// let set: BitSet = [1, 5, 300].iter().collect();
+    /// let mut sum = 0;
+    /// set.for_each(|i| sum += i);
+    /// assert_eq!(sum, 306);
+    /// ```
+    ///
+    /// [`BitIter`]: struct.BitIter.html
+    fn for_each<F>(&self, mut f: F)
+    where
+        Self: Sized,
+        F: FnMut(Index),
+    {
+        BitIter::new(self, [0, 0, 0, self.layer3()], [0; LAYERS - 1]).fold((), |(), i| f(i))
+    }
+
+    /// Create an iterator over the keyspace that yields the indices in
+    /// no particular order.
+    ///
+    /// Whole layer 0 words are taken at a time and drained in a tight
+    /// loop, which is faster than [`iter`] for dense sets and when
+    /// consumed with `for_each` or `fold`. The order may change between
+    /// releases, whenever a different one is measurably faster.
+    ///
+    /// [`iter`]: #method.iter
+    fn iter_unordered(self) -> BitUnorderedIter<Self>
+    where
+        Self: Sized,
+    {
+        BitUnorderedIter::new(self)
+    }
+
+    /// Create an iterator that will scan over the keyspace, starting at
+    /// `start`.
+    ///
+    /// The layers are seeked straight to `start`, so no time is spent
+    /// on the indices before it.
+    fn iter_from(self, start: Index) -> BitIter<Self>
+    where
+        Self: Sized,
+    {
+        BitIter::starting_at(self, start)
+    }
+
+    /// Create an iterator that will scan over the indices in `range`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::{BitSet, BitSetLike};
+    ///
+    /// let set: BitSet = (0..100).filter(|i| i % 10 == 0).collect();
+    /// assert_eq!(set.iter_range(15..45).collect::<Vec<_>>(), [20, 30, 40]);
+    /// ```
+    fn iter_range(self, range: Range<Index>) -> BitRangeIter<Self>
+    where
+        Self: Sized,
+    {
+        BitRangeIter::new(self, range.start, range.end)
+    }
+
+    /// Create a cursor, which iterates over the keyspace like `iter` but
+    /// can be moved around and resumed from a stored position.
+    fn cursor(self) -> BitCursor<Self>
+    where
+        Self: Sized,
+    {
+        BitCursor::new(self)
+    }
+
+    /// Create an iterator over the indices of the set, each paired with
+    /// a mutable reference to the element at that index of `slice`.
+    ///
+    /// The slice is checked to be long enough once per layer 0 word
+    /// rather than for every index, and the iterator panics on the first
+    /// word with an index past its end.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{BitSet, BitSetLike};
+    /// let alive: BitSet = [1, 3].iter().collect();
+    /// let mut health = [10, 10, 10, 10];
+    /// for (_, health) in (&alive).zip_mut(&mut health) {
+    ///     *health -= 1;
+    /// }
+    /// assert_eq!(health, [10, 9, 10, 9]);
+    /// ```
+    fn zip_mut<V>(self, slice: &mut [V]) -> BitZipMut<'_, Self, V>
+    where
+        Self: Sized,
+    {
+        BitZipMut::new(self, slice)
+    }
+
+    /// Create an iterator over the non-zero layer 0 words, each paired
+    /// with the index that its lowest bit stands for.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::{BitSet, BitSetLike};
+    ///
+    /// let set: BitSet = (0..3).collect();
+    /// assert_eq!(set.masks().collect::<Vec<_>>(), [(0, 0b111)]);
+    /// ```
+    fn masks(self) -> BitMaskIter<Self>
+    where
+        Self: Sized,
+    {
+        BitMaskIter::new(self)
+    }
+
+    /// Create an iterator over the non-empty aligned blocks of
+    /// `usize::BITS` indices, as [`BlockRef`]s, for kernels that process
+    /// indices in groups, one per SIMD lane.
+    ///
+    /// This is [`masks`] with a handle that can list, count and intersect
+    /// the indices of a block.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hibitset::{BitSet, BitSetLike};
+    ///
+    /// let set: BitSet = [1, 2, 70].iter().collect();
+    /// let blocks: Vec<_> = set.iter_blocks().collect();
+    /// assert_eq!((blocks[0].base, blocks[0].mask), (0, 0b110));
+    /// assert_eq!(blocks[1].indices().collect::<Vec<_>>(), [70]);
+    /// ```
+    ///
+    /// [`BlockRef`]: struct.BlockRef.html
+    /// [`masks`]: #method.masks
+    fn iter_blocks(self) -> BitBlockIter<Self>
+    where
+        Self: Sized,
+    {
+        BitBlockIter::new(self)
+    }
+
+    /// Create an iterator over the runs of consecutive indices in the
+    /// set, yielding each run as a `Range`.
+    ///
+    /// Runs are found by counting trailing ones and zeros in whole layer 0
+    /// words, and runs spanning several words are merged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{BitSet, BitSetLike};
+    /// let set: BitSet = [1, 2, 3, 10, 64, 65].iter().collect();
+    /// assert_eq!(set.iter_ranges().collect::<Vec<_>>(), [1..4, 10..11, 64..66]);
+    /// ```
+    fn iter_ranges(self) -> BitRunIter<Self>
+    where
+        Self: Sized,
+    {
+        BitRunIter::new(self)
+    }
+
+    /// Create an iterator over the indices in `self` that are not in
+    /// `old`.
+    ///
+    /// Each layer 0 word is masked with the inverted word of `old`, and
+    /// only the parts of the tree `self` occupies are walked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{BitSet, BitSetLike};
+    /// let old: BitSet = [1, 2, 3].iter().collect();
+    /// let new: BitSet = [2, 3, 4].iter().collect();
+    /// assert_eq!((&new).iter_added(&old).collect::<Vec<_>>(), [4]);
+    /// assert_eq!((&new).iter_removed(&old).collect::<Vec<_>>(), [1]);
+    /// ```
+    fn iter_added<B: BitSetLike>(self, old: B) -> BitIter<BitSetAnd<Self, BitSetNot<B>>>
+    where
+        Self: Sized,
+    {
+        BitSetAnd(self, BitSetNot(old)).iter()
+    }
+
+    /// Create an iterator over the indices in `old` that are not in
+    /// `self`, the opposite of [`iter_added`].
+    ///
+    /// [`iter_added`]: #method.iter_added
+    fn iter_removed<B: BitSetLike>(self, old: B) -> BitIter<BitSetAnd<B, BitSetNot<Self>>>
+    where
+        Self: Sized,
+    {
+        BitSetAnd(old, BitSetNot(self)).iter()
+    }
+
+    /// Create an iterator over the indices in exactly one of `self` and
+    /// `old`, in ascending order, each tagged with whether it was added
+    /// to or removed from `old`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{BitSet, BitSetLike, Change};
+    /// let old: BitSet = [1, 2, 3].iter().collect();
+    /// let new: BitSet = [2, 3, 4].iter().collect();
+    /// assert_eq!(
+    ///     (&new).diff(&old).collect::<Vec<_>>(),
+    ///     [Change::Removed(1), Change::Added(4)]
+    /// );
+    /// ```
+    fn diff<B: BitSetLike>(self, old: B) -> BitDiffIter<Self, B>
+    where
+        Self: Sized,
+    {
+        BitDiffIter::new(self, old)
+    }
+
+    /// Returns a 64 bit digest of the indices in the set, for telling
+    /// whether two sets are likely equal without comparing them.
+    ///
+    /// The digest only depends on the indices, so it's the same for every
+    /// kind of set, on every platform and across releases: it's XXH64 with
+    /// a seed of zero over the index and the value of each non-zero 64 bit
+    /// word, both as little-endian `u64`s, in ascending order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{AtomicBitSet, BitSet, BitSetLike};
+    /// let mut set = BitSet::new();
+    /// set.add(3);
+    /// let atomic = AtomicBitSet::new();
+    /// atomic.add_atomic(3);
+    /// assert_eq!(set.content_hash(), atomic.content_hash());
+    /// ```
+    fn content_hash(&self) -> u64
+    where
+        Self: Sized,
+    {
+        digest::content_hash(self)
+    }
 
-        BitIter::new(self, [0, 0, 0, layer3], [0; LAYERS - 1])
+    /// Converts the set to a [`RoaringBitmap`], copying it word by word.
+    ///
+    /// [`RoaringBitmap`]: https://docs.rs/roaring/*/roaring/bitmap/struct.RoaringBitmap.html
+    #[cfg(feature = "roaring")]
+    fn to_roaring(&self) -> ::roaring::RoaringBitmap
+    where
+        Self: Sized,
+    {
+        roaring_bitmap::to_roaring(self)
+    }
+
+    /// Limits the set to the indices below `max`.
+    ///
+    /// Combinators holding a [`BitSetNot`] claim every subtree the inner
+    /// set doesn't fill, so they reach up to the maximum index. Bounded,
+    /// their upper layers are masked to the subtrees below `max` and they
+    /// can be iterated, sequentially or in parallel, like a stored set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{BitSet, BitSetLike};
+    /// let a: BitSet = (0..10).collect();
+    /// let b: BitSet = (0..10).filter(|i| i % 2 == 0).collect();
+    /// let odd = (!&b).bounded(10);
+    /// assert_eq!(odd.iter().collect::<Vec<_>>(), [1, 3, 5, 7, 9]);
+    /// assert_eq!((&a & !&b).bounded(5).iter().collect::<Vec<_>>(), [1, 3]);
+    /// ```
+    ///
+    /// [`BitSetNot`]: struct.BitSetNot.html
+    fn bounded(self, max: Index) -> BitSetAnd<Self, Range<Index>>
+    where
+        Self: Sized,
+    {
+        BitSetAnd(self, 0..max)
+    }
+
+    /// Wraps the set in a [`CachedBitSet`], which works out its contents
+    /// on the first access and keeps them until it is marked dirty.
+    ///
+    /// This is meant for combinators that are iterated many times, so the
+    /// words are only combined once.
+    ///
+    /// [`CachedBitSet`]: struct.CachedBitSet.html
+    fn materialize_when_hot(self) -> CachedBitSet<Self>
+    where
+        Self: Sized,
+    {
+        CachedBitSet::new(self)
+    }
+
+    /// Returns a [`BitSetView`] of the indices in `range`, re-based so
+    /// that `range.start` becomes index 0.
+    ///
+    /// [`BitSetView`]: struct.BitSetView.html
+    fn view(self, range: Range<Index>) -> BitSetView<Self>
+    where
+        Self: Sized,
+    {
+        BitSetView::new(self, range)
     }
 
     /// Create a parallel iterator that will scan over the keyspace
@@ -447,6 +2244,219 @@ pub trait BitSetLike {
     {
         BitParIter::new(self)
     }
+
+    /// Creates a parallel iterator over the non-zero layer 0 words of the
+    /// set, yielding each word along with its index in layer 0.
+    ///
+    /// Jobs are split on the layer 2 blocks of the set, so every job
+    /// walks up to `usize_bits` words.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rayon;
+    /// # extern crate hibitset;
+    /// # use hibitset::{BitSet, BitSetLike};
+    /// # use rayon::iter::ParallelIterator;
+    /// # fn main() {
+    /// let set: BitSet = (0..1_000).filter(|i| i % 64 == 1).collect();
+    /// let mut words = set.par_words().collect::<Vec<_>>();
+    /// words.sort();
+    /// assert_eq!(words.len(), 16);
+    /// assert_eq!(words[3], (3, 0b10));
+    /// # }
+    /// ```
+    #[cfg(feature = "parallel")]
+    fn par_words(self) -> BitParWordIter<Self>
+    where
+        Self: Sized,
+    {
+        BitParWordIter::new(self)
+    }
+
+    /// Counts the indices in the set in parallel.
+    ///
+    /// The layer 0 words are popcounted by rayon jobs split on the
+    /// occupied layer 2 blocks, like [`par_words`], and the partial counts
+    /// are added up. This pays off for large, dense sets and for
+    /// combinators, whose words have to be computed to be counted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{BitSet, BitSetLike};
+    /// let a: BitSet = (0..1_000_000).filter(|i| i % 3 == 0).collect();
+    /// let b: BitSet = (0..1_000_000).filter(|i| i % 5 == 0).collect();
+    /// assert_eq!((&a & &b).par_count(), 66_667);
+    /// ```
+    ///
+    /// [`par_words`]: #method.par_words
+    #[cfg(feature = "parallel")]
+    fn par_count(&self) -> usize
+    where
+        Self: Sized + Sync,
+    {
+        use rayon::iter::ParallelIterator;
+
+        self.par_words()
+            .map(|(_, word)| word.count_ones() as usize)
+            .sum()
+    }
+}
+
+/// Checks the subtree at `level`/`idx` for indices present in both sets,
+/// descending only where both have summary bits set.
+fn disjoint_at<A, B>(a: &A, b: &B, level: usize, idx: usize) -> bool
+where
+    A: BitSetLike + ?Sized,
+    B: BitSetLike + ?Sized,
+{
+    let mut mask = a.get_from_layer(level, idx) & b.get_from_layer(level, idx);
+    if level == 0 {
+        return mask == 0;
+    }
+    while mask != 0 {
+        let bit = mask.trailing_zeros() as usize;
+        mask &= !(1 << bit);
+        if !disjoint_at(a, b, level - 1, (idx << BITS) | bit) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Sums the popcounts of `word` over the layer 0 words of `a` and `b`,
+/// descending into the children set in `summary` of the upper layers.
+fn count_at<A, B>(
+    a: &A,
+    b: &B,
+    level: usize,
+    idx: usize,
+    summary: fn(usize, usize) -> usize,
+    word: fn(usize, usize) -> usize,
+) -> usize
+where
+    A: BitSetLike + ?Sized,
+    B: BitSetLike + ?Sized,
+{
+    let (ours, theirs) = (a.get_from_layer(level, idx), b.get_from_layer(level, idx));
+    if level == 0 {
+        return word(ours, theirs).count_ones() as usize;
+    }
+    let mut mask = summary(ours, theirs);
+    let mut count = 0;
+    while mask != 0 {
+        let bit = mask.trailing_zeros() as usize;
+        mask &= !(1 << bit);
+        count += count_at(a, b, level - 1, (idx << BITS) | bit, summary, word);
+    }
+    count
+}
+
+/// Counts the layer 0 words both sets occupy under the word at `idx` of
+/// `level`, going by layer 1 alone.
+fn common_words_at<A, B>(a: &A, b: &B, level: usize, idx: usize) -> usize
+where
+    A: BitSetLike + ?Sized,
+    B: BitSetLike + ?Sized,
+{
+    let mut mask = a.get_from_layer(level, idx) & b.get_from_layer(level, idx);
+    if level == 1 {
+        return mask.count_ones() as usize;
+    }
+    let mut count = 0;
+    while mask != 0 {
+        let bit = mask.trailing_zeros() as usize;
+        mask &= !(1 << bit);
+        count += common_words_at(a, b, level - 1, (idx << BITS) | bit);
+    }
+    count
+}
+
+/// Checks that the subtrees at `level`/`idx` of `a` and `b` hold the same
+/// indices.
+fn equal_at<A, B>(a: &A, b: &B, level: usize, idx: usize) -> bool
+where
+    A: BitSetLike + ?Sized,
+    B: BitSetLike + ?Sized,
+{
+    let (ours, theirs) = (a.get_from_layer(level, idx), b.get_from_layer(level, idx));
+    if level == 0 {
+        return ours == theirs;
+    }
+    // Subtrees only one side claims are checked first, as for exact sets
+    // they are a mismatch without descending into both sides.
+    let mut mask = ours ^ theirs;
+    while mask != 0 {
+        let bit = mask.trailing_zeros() as usize;
+        mask &= !(1 << bit);
+        let child = (idx << BITS) | bit;
+        let empty = if ours & (1 << bit) != 0 {
+            empty_at(a, level - 1, child)
+        } else {
+            empty_at(b, level - 1, child)
+        };
+        if !empty {
+            return false;
+        }
+    }
+    let mut mask = ours & theirs;
+    while mask != 0 {
+        let bit = mask.trailing_zeros() as usize;
+        mask &= !(1 << bit);
+        if !equal_at(a, b, level - 1, (idx << BITS) | bit) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Checks that the subtree at `level`/`idx` of `a` is contained in `b`.
+fn subset_at<A, B>(a: &A, b: &B, level: usize, idx: usize) -> bool
+where
+    A: BitSetLike + ?Sized,
+    B: BitSetLike + ?Sized,
+{
+    let mut mask = a.get_from_layer(level, idx);
+    if level == 0 {
+        return mask & !b.layer0(idx) == 0;
+    }
+    let theirs = b.get_from_layer(level, idx);
+    while mask != 0 {
+        let bit = mask.trailing_zeros() as usize;
+        mask &= !(1 << bit);
+        let child = (idx << BITS) | bit;
+        // The summary bits of combinators may be set for empty subtrees,
+        // so a missing bit in `b` only fails if `a` really has indices there.
+        let contained = if theirs & (1 << bit) != 0 {
+            subset_at(a, b, level - 1, child)
+        } else {
+            empty_at(a, level - 1, child)
+        };
+        if !contained {
+            return false;
+        }
+    }
+    true
+}
+
+/// Checks that the subtree at `level`/`idx` has no indices.
+fn empty_at<A>(a: &A, level: usize, idx: usize) -> bool
+where
+    A: BitSetLike + ?Sized,
+{
+    let mut mask = a.get_from_layer(level, idx);
+    if level == 0 {
+        return mask == 0;
+    }
+    while mask != 0 {
+        let bit = mask.trailing_zeros() as usize;
+        mask &= !(1 << bit);
+        if !empty_at(a, level - 1, (idx << BITS) | bit) {
+            return false;
+        }
+    }
+    true
 }
 
 /// A extension to the [`BitSetLike`] trait which allows draining it.
@@ -456,48 +2466,200 @@ pub trait DrainableBitSet: BitSetLike {
     /// Returns `true` if removal happened and `false` otherwise.
     fn remove(&mut self, i: Index) -> bool;
 
-    /// Create a draining iterator that will scan over the keyspace and clears it while doing so.
-    fn drain<'a>(&'a mut self) -> DrainBitIter<'a, Self>
-    where
-        Self: Sized,
-    {
-        let layer3 = self.layer3();
+    /// Removes the indices of `mask` from the layer 0 word at `p0`.
+    ///
+    /// The default removes them one by one, sets that store their words
+    /// clear them in one go.
+    fn remove_word(&mut self, p0: usize, mut mask: usize) {
+        while mask != 0 {
+            let bit = mask.trailing_zeros();
+            mask &= mask - 1;
+            self.remove(((p0 << BITS) as Index) | bit);
+        }
+    }
+
+    /// Create a draining iterator that will scan over the keyspace and clears it while doing so.
+    fn drain<'a>(&'a mut self) -> DrainBitIter<'a, Self>
+    where
+        Self: Sized,
+    {
+        let layer3 = self.layer3();
+
+        DrainBitIter::new(self, [0, 0, 0, layer3], [0; LAYERS - 1])
+    }
+
+    /// Create an iterator that removes and yields the indices for which
+    /// `pred` returns `true`, leaving the others in the set.
+    ///
+    /// Like `Vec::extract_if`, indices are only removed as the iterator is
+    /// advanced.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{BitSet, BitSetLike, DrainableBitSet};
+    /// let mut set: BitSet = (0..10).collect();
+    /// let even = set.drain_filter(|i| i % 2 == 0).collect::<Vec<_>>();
+    /// assert_eq!(even, [0, 2, 4, 6, 8]);
+    /// assert_eq!(set.iter().collect::<Vec<_>>(), [1, 3, 5, 7, 9]);
+    /// ```
+    fn drain_filter<F>(&mut self, pred: F) -> DrainFilterIter<'_, Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Index) -> bool,
+    {
+        DrainFilterIter::new(self, pred)
+    }
+
+    /// Create a draining iterator over the runs of consecutive indices in
+    /// the set, yielding each run as a `Range`.
+    ///
+    /// Runs are removed a layer 0 word at a time rather than index by
+    /// index, which makes draining dense sets much cheaper when the
+    /// consumer works on ranges anyway.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{BitSet, BitSetLike, DrainableBitSet};
+    /// let mut set: BitSet = [1, 2, 3, 10, 64, 65].iter().collect();
+    /// assert_eq!(set.drain_ranges().collect::<Vec<_>>(), [1..4, 10..11, 64..66]);
+    /// assert!(set.is_empty());
+    /// ```
+    fn drain_ranges(&mut self) -> DrainRangeIter<'_, Self>
+    where
+        Self: Sized,
+    {
+        DrainRangeIter::new(self)
+    }
+}
+
+impl<'a, T> BitSetLike for &'a T
+where
+    T: BitSetLike + ?Sized,
+{
+    #[inline]
+    fn layer3(&self) -> usize {
+        (*self).layer3()
+    }
+
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        (*self).layer2(i)
+    }
+
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        (*self).layer1(i)
+    }
+
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        (*self).layer0(i)
+    }
+
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        (*self).contains(i)
+    }
+
+    #[inline]
+    fn prefetch(&self, level: usize, idx: usize) {
+        (*self).prefetch(level, idx)
+    }
+
+    #[inline]
+    fn exact_summaries(&self) -> bool {
+        (*self).exact_summaries()
+    }
+
+    #[inline]
+    fn len_upper_bound(&self) -> usize {
+        (*self).len_upper_bound()
+    }
+
+    #[inline]
+    fn backing_bitset(&self) -> Option<&BitSet> {
+        (*self).backing_bitset()
+    }
+
+    #[inline]
+    fn to_bitset(&self) -> BitSet {
+        (*self).to_bitset()
+    }
+}
+
+impl<'a, T> BitSetLike for &'a mut T
+where
+    T: BitSetLike + ?Sized,
+{
+    #[inline]
+    fn layer3(&self) -> usize {
+        (**self).layer3()
+    }
+
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        (**self).layer2(i)
+    }
+
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        (**self).layer1(i)
+    }
+
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        (**self).layer0(i)
+    }
+
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        (**self).contains(i)
+    }
+
+    #[inline]
+    fn prefetch(&self, level: usize, idx: usize) {
+        (**self).prefetch(level, idx)
+    }
 
-        DrainBitIter::new(self, [0, 0, 0, layer3], [0; LAYERS - 1])
+    #[inline]
+    fn exact_summaries(&self) -> bool {
+        (**self).exact_summaries()
     }
-}
 
-impl<'a, T> BitSetLike for &'a T
-where
-    T: BitSetLike + ?Sized,
-{
     #[inline]
-    fn layer3(&self) -> usize {
-        (*self).layer3()
+    fn len_upper_bound(&self) -> usize {
+        (**self).len_upper_bound()
     }
 
     #[inline]
-    fn layer2(&self, i: usize) -> usize {
-        (*self).layer2(i)
+    fn backing_bitset(&self) -> Option<&BitSet> {
+        (**self).backing_bitset()
     }
 
     #[inline]
-    fn layer1(&self, i: usize) -> usize {
-        (*self).layer1(i)
+    fn to_bitset(&self) -> BitSet {
+        (**self).to_bitset()
     }
+}
 
+impl<'a, T> DrainableBitSet for &'a mut T
+where
+    T: DrainableBitSet,
+{
     #[inline]
-    fn layer0(&self, i: usize) -> usize {
-        (*self).layer0(i)
+    fn remove(&mut self, i: Index) -> bool {
+        (**self).remove(i)
     }
 
     #[inline]
-    fn contains(&self, i: Index) -> bool {
-        (*self).contains(i)
+    fn remove_word(&mut self, p0: usize, mask: usize) {
+        (**self).remove_word(p0, mask)
     }
 }
 
-impl<'a, T> BitSetLike for &'a mut T
+impl<T> BitSetLike for Box<T>
 where
     T: BitSetLike + ?Sized,
 {
@@ -525,19 +2687,49 @@ where
     fn contains(&self, i: Index) -> bool {
         (**self).contains(i)
     }
+
+    #[inline]
+    fn prefetch(&self, level: usize, idx: usize) {
+        (**self).prefetch(level, idx)
+    }
+
+    #[inline]
+    fn exact_summaries(&self) -> bool {
+        (**self).exact_summaries()
+    }
+
+    #[inline]
+    fn len_upper_bound(&self) -> usize {
+        (**self).len_upper_bound()
+    }
+
+    #[inline]
+    fn backing_bitset(&self) -> Option<&BitSet> {
+        (**self).backing_bitset()
+    }
+
+    #[inline]
+    fn to_bitset(&self) -> BitSet {
+        (**self).to_bitset()
+    }
 }
 
-impl<'a, T> DrainableBitSet for &'a mut T
+impl<T> DrainableBitSet for Box<T>
 where
-    T: DrainableBitSet,
+    T: DrainableBitSet + ?Sized,
 {
     #[inline]
     fn remove(&mut self, i: Index) -> bool {
         (**self).remove(i)
     }
+
+    #[inline]
+    fn remove_word(&mut self, p0: usize, mask: usize) {
+        (**self).remove_word(p0, mask)
+    }
 }
 
-impl BitSetLike for BitSet {
+impl<A: LayerAlloc> BitSetLike for BitSet<A> {
     #[inline]
     fn layer3(&self) -> usize {
         self.layer3
@@ -555,59 +2747,176 @@ impl BitSetLike for BitSet {
 
     #[inline]
     fn layer0(&self, i: usize) -> usize {
-        self.layer0.get(i).map(|&x| x).unwrap_or(0)
+        self.layer0.get(i)
     }
 
     #[inline]
     fn contains(&self, i: Index) -> bool {
         self.contains(i)
     }
+
+    #[inline]
+    fn prefetch(&self, level: usize, idx: usize) {
+        let word = match level {
+            0 => self
+                .layer0
+                .page(idx >> BITS)
+                .map(|page| &page[idx & ((1 << BITS) - 1)]),
+            1 => self.layer1.get(idx),
+            2 => self.layer2.get(idx),
+            _ => return,
+        };
+        if let Some(word) = word {
+            simd::prefetch(word);
+        }
+    }
+
+    #[inline]
+    fn exact_summaries(&self) -> bool {
+        true
+    }
+
+    #[inline]
+    fn len_upper_bound(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    fn backing_bitset(&self) -> Option<&BitSet> {
+        A::global_set(self)
+    }
+
+    #[inline]
+    fn to_bitset(&self) -> BitSet {
+        BitSet::from_layer0(self.layer0.to_global())
+    }
 }
 
-impl DrainableBitSet for BitSet {
+impl<A: LayerAlloc> DrainableBitSet for BitSet<A> {
     #[inline]
     fn remove(&mut self, i: Index) -> bool {
         self.remove(i)
     }
-}
 
-impl PartialEq for BitSet {
     #[inline]
-    fn eq(&self, rhv: &BitSet) -> bool {
-        if self.layer3 != rhv.layer3 {
-            return false;
+    fn remove_word(&mut self, p0: usize, mask: usize) {
+        let old = self.layer0.get(p0);
+        if old & mask == 0 {
+            return;
         }
-        if self.layer2.len() != rhv.layer2.len()
-            || self.layer1.len() != rhv.layer1.len()
-            || self.layer0.len() != rhv.layer0.len()
-        {
-            return false;
+        self.layer0[p0] = old & !mask;
+        self.saturated.update(p0, old, old & !mask);
+        self.len -= (old & mask).count_ones() as usize;
+        if old & !mask == 0 {
+            self.remove_slow((p0 << BITS) as Index);
         }
+    }
+}
 
-        for i in 0..self.layer2.len() {
-            if self.layer2(i) != rhv.layer2(i) {
-                return false;
-            }
+/// A `BitSet` is equal to any [`BitSetLike`] with the same indices, no
+/// matter how much capacity either has. See [`is_equal`].
+///
+/// [`BitSetLike`]: trait.BitSetLike.html
+/// [`is_equal`]: trait.BitSetLike.html#method.is_equal
+impl<A: LayerAlloc, B: BitSetLike> PartialEq<B> for BitSet<A> {
+    #[inline]
+    fn eq(&self, rhv: &B) -> bool {
+        self.is_equal(rhv)
+    }
+}
+impl<A: LayerAlloc> Eq for BitSet<A> {}
+
+impl Hash for BitSet {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let words = self.layer0.trimmed_len();
+        words.hash(state);
+        for word in self.layer0.words().take(words) {
+            word.hash(state);
         }
-        for i in 0..self.layer1.len() {
-            if self.layer1(i) != rhv.layer1(i) {
-                return false;
+    }
+}
+
+/// `BitSet`s are ordered like sorted sequences of their indices,
+/// the same way `BTreeSet`s are.
+impl Ord for BitSet {
+    fn cmp(&self, other: &BitSet) -> Ordering {
+        let (ours, theirs) = (self.layer0.trimmed_len(), other.layer0.trimmed_len());
+        let mut words = self
+            .layer0
+            .words()
+            .zip(other.layer0.words())
+            .take(cmp::min(ours, theirs))
+            .enumerate();
+        let (i, a, b) = match words.find(|&(_, (a, b))| a != b) {
+            Some((i, (a, b))) => (i, a, b),
+            None => return ours.cmp(&theirs),
+        };
+
+        // Up to the lowest differing bit both sequences are the same, and
+        // the set which has that bit is less, unless the other set has
+        // nothing after it and so is a prefix of it.
+        let bit = (a ^ b).trailing_zeros();
+        let rest_after = |word: usize, words: usize| word >> bit >> 1 != 0 || words > i + 1;
+        if a & (1 << bit) != 0 {
+            if rest_after(b, theirs) {
+                Ordering::Less
+            } else {
+                Ordering::Greater
             }
+        } else if rest_after(a, ours) {
+            Ordering::Greater
+        } else {
+            Ordering::Less
         }
-        for i in 0..self.layer0.len() {
-            if self.layer0(i) != rhv.layer0(i) {
-                return false;
-            }
+    }
+}
+
+impl PartialOrd for BitSet {
+    fn partial_cmp(&self, other: &BitSet) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Returns a word with a bit set for each non-zero word of `words`.
+fn summary(words: &[usize]) -> usize {
+    words
+        .iter()
+        .enumerate()
+        .fold(0, |word, (i, &lower)| word | (((lower != 0) as usize) << i))
+}
+
+/// Strips the trailing zero words from a layer.
+fn trim_words(words: &[usize]) -> &[usize] {
+    let len = words.iter().rposition(|&w| w != 0).map_or(0, |i| i + 1);
+    &words[..len]
+}
+
+impl<A: LayerAlloc> Clone for BitSet<A> {
+    fn clone(&self) -> Self {
+        BitSet {
+            layer3: self.layer3,
+            layer2: self.layer2.clone(),
+            layer1: self.layer1.clone(),
+            layer0: self.layer0.clone(),
+            saturated: self.saturated.clone(),
+            len: self.len,
         }
+    }
 
-        true
+    /// Copies `source` layer by layer, reusing the existing allocations.
+    fn clone_from(&mut self, source: &Self) {
+        self.layer3 = source.layer3;
+        Self::copy_layer(&mut self.layer2, &source.layer2);
+        Self::copy_layer(&mut self.layer1, &source.layer1);
+        self.layer0.clone_from(&source.layer0);
+        self.saturated.clone_from(&source.saturated);
+        self.len = source.len;
     }
 }
-impl Eq for BitSet {}
 
 #[cfg(test)]
 mod tests {
-    use super::{BitSet, BitSetAnd, BitSetLike, BitSetNot};
+    use super::{BitSet, BitSetAnd, BitSetLike, BitSetNot, Index};
 
     #[test]
     fn insert() {
@@ -649,6 +2958,767 @@ mod tests {
         }
     }
 
+    #[test]
+    fn add_sorted() {
+        let ids = (0..100_000).filter(|i| i % 7 < 3).collect::<Vec<_>>();
+        let mut c = BitSet::new();
+        c.add_sorted(&ids);
+        assert_eq!((&c).iter().collect::<Vec<_>>(), ids);
+
+        let mut unsorted = BitSet::new();
+        unsorted.add_sorted(&[500, 3, 70_000, 4, 500]);
+        unsorted.add_sorted(&[]);
+        assert_eq!((&unsorted).iter().collect::<Vec<_>>(), [3, 4, 500, 70_000]);
+        assert_eq!(unsorted, [3, 4, 500, 70_000].iter().collect::<BitSet>());
+    }
+
+    #[test]
+    fn toggle() {
+        let mut c = BitSet::new();
+        assert!(!c.toggle(70_000));
+        assert!(c.contains(70_000));
+        assert!(!c.toggle(70_001));
+        assert!(c.toggle(70_000));
+        assert!(!c.contains(70_000));
+        assert!(c.toggle(70_001));
+        assert!(c.is_empty());
+        assert_eq!(c.layer3, 0);
+        assert!(c.layer1.iter().chain(&c.layer2).all(|&w| w == 0));
+        assert_eq!((&c).iter().next(), None);
+    }
+
+    #[test]
+    fn set() {
+        let mut c = BitSet::new();
+        assert!(!c.set(5_000, false));
+        assert_eq!(c.capacity(), 0);
+        assert!(!c.set(5_000, true));
+        assert!(c.set(5_000, true));
+        assert_eq!((&c).iter().collect::<Vec<_>>(), [5_000]);
+        assert!(c.set(5_000, false));
+        assert!(!c.set(5_000, false));
+        assert!(c.is_empty());
+        assert_eq!(c.layer3, 0);
+    }
+
+    #[test]
+    fn retain() {
+        let mut c = BitSet::new();
+        for i in 0..100_000 {
+            c.add(i);
+        }
+
+        c.retain(|i| i % 3 == 0);
+        for i in 0..100_000 {
+            assert_eq!(c.contains(i), i % 3 == 0);
+        }
+        assert_eq!((&c).iter().count(), 33_334);
+
+        c.retain(|i| i >= 50_000);
+        assert_eq!((&c).iter().next(), Some(50_001));
+
+        c.retain(|_| false);
+        assert!(c.is_empty());
+        assert!(c.layer2.iter().all(|&w| w == 0));
+        assert!(c.layer1.iter().all(|&w| w == 0));
+        assert!(c.layer0.words().all(|w| w == 0));
+    }
+
+    #[test]
+    fn disjoint_subset_superset() {
+        let odd: BitSet = (0..10_000).filter(|i| i % 2 == 1).collect();
+        let even: BitSet = (0..10_000).filter(|i| i % 2 == 0).collect();
+        let far: BitSet = (500_000..500_010).collect();
+        let some_odd: BitSet = (0..10_000).filter(|i| i % 6 == 1).collect();
+        let empty = BitSet::new();
+
+        assert!(odd.is_disjoint(&even));
+        assert!(odd.is_disjoint(&far));
+        assert!(!odd.is_disjoint(&some_odd));
+        assert!(odd.is_disjoint(&empty));
+
+        assert!(some_odd.is_subset(&odd));
+        assert!(!some_odd.is_subset(&even));
+        assert!(!far.is_subset(&odd));
+        assert!(empty.is_subset(&odd));
+        assert!(odd.is_superset(&some_odd));
+        assert!(!some_odd.is_superset(&odd));
+        assert!(odd.contains_set(&some_odd));
+
+        // combinators may report summary bits for empty subtrees
+        let nothing = BitSetAnd(&odd, &even);
+        assert!(nothing.is_subset(&empty));
+        assert!(!BitSetNot(&odd).is_subset(&even));
+        assert!(even.is_subset(&BitSetNot(&odd)));
+    }
+
+    #[test]
+    fn clone_from() {
+        let small: BitSet = (0..100).collect();
+        let large: BitSet = (0..100_000).filter(|i| i % 7 == 0).collect();
+
+        let mut c = large.clone();
+        c.clone_from(&small);
+        assert_eq!(c, small);
+        c.clone_from(&large);
+        assert_eq!(c, large);
+    }
+
+    #[test]
+    fn copy_from() {
+        let odd: BitSet = (0..10_000).filter(|i| i % 2 == 1).collect();
+        let third: BitSet = (0..10_000).filter(|i| i % 3 == 0).collect();
+
+        let mut c: BitSet = (50_000..60_000).collect();
+        c.copy_from(&BitSetAnd(&odd, &third));
+        assert_eq!(
+            (&c).iter().collect::<Vec<_>>(),
+            (0..10_000).filter(|i| i % 6 == 3).collect::<Vec<_>>()
+        );
+        assert!(!c.contains(50_000));
+
+        c.copy_from(&BitSet::new());
+        assert!(c.is_empty());
+        assert_eq!((&c).iter().next(), None);
+    }
+
+    #[test]
+    fn eq_ignores_capacity() {
+        let mut a = BitSet::with_capacity(100_000);
+        let mut b = BitSet::new();
+        assert_eq!(a, b);
+
+        a.add(5);
+        b.add(5);
+        b.add(90_000);
+        assert_ne!(a, b);
+        b.remove(90_000);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn len() {
+        use {AtomicBitSet, BitSetBuilder, DrainableBitSet};
+
+        fn check(set: &BitSet) {
+            assert_eq!(set.len(), set.count());
+        }
+
+        let mut set: BitSet = (0..10_000).filter(|i| i % 3 == 0).collect();
+        check(&set);
+        set.add(1);
+        set.add(1);
+        set.remove(0);
+        set.remove(0);
+        set.toggle(2);
+        set.toggle(3);
+        set.set(4, true);
+        set.set(6, false);
+        check(&set);
+        set.add_sorted(&[20_000, 20_001, 20_001, 30_000]);
+        set.extend(5_000..6_000);
+        check(&set);
+
+        let other: BitSet = (0..20_000).filter(|i| i % 2 == 0).collect();
+        let mut ops = set.clone();
+        ops |= &other;
+        check(&ops);
+        ops &= &(100..15_000).collect::<BitSet>();
+        check(&ops);
+        ops ^= &other;
+        check(&ops);
+        ops -= &(0..1_000).collect::<BitSet>();
+        check(&ops);
+        ops.union_with(&set);
+        check(&ops);
+        ops.intersect_with(&other);
+        check(&ops);
+        ops.retain(|i| i % 5 != 0);
+        check(&ops);
+        ops.clone_from(&set);
+        check(&ops);
+        assert_eq!(ops.drain().count(), set.len());
+        check(&ops);
+
+        let atomic = AtomicBitSet::new();
+        atomic.add_atomic(70_000);
+        atomic.add_atomic(5);
+        check(&atomic.to_bitset());
+        let mut builder = BitSetBuilder::from(set.clone());
+        builder.add_fast(1_000_000);
+        check(&builder.build());
+        check(&BitSet::from_bytes(&[0xff, 0x0f]));
+        set.clear();
+        assert_eq!(set.len(), 0);
+    }
+
+    #[test]
+    fn bytes() {
+        assert!(BitSet::from_bytes(&[]).is_empty());
+        assert!(BitSet::from_bytes(&[0; 100]).is_empty());
+        assert_eq!(BitSet::new().to_bytes(), Vec::<u8>::new());
+        assert_eq!(BitSet::from_bytes(&[0, 0, 0]).to_bytes(), Vec::<u8>::new());
+
+        let bytes = (0..1_000).map(|i| (i * 37 % 256) as u8).collect::<Vec<_>>();
+        let set = BitSet::from_bytes(&bytes);
+        for i in 0..8_000u32 {
+            let bit = bytes[i as usize / 8] >> (i % 8) & 1 != 0;
+            assert_eq!(set.contains(i), bit);
+        }
+        assert_eq!(set.to_bytes(), bytes);
+
+        let mut sparse = BitSet::new();
+        sparse.add(3);
+        sparse.add(100_000);
+        let bytes = sparse.to_bytes();
+        assert_eq!(bytes.len(), 100_000 / 8 + 1);
+        let read = BitSet::from_bytes(&bytes);
+        assert_eq!(read, sparse);
+        assert_eq!((&read).iter().collect::<Vec<_>>(), [3, 100_000]);
+    }
+
+    #[test]
+    fn split_off() {
+        let all: BitSet = (0..200_000).filter(|i| i % 3 == 0).collect();
+        for &at in &[0, 1, 63, 64, 65, 4_097, 99_999, 199_998, 199_999, 1_000_000] {
+            let mut low = all.clone();
+            let high = low.split_off(at);
+            let expected_low: Vec<_> = (&all).iter().filter(|&i| i < at).collect();
+            let expected_high: Vec<_> = (&all).iter().filter(|&i| i >= at).collect();
+            assert_eq!((&low).iter().collect::<Vec<_>>(), expected_low);
+            assert_eq!((&high).iter().collect::<Vec<_>>(), expected_high);
+            assert_eq!(low.len(), expected_low.len());
+            assert_eq!(high.len(), expected_high.len());
+            assert_eq!(
+                low.layer3(),
+                expected_low.iter().collect::<BitSet>().layer3()
+            );
+        }
+
+        let mut low = all.clone();
+        let high = low.split_off(100_000);
+        low.add(150_000);
+        assert!(low.contains(150_000));
+        assert!(BitSet::new().split_off(10).is_empty());
+        assert_eq!((&high).iter().next(), Some(100_002));
+    }
+
+    #[test]
+    fn try_extend() {
+        use std::error::Error;
+
+        let mut set = BitSet::new();
+        let stats = set.try_extend(vec![7, 7, 1, 99, 7], 100).unwrap();
+        assert_eq!(stats.added, 3);
+        assert_eq!(stats.duplicates, 2);
+        assert_eq!(stats.out_of_range, 0);
+        assert_eq!(stats.max, Some(99));
+        assert_eq!(set.try_extend(None, 0).unwrap(), Default::default());
+
+        let err = set.try_extend(vec![2, 1, 100, 3], 100).unwrap_err();
+        assert_eq!(err.stats().added, 1);
+        assert_eq!(err.stats().duplicates, 1);
+        assert_eq!(err.stats().max, Some(100));
+        assert_eq!(
+            (err.out_of_range().index(), err.out_of_range().max()),
+            (100, 100)
+        );
+        assert!(err.source().is_some());
+        assert!(!set.contains(3));
+
+        let err = set.try_extend(Some(::MAX_EID as u32), !0).unwrap_err();
+        assert_eq!(err.out_of_range().max(), ::MAX_EID);
+
+        let stats = set.extend_within(vec![5_000, 3, 2_000, 4, 4], 1_000);
+        assert_eq!((stats.added, stats.duplicates), (2, 1));
+        assert_eq!((stats.out_of_range, stats.max), (2, Some(5_000)));
+        assert_eq!((&set).iter().collect::<Vec<_>>(), [1, 2, 3, 4, 7, 99]);
+    }
+
+    #[test]
+    fn shift() {
+        let original: BitSet = [0, 1, 63, 64, 127, 4_095, 4_096, 70_000, 300_001]
+            .iter()
+            .collect();
+        for &n in &[0, 1, 5, 63, 64, 65, 4_096, 70_001, 300_002] {
+            let mut set = original.clone();
+            set.shift_left(n);
+            let expected: BitSet = (&original).iter().map(|i| i + n as u32).collect();
+            assert_eq!(set, expected, "left {}", n);
+            assert_eq!(set.len, (&expected).iter().count());
+            assert_eq!(set.layer3, expected.layer3);
+
+            set.shift_right(n);
+            assert_eq!(set, original, "left and right {}", n);
+            set.shift_right(n);
+            let expected: BitSet = (&original)
+                .iter()
+                .filter(|&i| i as usize >= n)
+                .map(|i| i - n as u32)
+                .collect();
+            assert_eq!(set, expected, "right {}", n);
+            assert_eq!(set.len, (&expected).iter().count());
+        }
+
+        let mut set = original.clone();
+        set.shift_left(::MAX_EID - 64);
+        assert_eq!(
+            (&set).iter().collect::<Vec<_>>(),
+            [
+                ::MAX_EID as u32 - 64,
+                ::MAX_EID as u32 - 63,
+                ::MAX_EID as u32 - 1
+            ]
+        );
+        set.shift_left(::MAX_EID);
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn invert_up_to() {
+        let original: BitSet = [0, 5, 63, 64, 4_095, 4_096, 70_000, 900_000]
+            .iter()
+            .collect();
+        for &max in &[0, 62, 63, 64, 4_095, 70_000, 262_143, 300_000] {
+            let mut set = original.clone();
+            set.invert_up_to(max);
+            let expected: BitSet = BitSetAnd(BitSetNot(&original), 0..max + 1).iter().collect();
+            assert_eq!(set, expected, "max {}", max);
+            assert_eq!(set.len, (&expected).iter().count());
+            assert_eq!(set.layer3, expected.layer3);
+            set.invert_up_to(max);
+            let expected: BitSet = (&original).iter().filter(|&i| i <= max).collect();
+            assert_eq!(set, expected);
+        }
+        let mut empty = BitSet::new();
+        empty.invert_up_to(::MAX_EID as u32 - 1);
+        assert_eq!(empty.len, ::MAX_EID);
+    }
+
+    #[test]
+    fn growth_is_amortized() {
+        let mut set = BitSet::new();
+        let mut reallocations = 0;
+        let mut capacity = set.layer1.capacity();
+        for id in (0..1_000_000).step_by(61) {
+            set.add(id);
+            if set.layer1.capacity() != capacity {
+                capacity = set.layer1.capacity();
+                reallocations += 1;
+            }
+        }
+        assert!(reallocations < 32, "{} reallocations", reallocations);
+
+        // A single high index only allocates the page holding it.
+        let mut set = BitSet::new();
+        set.add(10_000_000);
+        assert_eq!(set.layer0.iter().count(), 1);
+        assert!(set.memory_stats().layer0 < 32 * 1024);
+        assert_eq!(set.capacity(), 0);
+        set.add(10);
+        assert_eq!(set.capacity(), 64 * 64);
+        set.remove(10_000_000);
+        set.shrink_to_fit();
+        assert_eq!(set.layer0.len(), 64);
+    }
+
+    #[test]
+    fn heap_bytes_after_high_insert() {
+        // Layer 0 words up to 10_000_000 would take over a megabyte, the
+        // page table and the summaries above it only a few dozen kilobytes.
+        let mut set = BitSet::new();
+        set.add(10_000_000);
+        assert!(set.heap_bytes() < 64 * 1024, "{} bytes", set.heap_bytes());
+        assert!(set.contains(10_000_000));
+    }
+
+    #[test]
+    fn validate() {
+        let mut set: BitSet = (0..300_000).filter(|i| i % 1_000 < 3).collect();
+        assert_eq!(set.validate(), Ok(()));
+        set.clear();
+        assert_eq!(set.validate(), Ok(()));
+        set.extend(&[5, 4_100, 270_000]);
+        assert_eq!(*set.layer_words(3), [0b11]);
+        assert_eq!(set.layer_words(0).len(), set.layer0_as_slice().len());
+
+        // An emptied word that is still flagged above, and a word that
+        // isn't flagged at all.
+        set.layer0[64] = 0;
+        set.layer0[3] = 1;
+        let error = set.validate().unwrap_err();
+        let found: Vec<_> = error
+            .mismatches()
+            .iter()
+            .map(|m| (m.layer(), m.index(), m.expected(), m.found()))
+            .collect();
+        assert_eq!(found, [(1, 0, 0b1001, 0b1), (1, 1, 0, 0b1)]);
+        assert_eq!(error.len_mismatch(), None);
+
+        set.layer2.push(1);
+        set.len = 7;
+        let error = set.validate().unwrap_err();
+        let layers: Vec<_> = error.mismatches().iter().map(|m| m.layer()).collect();
+        assert_eq!(layers, [3, 2, 1, 1]);
+        assert_eq!(error.mismatches()[1].index(), set.layer2.len() - 1);
+        assert_eq!(error.len_mismatch(), Some((7, 3)));
+        assert!(error.to_string().starts_with("4 summary words"));
+    }
+
+    #[test]
+    fn with_capacity_never_reallocates() {
+        let mut set = BitSet::with_capacity(999_999);
+        let layers = |set: &BitSet| {
+            (
+                set.layer0.heap_size(),
+                set.layer1.as_ptr(),
+                set.layer2.as_ptr(),
+            )
+        };
+        let before = layers(&set);
+        for id in (0..1_000_000).rev() {
+            set.add(id);
+        }
+        assert_eq!(layers(&set), before);
+        assert_eq!(set.len(), 1_000_000);
+    }
+
+    #[test]
+    fn reserve() {
+        let mut set = BitSet::new();
+        assert_eq!(set.reserved_capacity(), 0);
+        assert!(set.add_within_capacity(0).is_err());
+
+        set.reserve(300_000);
+        assert_eq!(set.capacity(), 0);
+        assert!(set.reserved_capacity() > 300_000);
+        let layers = (
+            set.layer0.heap_size(),
+            set.layer1.as_ptr(),
+            set.layer2.as_ptr(),
+        );
+        for id in (0..=300_000).rev().step_by(7) {
+            assert_eq!(set.add_within_capacity(id), Ok(false));
+        }
+        assert_eq!(set.add_within_capacity(300_000), Ok(true));
+        assert_eq!(
+            layers,
+            (
+                set.layer0.heap_size(),
+                set.layer1.as_ptr(),
+                set.layer2.as_ptr()
+            )
+        );
+
+        let max = set.reserved_capacity() as Index;
+        let err = set.add_within_capacity(max).unwrap_err();
+        assert_eq!((err.index(), err.max()), (max, max as usize));
+        assert!(!set.contains(max));
+    }
+
+    #[test]
+    fn next_clear_bit() {
+        let mut c = BitSet::new();
+        assert_eq!(c.first_zero(), Some(0));
+        c.extend(0..1_000);
+        assert_eq!(c.first_zero(), Some(1_000));
+        c.remove(500);
+        assert_eq!(c.first_zero(), Some(500));
+        assert_eq!(c.next_clear_bit(501), Some(1_000));
+        assert_eq!(c.next_clear_bit(5_000_000), Some(5_000_000));
+        assert_eq!(c.next_clear_bit(!0), None);
+
+        let full: BitSet = (0..::MAX_EID as Index).collect();
+        assert_eq!(full.first_zero(), None);
+        assert_eq!(full.next_clear_bit(123_456), None);
+
+        assert_eq!(c.iter_clear(1_002).collect::<Vec<_>>(), [500, 1_000, 1_001]);
+        assert_eq!(c.iter_clear(0).next(), None);
+    }
+
+    #[test]
+    fn next_clear_bit_skips_saturated_blocks() {
+        let mut c: BitSet = (0..5_000_000).collect();
+        assert_eq!(c.first_zero(), Some(5_000_000));
+        assert_eq!(c.next_clear_bit(4_999_999), Some(5_000_000));
+        c.remove(4_000_000);
+        assert_eq!(c.first_zero(), Some(4_000_000));
+        assert_eq!(c.next_clear_bit(4_000_001), Some(5_000_000));
+        c.add(4_000_000);
+        c.add(5_000_000);
+        assert_eq!(c.first_zero(), Some(5_000_001));
+        c.clear();
+        assert_eq!(c.first_zero(), Some(0));
+    }
+
+    #[test]
+    fn saturated_summaries_follow_bulk_writes() {
+        use DrainableBitSet;
+
+        // Compares against a scan of the words, for the full words that
+        // every kind of write leaves behind.
+        fn check(set: &BitSet) {
+            let words = set.layer0.len();
+            for p0 in (0..words + 2).step_by(7) {
+                let scanned = (p0..)
+                    .find(|&p0| set.layer0.get(p0) != !0)
+                    .map(|p0| (p0 << ::BITS) | (!set.layer0.get(p0)).trailing_zeros() as usize);
+                assert_eq!(
+                    set.next_clear_bit((p0 << ::BITS) as Index),
+                    scanned.map(|id| id as Index),
+                    "from word {}",
+                    p0
+                );
+            }
+        }
+
+        let base: BitSet = (0..300_000).filter(|i| i % 50_000 != 7).collect();
+        check(&base);
+
+        let mut set = base.clone();
+        set.retain(|i| i < 200_000 || i % 2 == 0);
+        check(&set);
+        set.clone_from(&base);
+        check(&set);
+        set.intersect_with(&(100..250_000).collect());
+        check(&set);
+        set.union_with(&base);
+        check(&set);
+        set ^= &(0..100_000).collect::<BitSet>();
+        check(&set);
+        set -= &(150_000..160_000).collect::<BitSet>();
+        check(&set);
+        set |= &(0..50_000).collect::<BitSet>();
+        check(&set);
+        set.invert_up_to(310_000);
+        check(&set);
+        set.shift_left(100);
+        check(&set);
+        let high = set.split_off(200_000);
+        check(&set);
+        check(&high);
+        set.drain().take(64 * 64).count();
+        check(&set);
+        set.compact();
+        check(&set);
+        *set.get_word_mut(3) = 0;
+        check(&set);
+        set.shrink_to_fit();
+        check(&set);
+    }
+
+    #[test]
+    fn union_intersect_with() {
+        let a: BitSet = (0..100_000).filter(|i| i % 3 == 0).collect();
+        let mut b: BitSet = (0..50_000).filter(|i| i % 5 == 0).collect();
+        b.add(9_000_000);
+
+        let mut union = a.clone();
+        union.union_with(&b);
+        let mut expected = a.clone();
+        expected |= &b;
+        assert_eq!(union, expected);
+        assert_eq!(
+            (&union).iter().collect::<Vec<_>>(),
+            (&expected).iter().collect::<Vec<_>>()
+        );
+
+        let mut intersection = a.clone();
+        intersection.intersect_with(&b);
+        let mut expected = a.clone();
+        expected &= &b;
+        assert_eq!(
+            (&intersection).iter().collect::<Vec<_>>(),
+            (&expected).iter().collect::<Vec<_>>()
+        );
+        assert_eq!(intersection.count(), 3_334);
+
+        let mut shorter = b.clone();
+        shorter.intersect_with(&a);
+        assert_eq!(shorter, intersection);
+        shorter.intersect_with(&BitSet::new());
+        assert!(shorter.is_empty());
+        assert_eq!(shorter.layer3, 0);
+        assert_eq!(shorter.count(), 0);
+        assert_eq!(a.count(), 33_334);
+    }
+
+    #[test]
+    fn memory_stats() {
+        use std::mem::size_of;
+
+        let mut c = BitSet::new();
+        assert_eq!(c.heap_bytes(), 0);
+        c.add(100_000);
+        let stats = c.memory_stats();
+        assert!(stats.layer0 >= (1 << ::BITS) * size_of::<usize>());
+        assert!(stats.layer0 < (100_000 >> ::BITS) * size_of::<usize>());
+        assert!(stats.layer1 >= (100_000 >> (2 * ::BITS)) * size_of::<usize>());
+        assert!(stats.layer2 >= size_of::<usize>());
+        assert_eq!(c.heap_bytes(), stats.total());
+    }
+
+    #[test]
+    fn dyn_bitset_like() {
+        use {AtomicBitSet, BitSetOr, DrainableBitSet};
+
+        let plain: BitSet = (0..100).collect();
+        let atomic = AtomicBitSet::new();
+        atomic.add_atomic(1_000);
+        let odd: BitSet = (0..200).filter(|i| i % 2 == 1).collect();
+        let sets: Vec<Box<dyn BitSetLike>> = vec![
+            Box::new(BitSetAnd(plain.clone(), odd.clone())),
+            Box::new(atomic),
+            Box::new(BitSetNot(BitSetNot(odd))),
+        ];
+        let views: Vec<&dyn BitSetLike> = sets.iter().map(|set| &**set).collect();
+        assert_eq!(
+            (&sets[0]).iter().collect::<Vec<_>>(),
+            (0..100).filter(|i| i % 2 == 1).collect::<Vec<_>>()
+        );
+        assert!(views[1].contains(1_000));
+        assert_eq!(BitSetOr(&sets[0], views[1]).iter().count(), 51);
+        assert_eq!(BitSet::union_of(&views).iter().count(), 101);
+
+        let mut boxed: Box<dyn DrainableBitSet> = Box::new(plain);
+        assert!(boxed.remove(5));
+        assert!(!boxed.contains(5));
+        assert_eq!(boxed.drain().count(), 99);
+    }
+
+    #[test]
+    fn intersection_symmetric_difference_count() {
+        use std::collections::HashSet;
+        use BitSetXor;
+
+        let a: BitSet = (0..100_000).filter(|i| i % 3 == 0).collect();
+        let mut b: BitSet = (0..100_000).filter(|i| i % 5 == 0).collect();
+        b.add(9_000_000);
+        let ha = (&a).iter().collect::<HashSet<_>>();
+        let hb = (&b).iter().collect::<HashSet<_>>();
+        assert_eq!(a.intersection_count(&b), (&ha & &hb).len());
+        assert_eq!(a.symmetric_difference_count(&b), (&ha ^ &hb).len());
+        assert_eq!(b.symmetric_difference_count(&a), (&ha ^ &hb).len());
+
+        // Combinators may claim empty subtrees, which must count as 0.
+        let xor = BitSetXor(&a, &a);
+        assert_eq!(xor.intersection_count(&a), 0);
+        assert_eq!(xor.symmetric_difference_count(&b), hb.len());
+        assert_eq!(a.intersection_count(&BitSet::new()), 0);
+    }
+
+    #[test]
+    fn estimate_intersection() {
+        let a: BitSet = (0..100_000).filter(|i| i % 3 == 0).collect();
+        let b: BitSet = (50_000..300_000).filter(|i| i % 5 == 0).collect();
+        let (lower, upper) = a.estimate_intersection(&b);
+        let count = a.intersection_count(&b);
+        assert!(lower <= count && count <= upper.unwrap());
+        assert_eq!(upper, Some((100_032 - 49_984) as usize));
+        assert_eq!(b.estimate_intersection(&a), (lower, upper));
+
+        // Occupied words in common may share no bits at all.
+        let odd: BitSet = (0..1_000).filter(|i| i % 2 == 1).collect();
+        let even: BitSet = (0..1_000).filter(|i| i % 2 == 0).collect();
+        assert_eq!(odd.intersection_count(&even), 0);
+        assert_eq!(odd.estimate_intersection(&even), (0, Some(1_024)));
+        assert_eq!(a.estimate_intersection(&BitSet::new()), (0, Some(0)));
+    }
+
+    #[test]
+    fn eq_any_bitset_like() {
+        use {BitSetAll, BitSetOr, BitSetXor, SparseBitSet};
+
+        let a: BitSet = (0..10_000).filter(|i| i % 2 == 0).collect();
+        let b: BitSet = (0..10_000).filter(|i| i % 2 == 1).collect();
+        let all: BitSet = (0..10_000).collect();
+        assert_eq!(all, BitSetOr(&a, &b));
+        assert_eq!(all, BitSetXor(&a, &b));
+        assert_ne!(a, &b);
+        assert_ne!(all, BitSetAll);
+
+        // Xor keeps summary bits for subtrees that cancel out.
+        let xor = BitSetXor(&a, &a);
+        assert_eq!(BitSet::new(), xor);
+        assert!(xor.is_equal(&BitSet::new()));
+
+        let mut sparse = SparseBitSet::new();
+        for i in (&a).iter() {
+            sparse.add(i);
+        }
+        assert_eq!(a, sparse);
+        assert_eq!(sparse, a);
+        sparse.add(16_000_000);
+        assert_ne!(sparse, a);
+        assert!(!a.is_equal(&sparse));
+    }
+
+    #[test]
+    fn hash() {
+        use std::collections::HashSet;
+
+        let mut a = BitSet::with_capacity(100_000);
+        a.add(5);
+        let mut b = BitSet::new();
+        b.add(5);
+        b.add(90_000);
+        b.remove(90_000);
+
+        let mut sets = HashSet::new();
+        sets.insert(a);
+        assert!(!sets.insert(b));
+        assert!(sets.insert((0..5).collect()));
+    }
+
+    #[test]
+    fn ord() {
+        use rand::prelude::*;
+        use std::collections::BTreeSet;
+
+        let mut rng = thread_rng();
+        let sets = (0..200)
+            .map(|_| {
+                let len = rng.gen_range(0, 6);
+                (0..len)
+                    .map(|_| rng.gen_range(0, 200))
+                    .collect::<BTreeSet<u32>>()
+            })
+            .collect::<Vec<_>>();
+        for a in &sets {
+            for b in &sets {
+                let (x, y): (BitSet, BitSet) = (a.iter().collect(), b.iter().collect());
+                assert_eq!(x.cmp(&y), a.cmp(b), "{:?} {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn shrink_to_fit() {
+        let mut c = BitSet::with_capacity(1_000_000);
+        assert!(c.capacity() > 1_000_000);
+        c.shrink_to_fit();
+        assert_eq!(c.capacity(), 0);
+
+        c.add(100);
+        c.add(900_000);
+        c.remove(900_000);
+        c.shrink_to_fit();
+        // The page holding 100, which covers the indices below 4,096.
+        assert_eq!(c.capacity(), 4_096);
+        assert_eq!(c.memory_stats().layer0, c.layer0.heap_size());
+        assert_eq!(c.layer0.iter().count(), 1);
+        assert_eq!((&c).iter().collect::<Vec<_>>(), [100]);
+
+        // the set must still be able to grow again afterwards
+        c.add(127);
+        c.add(5_000);
+        assert_eq!((&c).iter().collect::<Vec<_>>(), [100, 127, 5_000]);
+        assert!(c.remove(100));
+        assert!(c.remove(127));
+        assert!(c.remove(5_000));
+        assert!(c.is_empty());
+    }
+
     #[test]
     fn iter() {
         let mut c = BitSet::new();
@@ -731,8 +3801,75 @@ mod tests {
 
 #[cfg(all(test, feature = "parallel"))]
 mod test_parallel {
-    use super::{BitSet, BitSetAnd, BitSetLike};
-    use rayon::iter::ParallelIterator;
+    use super::{AtomicBitSet, BitSet, BitSetAnd, BitSetLike};
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+    #[test]
+    fn par_iter_bounded() {
+        let a: BitSet = (0..200_000).filter(|i| i % 3 == 0).collect();
+        let b: BitSet = (0..200_000).filter(|i| i % 5 == 0).collect();
+        let expected = (0..150_000)
+            .filter(|i| i % 3 == 0 && i % 5 != 0)
+            .collect::<Vec<_>>();
+        let join = (&a & !&b).bounded(150_000);
+        let mut found = join.clone().par_iter().collect::<Vec<_>>();
+        found.sort();
+        assert_eq!(found, expected);
+
+        let complement = (!&a).bounded(1_000_000);
+        assert_eq!(
+            complement.par_iter().count(),
+            1_000_000 - (&a).iter().count()
+        );
+    }
+
+    #[test]
+    fn par_count() {
+        let a: BitSet = (0..1_000_000).filter(|i| i % 3 == 0).collect();
+        let b: BitSet = (0..1_000_000).filter(|i| i % 7 != 0).collect();
+        assert_eq!(a.par_count(), a.len());
+        assert_eq!((&a & &b).par_count(), (&a & &b).iter().count());
+        assert_eq!((&a & !&b).par_count(), (&a & !&b).iter().count());
+        let atomic = AtomicBitSet::from_bitset(&a);
+        assert_eq!(atomic.par_count(), a.len());
+        assert_eq!(BitSet::new().par_count(), 0);
+    }
+
+    #[test]
+    fn par_union_of() {
+        let sets: Vec<BitSet> = (0..50u32)
+            .map(|i| (0..i * 10_000).filter(|j| j % (i + 7) == 0).collect())
+            .collect();
+        let refs: Vec<&BitSet> = sets.iter().collect();
+        let union = BitSet::par_union_of(&refs);
+        let mut check = BitSet::new();
+        for set in &sets {
+            check |= set;
+        }
+        assert_eq!(union, check);
+        assert_eq!(union.len(), check.len());
+        assert_eq!(union.layer3(), check.layer3());
+        assert!(BitSet::par_union_of(&[]).is_empty());
+    }
+
+    #[test]
+    fn par_collect() {
+        let set: BitSet = (0..100_000u32)
+            .into_par_iter()
+            .filter(|i| i % 3 == 0)
+            .collect();
+        let check: BitSet = (0..100_000).filter(|i| i % 3 == 0).collect();
+        assert_eq!(
+            (&set).iter().collect::<Vec<_>>(),
+            check.iter().collect::<Vec<_>>()
+        );
+
+        let atomic: AtomicBitSet = (0..100_000u32)
+            .into_par_iter()
+            .filter(|i| i % 3 == 0)
+            .collect();
+        assert_eq!(atomic.to_bitset(), set);
+    }
 
     #[test]
     fn par_iter_one() {
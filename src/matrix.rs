@@ -0,0 +1,263 @@
+use std::iter::Enumerate;
+use std::slice;
+
+use util::*;
+use {BitSet, BitSetLike};
+
+/// A two-dimensional bit set, holding each row as a [`BitSet`].
+///
+/// Rows are allocated as they're first written to, and each of them only
+/// takes up as much memory as its highest column needs, so the matrix
+/// suits sparse relations like adjacency or collision pairs.
+///
+/// # Examples
+///
+/// ```
+/// # use hibitset::{BitMatrix, BitSetLike};
+/// let mut pairs = BitMatrix::new();
+/// pairs.add(0, 5);
+/// pairs.add(3, 5);
+/// pairs.add(3, 7);
+/// assert!(pairs.contains(3, 7));
+/// assert_eq!(pairs.row(3).iter().collect::<Vec<_>>(), [5, 7]);
+/// assert_eq!(pairs.column(5).collect::<Vec<_>>(), [0, 3]);
+/// ```
+///
+/// [`BitSet`]: struct.BitSet.html
+#[derive(Clone, Debug, Default)]
+pub struct BitMatrix {
+    rows: Vec<BitSet>,
+    // Returned for rows that haven't been allocated.
+    empty: BitSet,
+}
+
+impl BitMatrix {
+    /// Creates an empty matrix.
+    pub fn new() -> BitMatrix {
+        Default::default()
+    }
+
+    /// Creates an empty matrix with `rows` rows allocated.
+    pub fn with_rows(rows: usize) -> BitMatrix {
+        let mut matrix = BitMatrix::new();
+        matrix.rows.resize(rows, BitSet::new());
+        matrix
+    }
+
+    /// Returns the number of allocated rows, which is one more than the
+    /// highest row written to.
+    pub fn rows(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Adds `col` to row `row`. Returns `true` if it was already set.
+    #[inline]
+    pub fn add(&mut self, row: Index, col: Index) -> bool {
+        self.row_mut(row).add(col)
+    }
+
+    /// Removes `col` from row `row`. Returns `true` if it was set.
+    #[inline]
+    pub fn remove(&mut self, row: Index, col: Index) -> bool {
+        match self.rows.get_mut(row as usize) {
+            Some(set) => set.remove(col),
+            None => false,
+        }
+    }
+
+    /// Sets `col` of row `row` to `value`. Returns `true` if it was set
+    /// before.
+    #[inline]
+    pub fn set(&mut self, row: Index, col: Index, value: bool) -> bool {
+        if value {
+            self.add(row, col)
+        } else {
+            self.remove(row, col)
+        }
+    }
+
+    /// Returns `true` if `col` is set in row `row`.
+    #[inline]
+    pub fn contains(&self, row: Index, col: Index) -> bool {
+        self.row(row).contains(col)
+    }
+
+    /// Returns row `row`, which is empty if it was never written to.
+    pub fn row(&self, row: Index) -> &BitSet {
+        self.rows.get(row as usize).unwrap_or(&self.empty)
+    }
+
+    /// Returns row `row` for modification, allocating the rows up to it
+    /// if needed.
+    pub fn row_mut(&mut self, row: Index) -> &mut BitSet {
+        let row = row as usize;
+        if row >= self.rows.len() {
+            self.rows.resize(row + 1, BitSet::new());
+        }
+        &mut self.rows[row]
+    }
+
+    /// Iterates over the rows which have `col` set.
+    ///
+    /// Each row is checked in turn, so this is linear in the number of
+    /// rows. Use [`transpose`](#method.transpose) when many columns are
+    /// read.
+    pub fn column(&self, col: Index) -> BitColumnIter<'_> {
+        BitColumnIter {
+            rows: self.rows.iter().enumerate(),
+            col,
+        }
+    }
+
+    /// Adds every column of row `src` to row `dst`.
+    pub fn union_rows(&mut self, dst: Index, src: Index) {
+        if let Some((dst, src)) = self.row_pair(dst, src) {
+            dst.union_with(src);
+        }
+    }
+
+    /// Removes every column not in row `src` from row `dst`.
+    pub fn intersect_rows(&mut self, dst: Index, src: Index) {
+        if src as usize >= self.rows.len() {
+            if let Some(dst) = self.rows.get_mut(dst as usize) {
+                dst.clear();
+            }
+        } else if let Some((dst, src)) = self.row_pair(dst, src) {
+            dst.intersect_with(src);
+        }
+    }
+
+    /// Returns the transpose of the matrix, with the rows and columns
+    /// swapped.
+    pub fn transpose(&self) -> BitMatrix {
+        let mut transposed = BitMatrix::new();
+        for (row, set) in self.rows.iter().enumerate() {
+            for col in set {
+                transposed.add(col, row as Index);
+            }
+        }
+        transposed
+    }
+
+    /// Removes every bit, keeping the allocated rows.
+    pub fn clear(&mut self) {
+        for row in &mut self.rows {
+            row.clear();
+        }
+    }
+
+    /// Borrows rows `dst` and `src` at once, if they're distinct and
+    /// allocated.
+    fn row_pair(&mut self, dst: Index, src: Index) -> Option<(&mut BitSet, &BitSet)> {
+        let (dst, src) = (dst as usize, src as usize);
+        if dst == src || dst >= self.rows.len() || src >= self.rows.len() {
+            return None;
+        }
+        if dst < src {
+            let (head, tail) = self.rows.split_at_mut(src);
+            Some((&mut head[dst], &tail[0]))
+        } else {
+            let (head, tail) = self.rows.split_at_mut(dst);
+            Some((&mut tail[0], &head[src]))
+        }
+    }
+}
+
+/// An `Iterator` over the rows of a [`BitMatrix`] that have a column set.
+///
+/// [`BitMatrix`]: struct.BitMatrix.html
+#[derive(Debug, Clone)]
+pub struct BitColumnIter<'a> {
+    rows: Enumerate<slice::Iter<'a, BitSet>>,
+    col: Index,
+}
+
+impl Iterator for BitColumnIter<'_> {
+    type Item = Index;
+
+    fn next(&mut self) -> Option<Index> {
+        let col = self.col;
+        self.rows
+            .find(|(_, set)| set.contains(col))
+            .map(|(row, _)| row as Index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {BitMatrix, BitSetLike};
+
+    #[test]
+    fn add_remove() {
+        let mut m = BitMatrix::new();
+        assert!(!m.add(2, 100));
+        assert!(m.add(2, 100));
+        assert_eq!(m.rows(), 3);
+        assert!(m.contains(2, 100));
+        assert!(!m.contains(1, 100));
+        assert!(!m.contains(10, 100));
+        assert!(m.row(10).is_empty());
+
+        assert!(!m.set(5, 1, true));
+        assert!(m.set(5, 1, true));
+        assert!(m.set(5, 1, false));
+        assert!(!m.remove(5, 1));
+        assert!(!m.remove(50, 1));
+        assert!(m.remove(2, 100));
+        assert!(m.row(2).is_empty());
+    }
+
+    #[test]
+    fn column_transpose() {
+        let mut m = BitMatrix::with_rows(100);
+        for row in 0..100 {
+            for col in (0..200).filter(|col| col % (row + 1) == 0) {
+                m.add(row, col);
+            }
+        }
+        assert_eq!(m.column(6).collect::<Vec<_>>(), [0, 1, 2, 5]);
+        assert_eq!(m.column(7).collect::<Vec<_>>(), [0, 6]);
+        assert_eq!(m.column(1_000).next(), None);
+
+        let t = m.transpose();
+        for col in 0..200 {
+            let column = m.column(col).collect::<Vec<_>>();
+            assert_eq!(t.row(col).iter().collect::<Vec<_>>(), column);
+        }
+        assert_eq!(t.transpose().rows(), 100);
+    }
+
+    #[test]
+    fn row_ops() {
+        let mut m = BitMatrix::new();
+        m.row_mut(0).extend(0..10);
+        m.row_mut(1).extend(5..20);
+        m.row_mut(2).extend(8..12);
+
+        m.union_rows(2, 0);
+        assert_eq!(
+            m.row(2).iter().collect::<Vec<_>>(),
+            (0..12).collect::<Vec<_>>()
+        );
+        m.intersect_rows(1, 0);
+        assert_eq!(
+            m.row(1).iter().collect::<Vec<_>>(),
+            (5..10).collect::<Vec<_>>()
+        );
+        m.intersect_rows(0, 1);
+        assert_eq!(
+            m.row(0).iter().collect::<Vec<_>>(),
+            (5..10).collect::<Vec<_>>()
+        );
+
+        m.union_rows(0, 0);
+        m.union_rows(0, 7);
+        assert_eq!(m.row(0).iter().count(), 5);
+        m.intersect_rows(0, 7);
+        assert!(m.row(0).is_empty());
+
+        m.clear();
+        assert_eq!(m.rows(), 3);
+        assert!(m.row(2).is_empty());
+    }
+}
@@ -0,0 +1,217 @@
+use util::*;
+use {BitSet, BitSetLike};
+
+/// A [`BitSet`] that counts how many times each index was added.
+///
+/// Every index has an 8 bit counter next to the hierarchy. Adding an index
+/// increments its counter and removing it decrements it, and the index is
+/// only left out of the set once its counter drops back to zero. As a
+/// [`BitSetLike`], the multiset holds the indices with a non-zero count,
+/// so it can be iterated and joined like any other set.
+///
+/// The counters take a byte for every index up to the highest one added,
+/// on top of the memory of the `BitSet`.
+///
+/// # Examples
+///
+/// ```
+/// # use hibitset::{BitMultiSet, BitSetLike};
+/// let mut refs = BitMultiSet::new();
+/// refs.add(3);
+/// refs.add(3);
+/// refs.add(5);
+/// refs.remove(3);
+/// assert_eq!(refs.count(3), 1);
+/// assert_eq!((&refs).iter().collect::<Vec<_>>(), [3, 5]);
+/// ```
+///
+/// [`BitSet`]: struct.BitSet.html
+/// [`BitSetLike`]: trait.BitSetLike.html
+#[derive(Clone, Debug, Default)]
+pub struct BitMultiSet {
+    set: BitSet,
+    counts: Vec<u8>,
+}
+
+impl BitMultiSet {
+    /// Creates an empty `BitMultiSet`.
+    pub fn new() -> BitMultiSet {
+        Default::default()
+    }
+
+    /// Adds one to the count of `id`. Returns `true` if it was already in
+    /// the set.
+    ///
+    /// Panics if `id` is not below the maximum index of a `BitSet`, or if
+    /// the count would go past `u8::MAX`.
+    #[inline]
+    pub fn add(&mut self, id: Index) -> bool {
+        let i = id as usize;
+        if i >= self.counts.len() {
+            // Checked before the counters grow to cover `id`.
+            if i >= MAX_EID {
+                panic!("Expected index to be less then {}, found {}", MAX_EID, id);
+            }
+            // Keep the counters covering whole layer 0 words.
+            self.counts.resize((id.offset(SHIFT1) + 1) << BITS, 0);
+        }
+        let count = &mut self.counts[i];
+        *count = count
+            .checked_add(1)
+            .unwrap_or_else(|| panic!("Expected the count of {} to fit in a u8", id));
+        self.set.add(id)
+    }
+
+    /// Takes one off the count of `id`, removing it from the set when it
+    /// reaches zero. Returns `true` if `id` was in the set.
+    #[inline]
+    pub fn remove(&mut self, id: Index) -> bool {
+        match self.counts.get_mut(id as usize) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                if *count == 0 {
+                    self.set.remove(id);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Removes `id` from the set whatever its count, returning the count
+    /// it had.
+    #[inline]
+    pub fn remove_all(&mut self, id: Index) -> u8 {
+        match self.counts.get_mut(id as usize) {
+            Some(count) if *count > 0 => {
+                self.set.remove(id);
+                let old = *count;
+                *count = 0;
+                old
+            }
+            _ => 0,
+        }
+    }
+
+    /// Returns how many more times `id` was added than removed.
+    #[inline]
+    pub fn count(&self, id: Index) -> u8 {
+        self.counts.get(id as usize).cloned().unwrap_or(0)
+    }
+
+    /// Returns `true` if `id` has a non-zero count.
+    #[inline]
+    pub fn contains(&self, id: Index) -> bool {
+        self.set.contains(id)
+    }
+
+    /// Returns the set of the indices with a non-zero count.
+    pub fn as_set(&self) -> &BitSet {
+        &self.set
+    }
+
+    /// Removes every index, keeping the counters allocated.
+    pub fn clear(&mut self) {
+        for (base, _) in (&self.set).masks() {
+            let base = base as usize;
+            for count in &mut self.counts[base..base + (1 << BITS)] {
+                *count = 0;
+            }
+        }
+        self.set.clear();
+    }
+}
+
+impl BitSetLike for BitMultiSet {
+    #[inline]
+    fn layer3(&self) -> usize {
+        self.set.layer3()
+    }
+
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        self.set.layer2(i)
+    }
+
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        self.set.layer1(i)
+    }
+
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        self.set.layer0(i)
+    }
+
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        self.set.contains(i)
+    }
+
+    #[inline]
+    fn exact_summaries(&self) -> bool {
+        true
+    }
+
+    #[inline]
+    fn backing_bitset(&self) -> Option<&BitSet> {
+        Some(&self.set)
+    }
+}
+
+impl Extend<Index> for BitMultiSet {
+    fn extend<T: IntoIterator<Item = Index>>(&mut self, iter: T) {
+        for id in iter {
+            self.add(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {BitMultiSet, BitSetLike};
+
+    #[test]
+    fn add_remove() {
+        let mut c = BitMultiSet::new();
+        assert!(!c.add(100));
+        assert!(c.add(100));
+        assert!(c.add(100));
+        assert_eq!(c.count(100), 3);
+        assert!(c.remove(100));
+        assert!(c.remove(100));
+        assert!(c.contains(100));
+        assert!(c.remove(100));
+        assert!(!c.contains(100));
+        assert!(!c.remove(100));
+        assert!(!c.remove(1_000_000));
+        assert_eq!(c.count(100), 0);
+        assert_eq!(c.count(1_000_000), 0);
+
+        c.extend(vec![1, 1, 2, 70_000, 1]);
+        assert_eq!((&c).iter().collect::<Vec<_>>(), [1, 2, 70_000]);
+        assert_eq!(c.remove_all(1), 3);
+        assert_eq!(c.remove_all(1), 0);
+        assert_eq!(c.as_set().len(), 2);
+
+        c.clear();
+        assert!(c.as_set().is_empty());
+        assert_eq!(c.count(70_000), 0);
+        assert!(!c.add(70_000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected index to be less then")]
+    fn out_of_range() {
+        BitMultiSet::new().add(::MAX_EID as u32);
+    }
+
+    #[test]
+    #[should_panic]
+    fn overflow() {
+        let mut c = BitMultiSet::new();
+        for _ in 0..256 {
+            c.add(7);
+        }
+    }
+}
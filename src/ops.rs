@@ -1,10 +1,22 @@
+use std::cmp;
+use std::fmt;
 use std::iter::{FromIterator, IntoIterator};
-use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
+use std::mem;
+use std::ops::{
+    BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Range, RangeInclusive,
+    SubAssign,
+};
+use std::ptr;
 use std::usize;
 
+use pages::Pages;
+use simd;
 use util::*;
 
-use {AtomicBitSet, BitIter, BitSet, BitSetLike, DrainableBitSet};
+use {
+    AtomicBitSet, BitIter, BitMultiSet, BitSet, BitSetLike, BitSetView, CachedBitSet,
+    DrainableBitSet, FlatLayer0, Global, ImBitSet, SparseBitSet, TrackedBitSet,
+};
 
 impl<'a, B> BitOrAssign<&'a B> for BitSet
 where
@@ -16,39 +28,38 @@ where
         while let Some(level) = (1..LAYERS).find(|&level| iter.handle_level(level) == Continue) {
             let lower = level - 1;
             let idx = iter.prefix[lower] as usize >> BITS;
-            *self.layer_mut(lower, idx) |= lhs.get_from_layer(lower, idx);
+            let word = lhs.get_from_layer(lower, idx);
+            if lower == 0 {
+                self.update_word(idx, |ours| ours | word);
+            } else {
+                *self.layer_mut(lower, idx) |= word;
+            }
         }
         self.layer3 |= lhs.layer3();
     }
 }
 
+/// Keeps only the indices that are also in `lhs`.
+///
+/// Every non-zero layer 0 word of the set is anded with the matching word
+/// of `lhs`, and the summary bits are cleared as words become empty.
 impl<'a, B> BitAndAssign<&'a B> for BitSet
 where
     B: BitSetLike,
 {
     fn bitand_assign(&mut self, lhs: &B) {
-        use iter::State::*;
-        let mut iter = lhs.iter();
-        iter.masks[LAYERS - 1] &= self.layer3();
-        while let Some(level) = (1..LAYERS).find(|&level| iter.handle_level(level) == Continue) {
-            let lower = level - 1;
-            let idx = iter.prefix[lower] as usize >> BITS;
-            let our_layer = self.get_from_layer(lower, idx);
-            let their_layer = lhs.get_from_layer(lower, idx);
-
-            iter.masks[lower] &= our_layer;
-
-            let mut masks = [0; LAYERS];
-            masks[lower] = our_layer & !their_layer;
-            BitIter::new(&mut *self, masks, iter.prefix).clear();
-
-            *self.layer_mut(lower, idx) &= their_layer;
+        for p1 in 0..self.layer1.len() {
+            let mut summary = self.layer1[p1];
+            while summary != 0 {
+                let p0 = (p1 << BITS) | summary.trailing_zeros() as usize;
+                summary &= summary - 1;
+                let word = lhs.layer0(p0);
+                self.update_word(p0, |ours| ours & word);
+                if self.layer0[p0] == 0 {
+                    self.remove_slow((p0 << BITS) as Index);
+                }
+            }
         }
-        let mut masks = [0; LAYERS];
-        masks[LAYERS - 1] = self.layer3() & !lhs.layer3();
-        BitIter::new(&mut *self, masks, [0; LAYERS - 1]).clear();
-
-        self.layer3 &= lhs.layer3();
     }
 }
 
@@ -64,7 +75,8 @@ where
             let idx = iter.prefix[lower] as usize >> BITS;
 
             if lower == 0 {
-                *self.layer_mut(lower, idx) ^= lhs.get_from_layer(lower, idx);
+                let word = lhs.get_from_layer(lower, idx);
+                self.update_word(idx, |ours| ours ^ word);
 
                 let mut change_bit = |level| {
                     let lower = level - 1;
@@ -88,35 +100,214 @@ where
     }
 }
 
+/// Removes every index of `lhs` from the set.
+///
+/// Only the words both sets have in common are visited, and the summary
+/// bits are cleared as layer 0 words become empty.
+impl<B> SubAssign<&B> for BitSet
+where
+    B: BitSetLike,
+{
+    fn sub_assign(&mut self, lhs: &B) {
+        use iter::State::Continue;
+        let mut iter = lhs.iter();
+        iter.masks[LAYERS - 1] &= self.layer3;
+        while let Some(level) = (1..LAYERS).find(|&level| iter.handle_level(level) == Continue) {
+            let lower = level - 1;
+            let idx = iter.prefix[lower] as usize >> BITS;
+            if lower > 0 {
+                iter.masks[lower] &= self.get_from_layer(lower, idx);
+            } else {
+                let word = lhs.layer0(idx);
+                self.update_word(idx, |ours| ours & !word);
+                if self.layer0[idx] == 0 {
+                    self.remove_slow((idx << BITS) as Index);
+                }
+            }
+        }
+    }
+}
+
+/// `BitSetNotIn` takes a [`BitSetLike`] item and a universe size `n`, and
+/// produces the virtual set of the indices below `n` that are not in the
+/// item.
+///
+/// Unlike [`BitSetNot`], which holds every index the item doesn't, this
+/// set is finite: its layers mask out everything at or above `n`, so it
+/// can be iterated and counted, and it composes without pulling in the
+/// rest of the index space.
+///
+/// # Examples
+///
+/// ```
+/// # use hibitset::{BitSet, BitSetLike, BitSetNotIn};
+/// let set: BitSet = [1, 3].iter().collect();
+/// let missing = BitSetNotIn(&set, 6);
+/// assert_eq!(missing.iter().collect::<Vec<_>>(), [0, 2, 4, 5]);
+/// ```
+///
+/// [`BitSetLike`]: ../trait.BitSetLike.html
+/// [`BitSetNot`]: struct.BitSetNot.html
+#[derive(Debug, Clone)]
+pub struct BitSetNotIn<A: BitSetLike>(pub A, pub Index);
+
+impl<A: BitSetLike> BitSetNotIn<A> {
+    /// The bits of the word at `idx` of `level` that cover indices below
+    /// the universe size.
+    #[inline]
+    fn bound(&self, level: usize, idx: usize) -> usize {
+        let shift = BITS * level;
+        let end = cmp::min(self.1 as usize, MAX_EID);
+        let children = ((end + (1 << shift) - 1) >> shift).saturating_sub(idx << BITS);
+        if children >= 1 << BITS {
+            !0
+        } else {
+            (1 << children) - 1
+        }
+    }
+}
+
+impl<A: BitSetLike> BitSetLike for BitSetNotIn<A> {
+    #[inline]
+    fn layer3(&self) -> usize {
+        self.bound(3, 0)
+    }
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        self.bound(2, i)
+    }
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        self.bound(1, i)
+    }
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        !self.0.layer0(i) & self.bound(0, i)
+    }
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        i < self.1 && !self.0.contains(i)
+    }
+    #[inline]
+    fn prefetch(&self, level: usize, idx: usize) {
+        self.0.prefetch(level, idx);
+    }
+    #[inline]
+    fn to_bitset(&self) -> BitSet {
+        let set = match self.0.backing_bitset() {
+            Some(set) => set,
+            None => return BitSet::from_words(self),
+        };
+        let words = (cmp::min(self.1 as usize, MAX_EID) + (1 << BITS) - 1) >> BITS;
+        let layer0 = (0..words).map(|i| !set.layer0.get(i) & self.bound(0, i));
+        BitSet::from_layer0(Pages::from_words(layer0))
+    }
+}
+
 /// `BitSetAnd` takes two [`BitSetLike`] items, and merges the masks
 /// returning a new virtual set, which represents an intersection of the
 /// two original sets.
 ///
+/// When both items are backed by the same [`BitSet`], the set is exactly
+/// that one: its words are read from it once instead of being read twice
+/// and ANDed, its summaries are exact, and iterating it knows its length.
+/// When the layer 3 words of the items share no bits, `to_bitset` and
+/// iteration stop before reading any lower layer.
+///
 /// [`BitSetLike`]: ../trait.BitSetLike.html
+/// [`BitSet`]: ../struct.BitSet.html
 #[derive(Debug, Clone)]
 pub struct BitSetAnd<A: BitSetLike, B: BitSetLike>(pub A, pub B);
 
 impl<A: BitSetLike, B: BitSetLike> BitSetLike for BitSetAnd<A, B> {
     #[inline]
     fn layer3(&self) -> usize {
+        if let Some(set) = self.backing_bitset() {
+            return set.layer3();
+        }
         self.0.layer3() & self.1.layer3()
     }
     #[inline]
     fn layer2(&self, i: usize) -> usize {
+        if let Some(set) = self.backing_bitset() {
+            return set.layer2(i);
+        }
         self.0.layer2(i) & self.1.layer2(i)
     }
     #[inline]
     fn layer1(&self, i: usize) -> usize {
+        if let Some(set) = self.backing_bitset() {
+            return set.layer1(i);
+        }
         self.0.layer1(i) & self.1.layer1(i)
     }
     #[inline]
     fn layer0(&self, i: usize) -> usize {
+        if let Some(set) = self.backing_bitset() {
+            return set.layer0(i);
+        }
         self.0.layer0(i) & self.1.layer0(i)
     }
     #[inline]
     fn contains(&self, i: Index) -> bool {
+        if let Some(set) = self.backing_bitset() {
+            return set.contains(i);
+        }
         self.0.contains(i) && self.1.contains(i)
     }
+    #[inline]
+    fn prefetch(&self, level: usize, idx: usize) {
+        self.0.prefetch(level, idx);
+        if self.backing_bitset().is_none() {
+            self.1.prefetch(level, idx);
+        }
+    }
+    #[inline]
+    fn exact_summaries(&self) -> bool {
+        match self.backing_bitset() {
+            Some(set) => set.exact_summaries(),
+            None => false,
+        }
+    }
+    #[inline]
+    fn len_upper_bound(&self) -> usize {
+        match self.backing_bitset() {
+            Some(set) => set.len_upper_bound(),
+            None => cmp::min(self.0.len_upper_bound(), self.1.len_upper_bound()),
+        }
+    }
+    #[inline]
+    fn backing_bitset(&self) -> Option<&BitSet> {
+        match (self.0.backing_bitset(), self.1.backing_bitset()) {
+            (Some(a), Some(b)) if ptr::eq(a, b) => Some(a),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn to_bitset(&self) -> BitSet {
+        if self.layer3() == 0 {
+            return BitSet::new();
+        }
+        let (a, b) = match (self.0.backing_bitset(), self.1.backing_bitset()) {
+            (Some(a), Some(b)) if ptr::eq(a, b) => return a.clone(),
+            (Some(a), Some(b)) => (&a.layer0, &b.layer0),
+            _ => return BitSet::from_words(self),
+        };
+        let mut layer0 = Pages::new_in(&Global);
+        let words = cmp::min(a.len(), b.len());
+        if words > 0 {
+            layer0.grow(words - 1);
+        }
+        for (p1, page) in a.iter() {
+            if let Some(other) = b.page(p1) {
+                let words = layer0.page_mut(p1);
+                *words = *page;
+                simd::and_into(words, other);
+            }
+        }
+        BitSet::from_layer0(layer0)
+    }
 }
 
 impl<A: DrainableBitSet, B: DrainableBitSet> DrainableBitSet for BitSetAnd<A, B> {
@@ -161,6 +352,29 @@ impl<A: BitSetLike, B: BitSetLike> BitSetLike for BitSetOr<A, B> {
     fn contains(&self, i: Index) -> bool {
         self.0.contains(i) || self.1.contains(i)
     }
+    #[inline]
+    fn prefetch(&self, level: usize, idx: usize) {
+        self.0.prefetch(level, idx);
+        self.1.prefetch(level, idx);
+    }
+    #[inline]
+    fn len_upper_bound(&self) -> usize {
+        cmp::min(self.0.len_upper_bound() + self.1.len_upper_bound(), MAX_EID)
+    }
+
+    #[inline]
+    fn to_bitset(&self) -> BitSet {
+        let (a, b) = match (self.0.backing_bitset(), self.1.backing_bitset()) {
+            (Some(a), Some(b)) => (&a.layer0, &b.layer0),
+            _ => return BitSet::from_words(self),
+        };
+        let (longer, other) = if a.len() < b.len() { (b, a) } else { (a, b) };
+        let mut layer0 = longer.to_global();
+        for (p1, page) in other.iter() {
+            simd::or_into(layer0.page_mut(p1), page);
+        }
+        BitSet::from_layer0(layer0)
+    }
 }
 
 impl<A: DrainableBitSet, B: DrainableBitSet> DrainableBitSet for BitSetOr<A, B> {
@@ -176,6 +390,217 @@ impl<A: DrainableBitSet, B: DrainableBitSet> DrainableBitSet for BitSetOr<A, B>
     }
 }
 
+/// `BitSetAndN` takes any number of [`BitSetLike`] items, and merges the
+/// masks returning a new virtual set, which represents an intersection of
+/// all of them.
+///
+/// With no sets at all, every index is contained, like [`BitSetAll`].
+///
+/// [`BitSetLike`]: ../trait.BitSetLike.html
+/// [`BitSetAll`]: struct.BitSetAll.html
+#[derive(Debug, Clone)]
+pub struct BitSetAndN<A: BitSetLike>(pub Vec<A>);
+
+impl<A: BitSetLike> BitSetLike for BitSetAndN<A> {
+    #[inline]
+    fn layer3(&self) -> usize {
+        self.0.iter().fold(!0, |acc, set| acc & set.layer3())
+    }
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        self.0.iter().fold(!0, |acc, set| acc & set.layer2(i))
+    }
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        self.0.iter().fold(!0, |acc, set| acc & set.layer1(i))
+    }
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        self.0.iter().fold(!0, |acc, set| acc & set.layer0(i))
+    }
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        self.0.iter().all(|set| set.contains(i))
+    }
+    #[inline]
+    fn prefetch(&self, level: usize, idx: usize) {
+        for set in &self.0 {
+            set.prefetch(level, idx);
+        }
+    }
+    #[inline]
+    fn len_upper_bound(&self) -> usize {
+        self.0
+            .iter()
+            .map(|set| set.len_upper_bound())
+            .fold(MAX_EID, cmp::min)
+    }
+}
+
+/// `BitSetOrN` takes any number of [`BitSetLike`] items, and merges the
+/// masks returning a new virtual set, which represents the union of all
+/// of them.
+///
+/// Unlike nesting [`BitSetOr`]s, the number of sets doesn't have to be
+/// known at compile time. Use `BitSetOrN<&dyn BitSetLike>` to mix
+/// different kinds of sets.
+///
+/// [`BitSetLike`]: ../trait.BitSetLike.html
+/// [`BitSetOr`]: struct.BitSetOr.html
+#[derive(Debug, Clone)]
+pub struct BitSetOrN<A: BitSetLike>(pub Vec<A>);
+
+impl<A: BitSetLike> BitSetLike for BitSetOrN<A> {
+    #[inline]
+    fn layer3(&self) -> usize {
+        self.0.iter().fold(0, |acc, set| acc | set.layer3())
+    }
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        self.0.iter().fold(0, |acc, set| acc | set.layer2(i))
+    }
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        self.0.iter().fold(0, |acc, set| acc | set.layer1(i))
+    }
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        self.0.iter().fold(0, |acc, set| acc | set.layer0(i))
+    }
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        self.0.iter().any(|set| set.contains(i))
+    }
+    #[inline]
+    fn prefetch(&self, level: usize, idx: usize) {
+        for set in &self.0 {
+            set.prefetch(level, idx);
+        }
+    }
+    #[inline]
+    fn len_upper_bound(&self) -> usize {
+        let sum = self.0.iter().map(|set| set.len_upper_bound()).sum();
+        cmp::min(sum, MAX_EID)
+    }
+}
+
+/// `BitSetThreshold` takes any number of [`BitSetLike`] items and a
+/// threshold `k`, returning a new virtual set of the indices that are in
+/// at least `k` of them.
+///
+/// A threshold of `1` is the union of the sets and a threshold of their
+/// number is the intersection. Each word is computed by adding up the
+/// words of the sets in bit-sliced counters, one bit of the count per
+/// plane, and comparing every count to `k` at once.
+///
+/// # Examples
+///
+/// ```
+/// # use hibitset::{BitSet, BitSetLike, BitSetThreshold};
+/// let a: BitSet = (0..10).collect();
+/// let b: BitSet = (5..15).collect();
+/// let c: BitSet = (8..20).collect();
+/// let sets: [&dyn BitSetLike; 3] = [&a, &b, &c];
+/// let majority = BitSetThreshold::majority(&sets);
+/// assert_eq!(majority.iter().collect::<Vec<_>>(), (5..15).collect::<Vec<_>>());
+/// ```
+///
+/// [`BitSetLike`]: ../trait.BitSetLike.html
+#[derive(Clone, Copy)]
+pub struct BitSetThreshold<'a>(pub &'a [&'a dyn BitSetLike], pub usize);
+
+impl<'a> BitSetThreshold<'a> {
+    /// Creates a set of the indices in more than half of `sets`.
+    pub fn majority(sets: &'a [&'a dyn BitSetLike]) -> Self {
+        BitSetThreshold(sets, sets.len() / 2 + 1)
+    }
+
+    /// Returns the bits set in at least `k` of `words`.
+    ///
+    /// An upper layer bit is only a hint that a set may have indices
+    /// below it, so counting them gives a superset of the upper layer
+    /// bits of the result, which is all the upper layers need.
+    #[inline]
+    fn word<F: Fn(&dyn BitSetLike) -> usize>(&self, word: F) -> usize {
+        let (sets, k) = (self.0, self.1);
+        if k == 0 {
+            return !0;
+        }
+        if k > sets.len() {
+            return 0;
+        }
+        let planes = (mem::size_of::<usize>() * 8) - sets.len().leading_zeros() as usize;
+        let mut counts = [0usize; mem::size_of::<usize>() * 8];
+        for &set in sets {
+            let mut carry = word(set);
+            for plane in &mut counts[..planes] {
+                if carry == 0 {
+                    break;
+                }
+                let next = *plane & carry;
+                *plane ^= carry;
+                carry = next;
+            }
+        }
+
+        // Compare the counts to `k` from the highest plane down, tracking
+        // which counts are already greater and which are equal so far.
+        let (mut greater, mut equal) = (0, !0);
+        for (plane, &count) in counts[..planes].iter().enumerate().rev() {
+            if k >> plane & 1 != 0 {
+                equal &= count;
+            } else {
+                greater |= equal & count;
+                equal &= !count;
+            }
+        }
+        greater | equal
+    }
+}
+
+impl<'a> BitSetLike for BitSetThreshold<'a> {
+    #[inline]
+    fn layer3(&self) -> usize {
+        self.word(|set| set.layer3())
+    }
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        self.word(|set| set.layer2(i))
+    }
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        self.word(|set| set.layer1(i))
+    }
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        self.word(|set| set.layer0(i))
+    }
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        self.0
+            .iter()
+            .filter(|set| set.contains(i))
+            .take(self.1)
+            .count()
+            == self.1
+    }
+    #[inline]
+    fn prefetch(&self, level: usize, idx: usize) {
+        for set in self.0 {
+            set.prefetch(level, idx);
+        }
+    }
+}
+
+impl fmt::Debug for BitSetThreshold<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BitSetThreshold")
+            .field("sets", &self.0.len())
+            .field("k", &self.1)
+            .finish()
+    }
+}
+
 /// `BitSetNot` takes a [`BitSetLike`] item, and produced an inverted virtual set.
 /// Note: the implementation is sub-optimal because layers 1-3 are not active.
 ///
@@ -204,6 +629,10 @@ impl<A: BitSetLike> BitSetLike for BitSetNot<A> {
     fn contains(&self, i: Index) -> bool {
         !self.0.contains(i)
     }
+    #[inline]
+    fn prefetch(&self, level: usize, idx: usize) {
+        self.0.prefetch(level, idx);
+    }
 }
 
 /// `BitSetXor` takes two [`BitSetLike`] items, and merges the masks
@@ -255,11 +684,46 @@ impl<A: BitSetLike, B: BitSetLike> BitSetLike for BitSetXor<A, B> {
         )
         .contains(i)
     }
+    #[inline]
+    fn prefetch(&self, level: usize, idx: usize) {
+        self.0.prefetch(level, idx);
+        self.1.prefetch(level, idx);
+    }
+    #[inline]
+    fn len_upper_bound(&self) -> usize {
+        cmp::min(self.0.len_upper_bound() + self.1.len_upper_bound(), MAX_EID)
+    }
+
+    #[inline]
+    fn to_bitset(&self) -> BitSet {
+        let (a, b) = match (self.0.backing_bitset(), self.1.backing_bitset()) {
+            (Some(a), Some(b)) => (&a.layer0, &b.layer0),
+            _ => return BitSet::from_words(self),
+        };
+        let (longer, other) = if a.len() < b.len() { (b, a) } else { (a, b) };
+        let mut layer0 = longer.to_global();
+        for (p1, page) in other.iter() {
+            simd::xor_into(layer0.page_mut(p1), page);
+        }
+        BitSet::from_layer0(layer0)
+    }
 }
 
 /// `BitSetAll` is a bitset with all bits set. Essentially the same as
 /// `BitSetNot(BitSet::new())` but without any allocation.
-#[derive(Debug, Clone)]
+///
+/// # Example
+///
+/// ```
+/// use hibitset::{BitSet, BitSetAll, BitSetLike};
+///
+/// let mut excluded = BitSet::new();
+/// excluded.add(1);
+///
+/// let everything_else = BitSetAll & !&excluded;
+/// assert_eq!(everything_else.iter().take(3).collect::<Vec<_>>(), [0, 2, 3]);
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
 pub struct BitSetAll;
 impl BitSetLike for BitSetAll {
     #[inline]
@@ -284,6 +748,217 @@ impl BitSetLike for BitSetAll {
     }
 }
 
+/// `BitSetFromFn` is a bitset whose membership is computed by a
+/// predicate.
+///
+/// The upper layers can't know which indices the predicate accepts, so
+/// they report every bit as set, and layer 0 words are built by calling
+/// the predicate for each of their bits. It's meant to be used in an
+/// intersection with a real set, which then decides which words are
+/// visited. Iterating over it on its own calls the predicate for every
+/// possible index.
+///
+/// # Example
+///
+/// ```
+/// use hibitset::{BitSet, BitSetAnd, BitSetFromFn, BitSetLike};
+///
+/// let set: BitSet = (0..10).collect();
+/// let multiples = BitSetAnd(&set, BitSetFromFn(|id| id % 3 == 0));
+/// assert_eq!(multiples.iter().collect::<Vec<_>>(), [0, 3, 6, 9]);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct BitSetFromFn<F>(pub F);
+
+impl<F: Fn(Index) -> bool> BitSetLike for BitSetFromFn<F> {
+    #[inline]
+    fn layer3(&self) -> usize {
+        usize::MAX
+    }
+    #[inline]
+    fn layer2(&self, _id: usize) -> usize {
+        usize::MAX
+    }
+    #[inline]
+    fn layer1(&self, _id: usize) -> usize {
+        usize::MAX
+    }
+    #[inline]
+    fn layer0(&self, id: usize) -> usize {
+        let base = (id << BITS) as Index;
+        (0..1 << BITS)
+            .filter(|&bit| (self.0)(base | bit))
+            .fold(0, |word, bit| word | 1 << bit)
+    }
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        (self.0)(i)
+    }
+}
+
+/// The bits of the word at `idx` of `level` that cover indices in
+/// `start..end`.
+#[inline]
+pub(crate) fn range_word(start: usize, end: usize, level: usize, idx: usize) -> usize {
+    let below = |children: usize| {
+        if children >= 1 << BITS {
+            !0
+        } else {
+            (1 << children) - 1
+        }
+    };
+    let shift = BITS * level;
+    let end = cmp::min(end, MAX_EID);
+    if start >= end {
+        return 0;
+    }
+    let first = (start >> shift).saturating_sub(idx << BITS);
+    let last = ((end + (1 << shift) - 1) >> shift).saturating_sub(idx << BITS);
+    below(last) & !below(first)
+}
+
+/// A range acts as the set of the indices it contains, with the words of
+/// each layer computed from its bounds.
+///
+/// # Example
+///
+/// ```
+/// use hibitset::{BitSet, BitSetAnd, BitSetLike};
+///
+/// let set: BitSet = (0..5_000).filter(|i| i % 1_000 == 0).collect();
+/// let first = BitSetAnd(&set, 0..2_048);
+/// assert_eq!(first.iter().collect::<Vec<_>>(), [0, 1_000, 2_000]);
+/// ```
+impl BitSetLike for Range<Index> {
+    #[inline]
+    fn layer3(&self) -> usize {
+        range_word(self.start as usize, self.end as usize, 3, 0)
+    }
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        range_word(self.start as usize, self.end as usize, 2, i)
+    }
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        range_word(self.start as usize, self.end as usize, 1, i)
+    }
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        range_word(self.start as usize, self.end as usize, 0, i)
+    }
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        Range::contains(self, &i)
+    }
+    #[inline]
+    fn len_upper_bound(&self) -> usize {
+        cmp::min(self.end as usize, MAX_EID).saturating_sub(self.start as usize)
+    }
+}
+
+/// The bits of the word at `idx` of `level` that cover indices in `range`.
+#[inline]
+fn inclusive_word(range: &RangeInclusive<Index>, level: usize, idx: usize) -> usize {
+    if range.is_empty() {
+        0
+    } else {
+        range_word(
+            *range.start() as usize,
+            *range.end() as usize + 1,
+            level,
+            idx,
+        )
+    }
+}
+
+/// An inclusive range acts as the set of the indices it contains, like a
+/// `Range` does.
+impl BitSetLike for RangeInclusive<Index> {
+    #[inline]
+    fn layer3(&self) -> usize {
+        inclusive_word(self, 3, 0)
+    }
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        inclusive_word(self, 2, i)
+    }
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        inclusive_word(self, 1, i)
+    }
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        inclusive_word(self, 0, i)
+    }
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        RangeInclusive::contains(self, &i)
+    }
+    #[inline]
+    fn len_upper_bound(&self) -> usize {
+        if self.is_empty() {
+            0
+        } else {
+            cmp::min(*self.end() as usize + 1, MAX_EID).saturating_sub(*self.start() as usize)
+        }
+    }
+}
+
+macro_rules! scalar_set {
+    ( $( $word:ty ),* ) => { $(
+        /// An integer acts as the set of the positions of its set bits, so
+        /// a small mask can take part in combinators without allocating.
+        impl BitSetLike for $word {
+            #[inline]
+            fn layer3(&self) -> usize {
+                (*self != 0) as usize
+            }
+            #[inline]
+            fn layer2(&self, i: usize) -> usize {
+                (i == 0 && *self != 0) as usize
+            }
+            #[inline]
+            fn layer1(&self, i: usize) -> usize {
+                if i != 0 {
+                    return 0;
+                }
+                let words = mem::size_of::<$word>().div_ceil(mem::size_of::<usize>());
+                (0..words)
+                    .filter(|&w| self.layer0(w) != 0)
+                    .fold(0, |mask, w| mask | 1 << w)
+            }
+            #[inline]
+            fn layer0(&self, i: usize) -> usize {
+                let shift = i * usize::BITS as usize;
+                if shift < <$word>::BITS as usize {
+                    (*self >> shift) as usize
+                } else {
+                    0
+                }
+            }
+            #[inline]
+            fn contains(&self, i: Index) -> bool {
+                i < <$word>::BITS && *self >> i & 1 != 0
+            }
+            #[inline]
+            fn exact_summaries(&self) -> bool {
+                true
+            }
+            #[inline]
+            fn len_upper_bound(&self) -> usize {
+                self.count_ones() as usize
+            }
+            fn iter(self) -> BitIter<Self> {
+                let mut iter = BitIter::new(self, [0, 0, 0, self.layer3()], [0; LAYERS - 1]);
+                iter.left = Some(self.count_ones() as usize);
+                iter
+            }
+        }
+    )* };
+}
+
+scalar_set!(usize, u64, u128);
+
 macro_rules! operator {
     ( impl < ( $( $lifetime:tt )* ) ( $( $arg:ident ),* ) > for $bitset:ty ) => {
         impl<$( $lifetime, )* $( $arg ),*> IntoIterator for $bitset
@@ -342,8 +1017,28 @@ operator!(impl<()()> for BitSet);
 operator!(impl<('a)()> for &'a BitSet);
 operator!(impl<()()> for AtomicBitSet);
 operator!(impl<('a)()> for &'a AtomicBitSet);
+operator!(impl<()()> for SparseBitSet);
+operator!(impl<('a)()> for &'a SparseBitSet);
+operator!(impl<()()> for BitMultiSet);
+operator!(impl<('a)()> for &'a BitMultiSet);
+operator!(impl<()()> for TrackedBitSet);
+operator!(impl<('a)()> for &'a TrackedBitSet);
+operator!(impl<()()> for ImBitSet);
+operator!(impl<('a)()> for &'a ImBitSet);
+operator!(impl<()(A)> for CachedBitSet<A>);
+operator!(impl<('a)(A)> for &'a CachedBitSet<A>);
+operator!(impl<()(A)> for BitSetView<A>);
+operator!(impl<('a)(A)> for &'a BitSetView<A>);
+operator!(impl<('a)()> for FlatLayer0<'a>);
+operator!(impl<()(A)> for BitSetAndN<A>);
+operator!(impl<('a)(A)> for &'a BitSetAndN<A>);
+operator!(impl<()(A)> for BitSetOrN<A>);
+operator!(impl<('a)(A)> for &'a BitSetOrN<A>);
+operator!(impl<('a)()> for BitSetThreshold<'a>);
 operator!(impl<()(A)> for BitSetNot<A>);
 operator!(impl<('a)(A)> for &'a BitSetNot<A>);
+operator!(impl<()(A)> for BitSetNotIn<A>);
+operator!(impl<('a)(A)> for &'a BitSetNotIn<A>);
 operator!(impl<()(A, B)> for BitSetAnd<A, B>);
 operator!(impl<('a)(A, B)> for &'a BitSetAnd<A, B>);
 operator!(impl<()(A, B)> for BitSetOr<A, B>);
@@ -353,6 +1048,62 @@ operator!(impl<('a)(A, B)> for &'a BitSetXor<A, B>);
 operator!(impl<()()> for BitSetAll);
 operator!(impl<('a)()> for &'a BitSetAll);
 
+macro_rules! tuple_and {
+    ( $( $arg:ident $idx:tt ),* ) => {
+        /// A tuple of sets is the intersection of all of them, like nested
+        /// [`BitSetAnd`]s but without the nesting.
+        ///
+        /// [`BitSetAnd`]: struct.BitSetAnd.html
+        impl<$( $arg: BitSetLike ),*> BitSetLike for ($( $arg, )*) {
+            #[inline]
+            fn layer3(&self) -> usize {
+                !0 $( & self.$idx.layer3() )*
+            }
+            #[inline]
+            fn layer2(&self, i: usize) -> usize {
+                !0 $( & self.$idx.layer2(i) )*
+            }
+            #[inline]
+            fn layer1(&self, i: usize) -> usize {
+                !0 $( & self.$idx.layer1(i) )*
+            }
+            #[inline]
+            fn layer0(&self, i: usize) -> usize {
+                !0 $( & self.$idx.layer0(i) )*
+            }
+            #[inline]
+            fn contains(&self, i: Index) -> bool {
+                true $( && self.$idx.contains(i) )*
+            }
+            #[inline]
+            fn prefetch(&self, level: usize, idx: usize) {
+                $( self.$idx.prefetch(level, idx); )*
+            }
+            #[inline]
+            fn len_upper_bound(&self) -> usize {
+                MAX_EID $( .min(self.$idx.len_upper_bound()) )*
+            }
+        }
+    };
+}
+
+tuple_and!(A 0);
+tuple_and!(A 0, B 1);
+tuple_and!(A 0, B 1, C 2);
+tuple_and!(A 0, B 1, C 2, D 3);
+tuple_and!(A 0, B 1, C 2, D 3, E 4);
+tuple_and!(A 0, B 1, C 2, D 3, E 4, F 5);
+tuple_and!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+tuple_and!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+tuple_and!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8);
+tuple_and!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9);
+tuple_and!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10);
+tuple_and!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11);
+tuple_and!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11, M 12);
+tuple_and!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11, M 12, N 13);
+tuple_and!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11, M 12, N 13, O 14);
+tuple_and!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11, M 12, N 13, O 14, P 15);
+
 macro_rules! iterator {
     ( $bitset:ident ) => {
         impl FromIterator<Index> for $bitset {
@@ -361,9 +1112,7 @@ macro_rules! iterator {
                 T: IntoIterator<Item = Index>,
             {
                 let mut bitset = $bitset::new();
-                for item in iter {
-                    bitset.add(item);
-                }
+                Extend::extend(&mut bitset, iter);
                 bitset
             }
         }
@@ -374,32 +1123,17 @@ macro_rules! iterator {
                 T: IntoIterator<Item = &'a Index>,
             {
                 let mut bitset = $bitset::new();
-                for item in iter {
-                    bitset.add(*item);
-                }
+                Extend::extend(&mut bitset, iter);
                 bitset
             }
         }
 
-        impl Extend<Index> for $bitset {
-            fn extend<T>(&mut self, iter: T)
-            where
-                T: IntoIterator<Item = Index>,
-            {
-                for item in iter {
-                    self.add(item);
-                }
-            }
-        }
-
         impl<'a> Extend<&'a Index> for $bitset {
             fn extend<T>(&mut self, iter: T)
             where
                 T: IntoIterator<Item = &'a Index>,
             {
-                for item in iter {
-                    self.add(*item);
-                }
+                Extend::extend(self, iter.into_iter().cloned());
             }
         }
     };
@@ -407,10 +1141,277 @@ macro_rules! iterator {
 
 iterator!(BitSet);
 iterator!(AtomicBitSet);
+iterator!(SparseBitSet);
+iterator!(ImBitSet);
+
+impl Extend<Index> for BitSet {
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = Index>,
+    {
+        // Runs of indices that fall into the same layer 0 word are
+        // gathered into one mask, so sorted input touches each word once.
+        let mut iter = iter.into_iter();
+        let first = match iter.next() {
+            Some(first) => first,
+            None => return,
+        };
+        let (mut p0, mut mask) = (first.offset(SHIFT1), first.mask(SHIFT0));
+        for item in iter {
+            let offset = item.offset(SHIFT1);
+            if offset != p0 {
+                self.add_word(p0, mask);
+                p0 = offset;
+                mask = 0;
+            }
+            mask |= item.mask(SHIFT0);
+        }
+        self.add_word(p0, mask);
+    }
+}
+
+impl Extend<Index> for AtomicBitSet {
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = Index>,
+    {
+        for item in iter {
+            self.add(item);
+        }
+    }
+}
+
+impl Extend<Index> for SparseBitSet {
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = Index>,
+    {
+        for item in iter {
+            self.add(item);
+        }
+    }
+}
+
+impl Extend<Range<Index>> for BitSet {
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = Range<Index>>,
+    {
+        for range in iter {
+            if range.start >= range.end {
+                continue;
+            }
+            let (start, end) = (range.start as usize, range.end as usize);
+            for p0 in (start >> BITS)..((end - 1) >> BITS) + 1 {
+                let low = cmp::max(start, p0 << BITS) - (p0 << BITS);
+                let high = cmp::min(end, (p0 + 1) << BITS) - (p0 << BITS);
+                let mask = (!0usize >> ((1 << BITS) - (high - low))) << low;
+                self.add_word(p0, mask);
+            }
+        }
+    }
+}
+
+impl FromIterator<Range<Index>> for BitSet {
+    fn from_iter<T>(iter: T) -> Self
+    where
+        T: IntoIterator<Item = Range<Index>>,
+    {
+        let mut bitset = BitSet::new();
+        Extend::extend(&mut bitset, iter);
+        bitset
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    use {BitSet, BitSetLike, BitSetXor, Index};
+    use {BitSet, BitSetAll, BitSetLike, BitSetXor, Index};
+
+    #[test]
+    fn and_aliased() {
+        use BitSetAnd;
+
+        let a: BitSet = (0..10_000).filter(|i| i % 3 == 0).collect();
+        let b = a.clone();
+        let same = BitSetAnd(&a, &a);
+        let clones = BitSetAnd(&a, &b);
+        assert_eq!(
+            same.backing_bitset().map(|s| s as *const _),
+            Some(&a as *const _)
+        );
+        assert!(clones.backing_bitset().is_none());
+        assert_eq!(
+            (&same).iter().collect::<Vec<_>>(),
+            (&clones).iter().collect::<Vec<_>>()
+        );
+        assert_eq!((&same).iter().size_hint(), (3_334, Some(3_334)));
+
+        let nested = BitSetAnd(&same, &a);
+        assert!(nested.backing_bitset().is_some());
+        assert_eq!((&nested).iter().count(), 3_334);
+
+        assert_eq!(same.layer0(0), a.layer0(0));
+        assert!(same.contains(9_999) && !same.contains(9_998));
+        assert_eq!(same.to_bitset(), a);
+
+        let high: BitSet = [1 << 20].iter().cloned().collect();
+        let disjoint = BitSetAnd(&a, &high);
+        assert_eq!((&disjoint).iter().size_hint(), (0, Some(0)));
+        assert!(disjoint.to_bitset().is_empty());
+
+        let deeper = BitSetAnd(&nested, BitSetAnd(&a, &same));
+        assert!(deeper.exact_summaries());
+        assert_eq!(deeper.len_upper_bound(), 3_334);
+        assert!(!BitSetAnd(&deeper, &b).exact_summaries());
+    }
+
+    #[test]
+    fn from_fn() {
+        use {BitSetAnd, BitSetFromFn};
+
+        let set: BitSet = (0..10_000).filter(|i| i % 2 == 0).collect();
+        let sixths = BitSetAnd(&set, BitSetFromFn(|i| i % 3 == 0));
+        assert_eq!(
+            (&sixths).iter().collect::<Vec<_>>(),
+            (0..10_000).filter(|i| i % 6 == 0).collect::<Vec<_>>()
+        );
+        assert!(sixths.contains(6));
+        assert!(!sixths.contains(3));
+
+        let none = BitSetFromFn(|_| false);
+        assert_eq!(BitSetAnd(&set, &none).iter().next(), None);
+        let first = BitSetFromFn(|i| i % 5 == 0).iter().take(3);
+        assert_eq!(first.collect::<Vec<_>>(), [0, 5, 10]);
+    }
+
+    #[test]
+    fn range() {
+        use BitSetAnd;
+
+        for &(start, end) in &[
+            (0, 0),
+            (0, 1),
+            (5, 5),
+            (63, 65),
+            (100, 5_000),
+            (4_096, 300_000),
+            (262_100, 262_200),
+            (::MAX_EID as u32 - 70, ::MAX_EID as u32),
+        ] {
+            let expected = (start..end).collect::<Vec<_>>();
+            assert_eq!((start..end).iter().collect::<Vec<_>>(), expected);
+            if end > start {
+                let inclusive = (start..=end - 1).iter().collect::<Vec<_>>();
+                assert_eq!(inclusive, expected);
+            }
+        }
+        assert_eq!((0..!0).iter().count(), ::MAX_EID);
+        let mut exhausted = 3..=3;
+        assert_eq!(exhausted.next(), Some(3));
+        assert_eq!(exhausted.iter().next(), None);
+
+        let set: BitSet = (0..10_000).filter(|i| i % 7 == 0).collect();
+        let masked = BitSetAnd(&set, 1_000..2_000);
+        assert_eq!(
+            masked.iter().collect::<Vec<_>>(),
+            (1_000..2_000).filter(|i| i % 7 == 0).collect::<Vec<_>>()
+        );
+        assert!(BitSetLike::contains(&(10..=20), 20));
+        assert!(!BitSetLike::contains(&(10..20), 20));
+    }
+
+    #[test]
+    fn to_bitset() {
+        use {BitSetAnd, BitSetNot, BitSetNotIn, BitSetOr};
+
+        let a: BitSet = (0..200_000).filter(|i| i % 3 == 0).collect();
+        let b: BitSet = (0..100_000).filter(|i| i % 5 == 0).collect();
+        let check = |set: &dyn BitSetLike| {
+            let expected: BitSet = set.iter().collect();
+            let collected = set.to_bitset();
+            assert_eq!(collected, expected);
+            assert_eq!(collected.len(), expected.len());
+        };
+        check(&BitSetAnd(&a, &b));
+        check(&BitSetAnd(&b, &a));
+        check(&BitSetOr(&a, &b));
+        check(&BitSetOr(&b, &a));
+        check(&BitSetXor(&a, &b));
+        check(&BitSetXor(&b, &a));
+        check(&BitSetNotIn(&a, 1_000));
+        check(&BitSetNotIn(&b, 150_000));
+        // Without backing sets on both sides the words are read one by one.
+        check(&BitSetAnd(&a, BitSetNot(&b)));
+        check(&BitSetOr(&a, 10..20));
+        check(&BitSetNotIn(BitSetAnd(&a, &b), 500));
+        check(&BitSetAnd(&a, &a));
+        assert!(BitSetAnd(&a, &BitSet::new()).to_bitset().is_empty());
+    }
+
+    #[test]
+    fn scalar() {
+        use BitSetAnd;
+
+        let mask: u128 = 1 | 1 << 63 | 1 << 64 | 1 << 127;
+        assert_eq!(mask.iter().collect::<Vec<_>>(), [0, 63, 64, 127]);
+        assert_eq!(mask.iter().size_hint(), (4, Some(4)));
+        assert!(mask.contains(64) && !mask.contains(65) && !mask.contains(128));
+        assert_eq!(0u64.iter().next(), None);
+        assert_eq!((1u64 << 40 | 3).iter().collect::<Vec<_>>(), [0, 1, 40]);
+        assert_eq!(0b1010usize.iter().collect::<Vec<_>>(), [1, 3]);
+
+        let set: BitSet = (0..1_000).filter(|i| i % 3 == 0).collect();
+        let small = BitSetAnd(&set, mask);
+        assert_eq!(small.iter().collect::<Vec<_>>(), [0, 63]);
+        assert_eq!((&set & !0u64).iter().count(), 22);
+    }
+
+    #[test]
+    fn not_in() {
+        use BitSetNotIn;
+
+        let set: BitSet = (0..100_000).filter(|i| i % 3 != 0).collect();
+        for &n in &[0, 1, 63, 64, 65, 4_096, 99_999, 262_145] {
+            let expected = (0..n).filter(|i| !set.contains(*i)).collect::<Vec<_>>();
+            let missing = BitSetNotIn(&set, n);
+            assert_eq!((&missing).iter().collect::<Vec<_>>(), expected);
+            assert!(!missing.contains(n));
+        }
+
+        let all = BitSetNotIn(BitSet::new(), !0);
+        assert_eq!(all.iter().count(), ::MAX_EID);
+
+        let mixed = &set | BitSetNotIn(&set, 10);
+        assert_eq!(
+            (&mixed).iter_range(0..12).collect::<Vec<_>>(),
+            [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]
+        );
+        assert_eq!(mixed.iter().count(), 66_666 + 4);
+    }
+
+    #[test]
+    fn into_iter_owned() {
+        use AtomicBitSet;
+
+        fn build(n: Index) -> impl Iterator<Item = Index> {
+            (0..n).map(|i| i * 3).collect::<BitSet>().into_iter()
+        }
+        fn build_atomic(n: Index) -> <AtomicBitSet as IntoIterator>::IntoIter {
+            let set = AtomicBitSet::new();
+            for i in 0..n {
+                set.add_atomic(i * 5);
+            }
+            set.into_iter()
+        }
+
+        assert_eq!(build(4).collect::<Vec<_>>(), [0, 3, 6, 9]);
+        assert_eq!(build_atomic(3).collect::<Vec<_>>(), [0, 5, 10]);
+        let mut sum = 0;
+        for i in build(100).collect::<BitSet>() {
+            sum += i;
+        }
+        assert_eq!(sum, 3 * 99 * 100 / 2);
+    }
 
     #[test]
     fn or_assign() {
@@ -568,6 +1569,58 @@ mod tests {
         assert_eq!(hs, set1.iter().collect());
     }
 
+    #[test]
+    fn and_assign_clears_layers() {
+        let mut c1: BitSet = [3110, 265451].iter().cloned().collect();
+        let c2: BitSet = [3110].iter().cloned().collect();
+        c1 &= &c2;
+        assert_eq!((&c1).iter().collect::<Vec<_>>(), [3110]);
+        assert_eq!(c1.len(), 1);
+        assert_eq!(c1.validate(), Ok(()));
+
+        let mut c1: BitSet = [101, 160].iter().cloned().collect();
+        let c2: BitSet = [13, 74, 139].iter().cloned().collect();
+        c1 &= &c2;
+        assert!(c1.is_empty());
+        assert!(BitSetLike::is_empty(&c1));
+        assert_eq!(c1.validate(), Ok(()));
+    }
+
+    #[test]
+    fn sub_assign() {
+        use std::collections::HashSet;
+        use std::mem::size_of;
+
+        let usize_bits = size_of::<usize>() as u32 * 8;
+        let n = 10_000;
+        let f1 = &|n| 7 * usize_bits * n;
+        let f2 = &|n| 13 * usize_bits * n;
+
+        let mut c1: BitSet = (0..n).map(f1).collect();
+        let c2: BitSet = (0..n).map(f2).collect();
+        c1 -= &c2;
+
+        let h1: HashSet<_> = (0..n).map(f1).collect();
+        let h2: HashSet<_> = (0..n).map(f2).collect();
+        assert_eq!(c1.iter().collect::<HashSet<_>>(), &h1 - &h2);
+    }
+
+    #[test]
+    fn sub_assign_clears_layers() {
+        let mut c1: BitSet = (0..100_000).collect();
+        let mut c2: BitSet = (0..50_000).collect();
+        c2.add(16_000_000);
+        c1 -= &c2;
+        assert_eq!((&c1).iter().next(), Some(50_000));
+
+        c1 -= &(&c2 | BitSetAll);
+        assert!(c1.is_empty());
+        assert_eq!(c1.layer3, 0);
+        assert!(c1.layer1.iter().chain(&c1.layer2).all(|&w| w == 0));
+        c1.add(7);
+        assert_eq!((&c1).iter().collect::<Vec<_>>(), [7]);
+    }
+
     #[test]
     fn xor_assign() {
         use std::collections::HashSet;
@@ -695,6 +1748,182 @@ mod tests {
         }
     }
 
+    #[test]
+    fn all() {
+        use BitSetAll;
+
+        let mut bitset = BitSet::new();
+        bitset.add(3);
+        bitset.add(50000);
+
+        let and = BitSetAll & &bitset;
+        assert_eq!(and.iter().collect::<Vec<Index>>(), vec![3, 50000]);
+
+        let not = BitSetAll & !&bitset;
+        assert_eq!(
+            (&not).iter().take(4).collect::<Vec<Index>>(),
+            vec![0, 1, 2, 4]
+        );
+        assert!(!not.contains(50000));
+        assert!(not.contains(50001));
+
+        let or = BitSetAll | &bitset;
+        assert_eq!(or.iter().take(2).collect::<Vec<Index>>(), vec![0, 1]);
+    }
+
+    #[test]
+    fn and_or_n() {
+        use {BitSetAndN, BitSetOrN};
+
+        let sets = (1..12)
+            .map(|n| (0..10_000).filter(|i| i % n == 0).collect::<BitSet>())
+            .collect::<Vec<_>>();
+
+        let or = BitSetOrN(sets.iter().skip(1).collect());
+        assert_eq!(
+            or.iter().collect::<Vec<Index>>(),
+            (0..10_000)
+                .filter(|i| (2..12).any(|n| i % n == 0))
+                .collect::<Vec<_>>()
+        );
+
+        let and = BitSetAndN(sets.iter().skip(1).take(3).collect());
+        assert_eq!(
+            and.iter().collect::<Vec<Index>>(),
+            (0..10_000).filter(|i| i % 12 == 0).collect::<Vec<_>>()
+        );
+
+        let not_all = !&sets[0];
+        let dynamic: BitSetOrN<&dyn BitSetLike> = BitSetOrN(vec![&sets[10], &not_all]);
+        assert!(dynamic.contains(11));
+        assert!(!dynamic.contains(12));
+
+        let empty: BitSetOrN<BitSet> = BitSetOrN(vec![]);
+        assert!(empty.iter().next().is_none());
+    }
+
+    #[test]
+    fn threshold() {
+        use BitSetThreshold;
+
+        let sets = (2..9)
+            .map(|n| (0..100_000).filter(|i| i % n == 0).collect::<BitSet>())
+            .collect::<Vec<_>>();
+        let views = sets
+            .iter()
+            .map(|set| set as &dyn BitSetLike)
+            .collect::<Vec<_>>();
+        let hits = |i: Index| sets.iter().filter(|set| set.contains(i)).count();
+        for k in 0..=views.len() + 1 {
+            let threshold = BitSetThreshold(&views, k);
+            let expected = (0..100_000).filter(|&i| hits(i) >= k);
+            if k == 0 {
+                assert!(threshold.contains(123_456));
+                assert_eq!(
+                    threshold.iter().take(5).collect::<Vec<_>>(),
+                    [0, 1, 2, 3, 4]
+                );
+                continue;
+            }
+            assert_eq!(
+                threshold.iter().collect::<Vec<_>>(),
+                expected.collect::<Vec<_>>()
+            );
+            assert_eq!(threshold.contains(840), hits(840) >= k);
+            assert_eq!(threshold.contains(35), hits(35) >= k);
+        }
+
+        let majority = BitSetThreshold::majority(&views);
+        assert_eq!(majority.1, 4);
+        assert!(majority.contains(0));
+        assert!(!majority.contains(6));
+        assert!(majority.contains(12));
+        assert!(BitSetThreshold(&[], 1).iter().next().is_none());
+    }
+
+    #[test]
+    fn tuples() {
+        use BitSetAnd;
+
+        let sets = (2..18)
+            .map(|n| (0..1_000_000).filter(|i| i % n == 0).collect::<BitSet>())
+            .collect::<Vec<_>>();
+        let pair = (&sets[0], &sets[1]);
+        assert_eq!(
+            pair.iter().collect::<Vec<_>>(),
+            BitSetAnd(&sets[0], &sets[1]).iter().collect::<Vec<_>>()
+        );
+        assert!(pair.contains(6) && !pair.contains(4));
+
+        let s = &sets;
+        let all = (
+            &s[0], &s[1], &s[2], &s[3], &s[4], &s[5], &s[6], &s[7], &s[8], &s[9], &s[10], &s[11],
+            &s[12], &s[13], &s[14], &s[15],
+        );
+        // The multiples of every n from 2 to 17.
+        assert_eq!(all.iter().collect::<Vec<_>>(), [0]);
+        assert!(all.contains(0) && !all.contains(720_720));
+        assert_eq!((&s[2], &s[4], BitSetAll).iter().nth(1), Some(12));
+    }
+
+    #[test]
+    fn union_intersection_of() {
+        let a: BitSet = (0..10_000).filter(|i| i % 2 == 0).collect();
+        let b: BitSet = (0..10_000).filter(|i| i % 3 == 0).collect();
+        let c: BitSet = (500_000..500_100).collect();
+
+        let union = BitSet::union_of(&[&a, &b, &c]);
+        assert_eq!(union, {
+            let mut union = a.clone();
+            union |= &b;
+            union |= &c;
+            union
+        });
+
+        let intersection = BitSet::intersection_of(&[&a, &b]);
+        assert_eq!(
+            (&intersection).iter().collect::<Vec<_>>(),
+            (0..10_000).filter(|i| i % 6 == 0).collect::<Vec<_>>()
+        );
+        assert!(BitSet::intersection_of(&[&a, &c]).is_empty());
+        assert!(BitSet::union_of(&[]).is_empty());
+    }
+
+    #[test]
+    fn extend() {
+        use rand::prelude::*;
+
+        let mut rng = thread_rng();
+        let mut indices = (0..10_000)
+            .map(|_| rng.gen_range(0, 1_048_576))
+            .collect::<Vec<Index>>();
+        let mut c = BitSet::new();
+        c.extend(indices.iter().cloned());
+        for &i in &indices {
+            assert!(c.contains(i));
+        }
+
+        indices.sort();
+        indices.dedup();
+        let mut sorted = BitSet::new();
+        sorted.extend(&indices);
+        assert_eq!((&sorted).iter().collect::<Vec<_>>(), indices);
+        assert_eq!(sorted, c);
+    }
+
+    #[test]
+    fn from_ranges() {
+        let c: BitSet = vec![3..5, 60..200, 10..10, 1_000..1_001]
+            .into_iter()
+            .collect();
+        let expected = (3..5).chain(60..200).chain(1_000..1_001);
+        assert_eq!(c.iter().collect::<Vec<_>>(), expected.collect::<Vec<_>>());
+
+        let mut c: BitSet = (0..10).collect();
+        c.extend(vec![64..128, 5..70]);
+        assert_eq!(c.iter().collect::<Vec<_>>(), (0..128).collect::<Vec<_>>());
+    }
+
     #[test]
     fn xor() {
         // 0011
@@ -0,0 +1,457 @@
+use std::borrow::Cow;
+use std::cmp;
+use std::fmt;
+use std::mem;
+use std::ops::{Index, IndexMut};
+
+use alloc::{self, Boxed, Layer};
+use util::*;
+use {simd, Global, LayerAlloc};
+
+/// The layer 0 words summarized by one layer 1 word.
+pub(crate) type Page = [usize; 1 << BITS];
+
+/// Layer 0 of a [`BitSet`], allocated a page at a time.
+///
+/// The pages cover the words below [`len`], but a page is only allocated
+/// once a word in it is written to, the others read as zero. A high index
+/// thus costs a pointer per page below it rather than the words.
+///
+/// [`BitSet`]: struct.BitSet.html
+/// [`len`]: #method.len
+pub(crate) struct Pages<A: LayerAlloc> {
+    pages: Layer<Option<Boxed<Page, A>>, A>,
+    /// The number of leading pages that are all allocated.
+    full: usize,
+    /// Zeroed pages set aside by `reserve`, taken before allocating.
+    spare: Layer<Boxed<Page, A>, A>,
+    /// The number of leading pages that can be written to without
+    /// allocating. There are at least as many spare pages as pages below
+    /// it that aren't allocated.
+    reserved: usize,
+}
+
+impl<A: LayerAlloc> Pages<A> {
+    pub(crate) fn new_in(alloc: &A) -> Self {
+        Pages {
+            pages: alloc::layer_in(alloc),
+            full: 0,
+            spare: alloc::layer_in(alloc),
+            reserved: 0,
+        }
+    }
+
+    /// Returns the number of words covered by the pages.
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.pages.len() << BITS
+    }
+
+    /// Returns the number of words that can be written to without
+    /// allocating.
+    #[inline]
+    pub(crate) fn capacity(&self) -> usize {
+        self.full << BITS
+    }
+
+    /// Returns the number of words that can be covered and written to
+    /// without allocating, counting what was set aside by `reserve`.
+    #[inline]
+    pub(crate) fn reserved(&self) -> usize {
+        cmp::max(self.full, self.reserved) << BITS
+    }
+
+    /// Returns the word at `p0`, zero if it isn't covered.
+    #[inline]
+    pub(crate) fn get(&self, p0: usize) -> usize {
+        match self.pages.get(p0 >> BITS) {
+            Some(Some(page)) => page[p0 & ((1 << BITS) - 1)],
+            _ => 0,
+        }
+    }
+
+    /// Returns the page `p1` if it is allocated.
+    #[inline]
+    pub(crate) fn page(&self, p1: usize) -> Option<&Page> {
+        match self.pages.get(p1) {
+            Some(Some(page)) => Some(page),
+            _ => None,
+        }
+    }
+
+    /// Returns the page `p1`, allocating it if needed.
+    ///
+    /// Panics if `p1` isn't covered.
+    #[inline]
+    pub(crate) fn page_mut(&mut self, p1: usize) -> &mut Page {
+        if self.pages[p1].is_none() {
+            self.allocate(p1);
+        }
+        match self.pages[p1] {
+            Some(ref mut page) => page,
+            None => unreachable!(),
+        }
+    }
+
+    #[inline(never)]
+    fn allocate(&mut self, p1: usize) {
+        // Pages past the reserved ones don't take from the spare pages,
+        // which are all needed below them.
+        let page = match self.spare.pop() {
+            Some(page) if p1 < self.reserved => page,
+            spare => {
+                self.spare.extend(spare);
+                alloc::box_like::<_, _, A>([0; 1 << BITS], &self.pages)
+            }
+        };
+        self.pages[p1] = Some(page);
+        while let Some(&Some(_)) = self.pages.get(self.full) {
+            self.full += 1;
+        }
+    }
+
+    /// Frees the page `p1`, which reads as zero afterwards.
+    pub(crate) fn free(&mut self, p1: usize) {
+        if let Some(slot) = self.pages.get_mut(p1) {
+            *slot = None;
+            self.full = cmp::min(self.full, p1);
+            self.reserved = cmp::min(self.reserved, self.full);
+        }
+    }
+
+    /// Covers the words up to and including `p0`, without allocating any
+    /// pages.
+    pub(crate) fn grow(&mut self, p0: usize) {
+        let p1 = p0 >> BITS;
+        if self.pages.len() <= p1 {
+            self.pages.resize_with(p1 + 1, || None);
+        }
+    }
+
+    /// Covers the words up to and including `p0` with allocated pages.
+    pub(crate) fn fill(&mut self, p0: usize) {
+        self.grow(p0);
+        for p1 in self.full..=p0 >> BITS {
+            self.page_mut(p1);
+        }
+    }
+
+    /// Makes room to cover the words up to and including `p0`, and sets
+    /// aside the pages to write to them, without covering them yet.
+    pub(crate) fn reserve(&mut self, p0: usize) {
+        let pages = (p0 >> BITS) + 1;
+        if pages <= cmp::max(self.full, self.reserved) {
+            return;
+        }
+        self.pages.reserve(pages.saturating_sub(self.pages.len()));
+        let allocated = self.iter().take_while(|&(p1, _)| p1 < pages).count();
+        let spare = (pages - allocated).saturating_sub(self.spare.len());
+        self.spare.reserve(spare);
+        for _ in 0..spare {
+            let page = alloc::box_like::<_, _, A>([0; 1 << BITS], &self.pages);
+            self.spare.push(page);
+        }
+        self.reserved = pages;
+    }
+
+    /// Drops the words from `len` on.
+    pub(crate) fn truncate(&mut self, len: usize) {
+        let pages = (len + (1 << BITS) - 1) >> BITS;
+        self.pages.truncate(pages);
+        self.full = cmp::min(self.full, pages);
+        self.reserved = cmp::min(self.reserved, self.full);
+        if let Some(&mut Some(ref mut page)) = self.pages.get_mut(len >> BITS) {
+            for word in &mut page[len & ((1 << BITS) - 1)..] {
+                *word = 0;
+            }
+        }
+    }
+
+    /// Zeroes the words below `p0`, freeing the pages entirely below it.
+    pub(crate) fn clear_below(&mut self, p0: usize) {
+        for slot in self.pages.iter_mut().take(p0 >> BITS) {
+            *slot = None;
+        }
+        self.full = 0;
+        self.reserved = 0;
+        if let Some(&mut Some(ref mut page)) = self.pages.get_mut(p0 >> BITS) {
+            for word in &mut page[..p0 & ((1 << BITS) - 1)] {
+                *word = 0;
+            }
+        }
+    }
+
+    /// Returns the number of words up to the last one that isn't zero.
+    pub(crate) fn trimmed_len(&self) -> usize {
+        self.pages
+            .iter()
+            .enumerate()
+            .rev()
+            .filter_map(|(p1, page)| {
+                let page = page.as_ref()?;
+                let last = page.iter().rposition(|&word| word != 0)?;
+                Some((p1 << BITS) + last + 1)
+            })
+            .next()
+            .unwrap_or(0)
+    }
+
+    /// Uncovers every page, keeping the pages as spare ones.
+    pub(crate) fn clear(&mut self) {
+        self.reserved = cmp::max(self.full, self.reserved);
+        self.full = 0;
+        for mut page in self.pages.drain(..).flatten() {
+            *page = [0; 1 << BITS];
+            self.spare.push(page);
+        }
+    }
+
+    /// Frees the pages that are all zero, and drops the ones past the
+    /// last page that isn't.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        for slot in self.pages.iter_mut() {
+            let empty = match *slot {
+                Some(ref page) => page.iter().all(|&word| word == 0),
+                None => false,
+            };
+            if empty {
+                *slot = None;
+            }
+        }
+        let pages = self
+            .pages
+            .iter()
+            .rposition(Option::is_some)
+            .map_or(0, |last| last + 1);
+        self.pages.truncate(pages);
+        self.pages.shrink_to_fit();
+        self.full = self.pages.iter().position(Option::is_none).unwrap_or(pages);
+        self.spare.clear();
+        self.spare.shrink_to_fit();
+        self.reserved = 0;
+    }
+
+    /// Iterates over the words covered by the pages.
+    pub(crate) fn words(&self) -> impl Iterator<Item = usize> + '_ {
+        self.pages.iter().flat_map(|page| {
+            let page = match *page {
+                Some(ref page) => &page[..],
+                None => &[0; 1 << BITS][..],
+            };
+            page.iter().cloned()
+        })
+    }
+
+    /// Iterates over the allocated pages, along with their layer 1 index.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (usize, &Page)> + '_ {
+        self.pages
+            .iter()
+            .enumerate()
+            .filter_map(|(p1, page)| page.as_ref().map(|page| (p1, &**page)))
+    }
+
+    /// Iterates mutably over the allocated pages, along with their layer 1
+    /// index.
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = (usize, &mut Page)> + '_ {
+        self.pages
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(p1, page)| page.as_mut().map(|page| (p1, &mut **page)))
+    }
+
+    /// Counts the set bits of all pages.
+    pub(crate) fn count_ones(&self) -> usize {
+        self.iter().map(|(_, page)| simd::count_ones(page)).sum()
+    }
+
+    /// Returns the bytes allocated for the pages and the page table.
+    pub(crate) fn heap_size(&self) -> usize {
+        let pages = self.iter().count() + self.spare.len();
+        self.pages.capacity() * mem::size_of::<Option<Boxed<Page, A>>>()
+            + self.spare.capacity() * mem::size_of::<Boxed<Page, A>>()
+            + pages * mem::size_of::<Page>()
+    }
+
+    /// Copies the pages into pages of the global allocator.
+    pub(crate) fn to_global(&self) -> Pages<Global> {
+        let mut pages = Pages::new_in(&Global);
+        if self.len() > 0 {
+            pages.grow(self.len() - 1);
+        }
+        for (p1, page) in self.iter() {
+            *pages.page_mut(p1) = *page;
+        }
+        pages
+    }
+
+    /// Returns the words covered by the pages, borrowed when there is a
+    /// single page to cover them and copied otherwise.
+    pub(crate) fn as_slice(&self) -> Cow<'_, [usize]> {
+        match *self.pages {
+            [] => Cow::Borrowed(&[]),
+            [Some(ref page)] => Cow::Borrowed(&page[..]),
+            _ => Cow::Owned(self.to_vec()),
+        }
+    }
+
+    /// Copies the words covered by the pages into a vector.
+    pub(crate) fn to_vec(&self) -> Vec<usize> {
+        self.words().collect()
+    }
+}
+
+impl Pages<Global> {
+    /// Puts `words` into pages, leaving the pages that are all zero out.
+    pub(crate) fn from_words<I>(words: I) -> Self
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        let mut pages = Pages::new_in(&Global);
+        let mut words = words.into_iter().peekable();
+        let mut p1 = 0;
+        while words.peek().is_some() {
+            let mut page = [0; 1 << BITS];
+            for (word, next) in page.iter_mut().zip(&mut words) {
+                *word = next;
+            }
+            pages.grow(p1 << BITS);
+            if page.iter().any(|&word| word != 0) {
+                *pages.page_mut(p1) = page;
+            }
+            p1 += 1;
+        }
+        pages
+    }
+}
+
+/// Reads the word at an index, zero if its page isn't allocated.
+///
+/// Panics if the word isn't covered, like indexing a `Vec`.
+impl<A: LayerAlloc> Index<usize> for Pages<A> {
+    type Output = usize;
+
+    #[inline]
+    fn index(&self, p0: usize) -> &usize {
+        match self.pages[p0 >> BITS] {
+            Some(ref page) => &page[p0 & ((1 << BITS) - 1)],
+            None => &0,
+        }
+    }
+}
+
+/// Writes the word at an index, allocating its page if needed.
+///
+/// Panics if the word isn't covered, like indexing a `Vec`.
+impl<A: LayerAlloc> IndexMut<usize> for Pages<A> {
+    #[inline]
+    fn index_mut(&mut self, p0: usize) -> &mut usize {
+        &mut self.page_mut(p0 >> BITS)[p0 & ((1 << BITS) - 1)]
+    }
+}
+
+/// Only the pages in use are copied, the spare ones set aside by
+/// `reserve` are not.
+impl<A: LayerAlloc> Clone for Pages<A> {
+    fn clone(&self) -> Self {
+        Pages {
+            pages: self.pages.clone(),
+            full: self.full,
+            spare: alloc::layer_like::<_, _, A>(&self.spare),
+            reserved: self.full,
+        }
+    }
+
+    /// Copies the words of `source` into the pages already allocated at
+    /// the same place, and reuses the pages it doesn't need for the ones
+    /// only `source` has, before allocating.
+    fn clone_from(&mut self, source: &Self) {
+        let len = source.pages.len();
+        if self.pages.len() > len {
+            self.spare.extend(self.pages.drain(len..).flatten());
+        }
+        self.pages.resize_with(len, || None);
+        for (ours, theirs) in self.pages.iter_mut().zip(&source.pages) {
+            if theirs.is_none() {
+                self.spare.extend(ours.take());
+            }
+        }
+        for (ours, theirs) in self.pages.iter_mut().zip(&source.pages) {
+            if let Some(ref theirs) = *theirs {
+                match *ours {
+                    Some(ref mut page) => **page = **theirs,
+                    None => {
+                        *ours = Some(match self.spare.pop() {
+                            Some(mut page) => {
+                                *page = **theirs;
+                                page
+                            }
+                            None => alloc::box_like::<_, _, A>(**theirs, &self.spare),
+                        })
+                    }
+                }
+            }
+        }
+        self.spare.clear();
+        self.full = source.full;
+        self.reserved = source.full;
+    }
+}
+
+impl<A: LayerAlloc> fmt::Debug for Pages<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.words()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pages;
+    use Global;
+
+    #[test]
+    fn pages() {
+        let mut pages = Pages::new_in(&Global);
+        pages.grow(1_000);
+        assert_eq!((pages.len(), pages.capacity()), (1_024, 0));
+        assert_eq!(pages.iter().count(), 0);
+        pages[1_000] = 5;
+        pages[3] = 1;
+        assert_eq!((pages[1_000], pages[999], pages.get(5_000)), (5, 0, 0));
+        assert_eq!(pages.iter().map(|(p1, _)| p1).collect::<Vec<_>>(), [0, 15]);
+        assert_eq!(pages.capacity(), 64);
+        assert_eq!(pages.count_ones(), 3);
+
+        pages.truncate(1_000);
+        assert_eq!((pages.len(), pages.get(1_000)), (1_024, 0));
+        pages.shrink_to_fit();
+        assert_eq!((pages.len(), pages.capacity()), (64, 64));
+        assert_eq!(pages.to_vec()[..4], [0, 0, 0, 1]);
+
+        let words = (0..200).map(|i| if i == 150 { 7 } else { 0 });
+        let pages = Pages::from_words(words);
+        assert_eq!(pages.len(), 256);
+        assert_eq!(pages.iter().map(|(p1, _)| p1).collect::<Vec<_>>(), [2]);
+        assert_eq!(pages.get(150), 7);
+    }
+
+    #[test]
+    fn reserve() {
+        let mut pages = Pages::new_in(&Global);
+        pages.reserve(300);
+        assert_eq!((pages.len(), pages.capacity()), (0, 0));
+        assert!(pages.reserved() > 300);
+        pages.fill(300);
+        assert_eq!((pages.len(), pages.capacity()), (320, 320));
+        assert!(pages.spare.is_empty());
+
+        // Clearing keeps the pages for writing them again in any order.
+        pages.clear();
+        assert_eq!((pages.len(), pages.reserved()), (0, 320));
+        pages.grow(1_000);
+        pages[300] = 1;
+        pages[1_000] = 1;
+        assert_eq!(pages.spare.len(), 4);
+        pages[0] = 1;
+        assert_eq!((pages.capacity(), pages.reserved()), (64, 320));
+    }
+}
@@ -0,0 +1,192 @@
+//! A compact binary format for storing bit sets, independent of the width
+//! of `usize`.
+//!
+//! The format starts with the four magic bytes `HiBS` and a version byte,
+//! currently `1`. The indices are then stored as 64 bit chunks, chunk `k`
+//! holding indices `64 * k` to `64 * k + 63`, grouped in runs of non-zero
+//! chunks. Each run is written as
+//!
+//! - the number of zero chunks skipped since the end of the previous run,
+//!   as a little-endian `u32`,
+//! - the number of chunks in the run, as a little-endian `u32`,
+//! - the chunks themselves, as little-endian `u64`s.
+//!
+//! A run of zero chunks ends the stream.
+
+use std::io::{self, Read, Write};
+
+use util::*;
+use {BitSet, BitSetLike};
+
+const MAGIC: [u8; 4] = *b"HiBS";
+const VERSION: u8 = 1;
+
+/// Chunks are 64 bits whatever the width of a layer 0 word is.
+const CHUNK_BITS: usize = 6;
+
+/// Writes the indices of `set` to `writer`, see the module docs for the
+/// format.
+pub(crate) fn write_to<T, W>(set: &T, mut writer: W) -> io::Result<()>
+where
+    T: BitSetLike,
+    W: Write,
+{
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&[VERSION])?;
+
+    let mut run = Vec::new();
+    // The chunk after the end of the last run written.
+    let mut written = 0;
+    // The chunk after the end of `run`.
+    let mut end = 0;
+    for (base, word) in set.masks() {
+        let chunk = base as usize >> CHUNK_BITS;
+        let word = (word as u64) << (base as usize & ((1 << CHUNK_BITS) - 1));
+        if chunk + 1 == end {
+            *run.last_mut().unwrap() |= word;
+            continue;
+        }
+        if chunk != end && !run.is_empty() {
+            write_run(&mut writer, end - run.len() - written, &run)?;
+            written = end;
+            run.clear();
+        }
+        run.push(word);
+        end = chunk + 1;
+    }
+    if !run.is_empty() {
+        write_run(&mut writer, end - run.len() - written, &run)?;
+    }
+    write_run(&mut writer, 0, &[])
+}
+
+fn write_run<W: Write>(writer: &mut W, skip: usize, chunks: &[u64]) -> io::Result<()> {
+    writer.write_all(&(skip as u32).to_le_bytes())?;
+    writer.write_all(&(chunks.len() as u32).to_le_bytes())?;
+    for chunk in chunks {
+        writer.write_all(&chunk.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Reads a set written by `write_to` from `reader`.
+pub(crate) fn read_from<R: Read>(mut reader: R) -> io::Result<BitSet> {
+    let mut header = [0; 5];
+    reader.read_exact(&mut header)?;
+    if header[..4] != MAGIC {
+        return Err(invalid("Not a bit set"));
+    }
+    if header[4] != VERSION {
+        return Err(invalid("Unsupported bit set format version"));
+    }
+
+    let mut set = BitSet::new();
+    let mut chunk = 0usize;
+    loop {
+        chunk = chunk.saturating_add(read_u32(&mut reader)? as usize);
+        let len = read_u32(&mut reader)? as usize;
+        if len == 0 {
+            return Ok(set);
+        }
+        if chunk.saturating_add(len) > MAX_EID >> CHUNK_BITS {
+            return Err(invalid("Bit set index out of range"));
+        }
+        for _ in 0..len {
+            let mut bytes = [0; 8];
+            reader.read_exact(&mut bytes)?;
+            add_chunk(&mut set, chunk, u64::from_le_bytes(bytes));
+            chunk += 1;
+        }
+    }
+}
+
+fn add_chunk(set: &mut BitSet, chunk: usize, bits: u64) {
+    let words = 1 << (CHUNK_BITS - BITS);
+    for part in 0..words {
+        let word = (bits >> (part << BITS)) as usize;
+        if word != 0 {
+            set.add_word(chunk * words + part, word);
+        }
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use BitSet;
+
+    fn round_trip(set: &BitSet) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        set.write_to(&mut bytes).unwrap();
+        assert_eq!(&BitSet::read_from(&bytes[..]).unwrap(), set);
+        bytes
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(round_trip(&BitSet::new()), b"HiBS\x01\0\0\0\0\0\0\0\0");
+    }
+
+    #[test]
+    fn runs() {
+        let mut set = BitSet::new();
+        set.add(1);
+        set.add(64);
+        set.add(64 * 10 + 3);
+        let bytes = round_trip(&set);
+        let mut expected = b"HiBS\x01".to_vec();
+        expected.extend_from_slice(&[0, 0, 0, 0, 2, 0, 0, 0]);
+        expected.extend_from_slice(&[2, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(&[8, 0, 0, 0, 1, 0, 0, 0]);
+        expected.extend_from_slice(&[8, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(&[0; 8]);
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn large() {
+        let set: BitSet = (0..1_000_000)
+            .filter(|i| i % 3 == 0 || i % 10_000 < 500)
+            .collect();
+        round_trip(&set);
+        let mut last = BitSet::new();
+        last.add(::MAX_EID as u32 - 1);
+        round_trip(&last);
+    }
+
+    #[test]
+    fn invalid() {
+        let mut bytes = Vec::new();
+        (0..100).collect::<BitSet>().write_to(&mut bytes).unwrap();
+
+        let mut magic = bytes.clone();
+        magic[0] = b'h';
+        let err = BitSet::read_from(&magic[..]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        let mut version = bytes.clone();
+        version[4] = 2;
+        let err = BitSet::read_from(&version[..]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        let mut range = bytes.clone();
+        range[5..9].copy_from_slice(&(1u32 << 20).to_le_bytes());
+        let err = BitSet::read_from(&range[..]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        let err = BitSet::read_from(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+}
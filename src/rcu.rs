@@ -0,0 +1,225 @@
+use std::mem;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+
+use arc_swap::ArcSwap;
+
+use util::*;
+use {AtomicBitSet, BitSet};
+
+/// A `BitSet` that many readers can iterate while writers keep adding to
+/// it, in the style of read-copy-update.
+///
+/// Readers take a [`snapshot`], an immutable `Arc<BitSet>` they can keep
+/// and iterate for as long as they like. Writers [`add`] to a pending
+/// [`AtomicBitSet`] delta, which readers don't see until it is merged
+/// into a new snapshot by [`publish`].
+///
+/// The snapshot and the delta are each behind an atomically swapped
+/// pointer, so taking a snapshot and adding never lock or wait, not even
+/// while a `publish` is running. Only publishers wait, for each other and
+/// for the `add`s still writing to the delta they merge.
+///
+/// # Examples
+///
+/// ```
+/// # use hibitset::{BitSetLike, RcuBitSet};
+/// let set = RcuBitSet::new();
+/// set.add(5);
+/// let before = set.snapshot();
+/// set.publish();
+/// let after = set.snapshot();
+/// assert!(!before.contains(5));
+/// assert!(after.contains(5));
+/// ```
+///
+/// [`snapshot`]: #method.snapshot
+/// [`add`]: #method.add
+/// [`publish`]: #method.publish
+/// [`AtomicBitSet`]: struct.AtomicBitSet.html
+#[derive(Debug, Default)]
+pub struct RcuBitSet {
+    snapshot: ArcSwap<BitSet>,
+    delta: ArcSwap<Delta>,
+    // Serializes publishers, and keeps the last merged delta around so
+    // its allocation can be reused.
+    spare: Mutex<Option<AtomicBitSet>>,
+}
+
+/// The pending delta. Once retired by a publisher, it sends its set to
+/// the publisher when the last writer holding it lets go.
+#[derive(Debug, Default)]
+struct Delta {
+    set: AtomicBitSet,
+    retired: Mutex<Option<Sender<AtomicBitSet>>>,
+}
+
+impl Drop for Delta {
+    fn drop(&mut self) {
+        if let Some(publisher) = self.retired.get_mut().unwrap().take() {
+            // The publisher waits for the set, so it is still listening.
+            let _ = publisher.send(mem::take(&mut self.set));
+        }
+    }
+}
+
+impl RcuBitSet {
+    /// Creates an empty `RcuBitSet`.
+    pub fn new() -> RcuBitSet {
+        Default::default()
+    }
+
+    /// Returns the current snapshot of the set.
+    ///
+    /// The snapshot doesn't change, indices added afterwards only show up
+    /// in the snapshots taken after the next `publish`.
+    pub fn snapshot(&self) -> Arc<BitSet> {
+        self.snapshot.load_full()
+    }
+
+    /// Adds `id` to the pending delta. Returns `true` if the value was
+    /// already in the delta.
+    ///
+    /// Like `AtomicBitSet::add_atomic`, this panics if `id` is out of
+    /// range.
+    pub fn add(&self, id: Index) -> bool {
+        self.delta.load().set.add_atomic(id)
+    }
+
+    /// Merges the pending delta into a new snapshot and returns it.
+    ///
+    /// A fresh delta is swapped in first, so writers keep going while the
+    /// old one is merged. The merge waits for the `add`s that were still
+    /// writing to the old delta to return, so no index is lost. It blocks
+    /// until the last of them drops the old delta, which hands it over.
+    pub fn publish(&self) -> Arc<BitSet> {
+        let mut spare = self.spare.lock().unwrap();
+        let fresh = Delta {
+            set: spare.take().unwrap_or_default(),
+            retired: Mutex::new(None),
+        };
+        let old = self.delta.swap(Arc::new(fresh));
+        // Nobody can load the old delta anymore, so once the writers
+        // holding it are done it's dropped and its set sent over here.
+        let (publisher, retired) = mpsc::channel();
+        *old.retired.lock().unwrap() = Some(publisher);
+        drop(old);
+        let mut old = retired.recv().unwrap();
+
+        let mut next = (*self.snapshot()).clone();
+        next |= &old;
+        let next = Arc::new(next);
+        self.snapshot.store(next.clone());
+
+        old.clear();
+        *spare = Some(old);
+        next
+    }
+
+    /// Builds a new snapshot by applying `f` to a copy of the current one.
+    ///
+    /// This is how indices are removed. The pending delta is not merged.
+    pub fn update<F>(&self, f: F) -> Arc<BitSet>
+    where
+        F: FnOnce(&mut BitSet),
+    {
+        let _publisher = self.spare.lock().unwrap();
+        let mut next = (*self.snapshot()).clone();
+        f(&mut next);
+        let next = Arc::new(next);
+        self.snapshot.store(next.clone());
+        next
+    }
+}
+
+impl From<BitSet> for RcuBitSet {
+    fn from(set: BitSet) -> Self {
+        RcuBitSet {
+            snapshot: ArcSwap::from_pointee(set),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod rcu_set_test {
+    use std::sync::Arc;
+    use std::thread;
+
+    use {BitSet, RcuBitSet};
+
+    #[test]
+    fn publish() {
+        let set = RcuBitSet::from((0..10).collect::<BitSet>());
+        assert!(!set.add(20));
+        assert!(set.add(20));
+        let old = set.snapshot();
+        let new = set.publish();
+        assert_eq!(old.count(), 10);
+        assert_eq!(new.count(), 11);
+        assert!(!set.add(20));
+
+        let removed = set.update(|set| {
+            set.remove(0);
+        });
+        assert!(!removed.contains(0) && removed.contains(20));
+        assert_eq!(set.publish().count(), 10);
+    }
+
+    #[test]
+    fn reads_during_publish() {
+        let set = Arc::new(RcuBitSet::from((0..10).collect::<BitSet>()));
+        // An `add` that hasn't returned yet keeps the publisher waiting.
+        let writer = set.delta.load_full();
+        let publisher = {
+            let set = set.clone();
+            thread::spawn(move || set.publish())
+        };
+        while Arc::ptr_eq(&set.delta.load(), &writer) {
+            thread::yield_now();
+        }
+
+        // Neither readers nor other writers wait for the publisher.
+        assert_eq!(set.snapshot().count(), 10);
+        assert!(!set.add(11));
+        writer.set.add_atomic(10);
+        drop(writer);
+
+        let published = publisher.join().unwrap();
+        assert!(published.contains(10) && !published.contains(11));
+        assert_eq!(set.publish().count(), 12);
+    }
+
+    #[test]
+    fn concurrent_writers_and_readers() {
+        let set = Arc::new(RcuBitSet::new());
+        let writers = (0..4)
+            .map(|t| {
+                let set = set.clone();
+                thread::spawn(move || {
+                    for i in 0..10_000 {
+                        set.add(i * 4 + t);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let reader = {
+            let set = set.clone();
+            thread::spawn(move || {
+                let mut last = 0;
+                for _ in 0..100 {
+                    let snapshot = set.snapshot();
+                    let count = snapshot.count();
+                    assert!(count >= last);
+                    last = count;
+                    set.publish();
+                }
+            })
+        };
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        reader.join().unwrap();
+        assert_eq!(set.publish().count(), 40_000);
+    }
+}
@@ -0,0 +1,128 @@
+use util::*;
+use {BitSet, BitSetLike};
+
+/// The renumbering done by [`BitSet::compact`], mapping each old index of
+/// the set to its position among them.
+///
+/// It keeps the old set along with the number of indices before each of
+/// its layer 0 words, so both directions are answered without a table
+/// the size of the old index space.
+///
+/// [`BitSet::compact`]: struct.BitSet.html#method.compact
+#[derive(Debug, Clone)]
+pub struct Remap {
+    old: BitSet,
+    ranks: Vec<u32>,
+}
+
+impl Remap {
+    pub(crate) fn new(old: BitSet) -> Remap {
+        let mut rank = 0;
+        let ranks = old
+            .layer0_as_slice()
+            .iter()
+            .map(|word| {
+                let before = rank;
+                rank += word.count_ones();
+                before
+            })
+            .collect();
+        Remap { old, ranks }
+    }
+
+    /// Returns the new index of `old`, or `None` if it wasn't in the set.
+    pub fn get(&self, old: Index) -> Option<Index> {
+        if !self.old.contains(old) {
+            return None;
+        }
+        let (p0, mask) = (old.offset(SHIFT1), old.mask(SHIFT0));
+        let below = self.old.layer0(p0) & (mask - 1);
+        Some(self.ranks[p0] + below.count_ones())
+    }
+
+    /// Returns the old index that was renumbered to `new`, or `None` if
+    /// `new` is past the end.
+    pub fn old(&self, new: Index) -> Option<Index> {
+        if new as usize >= self.len() {
+            return None;
+        }
+        let p0 = self.ranks.partition_point(|&rank| rank <= new) - 1;
+        let mut word = self.old.layer0(p0);
+        for _ in self.ranks[p0]..new {
+            word &= word - 1;
+        }
+        Some(((p0 << BITS) as Index) | word.trailing_zeros())
+    }
+
+    /// Returns the number of indices that were renumbered.
+    pub fn len(&self) -> usize {
+        self.old.len()
+    }
+
+    /// Returns `true` if the set was empty.
+    pub fn is_empty(&self) -> bool {
+        self.old.is_empty()
+    }
+
+    /// Returns the set of the old indices.
+    pub fn old_set(&self) -> &BitSet {
+        &self.old
+    }
+
+    /// Iterates over the `(old, new)` pairs, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = (Index, Index)> + '_ {
+        (&self.old).iter().zip(0..)
+    }
+
+    /// Moves the elements of `values`, indexed by the old indices, to
+    /// their new indices, and drops the rest.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `values` doesn't have an element for every old index.
+    pub fn apply<T>(&self, values: &mut Vec<T>) {
+        // The new index of an element is never above its old one, so the
+        // ones still to be moved are never overwritten.
+        for (old, new) in self.iter() {
+            values.swap(new as usize, old as usize);
+        }
+        values.truncate(self.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {BitSet, BitSetLike};
+
+    #[test]
+    fn compact() {
+        let mut set: BitSet = (0..100_000)
+            .filter(|i| i % 7 == 3 || i % 1_000 == 999)
+            .collect();
+        let members: Vec<_> = (&set).iter().collect();
+        let mut values: Vec<_> = (0..100_000).map(|i| i * 2).collect();
+
+        let remap = set.compact();
+        assert_eq!(set.len(), members.len());
+        assert_eq!(
+            (&set).iter().collect::<Vec<_>>(),
+            (0..members.len() as u32).collect::<Vec<_>>()
+        );
+        assert_eq!(set, (0..members.len() as u32).collect::<BitSet>());
+        for (new, &old) in members.iter().enumerate() {
+            assert_eq!(remap.get(old), Some(new as u32));
+            assert_eq!(remap.old(new as u32), Some(old));
+        }
+        assert_eq!(remap.get(0), None);
+        assert_eq!(remap.old(members.len() as u32), None);
+        assert!(remap.iter().eq(members.iter().cloned().zip(0..)));
+
+        remap.apply(&mut values);
+        assert_eq!(values, members.iter().map(|i| i * 2).collect::<Vec<_>>());
+
+        let mut empty = BitSet::new();
+        let remap = empty.compact();
+        assert!(remap.is_empty() && empty.is_empty());
+        assert_eq!(remap.old(0), None);
+    }
+}
@@ -0,0 +1,71 @@
+use roaring::RoaringBitmap;
+
+use util::*;
+use {BitSet, BitSetLike};
+
+/// Copies `set` into a `RoaringBitmap` a word at a time, inserting full
+/// words as ranges.
+pub fn to_roaring<T: BitSetLike>(set: &T) -> RoaringBitmap {
+    let mut bitmap = RoaringBitmap::new();
+    for (base, word) in set.masks() {
+        if word == !0 {
+            bitmap.insert_range(base..base + (1 << BITS));
+        } else {
+            let bits = (0..1 << BITS)
+                .filter(|bit| word & (1 << bit) != 0)
+                .map(|bit| base + bit);
+            bitmap
+                .append(bits)
+                .expect("the words of a set are visited in order");
+        }
+    }
+    bitmap
+}
+
+/// Creates a `BitSet` from a `RoaringBitmap`.
+///
+/// The indices come out of the bitmap in order, so each layer 0 word is
+/// written once.
+///
+/// Panics if the bitmap holds an index that is out of range for a
+/// `BitSet`.
+impl<'a> From<&'a RoaringBitmap> for BitSet {
+    fn from(bitmap: &'a RoaringBitmap) -> Self {
+        let mut set = BitSet::new();
+        set.extend(bitmap.iter());
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use roaring::RoaringBitmap;
+
+    use {BitSet, BitSetAnd, BitSetLike};
+
+    #[test]
+    fn round_trip() {
+        let set: BitSet = (0..1_000_000)
+            .filter(|i| i % 997 == 3 || (5_000..6_000).contains(i))
+            .collect();
+        let bitmap = set.to_roaring();
+        assert_eq!(bitmap.len(), (&set).iter().count() as u64);
+        assert_eq!(
+            bitmap.iter().collect::<Vec<_>>(),
+            (&set).iter().collect::<Vec<_>>()
+        );
+        assert_eq!(BitSet::from(&bitmap), set);
+    }
+
+    #[test]
+    fn combinator() {
+        let a: BitSet = (0..10_000).filter(|i| i % 2 == 0).collect();
+        let b: BitSet = (0..10_000).filter(|i| i % 3 == 0).collect();
+        let bitmap = BitSetAnd(&a, &b).to_roaring();
+        assert_eq!(
+            bitmap.iter().collect::<Vec<_>>(),
+            (0..10_000).filter(|i| i % 6 == 0).collect::<Vec<_>>()
+        );
+        assert!(BitSet::from(&RoaringBitmap::new()).is_empty());
+    }
+}
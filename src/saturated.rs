@@ -0,0 +1,246 @@
+use std::mem;
+
+use alloc::{self, Layer};
+use pages::Pages;
+use util::*;
+use LayerAlloc;
+
+/// The inverse of the summary layers of a [`BitSet`]: a bit is set when
+/// the word below it is all ones rather than when it isn't zero.
+///
+/// A bit of layer 1 is set when its layer 0 word is full, and a bit of
+/// layers 2 and 3 when the word below it has every bit set, so a block of
+/// indices that are all in the set is a single set bit. Searching for an
+/// index that isn't in the set can then skip it without reading the
+/// words of layer 0.
+///
+/// The layers are only as long as their last non-zero word, as the words
+/// past it read as zero anyway.
+///
+/// [`BitSet`]: struct.BitSet.html
+pub(crate) struct Saturated<A: LayerAlloc> {
+    layer3: usize,
+    layer2: Layer<usize, A>,
+    layer1: Layer<usize, A>,
+}
+
+impl<A: LayerAlloc> Saturated<A> {
+    pub(crate) fn new_in(alloc: &A) -> Self {
+        Saturated {
+            layer3: 0,
+            layer2: alloc::layer_in(alloc),
+            layer1: alloc::layer_in(alloc),
+        }
+    }
+
+    /// Records that the layer 0 word at `p0` changed from `old` to `new`.
+    #[inline]
+    pub(crate) fn update(&mut self, p0: usize, old: usize, new: usize) {
+        if new == !0 && old != !0 {
+            self.saturate(p0);
+        } else if old == !0 && new != !0 {
+            self.desaturate(p0);
+        }
+    }
+
+    #[inline(never)]
+    fn saturate(&mut self, p0: usize) {
+        let (p1, p2) = (p0 >> BITS, p0 >> (BITS * 2));
+        if self.layer1.len() <= p1 {
+            self.layer1.resize(p1 + 1, 0);
+        }
+        self.layer1[p1] |= 1 << (p0 & ((1 << BITS) - 1));
+        if self.layer1[p1] != !0 {
+            return;
+        }
+
+        if self.layer2.len() <= p2 {
+            self.layer2.resize(p2 + 1, 0);
+        }
+        self.layer2[p2] |= 1 << (p1 & ((1 << BITS) - 1));
+        if self.layer2[p2] != !0 {
+            return;
+        }
+
+        self.layer3 |= 1 << p2;
+    }
+
+    #[inline(never)]
+    fn desaturate(&mut self, p0: usize) {
+        // The word was full, so its bit and the words above it are stored.
+        let (p1, p2) = (p0 >> BITS, p0 >> (BITS * 2));
+        let full = self.layer1[p1] == !0;
+        self.layer1[p1] &= !(1 << (p0 & ((1 << BITS) - 1)));
+        if !full {
+            return;
+        }
+
+        let full = self.layer2[p2] == !0;
+        self.layer2[p2] &= !(1 << (p1 & ((1 << BITS) - 1)));
+        if !full {
+            return;
+        }
+
+        self.layer3 &= !(1 << p2);
+    }
+
+    /// Rebuilds every layer from the words of `layer0`.
+    pub(crate) fn rebuild<B: LayerAlloc>(&mut self, layer0: &Pages<B>) {
+        self.layer1.clear();
+        for (p1, page) in layer0.iter() {
+            let word = saturation(page);
+            if word != 0 {
+                self.layer1.resize(p1 + 1, 0);
+                self.layer1[p1] = word;
+            }
+        }
+        self.layer2.clear();
+        for (p2, chunk) in self.layer1.chunks(1 << BITS).enumerate() {
+            let word = saturation(chunk);
+            if word != 0 {
+                self.layer2.resize(p2 + 1, 0);
+                self.layer2[p2] = word;
+            }
+        }
+        self.layer3 = saturation(&self.layer2);
+    }
+
+    /// Returns the first layer 0 word at or after `p0` that isn't all
+    /// ones, which is `MAX_EID >> BITS` or more if there is none.
+    ///
+    /// The layers are climbed until one has a clear bit past the position
+    /// of `p0`, and descended back along the first clear bit of each word
+    /// below it, so this reads at most two words per layer.
+    pub(crate) fn next_unsaturated(&self, p0: usize) -> usize {
+        let mut level = 1;
+        let mut idx = p0;
+        let free = loop {
+            let free = !self.word(level, idx >> BITS) & (!0 << (idx & ((1 << BITS) - 1)));
+            if free != 0 {
+                break free;
+            }
+            if level == LAYERS - 1 {
+                return MAX_EID >> BITS;
+            }
+            idx = (idx >> BITS) + 1;
+            level += 1;
+        };
+        idx = (idx & !((1 << BITS) - 1)) | free.trailing_zeros() as usize;
+        while level > 1 {
+            level -= 1;
+            idx = (idx << BITS) | (!self.word(level, idx)).trailing_zeros() as usize;
+        }
+        idx
+    }
+
+    /// Returns the word `idx` of `level`. Layer 3 has a single word, the
+    /// ones past it are beyond the maximum index and read as full.
+    #[inline]
+    fn word(&self, level: usize, idx: usize) -> usize {
+        match level {
+            1 => self.layer1.get(idx).cloned().unwrap_or(0),
+            2 => self.layer2.get(idx).cloned().unwrap_or(0),
+            _ if idx == 0 => self.layer3,
+            _ => !0,
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.layer1.clear();
+        self.layer2.clear();
+        self.layer3 = 0;
+    }
+
+    /// Drops the trailing zero words and frees the memory past them.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        for layer in [&mut self.layer1, &mut self.layer2].iter_mut() {
+            let len = layer.iter().rposition(|&w| w != 0).map_or(0, |i| i + 1);
+            layer.truncate(len);
+            layer.shrink_to_fit();
+        }
+    }
+
+    /// Returns the bytes allocated for layers 1 and 2.
+    pub(crate) fn heap_size(&self) -> (usize, usize) {
+        let words = |layer: &Layer<usize, A>| layer.capacity() * mem::size_of::<usize>();
+        (words(&self.layer1), words(&self.layer2))
+    }
+}
+
+impl<A: LayerAlloc> Clone for Saturated<A> {
+    fn clone(&self) -> Self {
+        Saturated {
+            layer3: self.layer3,
+            layer2: self.layer2.clone(),
+            layer1: self.layer1.clone(),
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.layer3 = source.layer3;
+        self.layer2.clone_from(&source.layer2);
+        self.layer1.clone_from(&source.layer1);
+    }
+}
+
+/// Sets each bit of the result to whether the matching word of `words` is
+/// all ones.
+fn saturation(words: &[usize]) -> usize {
+    words.iter().enumerate().fold(0, |word, (i, &lower)| {
+        word | (((lower == !0) as usize) << i)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Saturated;
+    use pages::Pages;
+    use util::*;
+    use Global;
+
+    #[test]
+    fn next_unsaturated() {
+        let mut full = Saturated::new_in(&Global);
+        assert_eq!(full.next_unsaturated(0), 0);
+        full.update(0, 0, !0);
+        full.update(2, 0, !0);
+        assert_eq!(full.next_unsaturated(0), 1);
+        assert_eq!(full.next_unsaturated(2), 3);
+
+        // Fill the first two layer 2 blocks, but for one word.
+        let words = 2 << (BITS * 2);
+        for p0 in 0..words {
+            full.update(p0, 0, !0);
+        }
+        full.update(100, !0, 5);
+        assert_eq!(full.next_unsaturated(0), 100);
+        assert_eq!(full.next_unsaturated(101), words);
+        full.update(100, 5, !0);
+        assert_eq!(full.next_unsaturated(0), words);
+        assert_eq!(full.layer3, 0b11);
+
+        let mut rebuilt = Saturated::new_in(&Global);
+        rebuilt.rebuild(&Pages::from_words((0..words).map(|_| !0)));
+        assert_eq!(rebuilt.layer1, full.layer1);
+        assert_eq!(rebuilt.layer2, full.layer2);
+        assert_eq!(rebuilt.layer3, full.layer3);
+
+        full.update(words - 1, !0, 0);
+        assert_eq!(full.layer3, 0b01);
+        assert_eq!(full.next_unsaturated(0), words - 1);
+    }
+
+    #[test]
+    fn all_full() {
+        let mut full = Saturated::new_in(&Global);
+        let words = MAX_EID >> BITS;
+        for p0 in 0..words {
+            full.update(p0, 0, !0);
+        }
+        assert_eq!(full.layer3, !0);
+        assert_eq!(full.next_unsaturated(0), words);
+        assert_eq!(full.next_unsaturated(words - 1), words);
+        full.update(words - 1, !0, 0);
+        assert_eq!(full.next_unsaturated(0), words - 1);
+    }
+}
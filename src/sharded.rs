@@ -0,0 +1,224 @@
+use std::fmt::{Debug, Error as FormatError, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use debug;
+use util::*;
+use {AtomicBitSet, BitSet, BitSetLike};
+
+/// An `AtomicBitSet` split into shards, for sets that many threads add to
+/// at once.
+///
+/// Every thread that writes to an `AtomicBitSet` updates the same few
+/// layer 2 and 3 words, and with dozens of writers the cache lines
+/// holding them keep moving between cores. Here each thread adds to a
+/// shard of its own, picked when the thread first touches any
+/// `ShardedAtomicBitSet`, so writers only share words with the threads
+/// sharing their shard.
+///
+/// Reads merge the shards on the fly, [`flush`] moves them all into one
+/// plain `BitSet` so the reads after it are as cheap as a `BitSet`'s.
+///
+/// # Examples
+///
+/// ```
+/// # use hibitset::{BitSetLike, ShardedAtomicBitSet};
+/// use std::thread;
+///
+/// let mut set = ShardedAtomicBitSet::with_shards(4);
+/// thread::scope(|s| {
+///     for t in 0..4 {
+///         let set = &set;
+///         s.spawn(move || {
+///             for id in (t..1000).step_by(4) {
+///                 set.add_atomic(id);
+///             }
+///         });
+///     }
+/// });
+/// assert!(set.contains(999));
+/// set.flush();
+/// assert_eq!((&set).iter().count(), 1000);
+/// ```
+///
+/// [`flush`]: #method.flush
+pub struct ShardedAtomicBitSet {
+    flushed: BitSet,
+    shards: Box<[AtomicBitSet]>,
+}
+
+/// Hands out the shard numbers of the threads.
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+}
+
+impl ShardedAtomicBitSet {
+    /// Creates an empty `ShardedAtomicBitSet` with a shard for each thread
+    /// the machine can run in parallel.
+    pub fn new() -> ShardedAtomicBitSet {
+        let shards = thread::available_parallelism().map_or(1, |n| n.get());
+        ShardedAtomicBitSet::with_shards(shards)
+    }
+
+    /// Creates an empty `ShardedAtomicBitSet` with `shards` shards.
+    ///
+    /// Panics if `shards` is zero.
+    pub fn with_shards(shards: usize) -> ShardedAtomicBitSet {
+        assert!(shards > 0, "Expected at least one shard");
+        ShardedAtomicBitSet {
+            flushed: BitSet::new(),
+            shards: (0..shards).map(|_| AtomicBitSet::new()).collect(),
+        }
+    }
+
+    /// Returns the number of shards.
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard(&self) -> &AtomicBitSet {
+        let shard = SHARD.with(|&shard| shard);
+        &self.shards[shard % self.shards.len()]
+    }
+
+    /// Adds `id` to the shard of the calling thread. Returns `true` if the
+    /// value was already in that shard or flushed.
+    ///
+    /// An index another thread added to another shard since the last
+    /// `flush` is added again, and `false` is returned for it.
+    ///
+    /// Like `AtomicBitSet::add_atomic`, this panics if `id` is out of
+    /// range.
+    #[inline]
+    pub fn add_atomic(&self, id: Index) -> bool {
+        self.flushed.contains(id) || self.shard().add_atomic(id)
+    }
+
+    /// Returns `true` if `id` is in the flushed set or any shard.
+    #[inline]
+    pub fn contains(&self, id: Index) -> bool {
+        self.flushed.contains(id) || self.shards.iter().any(|shard| shard.contains(id))
+    }
+
+    /// Removes `id` from the set. Returns `true` if it was in the set.
+    pub fn remove(&mut self, id: Index) -> bool {
+        let mut removed = self.flushed.remove(id);
+        for shard in self.shards.iter_mut() {
+            removed |= shard.remove(id);
+        }
+        removed
+    }
+
+    /// Merges every shard into the flushed set and clears them, keeping
+    /// the blocks of the shards allocated for the next round of adds.
+    pub fn flush(&mut self) {
+        for shard in self.shards.iter_mut() {
+            self.flushed |= &*shard;
+            shard.clear();
+        }
+    }
+
+    /// Returns the flushed set, without the indices added since the last
+    /// [`flush`].
+    ///
+    /// [`flush`]: #method.flush
+    pub fn flushed(&self) -> &BitSet {
+        &self.flushed
+    }
+
+    /// Removes every index from the set.
+    pub fn clear(&mut self) {
+        self.flushed.clear();
+        for shard in self.shards.iter_mut() {
+            shard.clear();
+        }
+    }
+}
+
+impl BitSetLike for ShardedAtomicBitSet {
+    #[inline]
+    fn layer3(&self) -> usize {
+        self.shards
+            .iter()
+            .fold(self.flushed.layer3(), |word, shard| word | shard.layer3())
+    }
+
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        self.shards
+            .iter()
+            .fold(self.flushed.layer2(i), |word, shard| word | shard.layer2(i))
+    }
+
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        self.shards
+            .iter()
+            .fold(self.flushed.layer1(i), |word, shard| word | shard.layer1(i))
+    }
+
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        self.shards
+            .iter()
+            .fold(self.flushed.layer0(i), |word, shard| word | shard.layer0(i))
+    }
+
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        ShardedAtomicBitSet::contains(self, i)
+    }
+}
+
+impl Default for ShardedAtomicBitSet {
+    fn default() -> Self {
+        ShardedAtomicBitSet::new()
+    }
+}
+
+impl Debug for ShardedAtomicBitSet {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
+        debug::summary("ShardedAtomicBitSet", self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use {BitSet, BitSetLike, ShardedAtomicBitSet};
+
+    #[test]
+    fn add_flush() {
+        let mut set = ShardedAtomicBitSet::with_shards(3);
+        thread::scope(|s| {
+            for t in 0..6 {
+                let set = &set;
+                s.spawn(move || {
+                    for id in (t..60_000).step_by(6) {
+                        assert!(!set.add_atomic(id));
+                    }
+                });
+            }
+        });
+        let all = (0..60_000).collect::<BitSet>();
+        assert_eq!((&set).iter().count(), 60_000);
+        assert!(set.flushed().is_empty());
+
+        set.flush();
+        assert_eq!(*set.flushed(), all);
+        assert!(set.add_atomic(59_999));
+        assert!(!set.add_atomic(60_000));
+        assert!(set.contains(60_000));
+
+        assert!(set.remove(10));
+        assert!(set.remove(60_000));
+        assert!(!set.remove(60_000));
+        assert_eq!((&set).iter().count(), 59_999);
+
+        set.clear();
+        assert_eq!((&set).iter().next(), None);
+    }
+}
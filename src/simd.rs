@@ -0,0 +1,125 @@
+//! Word-wise kernels over whole layers.
+//!
+//! The loops are written so the compiler vectorizes them. With the `simd`
+//! feature on x86 targets they are additionally compiled for AVX2 and
+//! `popcnt`, which are picked at runtime when the CPU supports them, as
+//! the default x86 targets only assume SSE2. Other targets use the plain
+//! loops, which already vectorize for the baseline of e.g. aarch64 (NEON).
+//!
+//! It also holds the software prefetch used by `BitIter` when the
+//! `prefetch` feature is on.
+
+mod scalar {
+    #[inline(always)]
+    pub fn or_into(dst: &mut [usize], src: &[usize]) {
+        for (dst, src) in dst.iter_mut().zip(src) {
+            *dst |= *src;
+        }
+    }
+
+    #[inline(always)]
+    pub fn and_into(dst: &mut [usize], src: &[usize]) {
+        for (dst, src) in dst.iter_mut().zip(src) {
+            *dst &= *src;
+        }
+    }
+
+    #[inline(always)]
+    pub fn xor_into(dst: &mut [usize], src: &[usize]) {
+        for (dst, src) in dst.iter_mut().zip(src) {
+            *dst ^= *src;
+        }
+    }
+
+    #[inline(always)]
+    pub fn count_ones(words: &[usize]) -> usize {
+        words.iter().map(|word| word.count_ones() as usize).sum()
+    }
+}
+
+#[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+mod x86 {
+    use super::scalar;
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn or_into(dst: &mut [usize], src: &[usize]) {
+        scalar::or_into(dst, src)
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn and_into(dst: &mut [usize], src: &[usize]) {
+        scalar::and_into(dst, src)
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn xor_into(dst: &mut [usize], src: &[usize]) {
+        scalar::xor_into(dst, src)
+    }
+
+    #[target_feature(enable = "popcnt")]
+    pub unsafe fn count_ones(words: &[usize]) -> usize {
+        scalar::count_ones(words)
+    }
+}
+
+/// Ors every word of `src` into the word of `dst` at the same position.
+pub fn or_into(dst: &mut [usize], src: &[usize]) {
+    #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: The CPU supports AVX2.
+            return unsafe { x86::or_into(dst, src) };
+        }
+    }
+    scalar::or_into(dst, src)
+}
+
+/// Ands every word of `src` into the word of `dst` at the same position.
+pub fn and_into(dst: &mut [usize], src: &[usize]) {
+    #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: The CPU supports AVX2.
+            return unsafe { x86::and_into(dst, src) };
+        }
+    }
+    scalar::and_into(dst, src)
+}
+
+/// Xors every word of `src` into the word of `dst` at the same position.
+pub fn xor_into(dst: &mut [usize], src: &[usize]) {
+    #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: The CPU supports AVX2.
+            return unsafe { x86::xor_into(dst, src) };
+        }
+    }
+    scalar::xor_into(dst, src)
+}
+
+/// Counts the set bits of all `words`.
+pub fn count_ones(words: &[usize]) -> usize {
+    #[cfg(all(feature = "simd", any(target_arch = "x86", target_arch = "x86_64")))]
+    {
+        if is_x86_feature_detected!("popcnt") {
+            // SAFETY: The CPU supports `popcnt`.
+            return unsafe { x86::count_ones(words) };
+        }
+    }
+    scalar::count_ones(words)
+}
+
+/// Hints the CPU to load the cache line of `word`, on targets that have a
+/// stable prefetch instruction. Does nothing elsewhere.
+#[inline(always)]
+#[allow(unused_variables)]
+pub fn prefetch(word: &usize) {
+    #[cfg(all(feature = "prefetch", target_arch = "x86_64"))]
+    {
+        use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+        // SAFETY: SSE is part of the x86_64 baseline, and prefetching
+        // never faults.
+        unsafe { _mm_prefetch::<_MM_HINT_T0>(word as *const usize as *const i8) };
+    }
+}
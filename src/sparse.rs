@@ -0,0 +1,219 @@
+use util::*;
+use {BitSet, BitSetLike, DrainableBitSet, MemoryStats};
+
+/// This is a [`BitSet`] that frees each layer 0 page as soon as its last
+/// index is removed.
+///
+/// A `BitSet` allocates layer 0 in pages too, but keeps the pages it
+/// emptied until `shrink_to_fit` is called, so they can be written to
+/// again without allocating. A `SparseBitSet` gives them back right
+/// away, which makes it a better fit for large index spaces whose
+/// indices come and go.
+///
+/// The maximum index is the same as for a `BitSet`.
+///
+/// [`BitSet`]: ../struct.BitSet.html
+#[derive(Clone, Debug, Default)]
+pub struct SparseBitSet {
+    set: BitSet,
+}
+
+impl SparseBitSet {
+    /// Creates an empty `SparseBitSet`.
+    pub fn new() -> SparseBitSet {
+        Default::default()
+    }
+
+    /// Adds `id` to the `SparseBitSet`. Returns `true` if the value was
+    /// already in the set.
+    #[inline]
+    pub fn add(&mut self, id: Index) -> bool {
+        self.set.add(id)
+    }
+
+    /// Removes `id` from the set, returns `true` if the value
+    /// was removed, and `false` if the value was not set
+    /// to begin with.
+    ///
+    /// Removing the last index of a page frees that page.
+    #[inline]
+    pub fn remove(&mut self, id: Index) -> bool {
+        if !self.set.remove(id) {
+            return false;
+        }
+        let p1 = id.offset(SHIFT2);
+        if self.set.layer1(p1) == 0 {
+            self.set.layer0.free(p1);
+        }
+        true
+    }
+
+    /// Returns `true` if `id` is in the set.
+    #[inline]
+    pub fn contains(&self, id: Index) -> bool {
+        self.set.contains(id)
+    }
+
+    /// Returns the number of bytes the set has allocated on the heap.
+    pub fn heap_bytes(&self) -> usize {
+        self.memory_stats().total()
+    }
+
+    /// Returns the bytes the set has allocated on the heap for each layer.
+    ///
+    /// Layer 0 covers the page table and every allocated page.
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.set.memory_stats()
+    }
+
+    /// Completely wipes out the bit set, freeing all pages.
+    pub fn clear(&mut self) {
+        self.set.clear();
+        self.set.shrink_to_fit();
+    }
+}
+
+impl BitSetLike for SparseBitSet {
+    #[inline]
+    fn layer3(&self) -> usize {
+        self.set.layer3()
+    }
+
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        self.set.layer2(i)
+    }
+
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        self.set.layer1(i)
+    }
+
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        self.set.layer0(i)
+    }
+
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        self.contains(i)
+    }
+
+    #[inline]
+    fn exact_summaries(&self) -> bool {
+        true
+    }
+
+    #[inline]
+    fn len_upper_bound(&self) -> usize {
+        self.set.len()
+    }
+}
+
+impl DrainableBitSet for SparseBitSet {
+    #[inline]
+    fn remove(&mut self, i: Index) -> bool {
+        self.remove(i)
+    }
+}
+
+impl<B: BitSetLike> PartialEq<B> for SparseBitSet {
+    #[inline]
+    fn eq(&self, rhv: &B) -> bool {
+        self.is_equal(rhv)
+    }
+}
+
+impl Eq for SparseBitSet {}
+
+#[cfg(test)]
+mod sparse_set_test {
+    use {BitSetLike, SparseBitSet};
+
+    #[test]
+    fn insert() {
+        let mut c = SparseBitSet::new();
+        for i in 0..1_000 {
+            assert!(!c.add(i));
+            assert!(c.add(i));
+        }
+
+        for i in 0..1_000 {
+            assert!(c.contains(i));
+        }
+        assert!(!c.contains(1_000));
+    }
+
+    #[test]
+    fn remove() {
+        let mut c = SparseBitSet::new();
+        for i in 0..1_000 {
+            assert!(!c.add(i));
+        }
+
+        for i in 0..1_000 {
+            assert!(c.contains(i));
+            assert!(c.remove(i));
+            assert!(!c.contains(i));
+            assert!(!c.remove(i));
+        }
+        assert!(c.is_empty());
+        assert_eq!(c.set.layer0.iter().count(), 0);
+    }
+
+    #[test]
+    fn memory_stats() {
+        use std::mem::size_of;
+
+        let mut c = SparseBitSet::new();
+        assert_eq!(c.heap_bytes(), 0);
+        c.add(16_000_000);
+        let one_page = c.memory_stats().layer0;
+        assert!(one_page >= size_of::<::pages::Page>());
+        c.add(5);
+        assert_eq!(
+            c.memory_stats().layer0,
+            one_page + size_of::<::pages::Page>()
+        );
+        c.remove(5);
+        assert_eq!(c.memory_stats().layer0, one_page);
+    }
+
+    #[test]
+    fn sparse_pages() {
+        let mut c = SparseBitSet::new();
+        c.add(16_000_000);
+        c.add(5);
+        assert_eq!(c.set.layer0.iter().count(), 2);
+        assert_eq!((&c).iter().collect::<Vec<_>>(), [5, 16_000_000]);
+
+        c.remove(16_000_000);
+        assert_eq!(c.set.layer0.iter().count(), 1);
+        assert_eq!((&c).iter().collect::<Vec<_>>(), [5]);
+    }
+
+    #[test]
+    fn iter() {
+        let mut c = SparseBitSet::new();
+        for i in 0..100_000 {
+            c.add(i);
+        }
+
+        let mut count = 0;
+        for (idx, i) in c.iter().enumerate() {
+            count += 1;
+            assert_eq!(idx, i as usize);
+        }
+        assert_eq!(count, 100_000);
+    }
+
+    #[test]
+    fn drain() {
+        use DrainableBitSet;
+
+        let mut c: SparseBitSet = (0..10_000).filter(|i| i % 3 == 0).collect();
+        assert_eq!(c.drain().count(), 3_334);
+        assert!(c.is_empty());
+        assert_eq!(c.set.layer0.iter().count(), 0);
+    }
+}
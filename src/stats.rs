@@ -0,0 +1,37 @@
+/// The heap memory used by a set, broken down by layer, in bytes.
+///
+/// Returned by `memory_stats()` on the set types. Layer 3 is always
+/// stored inline, so it does not show up here. The sizes are those of
+/// the allocations, which may be larger than what the set currently uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct MemoryStats {
+    /// Bytes allocated for layer 2.
+    pub layer2: usize,
+    /// Bytes allocated for layer 1.
+    pub layer1: usize,
+    /// Bytes allocated for layer 0, including the bookkeeping of sets
+    /// which allocate it lazily.
+    pub layer0: usize,
+}
+
+impl MemoryStats {
+    /// The total number of bytes allocated.
+    pub fn total(&self) -> usize {
+        self.layer2 + self.layer1 + self.layer0
+    }
+}
+
+/// What a bulk load into a set did, returned by `BitSet::try_extend` and
+/// `BitSet::extend_within`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ExtendStats {
+    /// Indices that were added to the set.
+    pub added: usize,
+    /// Indices that were already in the set, including repeats within the
+    /// input.
+    pub duplicates: usize,
+    /// Indices that were left out for being too large.
+    pub out_of_range: usize,
+    /// The largest index seen in the input, whether it was added or not.
+    pub max: Option<u32>,
+}
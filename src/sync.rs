@@ -0,0 +1,193 @@
+//! The atomics `AtomicBitSet` is built on, which are [loom]'s when
+//! building with `--cfg loom`, so its concurrency can be model checked.
+//! On wasm32 without the `atomics` target feature there are no threads,
+//! and plain cells stand in for them.
+//!
+//! [loom]: https://docs.rs/loom
+
+#[cfg(all(target_arch = "wasm32", not(target_feature = "atomics"), not(loom)))]
+pub use self::cell::{AtomicIsize, AtomicPtr, AtomicUsize};
+#[cfg(loom)]
+pub use loom::sync::atomic::{AtomicIsize, AtomicPtr, AtomicUsize};
+pub use std::sync::atomic::Ordering;
+#[cfg(not(any(loom, all(target_arch = "wasm32", not(target_feature = "atomics")))))]
+pub use std::sync::atomic::{AtomicIsize, AtomicPtr, AtomicUsize};
+
+/// Plain access to the value of an atomic through a unique borrow.
+///
+/// The std atomics hand out a `&mut` to their value, while loom's only
+/// pass it to a closure, which is what both are wrapped into.
+pub trait UniqueAccess<T> {
+    fn with_unique<R, F: FnOnce(&mut T) -> R>(&mut self, f: F) -> R;
+}
+
+impl UniqueAccess<usize> for AtomicUsize {
+    #[inline]
+    fn with_unique<R, F: FnOnce(&mut usize) -> R>(&mut self, f: F) -> R {
+        #[cfg(loom)]
+        return self.with_mut(f);
+        #[cfg(not(loom))]
+        return f(self.get_mut());
+    }
+}
+
+impl UniqueAccess<isize> for AtomicIsize {
+    #[inline]
+    fn with_unique<R, F: FnOnce(&mut isize) -> R>(&mut self, f: F) -> R {
+        #[cfg(loom)]
+        return self.with_mut(f);
+        #[cfg(not(loom))]
+        return f(self.get_mut());
+    }
+}
+
+impl<T> UniqueAccess<*mut T> for AtomicPtr<T> {
+    #[inline]
+    fn with_unique<R, F: FnOnce(&mut *mut T) -> R>(&mut self, f: F) -> R {
+        #[cfg(loom)]
+        return self.with_mut(f);
+        #[cfg(not(loom))]
+        return f(self.get_mut());
+    }
+}
+
+/// Cells with the API of the std atomics, for targets without threads.
+///
+/// The orderings are ignored, as there's no other thread to order the
+/// accesses against.
+#[cfg(all(target_arch = "wasm32", not(target_feature = "atomics"), not(loom)))]
+mod cell {
+    use std::cell::Cell;
+    use std::fmt::{Debug, Error as FormatError, Formatter};
+
+    use super::Ordering;
+
+    pub struct AtomicUsize(Cell<usize>);
+
+    // SAFETY: Without the `atomics` target feature wasm32 can't spawn
+    // threads, so the cell is never accessed from two of them.
+    unsafe impl Sync for AtomicUsize {}
+
+    impl AtomicUsize {
+        #[inline]
+        pub const fn new(value: usize) -> Self {
+            AtomicUsize(Cell::new(value))
+        }
+
+        #[inline]
+        pub fn load(&self, _: Ordering) -> usize {
+            self.0.get()
+        }
+
+        #[inline]
+        pub fn store(&self, value: usize, _: Ordering) {
+            self.0.set(value)
+        }
+
+        #[inline]
+        pub fn swap(&self, value: usize, _: Ordering) -> usize {
+            self.0.replace(value)
+        }
+
+        #[inline]
+        pub fn fetch_add(&self, value: usize, _: Ordering) -> usize {
+            self.0.replace(self.0.get().wrapping_add(value))
+        }
+
+        #[inline]
+        pub fn fetch_or(&self, value: usize, _: Ordering) -> usize {
+            self.0.replace(self.0.get() | value)
+        }
+
+        #[inline]
+        pub fn get_mut(&mut self) -> &mut usize {
+            self.0.get_mut()
+        }
+    }
+
+    impl Debug for AtomicUsize {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
+            Debug::fmt(&self.0.get(), f)
+        }
+    }
+
+    pub struct AtomicIsize(Cell<isize>);
+
+    // SAFETY: See `AtomicUsize`.
+    unsafe impl Sync for AtomicIsize {}
+
+    impl AtomicIsize {
+        #[inline]
+        pub const fn new(value: isize) -> Self {
+            AtomicIsize(Cell::new(value))
+        }
+
+        #[inline]
+        pub fn load(&self, _: Ordering) -> isize {
+            self.0.get()
+        }
+
+        #[inline]
+        pub fn fetch_add(&self, value: isize, _: Ordering) -> isize {
+            self.0.replace(self.0.get().wrapping_add(value))
+        }
+
+        #[inline]
+        pub fn fetch_sub(&self, value: isize, _: Ordering) -> isize {
+            self.0.replace(self.0.get().wrapping_sub(value))
+        }
+
+        #[inline]
+        pub fn get_mut(&mut self) -> &mut isize {
+            self.0.get_mut()
+        }
+    }
+
+    impl Debug for AtomicIsize {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
+            Debug::fmt(&self.0.get(), f)
+        }
+    }
+
+    pub struct AtomicPtr<T>(Cell<*mut T>);
+
+    // SAFETY: Like `AtomicUsize`, the cell is never accessed from two
+    // threads, and the std `AtomicPtr` is `Send` and `Sync` for any `T`.
+    unsafe impl<T> Send for AtomicPtr<T> {}
+    // SAFETY: See above.
+    unsafe impl<T> Sync for AtomicPtr<T> {}
+
+    impl<T> AtomicPtr<T> {
+        #[inline]
+        pub const fn new(ptr: *mut T) -> Self {
+            AtomicPtr(Cell::new(ptr))
+        }
+
+        #[inline]
+        pub fn load(&self, _: Ordering) -> *mut T {
+            self.0.get()
+        }
+
+        #[inline]
+        pub fn compare_exchange(
+            &self,
+            current: *mut T,
+            new: *mut T,
+            _: Ordering,
+            _: Ordering,
+        ) -> Result<*mut T, *mut T> {
+            let old = self.0.get();
+            if old == current {
+                self.0.set(new);
+                Ok(old)
+            } else {
+                Err(old)
+            }
+        }
+
+        #[inline]
+        pub fn get_mut(&mut self) -> &mut *mut T {
+            self.0.get_mut()
+        }
+    }
+}
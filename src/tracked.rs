@@ -0,0 +1,192 @@
+use util::*;
+use {BitSet, BitSetAnd, BitSetLike, BitSetNot};
+
+/// A [`BitSet`] that keeps track of the indices added and removed since
+/// its flags were last cleared.
+///
+/// The changes are kept as two more `BitSet`s, [`added`] and [`removed`],
+/// which hold the net changes: an index that is added and removed again
+/// between two calls to [`clear_flags`] shows up in neither. They can be
+/// joined with other sets like any `BitSet`, for example to only visit
+/// the entities whose components were added this frame.
+///
+/// # Examples
+///
+/// ```
+/// # use hibitset::{BitSetLike, TrackedBitSet};
+/// let mut set = TrackedBitSet::new();
+/// set.add(1);
+/// set.add(2);
+/// set.clear_flags();
+///
+/// set.remove(1);
+/// set.add(3);
+/// assert_eq!(set.added().iter().collect::<Vec<_>>(), [3]);
+/// assert_eq!(set.removed().iter().collect::<Vec<_>>(), [1]);
+/// ```
+///
+/// [`BitSet`]: struct.BitSet.html
+/// [`added`]: #method.added
+/// [`removed`]: #method.removed
+/// [`clear_flags`]: #method.clear_flags
+#[derive(Clone, Debug, Default)]
+pub struct TrackedBitSet {
+    set: BitSet,
+    added: BitSet,
+    removed: BitSet,
+}
+
+impl TrackedBitSet {
+    /// Creates an empty `TrackedBitSet`.
+    pub fn new() -> TrackedBitSet {
+        Default::default()
+    }
+
+    /// Adds `id` to the set, flagging it as added unless it was removed
+    /// since the flags were cleared. Returns `true` if the value was
+    /// already in the set.
+    #[inline]
+    pub fn add(&mut self, id: Index) -> bool {
+        if self.set.add(id) {
+            return true;
+        }
+        if !self.removed.remove(id) {
+            self.added.add(id);
+        }
+        false
+    }
+
+    /// Removes `id` from the set, flagging it as removed unless it was
+    /// added since the flags were cleared. Returns `true` if the value was
+    /// in the set.
+    #[inline]
+    pub fn remove(&mut self, id: Index) -> bool {
+        if !self.set.remove(id) {
+            return false;
+        }
+        if !self.added.remove(id) {
+            self.removed.add(id);
+        }
+        true
+    }
+
+    /// Returns `true` if `id` is in the set.
+    #[inline]
+    pub fn contains(&self, id: Index) -> bool {
+        self.set.contains(id)
+    }
+
+    /// Returns the indices in the set.
+    pub fn as_set(&self) -> &BitSet {
+        &self.set
+    }
+
+    /// Returns the indices added since the flags were last cleared.
+    pub fn added(&self) -> &BitSet {
+        &self.added
+    }
+
+    /// Returns the indices removed since the flags were last cleared.
+    pub fn removed(&self) -> &BitSet {
+        &self.removed
+    }
+
+    /// Forgets the changes made so far, keeping the indices in the set.
+    pub fn clear_flags(&mut self) {
+        self.added.clear();
+        self.removed.clear();
+    }
+
+    /// Removes every index from the set, flagging the ones that weren't
+    /// added since the flags were cleared as removed.
+    pub fn clear(&mut self) {
+        self.removed |= &BitSetAnd(&self.set, BitSetNot(&self.added));
+        self.added.clear();
+        self.set.clear();
+    }
+}
+
+impl BitSetLike for TrackedBitSet {
+    #[inline]
+    fn layer3(&self) -> usize {
+        self.set.layer3()
+    }
+
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        self.set.layer2(i)
+    }
+
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        self.set.layer1(i)
+    }
+
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        self.set.layer0(i)
+    }
+
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        self.set.contains(i)
+    }
+
+    #[inline]
+    fn exact_summaries(&self) -> bool {
+        true
+    }
+
+    #[inline]
+    fn len_upper_bound(&self) -> usize {
+        self.set.len()
+    }
+
+    #[inline]
+    fn backing_bitset(&self) -> Option<&BitSet> {
+        Some(&self.set)
+    }
+}
+
+impl Extend<Index> for TrackedBitSet {
+    fn extend<T: IntoIterator<Item = Index>>(&mut self, iter: T) {
+        for id in iter {
+            self.add(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {BitSet, BitSetLike, TrackedBitSet};
+
+    #[test]
+    fn flags() {
+        let mut t = TrackedBitSet::new();
+        t.extend(0..10);
+        assert_eq!(*t.added(), (0..10).collect::<BitSet>());
+        assert!(t.removed().is_empty());
+        t.clear_flags();
+
+        assert!(t.remove(3));
+        assert!(!t.remove(3));
+        assert!(!t.add(3));
+        assert!(t.add(4));
+        assert!(!t.add(100));
+        assert!(t.remove(100));
+        assert!(t.added().is_empty() && t.removed().is_empty());
+
+        assert!(t.remove(5));
+        assert!(!t.add(20));
+        assert_eq!(t.added().iter().collect::<Vec<_>>(), [20]);
+        assert_eq!(t.removed().iter().collect::<Vec<_>>(), [5]);
+        assert_eq!((&t & t.removed()).iter().next(), None);
+
+        t.clear();
+        assert!(t.as_set().is_empty());
+        assert!(t.added().is_empty());
+        assert_eq!(*t.removed(), (0..10).collect::<BitSet>());
+        t.clear_flags();
+        assert!(t.removed().is_empty());
+    }
+}
@@ -0,0 +1,195 @@
+use std::cmp;
+use std::ops::Range;
+
+use ops::range_word;
+use util::*;
+use BitSetLike;
+
+/// A window into a [`BitSetLike`], holding the indices of a range of it
+/// re-based to start at 0.
+///
+/// Index `i` of the view is index `start + i` of the underlying set, for
+/// the `i` below the length of the range. As the view is a `BitSetLike`
+/// itself, it can be combined with sets of range-relative indices, for
+/// example when a set is processed in chunks. It is created with
+/// [`view`].
+///
+/// Layer 0 words are exact, shifted out of the underlying words. Unless
+/// the range starts on a word boundary of a layer, its upper words can
+/// only over-approximate, so iteration may visit a few empty words.
+///
+/// # Examples
+///
+/// ```
+/// # use hibitset::{BitSet, BitSetLike};
+/// let set: BitSet = [10, 4_100, 4_200, 9_000].iter().collect();
+/// let chunk: BitSet = [4, 104, 500].iter().collect();
+///
+/// let view = (&set).view(4_096..8_192);
+/// assert_eq!((&view).iter().collect::<Vec<_>>(), [4, 104]);
+/// assert_eq!((view & &chunk).iter().collect::<Vec<_>>(), [4, 104]);
+/// ```
+///
+/// [`BitSetLike`]: trait.BitSetLike.html
+/// [`view`]: trait.BitSetLike.html#method.view
+#[derive(Debug, Clone)]
+pub struct BitSetView<T> {
+    set: T,
+    start: usize,
+    len: usize,
+}
+
+impl<T: BitSetLike> BitSetView<T> {
+    /// Creates a view of the indices of `set` in `range`. The range is
+    /// clamped to the maximum index.
+    pub fn new(set: T, range: Range<Index>) -> Self {
+        let end = cmp::min(range.end as usize, MAX_EID);
+        let start = cmp::min(range.start as usize, end);
+        BitSetView {
+            set,
+            start,
+            len: end - start,
+        }
+    }
+
+    /// Returns the underlying index of index 0 of the view.
+    pub fn start(&self) -> Index {
+        self.start as Index
+    }
+
+    /// Returns the range of underlying indices the view covers.
+    pub fn range(&self) -> Range<Index> {
+        self.start as Index..(self.start + self.len) as Index
+    }
+
+    /// Returns the underlying set.
+    pub fn into_inner(self) -> T {
+        self.set
+    }
+
+    /// Computes the word at `idx` of `level` by shifting the underlying
+    /// words, read with `word`, into place.
+    #[inline]
+    fn word<F>(&self, level: usize, idx: usize, word: F) -> usize
+    where
+        F: Fn(usize) -> usize,
+    {
+        let shift = BITS * level;
+        let offset = self.start >> shift;
+        // The layer has `64^(3 - level)` words, none are read past those.
+        let words = 1 << (BITS * (LAYERS - 1 - level));
+        let mut bits = shifted(&word, offset + (idx << BITS), words);
+        // A child of the view straddles two underlying children whenever
+        // the range doesn't start on one of their boundaries.
+        if self.start & ((1 << shift) - 1) != 0 {
+            bits |= shifted(&word, offset + 1 + (idx << BITS), words);
+        }
+        bits & range_word(0, self.len, level, idx)
+    }
+}
+
+/// Reads the bits of a layer of `words` words starting at bit `offset`,
+/// as one word. The bits past the end of the layer are zero.
+#[inline]
+fn shifted<F>(word: &F, offset: usize, words: usize) -> usize
+where
+    F: Fn(usize) -> usize,
+{
+    let (idx, bit) = (offset >> BITS, offset & ((1 << BITS) - 1));
+    let read = |idx: usize| if idx < words { word(idx) } else { 0 };
+    if bit == 0 {
+        read(idx)
+    } else {
+        read(idx) >> bit | read(idx + 1) << ((1 << BITS) - bit)
+    }
+}
+
+impl<T: BitSetLike> BitSetLike for BitSetView<T> {
+    #[inline]
+    fn layer3(&self) -> usize {
+        self.word(3, 0, |i| if i == 0 { self.set.layer3() } else { 0 })
+    }
+
+    #[inline]
+    fn layer2(&self, i: usize) -> usize {
+        self.word(2, i, |i| self.set.layer2(i))
+    }
+
+    #[inline]
+    fn layer1(&self, i: usize) -> usize {
+        self.word(1, i, |i| self.set.layer1(i))
+    }
+
+    #[inline]
+    fn layer0(&self, i: usize) -> usize {
+        self.word(0, i, |i| self.set.layer0(i))
+    }
+
+    #[inline]
+    fn contains(&self, i: Index) -> bool {
+        (i as usize) < self.len && self.set.contains((self.start + i as usize) as Index)
+    }
+
+    #[inline]
+    fn len_upper_bound(&self) -> usize {
+        cmp::min(self.len, self.set.len_upper_bound())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {AtomicBitSet, BitSet, BitSetLike, BitSetNot};
+
+    #[test]
+    fn view() {
+        let set: BitSet = (0..300_000)
+            .filter(|i| i % 7 == 3 || i % 1_000 < 5)
+            .collect();
+        for &(start, end) in &[
+            (0, 300_000),
+            (64, 200),
+            (4_096, 8_192),
+            (100, 100_000),
+            (262_143, 300_000),
+            (299_990, 400_000),
+            (5, 5),
+        ] {
+            let view = (&set).view(start..end);
+            let expected: Vec<_> = (&set)
+                .iter()
+                .filter(|&i| i >= start && i < end)
+                .map(|i| i - start)
+                .collect();
+            assert_eq!((&view).iter().collect::<Vec<_>>(), expected);
+            for &i in &expected {
+                assert!(view.contains(i));
+            }
+            assert!(!view.contains(end - start));
+        }
+
+        let not = BitSetNot(&set).view(1_000..1_010);
+        assert_eq!((&not).iter().collect::<Vec<_>>(), [5, 6, 7, 8, 9]);
+        let chunk: BitSet = [0, 1, 7, 9].iter().collect();
+        assert_eq!((&not & &chunk).iter().collect::<Vec<_>>(), [7, 9]);
+        assert_eq!(not.range(), 1_000..1_010);
+    }
+
+    #[test]
+    fn view_top_of_atomic() {
+        let max = ::MAX_EID as u32;
+        let set = AtomicBitSet::new();
+        for &id in &[0, 4_095, 4_096, 262_143, max - 64, max - 1] {
+            set.add_atomic(id);
+        }
+        for &start in &[1, 63, 4_096, 4_097, 262_145, max - 100, max - 1] {
+            let view = (&set).view(start..max);
+            let expected: Vec<_> = (&set)
+                .iter()
+                .filter(|&i| i >= start)
+                .map(|i| i - start)
+                .collect();
+            assert_eq!((&view).iter().collect::<Vec<_>>(), expected);
+            assert!(view.contains(max - 1 - start));
+        }
+    }
+}
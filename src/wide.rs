@@ -0,0 +1,364 @@
+use std::collections::btree_map::{self, BTreeMap};
+use std::iter::FromIterator;
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Sub, SubAssign};
+
+use util::*;
+use {BitIter, BitSet, BitSetLike};
+
+/// A set of `u64` indices, built from [`BitSet`]s.
+///
+/// `Index` is a `u32` and a `BitSet` only holds indices below
+/// `usize_bits.pow(4)`, which is too little for things like log offsets.
+/// A `WideBitSet` adds another level on top of the hierarchy: the upper
+/// bits of an index select a `BitSet` page from an ordered map, and the
+/// lower bits are stored in that page. Pages are created on the first
+/// insertion and dropped once they become empty, so only the populated
+/// parts of the 64-bit index space take up memory.
+///
+/// Iteration yields the indices in ascending order.
+///
+/// Wide sets are combined with `&`, `|`, `^` and `-`, and their assigning
+/// forms, which work a page at a time with the operations of `BitSet`.
+/// To use the lazy [`BitSetLike`] combinators or a join instead, combine
+/// the pages with the same key from [`pages`].
+///
+/// [`BitSet`]: ../struct.BitSet.html
+/// [`BitSetLike`]: ../trait.BitSetLike.html
+/// [`pages`]: #method.pages
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct WideBitSet {
+    pages: BTreeMap<u64, BitSet>,
+}
+
+/// Splits `id` into its page key and the index inside the page.
+#[inline]
+fn split(id: u64) -> (u64, Index) {
+    (id >> MAX, (id & (MAX_EID as u64 - 1)) as Index)
+}
+
+impl WideBitSet {
+    /// Creates an empty `WideBitSet`.
+    pub fn new() -> WideBitSet {
+        Default::default()
+    }
+
+    /// Adds `id` to the `WideBitSet`. Returns `true` if the value was
+    /// already in the set.
+    pub fn add(&mut self, id: u64) -> bool {
+        let (page, id) = split(id);
+        self.pages.entry(page).or_default().add(id)
+    }
+
+    /// Removes `id` from the set, returns `true` if the value
+    /// was removed, and `false` if the value was not set
+    /// to begin with.
+    ///
+    /// Removing the last index of a page drops that page.
+    pub fn remove(&mut self, id: u64) -> bool {
+        let (key, id) = split(id);
+        let page = match self.pages.get_mut(&key) {
+            Some(page) => page,
+            None => return false,
+        };
+        if !page.remove(id) {
+            return false;
+        }
+        if page.is_empty() {
+            self.pages.remove(&key);
+        }
+        true
+    }
+
+    /// Returns `true` if `id` is in the set.
+    pub fn contains(&self, id: u64) -> bool {
+        let (page, id) = split(id);
+        match self.pages.get(&page) {
+            Some(page) => page.contains(id),
+            None => false,
+        }
+    }
+
+    /// Returns the number of indices in the set.
+    pub fn len(&self) -> usize {
+        self.pages.values().map(BitSet::len).sum()
+    }
+
+    /// Returns `true` if there are no indices in the set.
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    /// Completely wipes out the set, dropping all pages.
+    pub fn clear(&mut self) {
+        self.pages.clear();
+    }
+
+    /// Create an iterator over the indices in the set, in ascending order.
+    pub fn iter(&self) -> WideBitIter<'_> {
+        WideBitIter {
+            pages: self.pages.iter(),
+            page: None,
+        }
+    }
+
+    /// Iterates over the non-empty pages of the set in ascending order,
+    /// with the first index each of them covers.
+    ///
+    /// An index `i` of a page covering `base` stands for `base + i`.
+    pub fn pages(&self) -> WidePages<'_> {
+        WidePages {
+            pages: self.pages.iter(),
+        }
+    }
+
+    /// Drops the page at `key` if it became empty.
+    fn trim(&mut self, key: u64) {
+        if let Some(page) = self.pages.get(&key) {
+            if page.is_empty() {
+                self.pages.remove(&key);
+            }
+        }
+    }
+}
+
+impl BitAndAssign<&WideBitSet> for WideBitSet {
+    fn bitand_assign(&mut self, rhs: &WideBitSet) {
+        self.pages.retain(|key, page| match rhs.pages.get(key) {
+            Some(other) => {
+                *page &= other;
+                !page.is_empty()
+            }
+            None => false,
+        });
+    }
+}
+
+impl BitOrAssign<&WideBitSet> for WideBitSet {
+    fn bitor_assign(&mut self, rhs: &WideBitSet) {
+        for (&key, other) in &rhs.pages {
+            *self.pages.entry(key).or_default() |= other;
+        }
+    }
+}
+
+impl BitXorAssign<&WideBitSet> for WideBitSet {
+    fn bitxor_assign(&mut self, rhs: &WideBitSet) {
+        for (&key, other) in &rhs.pages {
+            *self.pages.entry(key).or_default() ^= other;
+            self.trim(key);
+        }
+    }
+}
+
+impl SubAssign<&WideBitSet> for WideBitSet {
+    fn sub_assign(&mut self, rhs: &WideBitSet) {
+        for (&key, other) in &rhs.pages {
+            if let Some(page) = self.pages.get_mut(&key) {
+                *page -= other;
+            }
+            self.trim(key);
+        }
+    }
+}
+
+macro_rules! wide_operator {
+    ( $( $op:ident :: $method:ident => $assign:ident ),* ) => { $(
+        impl $op<&WideBitSet> for &WideBitSet {
+            type Output = WideBitSet;
+
+            fn $method(self, rhs: &WideBitSet) -> WideBitSet {
+                let mut set = self.clone();
+                set.$assign(rhs);
+                set
+            }
+        }
+    )* };
+}
+
+wide_operator! {
+    BitOr::bitor => bitor_assign,
+    BitXor::bitxor => bitxor_assign,
+    Sub::sub => sub_assign
+}
+
+/// Only the pages both sets have are intersected, rather than cloning
+/// the left-hand side first.
+impl BitAnd<&WideBitSet> for &WideBitSet {
+    type Output = WideBitSet;
+
+    fn bitand(self, rhs: &WideBitSet) -> WideBitSet {
+        let pages = self
+            .pages
+            .iter()
+            .filter_map(|(&key, page)| {
+                let other = rhs.pages.get(&key)?;
+                let page = (page & other).to_bitset();
+                if page.is_empty() {
+                    None
+                } else {
+                    Some((key, page))
+                }
+            })
+            .collect();
+        WideBitSet { pages }
+    }
+}
+
+/// An `Iterator` over the pages of a [`WideBitSet`], see
+/// [`WideBitSet::pages`].
+///
+/// [`WideBitSet`]: struct.WideBitSet.html
+/// [`WideBitSet::pages`]: struct.WideBitSet.html#method.pages
+#[derive(Debug, Clone)]
+pub struct WidePages<'a> {
+    pages: btree_map::Iter<'a, u64, BitSet>,
+}
+
+impl<'a> Iterator for WidePages<'a> {
+    type Item = (u64, &'a BitSet);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.pages.next().map(|(&key, page)| (key << MAX, page))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.pages.size_hint()
+    }
+}
+
+/// An `Iterator` over a [`WideBitSet`].
+///
+/// [`WideBitSet`]: struct.WideBitSet.html
+#[derive(Debug, Clone)]
+pub struct WideBitIter<'a> {
+    pages: btree_map::Iter<'a, u64, BitSet>,
+    page: Option<(u64, BitIter<&'a BitSet>)>,
+}
+
+impl<'a> Iterator for WideBitIter<'a> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((base, ref mut iter)) = self.page {
+                if let Some(id) = iter.next() {
+                    return Some(base | id as u64);
+                }
+            }
+            let (&key, page) = self.pages.next()?;
+            self.page = Some((key << MAX, page.iter()));
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a WideBitSet {
+    type Item = u64;
+    type IntoIter = WideBitIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl Extend<u64> for WideBitSet {
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = u64>,
+    {
+        for id in iter {
+            self.add(id);
+        }
+    }
+}
+
+impl FromIterator<u64> for WideBitSet {
+    fn from_iter<T>(iter: T) -> Self
+    where
+        T: IntoIterator<Item = u64>,
+    {
+        let mut set = WideBitSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+#[cfg(test)]
+mod wide_set_test {
+    use WideBitSet;
+
+    #[test]
+    fn insert_remove() {
+        let ids = [0, 5, 1 << 24, (1 << 32) + 7, u64::MAX];
+        let mut c = WideBitSet::new();
+        for &id in &ids {
+            assert!(!c.add(id));
+            assert!(c.add(id));
+        }
+        for &id in &ids {
+            assert!(c.contains(id));
+            assert!(!c.contains(id ^ 1));
+        }
+        assert_eq!(c.iter().collect::<Vec<_>>(), ids);
+
+        for &id in &ids {
+            assert!(c.remove(id));
+            assert!(!c.remove(id));
+        }
+        assert!(c.is_empty());
+    }
+
+    #[test]
+    fn operators() {
+        use std::collections::BTreeSet;
+
+        let a: Vec<u64> = (0..2_000u64).map(|i| i * 0x8000_0007).collect();
+        let b: Vec<u64> = (0..2_000u64).map(|i| i * 0x4000_0005).collect();
+        let (wa, wb): (WideBitSet, WideBitSet) =
+            (a.iter().cloned().collect(), b.iter().cloned().collect());
+        let (ha, hb): (BTreeSet<u64>, BTreeSet<u64>) =
+            (a.into_iter().collect(), b.into_iter().collect());
+
+        let check = |set: WideBitSet, expected: BTreeSet<u64>| {
+            assert_eq!(set.iter().collect::<BTreeSet<_>>(), expected);
+            assert_eq!(set.len(), expected.len());
+            assert!(set.pages().all(|(_, page)| !page.is_empty()));
+        };
+        check(&wa & &wb, &ha & &hb);
+        check(&wa | &wb, &ha | &hb);
+        check(&wa ^ &wb, &ha ^ &hb);
+        check(&wa - &wb, &ha - &hb);
+        check(&wa - &wa, BTreeSet::new());
+
+        let mut c = wa.clone();
+        c &= &wb;
+        c |= &wb;
+        c ^= &wa;
+        check(c, &hb ^ &ha);
+    }
+
+    #[test]
+    fn pages() {
+        use {BitSetAnd, BitSetLike};
+
+        let a: WideBitSet = [3, 1 << 40, (1 << 40) + 9].iter().cloned().collect();
+        let b: WideBitSet = [3, (1 << 40) + 9].iter().cloned().collect();
+        let bases = a.pages().map(|(base, _)| base).collect::<Vec<_>>();
+        assert_eq!(bases, [0, 1 << 40]);
+
+        // Joining the pages with the same base.
+        let joined = a
+            .pages()
+            .zip(b.pages())
+            .flat_map(|((base, x), (_, y))| BitSetAnd(x, y).iter().map(move |i| base + i as u64))
+            .collect::<Vec<_>>();
+        assert_eq!(joined, [3, (1 << 40) + 9]);
+    }
+
+    #[test]
+    fn ordered_across_pages() {
+        let c: WideBitSet = (0..1_000u64).map(|i| i * 0x1_0000_0001).rev().collect();
+        let expected = (0..1_000u64).map(|i| i * 0x1_0000_0001).collect::<Vec<_>>();
+        assert_eq!((&c).into_iter().collect::<Vec<_>>(), expected);
+        assert_eq!(c.pages.len(), 1_000);
+    }
+}
@@ -0,0 +1,120 @@
+use std::ops::{Deref, DerefMut};
+
+use alloc::{Global, LayerAlloc};
+use util::*;
+use BitSet;
+
+/// A mutable borrow of a layer 0 word of a [`BitSet`], returned by
+/// [`BitSet::get_word_mut`].
+///
+/// The word can be changed freely through the guard. When it is dropped,
+/// the count of the set is updated, and so are the layers above if the
+/// word went from zero to non-zero or back. Leaking the guard leaves the
+/// set with stale layers, which hide the indices of the word from
+/// iteration until it is written to again.
+///
+/// [`BitSet`]: struct.BitSet.html
+/// [`BitSet::get_word_mut`]: struct.BitSet.html#method.get_word_mut
+#[derive(Debug)]
+pub struct WordMut<'a, A: LayerAlloc = Global> {
+    set: &'a mut BitSet<A>,
+    p0: usize,
+    old: usize,
+}
+
+impl<A: LayerAlloc> BitSet<A> {
+    /// Returns a guard to change the layer 0 word at `p0`, which holds the
+    /// indices from `p0 * usize::BITS` on, as a whole.
+    ///
+    /// The layer is grown to hold the word first. Panics if the word is
+    /// beyond the maximum index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use hibitset::{BitSet, BitSetLike};
+    /// let mut set = BitSet::new();
+    /// *set.get_word_mut(2) |= 0b101;
+    /// assert_eq!((&set).iter().collect::<Vec<_>>(), [128, 130]);
+    /// *set.get_word_mut(2) = 0;
+    /// assert!(set.is_empty());
+    /// ```
+    pub fn get_word_mut(&mut self, p0: usize) -> WordMut<'_, A> {
+        assert!(
+            p0 < MAX_EID >> BITS,
+            "Expected word index to be less then {}, found {}",
+            MAX_EID >> BITS,
+            p0
+        );
+        if p0 >= self.layer0.len() {
+            self.grow((p0 << BITS) as Index);
+        }
+        let old = self.layer0[p0];
+        WordMut { set: self, p0, old }
+    }
+}
+
+impl<A: LayerAlloc> Deref for WordMut<'_, A> {
+    type Target = usize;
+
+    #[inline]
+    fn deref(&self) -> &usize {
+        &self.set.layer0[self.p0]
+    }
+}
+
+impl<A: LayerAlloc> DerefMut for WordMut<'_, A> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut usize {
+        &mut self.set.layer0[self.p0]
+    }
+}
+
+impl<A: LayerAlloc> Drop for WordMut<'_, A> {
+    fn drop(&mut self) {
+        let (old, new) = (self.old, self.set.layer0[self.p0]);
+        self.set.saturated.update(self.p0, old, new);
+        self.set.len = self.set.len + new.count_ones() as usize - old.count_ones() as usize;
+        let id = (self.p0 << BITS) as Index;
+        if old == 0 && new != 0 {
+            self.set.add_slow(id);
+        } else if old != 0 && new == 0 {
+            self.set.remove_slow(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {BitSet, BitSetLike};
+
+    #[test]
+    fn get_word_mut() {
+        let mut set = BitSet::new();
+        set.add(4096);
+        *set.get_word_mut(70_000) = !0;
+        assert_eq!(set.len(), 65);
+        assert!(set.contains(70_000 << 6));
+        *set.get_word_mut(64) &= 0;
+        *set.get_word_mut(70_000) &= 0b11;
+        assert_eq!(set.len(), 2);
+        assert_eq!((&set).iter().collect::<Vec<_>>(), [4_480_000, 4_480_001]);
+        assert_eq!(set.layer3(), 1 << 17);
+
+        {
+            let mut word = set.get_word_mut(1);
+            *word = 1;
+            *word = 0;
+        }
+        *set.get_word_mut(70_000) = 0;
+        assert!(set.is_empty());
+        assert_eq!(set.layer3(), 0);
+        assert_eq!(set, BitSet::new());
+    }
+
+    #[test]
+    #[should_panic]
+    fn out_of_range() {
+        BitSet::new().get_word_mut(::MAX_EID >> 6);
+    }
+}